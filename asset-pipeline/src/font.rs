@@ -0,0 +1,456 @@
+//! Converts BDF bitmap fonts for `picoboy::text`.
+//!
+//! Only the parts of BDF needed for fixed-size bitmap fonts are read: the
+//! `FONT_ASCENT`, `FONT_DESCENT` and `DEFAULT_CHAR` properties and the
+//! `ENCODING`, `DWIDTH`, `BBX` and `BITMAP` of every glyph. Encodings are
+//! Unicode code points, glyphs without one are skipped. Empty rows and columns
+//! around a glyph are cropped to keep the bitmaps small.
+
+use std::fmt::{self, Write};
+
+/// Error in a BDF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Glyph with its bitmap cropped to the set pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glyph {
+    pub character: char,
+    /// Distance to the next glyph
+    pub advance: u8,
+    pub width: u8,
+    pub height: u8,
+    /// Offset of the left column from the origin
+    pub x_offset: i8,
+    /// Offset of the bottom row above the baseline
+    pub y_offset: i8,
+    /// Rows of bits, most significant bit first, each row starting at a new byte
+    pub bitmap: Vec<u8>,
+}
+
+/// Parsed BDF font
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    /// Name of the generated `static`
+    pub name: String,
+    /// Pixels above the baseline
+    pub ascent: u8,
+    /// Pixels below the baseline
+    pub descent: u8,
+    /// Drawn for characters without a glyph
+    pub replacement: Option<char>,
+    /// Sorted by character
+    pub glyphs: Vec<Glyph>,
+}
+
+/// Glyph while it is being parsed
+#[derive(Default)]
+struct Partial {
+    encoding: Option<u32>,
+    advance: Option<i32>,
+    bounds: Option<[i32; 4]>,
+    rows: Vec<Vec<u8>>,
+}
+
+/// Parses the source of a BDF file
+pub fn parse(name: &str, source: &str) -> Result<Font, ParseError> {
+    let mut ascent = None;
+    let mut descent = None;
+    let mut default_char = None;
+    let mut glyphs = Vec::new();
+
+    let mut glyph: Option<Partial> = None;
+    let mut in_bitmap = false;
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message| ParseError {
+            line: index + 1,
+            message,
+        };
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let numbers: Vec<i32> = words.filter_map(|word| word.parse().ok()).collect();
+
+        if in_bitmap {
+            let partial = glyph.as_mut().ok_or(error("bitmap outside of a glyph"))?;
+            if keyword == "ENDCHAR" {
+                in_bitmap = false;
+                let partial = glyph.take().unwrap_or_default();
+                if let Some(converted) = finish(partial).map_err(error)? {
+                    glyphs.push(converted);
+                }
+            } else {
+                partial
+                    .rows
+                    .push(hex(keyword).ok_or(error("invalid bitmap row"))?);
+            }
+            continue;
+        }
+
+        match keyword {
+            "FONT_ASCENT" => ascent = numbers.first().copied(),
+            "FONT_DESCENT" => descent = numbers.first().copied(),
+            "DEFAULT_CHAR" => default_char = numbers.first().copied(),
+            "STARTCHAR" => glyph = Some(Partial::default()),
+            "ENCODING" | "DWIDTH" | "BBX" => {
+                let partial = glyph.as_mut().ok_or(error("property outside of a glyph"))?;
+                match (keyword, numbers.as_slice()) {
+                    ("ENCODING", [encoding, ..]) => {
+                        partial.encoding = u32::try_from(*encoding).ok()
+                    }
+                    ("DWIDTH", [advance, ..]) => partial.advance = Some(*advance),
+                    ("BBX", &[width, height, x, y]) => partial.bounds = Some([width, height, x, y]),
+                    _ => return Err(error("missing values")),
+                }
+            }
+            "BITMAP" => in_bitmap = true,
+            _ => {}
+        }
+    }
+
+    let line = source.lines().count();
+    let ascent = ascent.ok_or(ParseError {
+        line,
+        message: "FONT_ASCENT is missing",
+    })?;
+    let descent = descent.ok_or(ParseError {
+        line,
+        message: "FONT_DESCENT is missing",
+    })?;
+
+    glyphs.sort_by_key(|glyph| glyph.character);
+    glyphs.dedup_by_key(|glyph| glyph.character);
+
+    let has_glyph = |c: char| glyphs.iter().any(|glyph| glyph.character == c);
+    let replacement = default_char
+        .and_then(|code| char::from_u32(code as u32))
+        .filter(|&c| has_glyph(c))
+        .or(Some('?').filter(|&c| has_glyph(c)));
+
+    Ok(Font {
+        name: String::from(name),
+        ascent: to_u8(ascent).ok_or(ParseError {
+            line,
+            message: "FONT_ASCENT is out of range",
+        })?,
+        descent: to_u8(descent).ok_or(ParseError {
+            line,
+            message: "FONT_DESCENT is out of range",
+        })?,
+        replacement,
+        glyphs,
+    })
+}
+
+/// Crops a parsed glyph, `None` if it has no Unicode encoding
+fn finish(partial: Partial) -> Result<Option<Glyph>, &'static str> {
+    let Some(character) = partial.encoding.and_then(char::from_u32) else {
+        return Ok(None);
+    };
+    let advance = partial.advance.ok_or("DWIDTH is missing")?;
+    let [width, height, x_offset, y_offset] = partial.bounds.ok_or("BBX is missing")?;
+    if partial.rows.len() != height as usize {
+        return Err("number of bitmap rows does not match BBX");
+    }
+
+    let is_set = |x: i32, y: i32| {
+        let row = &partial.rows[y as usize];
+        row.get(x as usize / 8)
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    };
+
+    // Bounds of the set pixels
+    let mut ink: Option<[i32; 4]> = None;
+    for y in 0..height {
+        for x in 0..width {
+            if is_set(x, y) {
+                let [left, top, right, bottom] = ink.get_or_insert([x, y, x, y]);
+                *left = (*left).min(x);
+                *top = (*top).min(y);
+                *right = (*right).max(x);
+                *bottom = (*bottom).max(y);
+            }
+        }
+    }
+
+    let out_of_range = "glyph metrics are out of range";
+    let advance = to_u8(advance).ok_or(out_of_range)?;
+    let Some([left, top, right, bottom]) = ink else {
+        // Nothing to draw, like a space
+        return Ok(Some(Glyph {
+            character,
+            advance,
+            width: 0,
+            height: 0,
+            x_offset: 0,
+            y_offset: 0,
+            bitmap: Vec::new(),
+        }));
+    };
+
+    let (cropped_width, cropped_height) = (right - left + 1, bottom - top + 1);
+    let stride = (cropped_width as usize).div_ceil(8);
+
+    let mut bitmap = vec![0; stride * cropped_height as usize];
+    for y in 0..cropped_height {
+        for x in 0..cropped_width {
+            if is_set(left + x, top + y) {
+                bitmap[y as usize * stride + x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+
+    Ok(Some(Glyph {
+        character,
+        advance,
+        width: to_u8(cropped_width).ok_or(out_of_range)?,
+        height: to_u8(cropped_height).ok_or(out_of_range)?,
+        x_offset: i8::try_from(x_offset + left).map_err(|_| out_of_range)?,
+        // Rows below the ink move the bottom row up
+        y_offset: i8::try_from(y_offset + height - 1 - bottom).map_err(|_| out_of_range)?,
+        bitmap,
+    }))
+}
+
+fn hex(word: &str) -> Option<Vec<u8>> {
+    if !word.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..word.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(word.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_u8(value: i32) -> Option<u8> {
+    u8::try_from(value).ok()
+}
+
+impl Font {
+    /// Returns the Rust source of the `static`, `path` is the module of `Font` and `Glyph`
+    pub fn to_rust(&self, file_name: &str, path: &str) -> String {
+        let mut source = String::new();
+        let mut bitmap = Vec::new();
+
+        let _ = writeln!(
+            source,
+            "/// Generated from `{file_name}`, {} glyphs, {} pixels high",
+            self.glyphs.len(),
+            u32::from(self.ascent) + u32::from(self.descent)
+        );
+        let _ = writeln!(
+            source,
+            "pub static {}: {path}::Font<'static> = {path}::Font::new(\n    {},\n    {},\n    {},\n    &[",
+            self.name,
+            self.ascent,
+            self.descent,
+            match self.replacement {
+                Some(c) => format!("Some({c:?})"),
+                None => String::from("None"),
+            },
+        );
+        for glyph in &self.glyphs {
+            let _ = writeln!(
+                source,
+                "        {path}::Glyph::new({:?}, {}, {}, {}, {}, {}, {}),",
+                glyph.character,
+                glyph.advance,
+                glyph.width,
+                glyph.height,
+                glyph.x_offset,
+                glyph.y_offset,
+                bitmap.len(),
+            );
+            bitmap.extend_from_slice(&glyph.bitmap);
+        }
+        source.push_str("    ],\n");
+        source.push_str(&crate::array(bitmap.iter().map(|b| format!("{b:#04X}"))));
+        source.push_str(");\n");
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two glyphs of a 6x8 font, the `A` with an empty column and row around it
+    const BDF: &str = "STARTFONT 2.1
+FONT test
+SIZE 8 75 75
+FONTBOUNDINGBOX 6 8 0 -2
+STARTPROPERTIES 3
+DEFAULT_CHAR 63
+FONT_ASCENT 6
+FONT_DESCENT 2
+ENDPROPERTIES
+CHARS 3
+STARTCHAR question
+ENCODING 63
+DWIDTH 6 0
+BBX 6 8 0 -2
+BITMAP
+00
+70
+08
+30
+20
+00
+20
+00
+ENDCHAR
+STARTCHAR A
+ENCODING 65
+DWIDTH 6 0
+BBX 6 8 0 -2
+BITMAP
+00
+20
+50
+88
+F8
+88
+00
+00
+ENDCHAR
+STARTCHAR space
+ENCODING 32
+DWIDTH 6 0
+BBX 6 8 0 -2
+BITMAP
+00
+00
+00
+00
+00
+00
+00
+00
+ENDCHAR
+ENDFONT
+";
+
+    fn glyph(font: &Font, c: char) -> &Glyph {
+        font.glyphs
+            .iter()
+            .find(|glyph| glyph.character == c)
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_font_metrics() {
+        let font = parse("TEST", BDF).unwrap();
+        assert_eq!(font.name, "TEST");
+        assert_eq!(font.ascent, 6);
+        assert_eq!(font.descent, 2);
+        assert_eq!(font.replacement, Some('?'));
+    }
+
+    #[test]
+    fn sorts_glyphs() {
+        let font = parse("TEST", BDF).unwrap();
+        let characters: Vec<char> = font.glyphs.iter().map(|glyph| glyph.character).collect();
+        assert_eq!(characters, [' ', '?', 'A']);
+    }
+
+    #[test]
+    fn crops_glyphs_to_their_pixels() {
+        let font = parse("TEST", BDF).unwrap();
+        let a = glyph(&font, 'A');
+        assert_eq!((a.width, a.height), (5, 5));
+        assert_eq!(a.advance, 6);
+        // The box starts two rows below the baseline, the two empty rows are cropped
+        assert_eq!((a.x_offset, a.y_offset), (0, 0));
+        assert_eq!(a.bitmap, [0x20, 0x50, 0x88, 0xF8, 0x88]);
+
+        let question = glyph(&font, '?');
+        assert_eq!((question.width, question.height), (4, 6));
+        // The dot sits one row below the baseline
+        assert_eq!((question.x_offset, question.y_offset), (1, -1));
+        assert_eq!(question.bitmap, [0xE0, 0x10, 0x60, 0x40, 0x00, 0x40]);
+    }
+
+    #[test]
+    fn keeps_empty_glyphs() {
+        let font = parse("TEST", BDF).unwrap();
+        let space = glyph(&font, ' ');
+        assert_eq!((space.width, space.height, space.advance), (0, 0, 6));
+        assert!(space.bitmap.is_empty());
+    }
+
+    #[test]
+    fn keeps_rows_of_wide_glyphs_apart() {
+        let bdf = "FONT_ASCENT 2\nFONT_DESCENT 0\nSTARTCHAR wide\nENCODING 87\nDWIDTH 10 0\nBBX 10 2 0 0\nBITMAP\nFFC0\n8040\nENDCHAR\n";
+        let font = parse("WIDE", bdf).unwrap();
+        let wide = glyph(&font, 'W');
+        assert_eq!((wide.width, wide.height), (10, 2));
+        assert_eq!(wide.bitmap, [0xFF, 0xC0, 0x80, 0x40]);
+    }
+
+    #[test]
+    fn skips_glyphs_without_encoding() {
+        let bdf = BDF.replace("ENCODING 65", "ENCODING -1");
+        let font = parse("TEST", &bdf).unwrap();
+        assert_eq!(font.glyphs.len(), 2);
+    }
+
+    #[test]
+    fn falls_back_to_question_mark() {
+        let bdf = BDF.replace("DEFAULT_CHAR 63", "DEFAULT_CHAR 0");
+        assert_eq!(parse("TEST", &bdf).unwrap().replacement, Some('?'));
+
+        let bdf = bdf.replace("ENCODING 63", "ENCODING 66");
+        assert_eq!(parse("TEST", &bdf).unwrap().replacement, None);
+    }
+
+    #[test]
+    fn reports_errors_with_line() {
+        let bdf = BDF.replace("FONT_ASCENT 6\n", "");
+        assert_eq!(
+            parse("TEST", &bdf).unwrap_err().message,
+            "FONT_ASCENT is missing"
+        );
+
+        let bdf = BDF.replace(
+            "BBX 6 8 0 -2\nBITMAP\n00\n20",
+            "BBX 6 9 0 -2\nBITMAP\n00\n20",
+        );
+        let error = parse("TEST", &bdf).unwrap_err();
+        assert_eq!(error.message, "number of bitmap rows does not match BBX");
+        assert_eq!(error.line, 38);
+
+        let bdf = BDF.replace("\n88\nF8", "\nXY\nF8");
+        let error = parse("TEST", &bdf).unwrap_err();
+        assert_eq!(error.to_string(), "line 33: invalid bitmap row");
+
+        let error = parse("TEST", "ENCODING 65\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: property outside of a glyph");
+    }
+
+    #[test]
+    fn generates_rust_source() {
+        let font = parse("TEST", BDF).unwrap();
+        let source = font.to_rust("test.bdf", "crate::text");
+        assert!(source.starts_with("/// Generated from `test.bdf`, 3 glyphs, 8 pixels high\n"));
+        assert!(source
+            .contains("pub static TEST: crate::text::Font<'static> = crate::text::Font::new("));
+        assert!(source.contains("    Some('?'),\n"));
+        // Bitmaps follow each other in the order of the glyphs
+        assert!(source.contains("crate::text::Glyph::new(' ', 6, 0, 0, 0, 0, 0),"));
+        assert!(source.contains("crate::text::Glyph::new('?', 6, 4, 6, 1, -1, 0),"));
+        assert!(source.contains("crate::text::Glyph::new('A', 6, 5, 5, 0, 0, 6),"));
+    }
+}
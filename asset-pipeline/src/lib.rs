@@ -0,0 +1,624 @@
+//! Converts PNG assets into images for `picoboy::sprite` and BDF fonts for
+//! `picoboy::text`.
+//!
+//! Meant to run in a build script: [`generate`] turns every PNG file of a
+//! directory into a `const` [`Image`](../picoboy/sprite/struct.Image.html),
+//! named after the file, so `player_ship.png` becomes `PLAYER_SHIP`.
+//! [`generate_fonts`] does the same for BDF files, see [`font`].
+//!
+//! Colors are reduced to RGB565. Images with few colors are stored as palette
+//! indices with 1, 2, 4 or 8 bits per pixel, whichever is smaller, all others
+//! as raw RGB565. Pixels with less than half opacity become transparent and
+//! are replaced by [`TRANSPARENT_KEY`].
+
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use png::{ColorType, Decoder, Transformations};
+
+pub mod font;
+
+/// Raw RGB565 color marking transparent pixels, magenta
+pub const TRANSPARENT_KEY: u16 = 0xF81F;
+
+/// Opaque pixels of the key color are shifted to this one
+const KEY_REPLACEMENT: u16 = 0xF81E;
+
+/// Largest palette of an indexed image
+const MAX_PALETTE_LEN: usize = 256;
+
+/// Number of values per line of the generated arrays
+const VALUES_PER_LINE: usize = 16;
+
+/// Errors while generating the assets
+#[derive(Debug)]
+pub enum Error {
+    /// The directory or a file could not be read
+    Io(PathBuf, io::Error),
+    /// A file is not a valid PNG image
+    Png(PathBuf, png::DecodingError),
+    /// A file is not a valid BDF font
+    Bdf(PathBuf, font::ParseError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, error) => write!(f, "{}: {error}", path.display()),
+            Error::Png(path, error) => write!(f, "{}: {error}", path.display()),
+            Error::Bdf(path, error) => write!(f, "{}: {error}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Pixel storage of a converted image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Storage {
+    /// One raw RGB565 value per pixel
+    Rgb565(Vec<u16>),
+    /// Packed palette indices, most significant bits first, each row starting at a new byte
+    Indexed {
+        bits: u8,
+        palette: Vec<u16>,
+        data: Vec<u8>,
+    },
+}
+
+/// A converted image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Asset {
+    /// Name of the generated constant
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Raw color treated as transparent, `None` if the image is opaque
+    pub transparent: Option<u16>,
+    pub storage: Storage,
+}
+
+/// Converts an 8 bit per channel color to raw RGB565, rounding to the nearest value
+pub fn rgb565(r: u8, g: u8, b: u8) -> u16 {
+    let scale = |value: u8, max: u32| (u32::from(value) * max + 127) / 255;
+    (scale(r, 31) << 11 | scale(g, 63) << 5 | scale(b, 31)) as u16
+}
+
+/// Converts a file name into the name of its constant
+pub fn const_name(file_stem: &str) -> String {
+    let mut name: String = file_stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert(0, '_');
+    }
+
+    name
+}
+
+/// Converts the content of a PNG file
+pub fn convert(name: &str, png: &[u8]) -> Result<Asset, png::DecodingError> {
+    let mut decoder = Decoder::new(png);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let (color_type, _) = reader.output_color_type();
+    let bytes = &buffer[..info.buffer_size()];
+
+    // Normalize everything to RGBA
+    let rgba: Vec<[u8; 4]> = match color_type {
+        ColorType::Grayscale => bytes.iter().map(|&l| [l, l, l, u8::MAX]).collect(),
+        ColorType::GrayscaleAlpha => bytes
+            .chunks_exact(2)
+            .map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        ColorType::Rgb => bytes
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2], u8::MAX])
+            .collect(),
+        ColorType::Rgba => bytes
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect(),
+        // Expanded by the transformations
+        ColorType::Indexed => unreachable!(),
+    };
+
+    let pixels: Vec<Option<u16>> = rgba
+        .iter()
+        .map(|&[r, g, b, a]| (a >= 128).then(|| rgb565(r, g, b)))
+        .collect();
+
+    Ok(Asset::from_pixels(name, info.width, info.height, &pixels))
+}
+
+impl Asset {
+    /// Creates an asset from raw RGB565 pixels, `None` for transparent ones
+    pub fn from_pixels(name: &str, width: u32, height: u32, pixels: &[Option<u16>]) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+
+        let transparent = pixels
+            .iter()
+            .any(Option::is_none)
+            .then_some(TRANSPARENT_KEY);
+        let raw: Vec<u16> = pixels
+            .iter()
+            .map(|pixel| match (pixel, transparent) {
+                (None, _) => TRANSPARENT_KEY,
+                (Some(TRANSPARENT_KEY), Some(_)) => KEY_REPLACEMENT,
+                (&Some(color), _) => color,
+            })
+            .collect();
+
+        // The key comes first, then the colors in order of appearance
+        let mut palette: Vec<u16> = transparent.into_iter().collect();
+        for &color in &raw {
+            if !palette.contains(&color) {
+                palette.push(color);
+                if palette.len() > MAX_PALETTE_LEN {
+                    break;
+                }
+            }
+        }
+
+        let storage = match indexed(width, height, &raw, palette) {
+            Some(indexed) if storage_size(&indexed) < raw.len() * 2 => indexed,
+            _ => Storage::Rgb565(raw),
+        };
+
+        Self {
+            name: name.to_owned(),
+            width,
+            height,
+            transparent,
+            storage,
+        }
+    }
+
+    /// Returns all pixels row by row, `None` for transparent ones
+    pub fn decode(&self) -> Vec<Option<u16>> {
+        let raw: Vec<u16> = match &self.storage {
+            Storage::Rgb565(pixels) => pixels.clone(),
+            Storage::Indexed {
+                bits,
+                palette,
+                data,
+            } => {
+                let stride = stride(self.width, *bits);
+                (0..self.height)
+                    .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let bit = x as usize * usize::from(*bits);
+                        let byte = data[y as usize * stride + bit / 8];
+                        let shift = 8 - usize::from(*bits) - bit % 8;
+                        let index = (byte >> shift) & (u8::MAX >> (8 - bits));
+                        palette[usize::from(index)]
+                    })
+                    .collect()
+            }
+        };
+
+        raw.into_iter()
+            .map(|color| Some(color).filter(|&color| Some(color) != self.transparent))
+            .collect()
+    }
+
+    /// Returns the Rust source of the constant
+    pub fn to_rust(&self, file_name: &str) -> String {
+        let mut source = String::new();
+        let transparent = match self.transparent {
+            Some(key) => format!("Some({key:#06X})"),
+            None => String::from("None"),
+        };
+
+        match &self.storage {
+            Storage::Rgb565(pixels) => {
+                let _ = writeln!(
+                    source,
+                    "/// Generated from `{file_name}`, {}x{} pixels, RGB565",
+                    self.width, self.height
+                );
+                let _ = writeln!(
+                    source,
+                    "pub const {}: picoboy::sprite::Image<'static> = picoboy::sprite::Image::rgb565(\n    {},\n    {},\n{}    {transparent},\n);",
+                    self.name,
+                    self.width,
+                    self.height,
+                    array(pixels.iter().map(|p| format!("{p:#06X}"))),
+                );
+            }
+            Storage::Indexed {
+                bits,
+                palette,
+                data,
+            } => {
+                let _ = writeln!(
+                    source,
+                    "/// Generated from `{file_name}`, {}x{} pixels, {bits} bits per pixel",
+                    self.width, self.height
+                );
+                let _ = writeln!(
+                    source,
+                    "pub const {}: picoboy::sprite::Image<'static> = picoboy::sprite::Image::indexed(\n    {},\n    {},\n    {bits},\n{}{}    {transparent},\n);",
+                    self.name,
+                    self.width,
+                    self.height,
+                    array(palette.iter().map(|p| format!("{p:#06X}"))),
+                    array(data.iter().map(|b| format!("{b:#04X}"))),
+                );
+            }
+        }
+
+        source
+    }
+}
+
+/// Packs the pixels as palette indices, `None` if there are too many colors
+fn indexed(width: u32, height: u32, raw: &[u16], palette: Vec<u16>) -> Option<Storage> {
+    if palette.len() > MAX_PALETTE_LEN {
+        return None;
+    }
+
+    let bits = [1u8, 2, 4, 8]
+        .into_iter()
+        .find(|&bits| palette.len() <= 1 << bits)?;
+
+    let stride = stride(width, bits);
+    let mut data = vec![0; stride * height as usize];
+    for (i, color) in raw.iter().enumerate() {
+        let (x, y) = (i % width as usize, i / width as usize);
+        let index = palette.iter().position(|c| c == color)? as u8;
+        let bit = x * usize::from(bits);
+        data[y * stride + bit / 8] |= index << (8 - usize::from(bits) - bit % 8);
+    }
+
+    Some(Storage::Indexed {
+        bits,
+        palette,
+        data,
+    })
+}
+
+fn storage_size(storage: &Storage) -> usize {
+    match storage {
+        Storage::Rgb565(pixels) => pixels.len() * 2,
+        Storage::Indexed { palette, data, .. } => palette.len() * 2 + data.len(),
+    }
+}
+
+/// Number of bytes per row of an indexed image
+fn stride(width: u32, bits: u8) -> usize {
+    (width as usize * usize::from(bits)).div_ceil(8)
+}
+
+/// Formats values as the body of an array reference, indented by one level
+fn array(values: impl Iterator<Item = String>) -> String {
+    let values: Vec<String> = values.collect();
+    let mut source = String::from("    &[\n");
+    for line in values.chunks(VALUES_PER_LINE) {
+        let _ = writeln!(source, "        {},", line.join(", "));
+    }
+    source.push_str("    ],\n");
+    source
+}
+
+/// Converts all PNG files of a directory and returns the Rust source of their constants
+pub fn generate(dir: &Path) -> Result<String, Error> {
+    let mut source = String::from("// Generated by the asset pipeline, do not edit\n");
+    for path in files(dir, "png")? {
+        let png = fs::read(&path).map_err(|error| Error::Io(path.clone(), error))?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let asset =
+            convert(&const_name(&stem), &png).map_err(|error| Error::Png(path.clone(), error))?;
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        source.push('\n');
+        source.push_str(&asset.to_rust(&file_name));
+    }
+
+    Ok(source)
+}
+
+/// Converts all BDF files of a directory and returns the Rust source of their statics.
+///
+/// `path` is the module providing `Font` and `Glyph`, usually `picoboy::text`.
+pub fn generate_fonts(dir: &Path, path: &str) -> Result<String, Error> {
+    let mut source = String::from("// Generated by the asset pipeline, do not edit\n");
+    for file in files(dir, "bdf")? {
+        // BDF files are ASCII apart from comments, which may use Latin-1
+        let bytes = fs::read(&file).map_err(|error| Error::Io(file.clone(), error))?;
+        let bdf: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let font = font::parse(&const_name(&stem), &bdf)
+            .map_err(|error| Error::Bdf(file.clone(), error))?;
+
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        source.push('\n');
+        source.push_str(&font.to_rust(&file_name, path));
+    }
+
+    Ok(source)
+}
+
+/// Returns the files of a directory with the given extension, sorted by name
+fn files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(dir).map_err(|error| Error::Io(dir.to_owned(), error))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|error| Error::Io(dir.to_owned(), error))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        {
+            paths.push(path);
+        }
+    }
+    // Keep the output stable regardless of the directory order
+    paths.sort();
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use png::{BitDepth, Encoder};
+
+    /// Encodes pixels with the given PNG color type
+    fn encode(width: u32, height: u32, color: ColorType, depth: BitDepth, data: &[u8]) -> Vec<u8> {
+        encode_with_palette(width, height, color, depth, data, None)
+    }
+
+    fn encode_with_palette(
+        width: u32,
+        height: u32,
+        color: ColorType,
+        depth: BitDepth,
+        data: &[u8],
+        palette: Option<(&[u8], &[u8])>,
+    ) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if let Some((palette, alpha)) = palette {
+            encoder.set_palette(palette);
+            encoder.set_trns(alpha);
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        png
+    }
+
+    /// RGBA test image with a gradient, so it has too many colors for a palette
+    fn gradient(width: u32, height: u32) -> Vec<[u8; 4]> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 8) as u8, (y * 8) as u8, (x * y) as u8, u8::MAX]
+            })
+            .collect()
+    }
+
+    fn expected(rgba: &[[u8; 4]]) -> Vec<Option<u16>> {
+        rgba.iter()
+            .map(|&[r, g, b, a]| (a >= 128).then(|| rgb565(r, g, b)))
+            .collect()
+    }
+
+    #[test]
+    fn rounds_to_rgb565() {
+        assert_eq!(rgb565(0, 0, 0), 0x0000);
+        assert_eq!(rgb565(255, 255, 255), 0xFFFF);
+        assert_eq!(rgb565(255, 0, 0), 0xF800);
+        assert_eq!(rgb565(0, 255, 0), 0x07E0);
+        assert_eq!(rgb565(0, 0, 255), 0x001F);
+        // 4 of 255 is closer to 0 of 31, 5 closer to 1
+        assert_eq!(rgb565(4, 0, 0), 0x0000);
+        assert_eq!(rgb565(5, 0, 0), 0x0800);
+    }
+
+    #[test]
+    fn names_constants() {
+        assert_eq!(const_name("player_ship"), "PLAYER_SHIP");
+        assert_eq!(const_name("tiles-16x16"), "TILES_16X16");
+        assert_eq!(const_name("8ball"), "_8BALL");
+    }
+
+    #[test]
+    fn keeps_many_colors_as_rgb565() {
+        let rgba = gradient(32, 32);
+        let data: Vec<u8> = rgba.iter().flatten().copied().collect();
+        let png = encode(32, 32, ColorType::Rgba, BitDepth::Eight, &data);
+
+        let asset = convert("GRADIENT", &png).unwrap();
+        assert!(matches!(asset.storage, Storage::Rgb565(_)));
+        assert_eq!(asset.transparent, None);
+        assert_eq!(asset.decode(), expected(&rgba));
+    }
+
+    #[test]
+    fn decodes_rgb() {
+        let rgba = gradient(20, 10);
+        let data: Vec<u8> = rgba.iter().flat_map(|p| [p[0], p[1], p[2]]).collect();
+        let png = encode(20, 10, ColorType::Rgb, BitDepth::Eight, &data);
+
+        assert_eq!(convert("RGB", &png).unwrap().decode(), expected(&rgba));
+    }
+
+    #[test]
+    fn decodes_sixteen_bit_channels() {
+        let rgba = gradient(8, 8);
+        // The high byte of each channel, big endian
+        let data: Vec<u8> = rgba
+            .iter()
+            .flat_map(|p| [p[0], 0x7f, p[1], 0x7f, p[2], 0x7f])
+            .collect();
+        let png = encode(8, 8, ColorType::Rgb, BitDepth::Sixteen, &data);
+
+        assert_eq!(convert("DEEP", &png).unwrap().decode(), expected(&rgba));
+    }
+
+    #[test]
+    fn decodes_grayscale() {
+        let levels: Vec<u8> = (0..16).map(|i| i * 17).collect();
+        let png = encode(4, 4, ColorType::Grayscale, BitDepth::Eight, &levels);
+
+        let asset = convert("GRAY", &png).unwrap();
+        let rgba: Vec<[u8; 4]> = levels.iter().map(|&l| [l, l, l, u8::MAX]).collect();
+        assert_eq!(asset.decode(), expected(&rgba));
+    }
+
+    #[test]
+    fn decodes_grayscale_alpha() {
+        let data = [0, 255, 100, 127, 200, 128, 255, 0];
+        let png = encode(2, 2, ColorType::GrayscaleAlpha, BitDepth::Eight, &data);
+
+        let asset = convert("GRAY_ALPHA", &png).unwrap();
+        assert_eq!(asset.transparent, Some(TRANSPARENT_KEY));
+        assert_eq!(
+            asset.decode(),
+            [
+                Some(rgb565(0, 0, 0)),
+                None,
+                Some(rgb565(200, 200, 200)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn packs_few_colors_into_palette() {
+        // Four colors, one of them transparent, in 16x4 pixels
+        let colors = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [0, 0, 0, 0],
+        ];
+        let rgba: Vec<[u8; 4]> = (0..64).map(|i| colors[i % 7 % 4]).collect();
+        let data: Vec<u8> = rgba.iter().flatten().copied().collect();
+        let png = encode(16, 4, ColorType::Rgba, BitDepth::Eight, &data);
+
+        let asset = convert("FOUR", &png).unwrap();
+        match &asset.storage {
+            Storage::Indexed {
+                bits,
+                palette,
+                data,
+            } => {
+                assert_eq!(*bits, 2);
+                assert_eq!(palette[0], TRANSPARENT_KEY);
+                assert_eq!(palette.len(), 4);
+                assert_eq!(data.len(), 4 * 4);
+            }
+            storage => panic!("expected a palette, got {storage:?}"),
+        }
+        assert_eq!(asset.decode(), expected(&rgba));
+    }
+
+    #[test]
+    fn chooses_the_smallest_index_size() {
+        for (colors, bits) in [(2, 1), (3, 2), (4, 2), (5, 4), (16, 4), (17, 8), (200, 8)] {
+            let pixels: Vec<Option<u16>> = (0..1600).map(|i| Some((i % colors) as u16)).collect();
+            let asset = Asset::from_pixels("COLORS", 40, 40, &pixels);
+            match &asset.storage {
+                Storage::Indexed { bits: actual, .. } => {
+                    assert_eq!(*actual, bits, "{colors} colors")
+                }
+                storage => panic!("expected a palette for {colors} colors, got {storage:?}"),
+            }
+            assert_eq!(asset.decode(), pixels);
+        }
+    }
+
+    #[test]
+    fn starts_rows_at_a_new_byte() {
+        // 3 pixels of 1 bit per row, padded to a byte
+        let pixels = [Some(1), Some(2), Some(1), Some(2), Some(2), Some(2)];
+        let asset = Asset::from_pixels("ODD", 3, 2, &pixels);
+        assert_eq!(
+            asset.storage,
+            Storage::Indexed {
+                bits: 1,
+                palette: vec![1, 2],
+                data: vec![0b0100_0000, 0b1110_0000],
+            }
+        );
+        assert_eq!(asset.decode(), pixels);
+    }
+
+    #[test]
+    fn shifts_opaque_key_color() {
+        let pixels = [Some(TRANSPARENT_KEY), None];
+        let asset = Asset::from_pixels("KEY", 2, 1, &pixels);
+        assert_eq!(asset.decode(), [Some(KEY_REPLACEMENT), None]);
+
+        // Without transparency the key color stays as it is
+        let asset = Asset::from_pixels("OPAQUE", 1, 1, &[Some(TRANSPARENT_KEY)]);
+        assert_eq!(asset.decode(), [Some(TRANSPARENT_KEY)]);
+    }
+
+    #[test]
+    fn decodes_indexed_png() {
+        let palette = [255, 255, 255, 10, 20, 30, 0, 0, 0];
+        let alpha = [255, 255, 0];
+        // 4 bits per pixel, two pixels per byte
+        let data = [0x01, 0x20, 0x12, 0x00];
+        let png = encode_with_palette(
+            4,
+            2,
+            ColorType::Indexed,
+            BitDepth::Four,
+            &data,
+            Some((&palette, &alpha)),
+        );
+
+        let asset = convert("INDEXED", &png).unwrap();
+        let white = Some(rgb565(255, 255, 255));
+        let dark = Some(rgb565(10, 20, 30));
+        assert_eq!(
+            asset.decode(),
+            [white, dark, None, white, dark, None, white, white]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_png() {
+        assert!(convert("BROKEN", b"not a png").is_err());
+    }
+
+    #[test]
+    fn generates_rust_source() {
+        let asset = Asset::from_pixels("DOT", 1, 1, &[Some(0x1234)]);
+        let source = asset.to_rust("dot.png");
+        assert!(source.starts_with("/// Generated from `dot.png`, 1x1 pixels, RGB565\n"));
+        assert!(source.contains("pub const DOT: picoboy::sprite::Image<'static>"));
+        assert!(source.contains("0x1234"));
+        assert!(source.contains("    None,\n"));
+
+        let asset = Asset::from_pixels("PAIR", 2, 8, &[Some(1), None].repeat(8));
+        let source = asset.to_rust("pair.png");
+        assert!(source.contains("1 bits per pixel"));
+        assert!(source.contains("Image::indexed("));
+        assert!(source.contains("Some(0xF81F)"));
+    }
+}
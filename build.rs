@@ -1,31 +1,99 @@
-//! This build script copies the `memory.x` file from the crate root into
-//! a directory where the linker can always find it at build time.
-//! For many projects this is optional, as the linker always searches the
-//! project root directory -- wherever `Cargo.toml` is. However, if you
-//! are using a workspace or have a more complicated build setup, this
-//! build script becomes required. Additionally, by requesting that
-//! Cargo re-run the build script whenever `memory.x` is changed,
-//! updating `memory.x` ensures a rebuild of the application with the
-//! new memory settings.
+//! This build script writes the `memory.x` linker script into a directory
+//! where the linker can always find it at build time.
+//!
+//! Usually it is a copy of `memory/standalone.x`, for firmware that owns the
+//! whole flash. With `PICOBOY_SLOT` set to the number of a flash slot, the
+//! firmware is linked to be started by the launcher from that slot instead,
+//! see `picoboy::slots`. The linker looks into the project root before the
+//! output directory, which is why no `memory.x` may be kept there.
 
 use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 
+use flash_layout::{
+    slot_address, FLASH_BASE, LAUNCHER_LEN, SAVE_BASE, SAVE_LEN, SLOT_COUNT, SLOT_LEN,
+    VECTOR_TABLE_OFFSET,
+};
+
 fn main() {
+    let memory = match env::var("PICOBOY_SLOT") {
+        Ok(slot) => match slot.parse::<usize>() {
+            Ok(slot) if slot < SLOT_COUNT => slot_memory(slot),
+            _ => panic!("PICOBOY_SLOT must be a slot number below {SLOT_COUNT}, not {slot:?}"),
+        },
+        Err(_) => include_str!("memory/standalone.x").to_string(),
+    };
+
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(memory.as_bytes())
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
+    // The launcher has to stay in front of the slots
+    File::create(out.join("launcher.x"))
+        .unwrap()
+        .write_all(launcher_checks().as_bytes())
+        .unwrap();
+    println!("cargo:rustc-link-arg-bin=launcher=-Tlauncher.x");
+
     // By default, Cargo will re-run a build script whenever
-    // any file in the project changes. By specifying `memory.x`
+    // any file in the project changes. By specifying the inputs
     // here, we ensure the build script is only re-run when
-    // `memory.x` is changed.
-    println!("cargo:rerun-if-changed=memory.x");
+    // one of them is changed.
+    println!("cargo:rerun-if-changed=memory/standalone.x");
+    println!("cargo:rerun-if-env-changed=PICOBOY_SLOT");
+}
+
+/// Memory layout of an app in `slot`, with its header in front of the vector table
+fn slot_memory(slot: usize) -> String {
+    let start = slot_address(slot);
+
+    format!(
+        "\
+/* Generated by build.rs for slot {slot} */
+MEMORY {{
+    HEADER : ORIGIN = {start:#010x}, LENGTH = {VECTOR_TABLE_OFFSET:#x}
+    FLASH  : ORIGIN = {flash:#010x}, LENGTH = {flash_len:#x}
+    SAVE   : ORIGIN = {SAVE_BASE:#010x}, LENGTH = {SAVE_LEN:#x}
+    RAM    : ORIGIN = 0x20000000, LENGTH = 256K
+}}
+
+__save_start = ORIGIN(SAVE);
+__save_end = ORIGIN(SAVE) + LENGTH(SAVE);
+
+SECTIONS {{
+    /* ### App header, read by the launcher */
+    .app_header ORIGIN(HEADER) :
+    {{
+        KEEP(*(.app_header));
+    }} > HEADER
+
+    /* The launcher brings its own boot loader */
+    /DISCARD/ :
+    {{
+        *(.boot2);
+    }}
+}} INSERT BEFORE .text;
+",
+        flash = start + VECTOR_TABLE_OFFSET,
+        flash_len = SLOT_LEN - VECTOR_TABLE_OFFSET,
+    )
+}
+
+/// Linker checks for the launcher, which must be linked without a slot and fit its space
+fn launcher_checks() -> String {
+    format!(
+        "\
+ASSERT(ORIGIN(FLASH) == {flash:#010x}, \"build the launcher without PICOBOY_SLOT\");
+ASSERT(LOADADDR(.data) + SIZEOF(.data) <= {end:#010x}, \"the launcher is larger than its space in front of the slots\");
+",
+        flash = FLASH_BASE + VECTOR_TABLE_OFFSET,
+        end = FLASH_BASE + LAUNCHER_LEN,
+    )
 }
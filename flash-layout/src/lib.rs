@@ -0,0 +1,42 @@
+//! Flash layout of the launcher, the app slots and the save data.
+//!
+//! Shared by the firmware, which reads the slots through `picoboy::slots`,
+//! and the build script, which links apps for a slot. Everything here is
+//! `const`, so it works in `no_std` code and at build time alike.
+#![no_std]
+
+/// Start of the memory mapped flash
+pub const FLASH_BASE: u32 = 0x1000_0000;
+
+/// Size of the flash
+pub const FLASH_LEN: u32 = 2048 * 1024;
+
+/// Size of the `SAVE` region at the end of the flash
+pub const SAVE_LEN: u32 = 64 * 1024;
+
+/// Space reserved for the launcher at the start of the flash
+pub const LAUNCHER_LEN: u32 = 256 * 1024;
+
+/// Number of slots for apps
+pub const SLOT_COUNT: usize = 3;
+
+/// Size of each slot, together they fill the flash between launcher and save data
+pub const SLOT_LEN: u32 = (FLASH_LEN - LAUNCHER_LEN - SAVE_LEN) / SLOT_COUNT as u32;
+
+/// Start of the `SAVE` region, right behind the last slot
+pub const SAVE_BASE: u32 = FLASH_BASE + LAUNCHER_LEN + SLOT_COUNT as u32 * SLOT_LEN;
+
+/// Offset of the vector table from the start of a slot, the header comes before it.
+///
+/// The vector table has to be aligned to 256 bytes on the Cortex-M0+.
+pub const VECTOR_TABLE_OFFSET: u32 = 0x100;
+
+/// Returns the address of a slot in the memory mapped flash
+pub const fn slot_address(slot: usize) -> u32 {
+    FLASH_BASE + LAUNCHER_LEN + slot as u32 * SLOT_LEN
+}
+
+/// Returns the address of the vector table of the app in a slot
+pub const fn vector_table_address(slot: usize) -> u32 {
+    slot_address(slot) + VECTOR_TABLE_OFFSET
+}
@@ -0,0 +1,17 @@
+//! Converts the PNG files in `assets/` into images, see the `asset-pipeline`
+//! crate. The generated constants are included by `src/assets.rs`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let source =
+        asset_pipeline::generate(Path::new("assets")).unwrap_or_else(|error| panic!("{error}"));
+    fs::write(out.join("assets.rs"), source).unwrap();
+
+    // Re-run whenever a file in the directory is added, removed or changed
+    println!("cargo:rerun-if-changed=assets");
+}
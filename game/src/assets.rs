@@ -0,0 +1,3 @@
+//! Images generated from the PNG files in `assets/` by the build script.
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
@@ -0,0 +1,142 @@
+//! Game logic shared by the firmware and the simulator.
+//!
+//! The game only talks to the hardware through [`Display`] and [`Input`],
+//! so it runs unchanged on the Picoboy Color and on the host. It starts on
+//! the [`Title`] screen, continues with the ball in [`Play`] and can be
+//! paused with [`Pause`]. Each scene has its own music, and changing scenes
+//! plays a short sound effect.
+#![no_std]
+
+use core::mem::discriminant;
+use core::ptr;
+
+use embedded_graphics::geometry::Size;
+
+use picoboy::audio::{Mixer, Song};
+use picoboy::display::Display;
+use picoboy::input::Input;
+use picoboy::scene::{Scene, SceneManager, Transition};
+
+pub mod assets;
+mod pause;
+mod play;
+pub mod sounds;
+mod title;
+
+pub use pause::Pause;
+pub use play::Play;
+pub use title::Title;
+
+/// Time between two updates in milliseconds
+pub const UPDATE_INTERVAL_MS: u32 = 50;
+
+/// Maximum number of scenes stacked on top of each other
+const SCENE_STACK_DEPTH: usize = 4;
+
+/// All scenes of the game
+pub enum Scenes {
+    Title(Title),
+    Play(Play),
+    Pause(Pause),
+}
+
+impl Scene for Scenes {
+    fn update(&mut self, input: &Input) -> Transition<Self> {
+        match self {
+            Scenes::Title(title) => title.update(input),
+            Scenes::Play(play) => play.update(input),
+            Scenes::Pause(pause) => pause.update(input),
+        }
+    }
+
+    fn draw<D: Display>(&mut self, display: &mut D) -> Result<(), D::Error> {
+        match self {
+            Scenes::Title(title) => title.draw(display),
+            Scenes::Play(play) => play.draw(display),
+            Scenes::Pause(pause) => pause.draw(display),
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        matches!(self, Scenes::Pause(_))
+    }
+}
+
+impl Scenes {
+    /// Returns the music played while the scene is on top
+    fn music(&self) -> Option<&'static Song<'static>> {
+        match self {
+            Scenes::Title(_) => Some(&sounds::TITLE_THEME),
+            Scenes::Play(_) => Some(&sounds::GAME_THEME),
+            Scenes::Pause(_) => None,
+        }
+    }
+}
+
+/// The whole game, a stack of scenes
+pub struct Game {
+    scenes: SceneManager<Scenes, SCENE_STACK_DEPTH>,
+    audio: Mixer<'static>,
+    music: Option<&'static Song<'static>>,
+}
+
+impl Game {
+    /// Creates a new game showing the title screen.
+    ///
+    /// `screen` is the size of the picture the game draws, e.g. the size of
+    /// its [`FrameBuffer`](picoboy::framebuffer::FrameBuffer).
+    pub fn new(screen: Size) -> Self {
+        let mut game = Self {
+            scenes: SceneManager::new(Scenes::Title(Title::new(screen))),
+            audio: Mixer::new(),
+            music: None,
+        };
+        game.update_music();
+        game
+    }
+
+    /// Advances the game by `dt_ms`, normally [`UPDATE_INTERVAL_MS`]
+    pub fn update(&mut self, input: &Input, dt_ms: u32) {
+        let depth = self.scenes.depth();
+        let scene = discriminant(self.scenes.current());
+
+        self.scenes.update(input, dt_ms);
+
+        if self.scenes.depth() < depth {
+            self.audio.play_effect(&sounds::LEAVE);
+        } else if self.scenes.depth() > depth || discriminant(self.scenes.current()) != scene {
+            self.audio.play_effect(&sounds::ENTER);
+        }
+        self.update_music();
+    }
+
+    /// Renders the next audio samples at [`SAMPLE_RATE`](picoboy::audio::SAMPLE_RATE)
+    pub fn render_audio(&mut self, samples: &mut [u8]) {
+        self.audio.render(samples);
+    }
+
+    fn update_music(&mut self) {
+        let music = self.scenes.current().music();
+        let unchanged = match (music, self.music) {
+            (Some(music), Some(playing)) => ptr::eq(music, playing),
+            (None, None) => true,
+            _ => false,
+        };
+
+        if !unchanged {
+            match music {
+                Some(song) => self.audio.play_music(song),
+                None => self.audio.stop_music(),
+            }
+            self.music = music;
+        }
+    }
+
+    /// Draws the whole scene.
+    ///
+    /// Meant to draw into a [`FrameBuffer`](picoboy::framebuffer::FrameBuffer),
+    /// which only sends the pixels that changed to the display.
+    pub fn draw<D: Display>(&mut self, display: &mut D) -> Result<(), D::Error> {
+        self.scenes.draw(display)
+    }
+}
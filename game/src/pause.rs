@@ -0,0 +1,79 @@
+//! Pause overlay on top of the gameplay.
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Alignment,
+};
+
+use picoboy::display::Display;
+use picoboy::input::{Button, Input};
+use picoboy::scene::Transition;
+use picoboy::text::{fonts, FontStyle, TextBox};
+
+use crate::Scenes;
+
+/// Size of the pause box in pixels
+const BOX_SIZE: Size = Size::new(120, 100);
+
+/// Size of the pause symbol in pixels
+const SYMBOL_SIZE: u32 = 40;
+
+/// Pause symbol drawn over the frozen game, B resumes
+pub struct Pause {
+    _private: (),
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+
+    pub(crate) fn update(&mut self, input: &Input) -> Transition<Scenes> {
+        if input.pressed(Button::B) {
+            Transition::pop()
+        } else {
+            Transition::none()
+        }
+    }
+
+    pub(crate) fn draw<D: Display>(&self, display: &mut D) -> Result<(), D::Error> {
+        let size = display.bounding_box().size;
+        let center = Point::new(size.width as i32 / 2, size.height as i32 / 2);
+
+        Rectangle::with_center(center, BOX_SIZE)
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(Rgb565::new(4, 8, 4))
+                    .stroke_color(Rgb565::WHITE)
+                    .stroke_width(2)
+                    .build(),
+            )
+            .draw(display)?;
+
+        // Two bars of the pause symbol
+        let symbol = center - Point::new(0, 12);
+        let bar = Size::new(SYMBOL_SIZE / 3, SYMBOL_SIZE);
+        for offset in [-12, 12] {
+            Rectangle::with_center(symbol + Point::new(offset, 0), bar)
+                .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+                .draw(display)?;
+        }
+
+        TextBox::new(
+            "^1B^0 resumes",
+            Rectangle::new(center + Point::new(-50, 20), Size::new(100, 20)),
+            FontStyle::new(&fonts::NORMAL, Rgb565::WHITE),
+        )
+        .with_alignment(Alignment::Center)
+        .with_palette(&[Rgb565::WHITE, Rgb565::YELLOW])
+        .draw(display)
+    }
+}
+
+impl Default for Pause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,181 @@
+//! The ball controlled by the joystick, in front of a tiled room.
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use picoboy::display::{Display, DISPLAY_HEIGHT};
+use picoboy::input::{Button, Input};
+use picoboy::physics::{Aabb, Body, Fixed, Vec2};
+use picoboy::scene::Transition;
+use picoboy::sprite::{Flip, Sprite};
+use picoboy::text::{fonts, FontStyle};
+use picoboy::tilemap::{TileMap, Tileset};
+
+use crate::assets;
+use crate::pause::Pause;
+use crate::{Scenes, UPDATE_INTERVAL_MS};
+
+/// Speed of the ball in pixels per second
+const SPEED: i32 = 40;
+
+/// Size of the tiles in `tiles.png`
+const TILE_SIZE: Size = Size::new(16, 16);
+
+const FLOOR: u8 = 0;
+const WALL: u8 = 1;
+
+/// Most tiles along a side of the room, enough to cover the longer side of the display
+const MAX_SIDE: u32 = (DISPLAY_HEIGHT as u32).div_ceil(TILE_SIZE.width);
+
+/// Tiles of the largest room
+const MAX_TILES: usize = (MAX_SIDE * MAX_SIDE) as usize;
+
+/// Floor surrounded by walls, covering the screen in whole tiles.
+///
+/// Only the size is kept, the tiles are laid out when needed so the scene
+/// stays small on the scene stack.
+struct Room {
+    columns: u32,
+    rows: u32,
+    // Centers the room, the walls stick out by the same amount on either side
+    position: Point,
+}
+
+impl Room {
+    fn new(screen: Size) -> Self {
+        let columns = screen.width.div_ceil(TILE_SIZE.width).clamp(1, MAX_SIDE);
+        let rows = screen.height.div_ceil(TILE_SIZE.height).clamp(1, MAX_SIDE);
+        let center =
+            |screen: u32, tiles: u32, tile: u32| (screen as i32 - (tiles * tile) as i32) / 2;
+
+        Self {
+            columns,
+            rows,
+            position: Point::new(
+                center(screen.width, columns, TILE_SIZE.width),
+                center(screen.height, rows, TILE_SIZE.height),
+            ),
+        }
+    }
+
+    /// The room as drawn, laid out in `tiles`, its walls stop the ball
+    fn map<'a>(&self, tiles: &'a mut [u8; MAX_TILES]) -> TileMap<'a> {
+        let tiles = &mut tiles[..(self.columns * self.rows) as usize];
+        for (i, tile) in (0..).zip(tiles.iter_mut()) {
+            let (column, row) = (i % self.columns, i / self.columns);
+            let edge =
+                column == 0 || row == 0 || column == self.columns - 1 || row == self.rows - 1;
+            *tile = if edge { WALL } else { FLOOR };
+        }
+
+        let mut map = TileMap::new(Tileset::new(&assets::TILES, TILE_SIZE), self.columns, tiles);
+        map.set_position(self.position);
+        map
+    }
+}
+
+/// Gameplay, B pauses
+pub struct Play {
+    ball: Body,
+    flip: Flip,
+    room: Room,
+    // Area the ball stays within, the whole screen
+    screen: Aabb,
+    // Number of updates since the start, shown as seconds
+    ticks: u32,
+}
+
+impl Play {
+    /// Starts with the ball in the middle of a room filling a screen of `screen` pixels
+    pub fn new(screen: Size) -> Self {
+        let room = Room::new(screen);
+        let screen = Aabb::new(Vec2::ZERO, Vec2::from(screen));
+        Self {
+            ball: Body::new(screen.center()),
+            flip: Flip::NONE,
+            room,
+            screen,
+            ticks: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, input: &Input) -> Transition<Scenes> {
+        if input.pressed(Button::B) {
+            return Transition::push(Scenes::Pause(Pause::new()));
+        }
+
+        self.ticks = self.ticks.saturating_add(1);
+
+        // Check entries and adjust velocity
+        let axis = |negative, positive| match (input.is_down(negative), input.is_down(positive)) {
+            (true, false) => Fixed::from_int(-SPEED),
+            (false, true) => Fixed::from_int(SPEED),
+            _ => Fixed::ZERO,
+        };
+        self.ball.velocity = Vec2::new(
+            axis(Button::Left, Button::Right),
+            axis(Button::Up, Button::Down),
+        );
+
+        if input.is_down(Button::Right) {
+            self.flip = Flip::NONE;
+        }
+
+        if input.is_down(Button::Left) {
+            // Face the direction of movement
+            self.flip = Flip::HORIZONTAL;
+        }
+
+        // The walls stop the ball, the screen edges where a wall is cut off
+        let size = Vec2::from(assets::BALL.size());
+        let mut tiles = [FLOOR; MAX_TILES];
+        let room = self.room.map(&mut tiles);
+        self.ball
+            .move_in(size, UPDATE_INTERVAL_MS, &room, |tile| tile == WALL);
+        self.ball.clamp_to(size, &self.screen);
+
+        Transition::none()
+    }
+
+    pub(crate) fn draw<D: Display>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut tiles = [FLOOR; MAX_TILES];
+        self.room.map(&mut tiles).draw(display)?;
+
+        Sprite::new(&assets::BALL, self.ball.position.to_point())
+            .with_flip(self.flip)
+            .draw(display)?;
+
+        // Seconds played in the top center
+        let mut digits = [0; 10];
+        let seconds = self.ticks / (1000 / UPDATE_INTERVAL_MS);
+        Text::with_text_style(
+            format(seconds, &mut digits),
+            Point::new(display.bounding_box().size.width as i32 / 2, 16),
+            FontStyle::new(&fonts::SCORE, Rgb565::WHITE),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(display)?;
+
+        Ok(())
+    }
+}
+
+/// Formats a number into the buffer without allocating
+fn format(mut value: u32, buffer: &mut [u8; 10]) -> &str {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    core::str::from_utf8(&buffer[start..]).unwrap_or_default()
+}
@@ -0,0 +1,113 @@
+//! Music and sound effects.
+
+use picoboy::audio::{note, Instrument, Pitch::*, Song, Track, Waveform, HOLD as __, OFF as XX};
+
+const LEAD: Instrument = Instrument {
+    waveform: Waveform::Square,
+    volume: 6,
+    decay_ms: 300,
+};
+
+const BASS: Instrument = Instrument {
+    waveform: Waveform::Triangle,
+    volume: 15,
+    decay_ms: 0,
+};
+
+const DRUM: Instrument = Instrument {
+    waveform: Waveform::Noise,
+    volume: 8,
+    decay_ms: 60,
+};
+
+const BLIP: Instrument = Instrument {
+    waveform: Waveform::Square,
+    volume: 8,
+    decay_ms: 0,
+};
+
+const C3: u8 = note(C, 3);
+const E3: u8 = note(E, 3);
+const F3: u8 = note(F, 3);
+const G3: u8 = note(G, 3);
+const A3: u8 = note(A, 3);
+const C5: u8 = note(C, 5);
+const D5: u8 = note(D, 5);
+const E5: u8 = note(E, 5);
+const F5: u8 = note(F, 5);
+const G5: u8 = note(G, 5);
+const A5: u8 = note(A, 5);
+const C6: u8 = note(C, 6);
+
+/// Hi-hat and kick drum, two letters like `__` and `XX` to keep the rows aligned
+const HH: u8 = note(C, 9);
+const KK: u8 = note(C, 6);
+
+/// Calm arpeggio on the title screen
+pub static TITLE_THEME: Song<'static> = Song {
+    row_ms: 150,
+    tracks: &[
+        Track {
+            instrument: LEAD,
+            rows: &[
+                C5, E5, G5, C6, G5, E5, C5, __, A3, C5, E5, A5, E5, C5, A3, __,
+            ],
+        },
+        Track {
+            instrument: BASS,
+            rows: &[
+                C3, __, __, __, __, __, __, XX, A3, __, __, __, __, __, __, XX,
+            ],
+        },
+    ],
+    looping: true,
+};
+
+/// Upbeat loop while playing
+pub static GAME_THEME: Song<'static> = Song {
+    row_ms: 125,
+    tracks: &[
+        Track {
+            instrument: LEAD,
+            rows: &[
+                E5, __, G5, __, A5, G5, E5, __, D5, __, E5, __, C5, __, __, __, //
+                F5, __, A5, __, C6, A5, F5, __, E5, __, D5, __, C5, __, __, __,
+            ],
+        },
+        Track {
+            instrument: BASS,
+            rows: &[
+                C3, __, C3, __, G3, __, G3, __, A3, __, A3, __, E3, __, E3, __, //
+                F3, __, F3, __, C3, __, C3, __, G3, __, G3, __, C3, __, XX, __,
+            ],
+        },
+        Track {
+            instrument: DRUM,
+            rows: &[
+                KK, __, HH, __, KK, __, HH, HH, KK, __, HH, __, KK, __, HH, HH, //
+                KK, __, HH, __, KK, __, HH, HH, KK, __, HH, __, KK, HH, HH, HH,
+            ],
+        },
+    ],
+    looping: true,
+};
+
+/// Rising blip when a scene starts
+pub static ENTER: Song<'static> = Song {
+    row_ms: 40,
+    tracks: &[Track {
+        instrument: BLIP,
+        rows: &[G5, C6, XX],
+    }],
+    looping: false,
+};
+
+/// Falling blip when a scene ends
+pub static LEAVE: Song<'static> = Song {
+    row_ms: 40,
+    tracks: &[Track {
+        instrument: BLIP,
+        rows: &[C6, G5, XX],
+    }],
+    looping: false,
+};
@@ -0,0 +1,95 @@
+//! Title screen shown after power-up.
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle, Rectangle, Triangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use picoboy::display::Display;
+use picoboy::input::{Button, Input};
+use picoboy::scene::Transition;
+use picoboy::text::{fonts, FontStyle, TextBox};
+
+use crate::play::Play;
+use crate::Scenes;
+
+/// Smallest and largest diameter of the pulsing ring in pixels
+const RING_DIAMETER: (u32, u32) = (90, 110);
+
+/// Hint below the ring, the button in yellow
+const HINT: &str = "Press ^1A^0 or the joystick to start";
+
+/// Name and pulsing play symbol, A or the joystick starts the game
+pub struct Title {
+    ticks: u32,
+    // Size of the screen, passed on to the game
+    screen: Size,
+}
+
+impl Title {
+    /// Creates the title of a screen of `screen` pixels
+    pub fn new(screen: Size) -> Self {
+        Self { ticks: 0, screen }
+    }
+
+    pub(crate) fn update(&mut self, input: &Input) -> Transition<Scenes> {
+        self.ticks = self.ticks.wrapping_add(1);
+
+        if input.pressed(Button::A) || input.pressed(Button::Center) {
+            Transition::replace(Scenes::Play(Play::new(self.screen))).with_fade()
+        } else {
+            Transition::none()
+        }
+    }
+
+    pub(crate) fn draw<D: Display>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb565::BLACK)?;
+
+        // Centered in whatever orientation the display is in
+        let size = display.bounding_box().size;
+        let center = Point::new(size.width as i32 / 2, size.height as i32 / 2);
+
+        // Grow and shrink by one pixel per update
+        let (min, max) = RING_DIAMETER;
+        let range = max - min;
+        let phase = self.ticks % (2 * range);
+        let diameter = min + phase.min(2 * range - phase);
+
+        Circle::with_center(center, diameter)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::MAGENTA, 4))
+            .draw(display)?;
+
+        Triangle::new(
+            center + Point::new(-12, -20),
+            center + Point::new(-12, 20),
+            center + Point::new(22, 0),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+        .draw(display)?;
+
+        Text::with_text_style(
+            "PicoBoy",
+            Point::new(center.x, 40),
+            FontStyle::new(&fonts::LARGE, Rgb565::WHITE),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(display)?;
+
+        TextBox::new(
+            HINT,
+            Rectangle::new(
+                Point::new(center.x - 60, size.height as i32 - 60),
+                Size::new(120, 40),
+            ),
+            FontStyle::new(&fonts::NORMAL, Rgb565::CSS_LIGHT_GRAY),
+        )
+        .with_alignment(Alignment::Center)
+        .with_palette(&[Rgb565::CSS_LIGHT_GRAY, Rgb565::YELLOW])
+        .draw(display)
+    }
+}
@@ -0,0 +1,26 @@
+MEMORY {
+    BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 64K
+    /* Save data, kept across firmware updates, see picoboy::rom_flash */
+    SAVE  : ORIGIN = 0x10000000 + 2048K - 64K, LENGTH = 64K
+    RAM   : ORIGIN = 0x20000000, LENGTH = 256K
+}
+
+EXTERN(BOOT2_FIRMWARE)
+
+__save_start = ORIGIN(SAVE);
+__save_end = ORIGIN(SAVE) + LENGTH(SAVE);
+
+SECTIONS {
+    /* ### Boot loader */
+    .boot2 ORIGIN(BOOT2) :
+    {
+        KEEP(*(.boot2));
+    } > BOOT2
+
+    /* Only slot builds for the launcher need the app header, see build.rs */
+    /DISCARD/ :
+    {
+        *(.app_header);
+    }
+} INSERT BEFORE .text;
\ No newline at end of file
@@ -0,0 +1,17 @@
+//! Converts the BDF files in `fonts/` into fonts, see the `asset-pipeline`
+//! crate. The generated statics are included by `src/text.rs`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let source = asset_pipeline::generate_fonts(Path::new("fonts"), "crate::text")
+        .unwrap_or_else(|error| panic!("{error}"));
+    fs::write(out.join("fonts.rs"), source).unwrap();
+
+    // Re-run whenever a file in the directory is added, removed or changed
+    println!("cargo:rerun-if-changed=fonts");
+}
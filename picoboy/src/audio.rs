@@ -0,0 +1,647 @@
+//! Sound synthesis, tracker songs and sound effects.
+//!
+//! The [`Mixer`] renders unsigned 8 bit samples at [`SAMPLE_RATE`], centered
+//! around [`SILENCE`]. It plays one [`Song`] as music and a few more songs as
+//! one-shot sound effects on top. Everything here is integer-only and portable,
+//! the firmware moves the samples to the speaker and the simulator can write
+//! them to a file.
+//!
+//! A song consists of up to [`MAX_TRACKS`] tracks, each with an [`Instrument`]
+//! and one byte per row: [`HOLD`] keeps the current note, [`OFF`] silences the
+//! track and everything else starts a note, see [`note`].
+
+/// Samples per second
+pub const SAMPLE_RATE: u32 = 20_000;
+
+/// Sample value without any sound
+pub const SILENCE: u8 = 128;
+
+/// Maximum number of tracks of a song
+pub const MAX_TRACKS: usize = 4;
+
+/// Number of sound effects played at the same time
+pub const MAX_EFFECTS: usize = 2;
+
+/// Row value keeping the current note
+pub const HOLD: u8 = 0;
+
+/// Row value silencing the track
+pub const OFF: u8 = 1;
+
+/// Frequencies of the highest octave in millihertz, lower octaves are derived by halving
+const OCTAVE_9_MHZ: [u64; 12] = [
+    8_372_018, 8_869_844, 9_397_273, 9_956_063, 10_548_082, 11_175_303, 11_839_822, 12_543_854,
+    13_289_750, 14_080_000, 14_917_240, 15_804_266,
+];
+
+/// Pitch within an octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pitch {
+    C,
+    Cs,
+    D,
+    Ds,
+    E,
+    F,
+    Fs,
+    G,
+    Gs,
+    A,
+    As,
+    B,
+}
+
+/// Returns the row value of a note, numbered like MIDI, so `note(Pitch::A, 4)` is 440 Hz.
+///
+/// Octaves range from 0 to 9.
+pub const fn note(pitch: Pitch, octave: u8) -> u8 {
+    (octave + 1) * 12 + pitch as u8
+}
+
+/// Lowest row value starting a note
+const MIN_NOTE: u8 = note(Pitch::C, 0);
+
+/// Shape of the generated wave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Waveform {
+    Square,
+    Triangle,
+    /// Pseudo-random noise, the note sets how often the value changes
+    Noise,
+}
+
+/// Sound of a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Instrument {
+    pub waveform: Waveform,
+    /// Volume from 0 to 15
+    pub volume: u8,
+    /// Time in milliseconds a note takes to fade out, 0 keeps it until the next row value
+    pub decay_ms: u16,
+}
+
+/// Notes of a single instrument
+#[derive(Debug, Clone, Copy)]
+pub struct Track<'a> {
+    pub instrument: Instrument,
+    /// One value per row, see [`HOLD`], [`OFF`] and [`note`]
+    pub rows: &'a [u8],
+}
+
+/// Tracks played at the same time
+#[derive(Debug, Clone, Copy)]
+pub struct Song<'a> {
+    /// Duration of a row in milliseconds
+    pub row_ms: u16,
+    /// Up to [`MAX_TRACKS`] tracks, further ones are ignored
+    pub tracks: &'a [Track<'a>],
+    /// Start over after the last row instead of stopping
+    pub looping: bool,
+}
+
+impl Song<'_> {
+    /// Returns the number of rows of the longest track
+    pub fn len(&self) -> usize {
+        self.tracks
+            .iter()
+            .map(|track| track.rows.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns `true` if the song has no rows at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Level of a voice at full volume, 16 bit fixed point
+const FULL_LEVEL: u32 = 0xFFFF;
+
+/// A single oscillator
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    waveform: Waveform,
+    phase: u32,
+    increment: u32,
+    level: u32,
+    decay_step: u32,
+    noise: u16,
+}
+
+impl Voice {
+    const fn new() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            phase: 0,
+            increment: 0,
+            level: 0,
+            decay_step: 0,
+            noise: 1,
+        }
+    }
+
+    fn start(&mut self, note: u8, instrument: &Instrument) {
+        let octave = u32::from(note / 12).saturating_sub(1).min(9);
+        let millihertz = OCTAVE_9_MHZ[usize::from(note % 12)] >> (9 - octave);
+
+        self.waveform = instrument.waveform;
+        self.increment = ((millihertz << 32) / (u64::from(SAMPLE_RATE) * 1000)) as u32;
+        self.level = FULL_LEVEL * u32::from(instrument.volume.min(15)) / 15;
+
+        let decay_samples = u32::from(instrument.decay_ms) * SAMPLE_RATE / 1000;
+        self.decay_step = self
+            .level
+            .checked_div(decay_samples)
+            .map_or(0, |step| step.max(1));
+    }
+
+    fn stop(&mut self) {
+        self.level = 0;
+    }
+
+    /// Returns the next sample from -128 to 127, scaled by the level
+    fn sample(&mut self) -> i32 {
+        if self.level == 0 {
+            return 0;
+        }
+
+        let (phase, wrapped) = self.phase.overflowing_add(self.increment);
+        self.phase = phase;
+
+        let value = match self.waveform {
+            Waveform::Square => {
+                if phase < 0x8000_0000 {
+                    127
+                } else {
+                    -128
+                }
+            }
+            Waveform::Triangle => {
+                let ramp = (phase >> 23) as i32;
+                if ramp < 256 {
+                    ramp - 128
+                } else {
+                    383 - ramp
+                }
+            }
+            Waveform::Noise => {
+                if wrapped {
+                    // 15 bit linear feedback shift register
+                    let bit = (self.noise ^ (self.noise >> 1)) & 1;
+                    self.noise = (self.noise >> 1) | (bit << 14);
+                }
+                if self.noise & 1 == 0 {
+                    127
+                } else {
+                    -128
+                }
+            }
+        };
+
+        let sample = (value * self.level as i32) >> 16;
+        self.level = self.level.saturating_sub(self.decay_step);
+
+        sample
+    }
+}
+
+/// Plays the rows of a song
+struct Sequencer<'a> {
+    song: Option<&'a Song<'a>>,
+    row: usize,
+    samples_left: u32,
+    voices: [Voice; MAX_TRACKS],
+}
+
+impl<'a> Sequencer<'a> {
+    const fn new() -> Self {
+        Self {
+            song: None,
+            row: 0,
+            samples_left: 0,
+            voices: [Voice::new(); MAX_TRACKS],
+        }
+    }
+
+    fn play(&mut self, song: &'a Song<'a>) {
+        self.stop();
+        self.song = Some(song);
+    }
+
+    fn stop(&mut self) {
+        self.song = None;
+        self.row = 0;
+        self.samples_left = 0;
+        for voice in &mut self.voices {
+            voice.stop();
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.song.is_some()
+    }
+
+    fn sample(&mut self) -> i32 {
+        let Some(song) = self.song else {
+            return 0;
+        };
+
+        if self.samples_left == 0 {
+            if self.row >= song.len() {
+                if !song.looping || song.is_empty() {
+                    self.stop();
+                    return 0;
+                }
+                self.row = 0;
+            }
+
+            for (voice, track) in self.voices.iter_mut().zip(song.tracks) {
+                match track.rows.get(self.row).copied().unwrap_or(HOLD) {
+                    HOLD => {}
+                    OFF => voice.stop(),
+                    note if note >= MIN_NOTE => voice.start(note, &track.instrument),
+                    _ => voice.stop(),
+                }
+            }
+
+            self.row += 1;
+            self.samples_left = (u32::from(song.row_ms) * SAMPLE_RATE / 1000).max(1);
+        }
+        self.samples_left -= 1;
+
+        self.voices.iter_mut().map(Voice::sample).sum()
+    }
+}
+
+/// Music with sound effects on top
+pub struct Mixer<'a> {
+    music: Sequencer<'a>,
+    effects: [Sequencer<'a>; MAX_EFFECTS],
+    next_effect: usize,
+    volume: u8,
+}
+
+impl<'a> Mixer<'a> {
+    /// Creates a silent mixer at full volume
+    pub const fn new() -> Self {
+        Self {
+            music: Sequencer::new(),
+            effects: [const { Sequencer::new() }; MAX_EFFECTS],
+            next_effect: 0,
+            volume: u8::MAX,
+        }
+    }
+
+    /// Changes the overall volume, 255 is full volume
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    /// Starts a song as music, replacing the current one
+    pub fn play_music(&mut self, song: &'a Song<'a>) {
+        self.music.play(song);
+    }
+
+    /// Silences the music
+    pub fn stop_music(&mut self) {
+        self.music.stop();
+    }
+
+    /// Returns `true` while music is playing
+    pub fn is_music_playing(&self) -> bool {
+        self.music.is_playing()
+    }
+
+    /// Plays a song once on top of the music.
+    ///
+    /// If all effect slots are busy, the oldest effect is cut off.
+    pub fn play_effect(&mut self, effect: &'a Song<'a>) {
+        let slot = self
+            .effects
+            .iter()
+            .position(|effect| !effect.is_playing())
+            .unwrap_or(self.next_effect);
+
+        // Slots are taken in turns, so the next one holds the oldest effect
+        self.next_effect = (slot + 1) % MAX_EFFECTS;
+        self.effects[slot].play(effect);
+    }
+
+    /// Returns the next sample
+    pub fn sample(&mut self) -> u8 {
+        let mut mixed = self.music.sample();
+        for effect in &mut self.effects {
+            mixed += effect.sample();
+        }
+
+        // Leave headroom for a few loud voices at once
+        let scaled = mixed * i32::from(self.volume) / (4 * 255);
+        (scaled.clamp(-128, 127) + 128) as u8
+    }
+
+    /// Fills a buffer with the next samples
+    pub fn render(&mut self, buffer: &mut [u8]) {
+        for sample in buffer {
+            *sample = self.sample();
+        }
+    }
+}
+
+impl Default for Mixer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of one row of the test songs, 100 ms
+    const ROW: usize = 2_000;
+
+    const SQUARE: Instrument = Instrument {
+        waveform: Waveform::Square,
+        volume: 15,
+        decay_ms: 0,
+    };
+
+    const A4: u8 = note(Pitch::A, 4);
+
+    fn song<'a>(tracks: &'a [Track<'a>], looping: bool) -> Song<'a> {
+        Song {
+            row_ms: 100,
+            tracks,
+            looping,
+        }
+    }
+
+    fn render(mixer: &mut Mixer<'_>, len: usize) -> [u8; SAMPLE_RATE as usize] {
+        let mut buffer = [SILENCE; SAMPLE_RATE as usize];
+        mixer.render(&mut buffer[..len]);
+        buffer
+    }
+
+    /// Number of changes from below to above silence
+    fn rising_edges(samples: &[u8]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] <= SILENCE && pair[1] > SILENCE)
+            .count()
+    }
+
+    fn is_silent(samples: &[u8]) -> bool {
+        samples.iter().all(|&sample| sample == SILENCE)
+    }
+
+    #[test]
+    fn numbers_notes_like_midi() {
+        assert_eq!(note(Pitch::C, 0), 12);
+        assert_eq!(A4, 69);
+        assert_eq!(note(Pitch::B, 9), 131);
+    }
+
+    #[test]
+    fn stays_silent_without_music() {
+        let mut mixer = Mixer::new();
+        assert!(is_silent(&render(&mut mixer, 1000)));
+    }
+
+    #[test]
+    fn plays_square_at_note_frequency() {
+        let tracks = [Track {
+            instrument: SQUARE,
+            rows: &[A4; 10],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+
+        // One second of 440 Hz
+        let samples = render(&mut mixer, SAMPLE_RATE as usize);
+        assert!((439..=441).contains(&rising_edges(&samples)));
+
+        // Two levels, a quarter of the range each at full volume
+        assert!(samples.iter().all(|&sample| sample == 159 || sample == 96));
+    }
+
+    #[test]
+    fn halves_frequency_per_octave() {
+        for (octave, edges) in [(2, 110), (3, 220), (5, 880)] {
+            let rows = [note(Pitch::A, octave); 10];
+            let tracks = [Track {
+                instrument: SQUARE,
+                rows: &rows,
+            }];
+            let song = song(&tracks, false);
+            let mut mixer = Mixer::new();
+            mixer.play_music(&song);
+
+            let samples = render(&mut mixer, SAMPLE_RATE as usize);
+            let counted = rising_edges(&samples);
+            assert!(counted.abs_diff(edges) <= 1, "octave {octave}: {counted}");
+        }
+    }
+
+    #[test]
+    fn plays_triangle_without_jumps() {
+        let tracks = [Track {
+            instrument: Instrument {
+                waveform: Waveform::Triangle,
+                ..SQUARE
+            },
+            rows: &[note(Pitch::C, 4)],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+
+        let samples = render(&mut mixer, ROW);
+        let steepest = samples[..ROW]
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .max()
+            .unwrap();
+        assert!(steepest <= 2, "step of {steepest}");
+        assert!(samples.iter().any(|&sample| sample > 150));
+        assert!(samples.iter().any(|&sample| sample < 105));
+    }
+
+    #[test]
+    fn plays_noise() {
+        let tracks = [Track {
+            instrument: Instrument {
+                waveform: Waveform::Noise,
+                ..SQUARE
+            },
+            rows: &[note(Pitch::C, 9); 10],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+
+        let samples = render(&mut mixer, SAMPLE_RATE as usize);
+        let high = samples.iter().filter(|&&sample| sample > SILENCE).count();
+        // Roughly as often high as low, but not a regular square of 8372 Hz
+        let len = samples.len();
+        assert!((len * 2 / 5..len * 3 / 5).contains(&high), "{high} high");
+        assert!(rising_edges(&samples) < 8372 / 2);
+    }
+
+    #[test]
+    fn holds_and_stops_notes() {
+        let tracks = [Track {
+            instrument: SQUARE,
+            rows: &[A4, HOLD, OFF, HOLD],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+
+        let samples = render(&mut mixer, 4 * ROW);
+        assert!(!is_silent(&samples[ROW..2 * ROW]));
+        assert!(is_silent(&samples[2 * ROW..4 * ROW]));
+    }
+
+    #[test]
+    fn ends_songs_unless_looping() {
+        let tracks = [Track {
+            instrument: SQUARE,
+            rows: &[A4, A4],
+        }];
+
+        let once = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&once);
+        let samples = render(&mut mixer, 3 * ROW);
+        assert!(!is_silent(&samples[..2 * ROW]));
+        assert!(is_silent(&samples[2 * ROW + 1..3 * ROW]));
+        assert!(!mixer.is_music_playing());
+
+        let looping = song(&tracks, true);
+        mixer.play_music(&looping);
+        let samples = render(&mut mixer, 5 * ROW);
+        assert!(!is_silent(&samples[4 * ROW..5 * ROW]));
+        assert!(mixer.is_music_playing());
+
+        mixer.stop_music();
+        assert!(is_silent(&render(&mut mixer, ROW)));
+    }
+
+    #[test]
+    fn fades_out_with_decay() {
+        let tracks = [Track {
+            instrument: Instrument {
+                decay_ms: 50,
+                ..SQUARE
+            },
+            rows: &[A4, HOLD],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+
+        let samples = render(&mut mixer, 2 * ROW);
+        let loudness = |samples: &[u8]| {
+            samples
+                .iter()
+                .map(|&sample| sample.abs_diff(SILENCE))
+                .max()
+                .unwrap()
+        };
+        assert!(loudness(&samples[..100]) > loudness(&samples[600..700]));
+        // Silent after 50 ms
+        assert!(is_silent(&samples[ROW / 2 + 1..2 * ROW]));
+    }
+
+    #[test]
+    fn mixes_effects_over_music() {
+        let music_tracks = [Track {
+            instrument: SQUARE,
+            rows: &[A4; 4],
+        }];
+        let effect_tracks = [Track {
+            instrument: SQUARE,
+            rows: &[note(Pitch::E, 5)],
+        }];
+        let music = song(&music_tracks, true);
+        let effect = song(&effect_tracks, false);
+
+        let mut mixer = Mixer::new();
+        mixer.play_music(&music);
+        mixer.play_effect(&effect);
+
+        let samples = render(&mut mixer, 2 * ROW);
+        // Both high at once reach twice the level of one voice
+        assert!(samples[..ROW].contains(&191));
+        // The effect is over, the music goes on
+        assert!(samples[ROW + 1..2 * ROW]
+            .iter()
+            .all(|&sample| sample == 159 || sample == 96));
+    }
+
+    #[test]
+    fn cuts_off_the_oldest_effect() {
+        let long = [Track {
+            instrument: SQUARE,
+            rows: &[A4; 10],
+        }];
+        let short = [Track {
+            instrument: SQUARE,
+            rows: &[A4],
+        }];
+        let first = song(&long, false);
+        let second = song(&long, false);
+        let third = song(&short, false);
+
+        let mut mixer = Mixer::new();
+        mixer.play_effect(&first);
+        mixer.play_effect(&second);
+        // Replaces the first effect, which ends with the third after one row
+        mixer.play_effect(&third);
+
+        let samples = render(&mut mixer, 3 * ROW);
+        assert!(!is_silent(&samples[2 * ROW..3 * ROW]));
+        assert!(samples[ROW + 1..3 * ROW]
+            .iter()
+            .all(|&sample| sample == 159 || sample == 96));
+    }
+
+    #[test]
+    fn scales_with_volume() {
+        let tracks = [Track {
+            instrument: SQUARE,
+            rows: &[A4],
+        }];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+
+        mixer.set_volume(0);
+        mixer.play_music(&song);
+        assert!(is_silent(&render(&mut mixer, ROW)));
+
+        mixer.set_volume(128);
+        mixer.play_music(&song);
+        let samples = render(&mut mixer, ROW);
+        assert!(samples[..ROW]
+            .iter()
+            .all(|&sample| sample == 143 || sample == 112));
+    }
+
+    #[test]
+    fn ignores_tracks_beyond_the_limit() {
+        let mut tracks = [Track {
+            instrument: SQUARE,
+            rows: &[OFF],
+        }; MAX_TRACKS + 1];
+        tracks[MAX_TRACKS].rows = &[A4];
+        let song = song(&tracks, false);
+        let mut mixer = Mixer::new();
+        mixer.play_music(&song);
+        assert!(is_silent(&render(&mut mixer, ROW)));
+    }
+}
@@ -0,0 +1,69 @@
+//! PWM output to the display backlight.
+//!
+//! Channel A of PWM slice 5 drives the backlight on GPIO26. With the full
+//! 16 bit range at the system clock the PWM runs at about 1.9 kHz, well above
+//! visible flicker, and still resolves the low end of the [`duty`] curve.
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use picoboy_color::hal::{
+    gpio::{bank0::Gpio26, FunctionPwm, Pin, PullDown},
+    pwm::{FreeRunning, Pwm5, Slice},
+};
+
+use crate::dimmer::{duty, MAX_BRIGHTNESS, MAX_DUTY};
+
+/// Display backlight with adjustable brightness
+pub struct Backlight {
+    pwm: Slice<Pwm5, FreeRunning>,
+    _pin: Pin<Gpio26, FunctionPwm, PullDown>,
+    brightness: u8,
+}
+
+impl Backlight {
+    pub(crate) fn new(
+        mut pwm: Slice<Pwm5, FreeRunning>,
+        pin: Pin<Gpio26, FunctionPwm, PullDown>,
+        brightness: u8,
+    ) -> Self {
+        pwm.set_top(MAX_DUTY - 1);
+        pwm.set_div_int(1);
+        pwm.enable();
+
+        let mut backlight = Self {
+            pwm,
+            _pin: pin,
+            brightness,
+        };
+        backlight.output(brightness);
+        backlight
+    }
+
+    /// Sets the brightness in percent of the perceived lightness
+    pub fn set_brightness(&mut self, brightness: u8) {
+        if brightness != self.brightness {
+            self.output(brightness);
+        }
+    }
+
+    /// Returns the brightness in percent
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Switches the backlight fully on
+    pub fn on(&mut self) {
+        self.set_brightness(MAX_BRIGHTNESS);
+    }
+
+    /// Switches the backlight off
+    pub fn off(&mut self) {
+        self.set_brightness(0);
+    }
+
+    fn output(&mut self, brightness: u8) {
+        // With the top one below MAX_DUTY, the full duty keeps the output high all the time
+        let _ = self.pwm.channel_a.set_duty_cycle(duty(brightness));
+        self.brightness = brightness.min(MAX_BRIGHTNESS);
+    }
+}
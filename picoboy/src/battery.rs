@@ -0,0 +1,440 @@
+//! Battery charge estimate, low-battery warnings and a battery icon.
+//!
+//! The voltage monitor measures VSYS, which follows the battery while it runs
+//! the device. Single samples are noisy and sag while the speaker or backlight
+//! draw current, so a [`Filter`] smooths them before [`percent`] maps the
+//! voltage to the remaining charge along the discharge curve of a LiPo cell.
+//! A [`Battery`] combines both and reports a [`BatteryLevel`] whenever the
+//! charge crosses one of its thresholds. [`BatteryIndicator`] draws the charge
+//! as a small icon on top of any screen.
+//!
+//! Everything here works on millivolts, so recorded samples can be replayed on
+//! the host.
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+/// Voltage above which the device runs from USB rather than the battery, in millivolts
+pub const EXTERNAL_POWER_MV: u16 = 4_400;
+
+/// Remaining charge of a LiPo cell over its voltage at rest, in millivolts and percent
+const DISCHARGE_CURVE: [(u16, u8); 11] = [
+    (3_300, 0),
+    (3_500, 5),
+    (3_600, 12),
+    (3_700, 30),
+    (3_750, 45),
+    (3_800, 55),
+    (3_850, 65),
+    (3_900, 72),
+    (4_000, 85),
+    (4_100, 94),
+    (4_200, 100),
+];
+
+/// Returns the remaining charge in percent of a battery at `voltage_mv`
+pub fn percent(voltage_mv: u16) -> u8 {
+    let (first_mv, first_percent) = DISCHARGE_CURVE[0];
+    if voltage_mv <= first_mv {
+        return first_percent;
+    }
+
+    // Interpolate linearly between the two points around the voltage
+    for pair in DISCHARGE_CURVE.windows(2) {
+        let ((low_mv, low_percent), (high_mv, high_percent)) = (pair[0], pair[1]);
+        if voltage_mv <= high_mv {
+            let range = u32::from(high_percent - low_percent);
+            let offset = u32::from(voltage_mv - low_mv) * range / u32::from(high_mv - low_mv);
+            return low_percent + offset as u8;
+        }
+    }
+
+    DISCHARGE_CURVE[DISCHARGE_CURVE.len() - 1].1
+}
+
+/// Exponential moving average of voltage samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Filter {
+    shift: u32,
+    // Average scaled by 2^shift, `None` until the first sample
+    sum: Option<u32>,
+}
+
+impl Filter {
+    /// Creates a filter weighting each sample with 1/2^`shift`, larger shifts smooth more
+    pub const fn new(shift: u32) -> Self {
+        Self { shift, sum: None }
+    }
+
+    /// Adds a sample and returns the new average, the first sample is taken as it is
+    pub fn update(&mut self, sample: u16) -> u16 {
+        let sum = match self.sum {
+            Some(sum) => sum - (sum >> self.shift) + u32::from(sample),
+            None => u32::from(sample) << self.shift,
+        };
+        self.sum = Some(sum);
+        self.value()
+    }
+
+    /// Returns the average, 0 before the first sample
+    pub fn value(&self) -> u16 {
+        self.sum.map_or(0, |sum| (sum >> self.shift) as u16)
+    }
+
+    /// Forgets all samples
+    pub fn reset(&mut self) {
+        self.sum = None;
+    }
+}
+
+/// How urgently the battery needs charging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BatteryLevel {
+    /// Powered through USB
+    External,
+    /// Enough charge left
+    Normal,
+    /// Charge below [`BatteryConfig::low_percent`]
+    Low,
+    /// Charge below [`BatteryConfig::critical_percent`], the device may turn off any moment
+    Critical,
+}
+
+/// Thresholds and smoothing of a [`Battery`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryConfig {
+    /// Charge below which the level is [`BatteryLevel::Low`] in percent
+    pub low_percent: u8,
+    /// Charge below which the level is [`BatteryLevel::Critical`] in percent
+    pub critical_percent: u8,
+    /// Charge a level has to be exceeded by before it is left again in percent
+    pub hysteresis_percent: u8,
+    /// Smoothing of the samples, see [`Filter::new`]
+    pub filter_shift: u32,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            low_percent: 15,
+            critical_percent: 5,
+            hysteresis_percent: 3,
+            filter_shift: 4,
+        }
+    }
+}
+
+/// Battery monitor fed with voltage samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Battery {
+    config: BatteryConfig,
+    filter: Filter,
+    level: BatteryLevel,
+}
+
+impl Battery {
+    /// Creates a monitor at [`BatteryLevel::Normal`] without any samples
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            config,
+            filter: Filter::new(config.filter_shift),
+            level: BatteryLevel::Normal,
+        }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> BatteryConfig {
+        self.config
+    }
+
+    /// Adds a sample of the supply voltage in millivolts.
+    ///
+    /// Returns the new level when it changed, e.g. [`BatteryLevel::Low`] once
+    /// the battery runs low.
+    pub fn update(&mut self, sample_mv: u16) -> Option<BatteryLevel> {
+        self.filter.update(sample_mv);
+
+        let level = self.level_for(self.voltage_mv());
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        Some(level)
+    }
+
+    /// Returns the smoothed supply voltage in millivolts
+    pub fn voltage_mv(&self) -> u16 {
+        self.filter.value()
+    }
+
+    /// Returns the remaining charge in percent, 100 on external power
+    pub fn percent(&self) -> u8 {
+        match self.level {
+            BatteryLevel::External => 100,
+            _ => percent(self.voltage_mv()),
+        }
+    }
+
+    /// Returns the current level
+    pub fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    fn level_for(&self, voltage_mv: u16) -> BatteryLevel {
+        if voltage_mv >= EXTERNAL_POWER_MV {
+            return BatteryLevel::External;
+        }
+
+        // Leaving a level upwards takes a bit more charge than entering it
+        let charge = percent(voltage_mv);
+        let raise = |limit: u8, raised: bool| {
+            if raised {
+                limit.saturating_add(self.config.hysteresis_percent)
+            } else {
+                limit
+            }
+        };
+        let critical = raise(
+            self.config.critical_percent,
+            self.level == BatteryLevel::Critical,
+        );
+        let low = raise(
+            self.config.low_percent,
+            matches!(self.level, BatteryLevel::Low | BatteryLevel::Critical),
+        );
+
+        if charge < critical {
+            BatteryLevel::Critical
+        } else if charge < low {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Normal
+        }
+    }
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self::new(BatteryConfig::default())
+    }
+}
+
+/// Battery icon showing the charge, colored by level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryIndicator {
+    position: Point,
+    percent: u8,
+    level: BatteryLevel,
+}
+
+impl BatteryIndicator {
+    /// Size of the icon in pixels, including the terminal on the right
+    pub const SIZE: Size = Size::new(22, 12);
+
+    /// Creates an icon for the current state of `battery` with its top left corner at `position`
+    pub fn new(position: Point, battery: &Battery) -> Self {
+        Self {
+            position,
+            percent: battery.percent(),
+            level: battery.level(),
+        }
+    }
+
+    fn color(&self) -> Rgb565 {
+        match self.level {
+            BatteryLevel::External => Rgb565::CYAN,
+            BatteryLevel::Normal => Rgb565::GREEN,
+            BatteryLevel::Low => Rgb565::YELLOW,
+            BatteryLevel::Critical => Rgb565::RED,
+        }
+    }
+}
+
+impl Dimensions for BatteryIndicator {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.position, Self::SIZE)
+    }
+}
+
+impl Drawable for BatteryIndicator {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Body with a black background, so the icon stays readable on any screen
+        let body = Rectangle::new(self.position, Self::SIZE - Size::new(2, 0));
+        body.into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(target)?;
+        body.into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+            .draw(target)?;
+
+        let terminal = Rectangle::new(
+            self.position + Point::new(body.size.width as i32, 3),
+            Size::new(2, Self::SIZE.height - 6),
+        );
+        terminal
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+            .draw(target)?;
+
+        // Charge inside a one pixel gap, a sliver stays visible while the battery is nearly empty
+        let inner = body.size - Size::new(4, 4);
+        let width = (inner.width * u32::from(self.percent.min(100)) / 100).max(1);
+        Rectangle::new(
+            self.position + Point::new(2, 2),
+            Size::new(width, inner.height),
+        )
+        .into_styled(PrimitiveStyle::with_fill(self.color()))
+        .draw(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// VSYS samples of a charged battery at 10 Hz, the dips are sound effects
+    const IDLE_SAMPLES_MV: [u16; 24] = [
+        3_912, 3_905, 3_918, 3_898, 3_910, 3_702, 3_695, 3_906, 3_915, 3_901, 3_909, 3_897, 3_914,
+        3_688, 3_903, 3_911, 3_899, 3_907, 3_916, 3_902, 3_710, 3_896, 3_908, 3_913,
+    ];
+
+    /// Samples every minute of a battery running down, with the same noise
+    fn discharge() -> impl Iterator<Item = u16> {
+        (0..100u16).map(|minute| {
+            let noise = IDLE_SAMPLES_MV[usize::from(minute) % IDLE_SAMPLES_MV.len()];
+            4_150 - minute * 9 + noise - 3_906
+        })
+    }
+
+    #[test]
+    fn percent_follows_discharge_curve() {
+        for (voltage_mv, charge) in DISCHARGE_CURVE {
+            assert_eq!(percent(voltage_mv), charge);
+        }
+        assert_eq!(percent(0), 0);
+        assert_eq!(percent(3_000), 0);
+        assert_eq!(percent(4_300), 100);
+        assert_eq!(percent(u16::MAX), 100);
+
+        // Halfway between two points
+        assert_eq!(percent(3_650), 21);
+        assert_eq!(percent(3_400), 2);
+
+        let mut last = 0;
+        for voltage_mv in 3_000..4_400 {
+            let charge = percent(voltage_mv);
+            assert!(charge >= last, "at {voltage_mv} mV");
+            last = charge;
+        }
+    }
+
+    #[test]
+    fn filter_starts_at_first_sample() {
+        let mut filter = Filter::new(4);
+        assert_eq!(filter.value(), 0);
+        assert_eq!(filter.update(3_900), 3_900);
+
+        filter.reset();
+        assert_eq!(filter.value(), 0);
+        assert_eq!(filter.update(4_100), 4_100);
+    }
+
+    #[test]
+    fn filter_follows_a_step() {
+        let mut filter = Filter::new(2);
+        filter.update(4_000);
+
+        // A quarter of the remaining difference per sample
+        assert_eq!(filter.update(3_600), 3_900);
+        assert_eq!(filter.update(3_600), 3_825);
+        for _ in 0..40 {
+            filter.update(3_600);
+        }
+        assert!(filter.value().abs_diff(3_600) <= 4);
+    }
+
+    #[test]
+    fn filter_smooths_recorded_samples() {
+        let mut filter = Filter::new(BatteryConfig::default().filter_shift);
+        for sample in IDLE_SAMPLES_MV {
+            let value = filter.update(sample);
+            assert!((3_860..=3_915).contains(&value), "{value} mV");
+        }
+    }
+
+    #[test]
+    fn stays_normal_through_load_dips() {
+        let mut battery = Battery::default();
+        for sample in IDLE_SAMPLES_MV.iter().cycle().take(200) {
+            assert_eq!(battery.update(*sample), None);
+        }
+        assert_eq!(battery.level(), BatteryLevel::Normal);
+        assert!((65..=67).contains(&battery.percent()));
+    }
+
+    #[test]
+    fn reports_each_level_once_while_discharging() {
+        let mut battery = Battery::default();
+        let changes: [Option<(u16, BatteryLevel)>; 4] = {
+            let mut changes = [None; 4];
+            let mut count = 0;
+            for (minute, sample) in (0..).zip(discharge()) {
+                if let Some(level) = battery.update(sample) {
+                    changes[count] = Some((minute, level));
+                    count += 1;
+                }
+            }
+            changes
+        };
+
+        assert!(matches!(changes[0], Some((_, BatteryLevel::Low))));
+        assert!(matches!(changes[1], Some((_, BatteryLevel::Critical))));
+        assert_eq!(changes[2], None);
+        assert!(battery.percent() < 5);
+    }
+
+    #[test]
+    fn hysteresis_prevents_flapping() {
+        let mut battery = Battery::new(BatteryConfig {
+            filter_shift: 0,
+            ..BatteryConfig::default()
+        });
+
+        // 15% is the low limit
+        assert_eq!(battery.update(3_620), None);
+        assert_eq!(battery.update(3_610), Some(BatteryLevel::Low));
+        assert_eq!(battery.update(3_620), None);
+        assert_eq!(battery.update(3_630), None);
+        assert_eq!(battery.level(), BatteryLevel::Low);
+
+        // Back above 18%
+        assert_eq!(battery.update(3_640), Some(BatteryLevel::Normal));
+        assert_eq!(battery.update(3_620), None);
+    }
+
+    #[test]
+    fn detects_external_power() {
+        let mut battery = Battery::new(BatteryConfig {
+            filter_shift: 0,
+            ..BatteryConfig::default()
+        });
+        battery.update(3_450);
+        assert_eq!(battery.level(), BatteryLevel::Critical);
+        assert_eq!(battery.percent(), 3);
+
+        assert_eq!(battery.update(5_000), Some(BatteryLevel::External));
+        assert_eq!(battery.percent(), 100);
+        assert_eq!(battery.update(4_150), Some(BatteryLevel::Normal));
+        assert_eq!(battery.percent(), 97);
+    }
+}
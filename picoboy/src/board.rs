@@ -0,0 +1,356 @@
+//! Hardware bring-up for the PicoBoy Color.
+//!
+//! [`Board::take`] configures clocks, the ST7789 display and its backlight, the
+//! buttons, the status LEDs, the speaker, the save data store, the voltage
+//! monitor, the power manager and the watchdog, so an application can start
+//! with its game loop right away. Core 1 stays idle until a task is spawned on
+//! it. With the `usb-serial` or `usb-gamepad` feature the USB device is set up
+//! as well.
+
+use cortex_m::delay::Delay;
+use embedded_hal::digital::InputPin;
+use st7789::ST7789;
+
+use picoboy_color as bsp;
+
+use crate::backlight::Backlight;
+use crate::crash_screen;
+use crate::dimmer::MAX_BRIGHTNESS;
+use crate::display::{DisplayConfig, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::dma_interface::{DmaInterface, TransferStatus, CHUNK_SIZE};
+use crate::game_loop::Clock as GameClock;
+use crate::input::{Button, Buttons, Controls};
+use crate::leds::Leds;
+use crate::multicore::Core1;
+use crate::panel::Panel;
+use crate::power::Power;
+use crate::rom_flash::RomFlash;
+use crate::sample_queue::SampleQueue;
+use crate::speaker::{AudioProducer, Speaker, AUDIO_QUEUE_LEN};
+use crate::storage::Store;
+use crate::supervisor::ResetReason;
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+use crate::usb::Usb;
+use crate::voltage_monitor::VoltageMonitor;
+use crate::watchdog::{self, Supervisor};
+
+use bsp::hal::{
+    adc::AdcPin,
+    clocks::{init_clocks_and_plls, Clock},
+    dma::DMAExt,
+    fugit::RateExtU32,
+    gpio::{
+        bank0::{Gpio0, Gpio1, Gpio16, Gpio18, Gpio19, Gpio2, Gpio27, Gpio28, Gpio3, Gpio4},
+        FunctionSioInput, FunctionSioOutput, FunctionSpi, Pin, PinId, PullDown, PullUp,
+    },
+    pac,
+    pwm::Slices,
+    sio::Sio,
+    spi::Enabled,
+    watchdog::Watchdog,
+    Adc, Spi, Timer,
+};
+
+/// SPI clock requested for the display, the HAL picks the closest possible rate
+pub(crate) const DISPLAY_SPI_FREQ_HZ: u32 = 125_000_000;
+
+pub(crate) type Output<I> = Pin<I, FunctionSioOutput, PullDown>;
+type Input<I> = Pin<I, FunctionSioInput, PullUp>;
+type SpiPin<I> = Pin<I, FunctionSpi, PullDown>;
+
+/// SPI0 as wired to the display (MOSI, MISO, SCK)
+pub type DisplaySpi = Spi<Enabled, pac::SPI0, (SpiPin<Gpio19>, SpiPin<Gpio16>, SpiPin<Gpio18>), 8>;
+
+/// Display interface sending data with DMA
+pub type DisplayInterface = DmaInterface;
+
+/// Initialised ST7789 display, drawing in the coordinates of its orientation
+pub type Display = Panel;
+
+/// Save data in the `SAVE` region of the flash
+pub type SaveStore = Store<RomFlash>;
+
+/// Errors which can occur while bringing up the board
+pub use crate::error::Error;
+
+/// Joystick and action buttons, all of them are active low
+pub struct ButtonPins {
+    up: Input<Gpio4>,
+    down: Input<Gpio2>,
+    left: Input<Gpio3>,
+    right: Input<Gpio1>,
+    center: Input<Gpio0>,
+    a: Input<Gpio28>,
+    b: Input<Gpio27>,
+}
+
+impl Controls for ButtonPins {
+    fn sample(&mut self) -> Buttons {
+        let mut buttons = Buttons::NONE;
+        buttons.set(Button::Up, is_pressed(&mut self.up));
+        buttons.set(Button::Down, is_pressed(&mut self.down));
+        buttons.set(Button::Left, is_pressed(&mut self.left));
+        buttons.set(Button::Right, is_pressed(&mut self.right));
+        buttons.set(Button::Center, is_pressed(&mut self.center));
+        buttons.set(Button::A, is_pressed(&mut self.a));
+        buttons.set(Button::B, is_pressed(&mut self.b));
+        buttons
+    }
+}
+
+#[cfg(feature = "async")]
+impl ButtonPins {
+    /// Enables interrupts on both edges of every button, see [`crate::button_edges`]
+    pub fn listen(&mut self) {
+        use bsp::hal::gpio::Interrupt::{EdgeHigh, EdgeLow};
+
+        for edge in [EdgeLow, EdgeHigh] {
+            self.up.set_interrupt_enabled(edge, true);
+            self.down.set_interrupt_enabled(edge, true);
+            self.left.set_interrupt_enabled(edge, true);
+            self.right.set_interrupt_enabled(edge, true);
+            self.center.set_interrupt_enabled(edge, true);
+            self.a.set_interrupt_enabled(edge, true);
+            self.b.set_interrupt_enabled(edge, true);
+        }
+    }
+}
+
+impl ButtonPins {
+    /// Lets a press of any button end dormant mode, see [`Power::dormant`]
+    pub fn set_dormant_wake(&mut self, enabled: bool) {
+        use bsp::hal::gpio::Interrupt::EdgeLow;
+
+        // Edges latched before would end dormant mode right away
+        if enabled {
+            self.up.clear_interrupt(EdgeLow);
+            self.down.clear_interrupt(EdgeLow);
+            self.left.clear_interrupt(EdgeLow);
+            self.right.clear_interrupt(EdgeLow);
+            self.center.clear_interrupt(EdgeLow);
+            self.a.clear_interrupt(EdgeLow);
+            self.b.clear_interrupt(EdgeLow);
+        }
+
+        self.up.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.down.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.left.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.right.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.center.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.a.set_dormant_wake_enabled(EdgeLow, enabled);
+        self.b.set_dormant_wake_enabled(EdgeLow, enabled);
+    }
+}
+
+impl GameClock for Timer {
+    fn now_us(&self) -> u64 {
+        self.get_counter().ticks()
+    }
+}
+
+fn is_pressed<I: PinId>(pin: &mut Input<I>) -> bool {
+    // GPIO reads on the RP2040 cannot fail
+    matches!(pin.is_low(), Ok(true))
+}
+
+/// Owned handles to the initialised PicoBoy Color hardware
+pub struct Board {
+    /// Display, upright and cleared to black
+    pub display: Display,
+    /// Completion flag of the display transfers
+    pub display_status: TransferStatus,
+    /// Joystick and button inputs with pull-ups enabled
+    pub buttons: ButtonPins,
+    /// Status LEDs, all switched off
+    pub leds: Leds,
+    /// Blocking delay based on the SysTick timer
+    pub delay: Delay,
+    /// Free-running microsecond timer, the clock of the game loop
+    pub timer: Timer,
+    /// Display backlight, at full brightness
+    pub backlight: Backlight,
+    /// Speaker, silent until started
+    pub speaker: Speaker,
+    /// Samples for the speaker
+    pub audio: AudioProducer,
+    /// Save data, kept across resets and firmware updates, `None` if the flash could not be mounted
+    pub storage: Option<SaveStore>,
+    /// Second core, idle until a task is spawned
+    pub core1: Core1,
+    /// Sleep between frames, slow clock and dormant mode
+    pub power: Power,
+    /// Supply voltage, to estimate the battery charge
+    pub voltage: VoltageMonitor,
+    /// USB device, waiting for the host until it is polled
+    #[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+    pub usb: Usb,
+    /// Watchdog, stopped until started with a timeout
+    pub watchdog: Supervisor,
+    /// Why the chip was reset before this start
+    pub reset_reason: ResetReason,
+}
+
+impl Board {
+    /// Takes the peripherals and brings up the board.
+    ///
+    /// Returns [`Error::AlreadyTaken`] if the peripherals were taken before.
+    /// A display or save data that fails is logged instead, the display is
+    /// initialised again by its next operation and [`Board::storage`] is `None`.
+    pub fn take() -> Result<Self, Error> {
+        let pac = pac::Peripherals::take().ok_or(Error::AlreadyTaken)?;
+        let core = pac::CorePeripherals::take().ok_or(Error::AlreadyTaken)?;
+
+        Self::new(pac, core)
+    }
+
+    /// Brings up the board from already taken peripherals.
+    pub fn new(mut pac: pac::Peripherals, mut core: pac::CorePeripherals) -> Result<Self, Error> {
+        let reset_reason = watchdog::take_reset_reason();
+        let mut watchdog = Watchdog::new(pac.WATCHDOG);
+        let sio = Sio::new(pac.SIO);
+
+        let clocks = init_clocks_and_plls(
+            bsp::XOSC_CRYSTAL_FREQ,
+            pac.XOSC,
+            pac.CLOCKS,
+            pac.PLL_SYS,
+            pac.PLL_USB,
+            &mut pac.RESETS,
+            &mut watchdog,
+        )
+        .map_err(|_| Error::Clocks)?;
+
+        crash_screen::record_system_clock(clocks.system_clock.freq().to_Hz());
+        let delay = Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+        let mut timer = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+        let pins = bsp::Pins::new(
+            pac.IO_BANK0,
+            pac.PADS_BANK0,
+            sio.gpio_bank0,
+            &mut pac.RESETS,
+        );
+
+        // Switch on backlight
+        let pwm = Slices::new(pac.PWM, &mut pac.RESETS);
+        let mut backlight_pwm = pwm.pwm5;
+        let backlight_pin = backlight_pwm.channel_a.output_to(pins.backlight);
+        let backlight = Backlight::new(backlight_pwm, backlight_pin, MAX_BRIGHTNESS);
+
+        // Configure SPI pins
+        let spi_sclk = pins.sck.into_function::<FunctionSpi>(); // SCK
+        let spi_mosi = pins.mosi.into_function::<FunctionSpi>(); // MOSI
+        let spi_miso = pins.gpio16.into_function::<FunctionSpi>(); // MISO (not needed)
+
+        // Create and init spi instance
+        let spi = Spi::<_, _, _, 8>::new(pac.SPI0, (spi_mosi, spi_miso, spi_sclk)).init(
+            &mut pac.RESETS,
+            clocks.peripheral_clock.freq(),
+            DISPLAY_SPI_FREQ_HZ.Hz(),
+            embedded_hal::spi::MODE_3, // ST7789 requires SPI mode 3
+        );
+
+        // Configure display pins
+        let dc = pins.dc.into_push_pull_output(); // Data/command pin
+        let rst = pins.reset.into_push_pull_output(); // Reset pin
+        let cs = pins.cs.into_push_pull_output(); // Chip select
+
+        // Create and init display
+        let dma = pac.DMA.split(&mut pac.RESETS);
+        let buffers = cortex_m::singleton!(: [[u8; CHUNK_SIZE]; 2] = [[0; CHUNK_SIZE]; 2])
+            .ok_or(Error::AlreadyTaken)?;
+        let di = DmaInterface::new(spi, dma.ch0, buffers, dc, cs);
+        let display_status = di.status();
+        let driver = ST7789::new(di, rst, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+
+        let display = Panel::new(driver, DisplayConfig::default(), timer);
+
+        // Configure LEDs, red shares its slice with the speaker
+        let leds = Leds::new(
+            pwm.pwm6,
+            pins.led_red.into_function(),
+            pins.led_yellow.into_function(),
+            pins.led_green.into_function(),
+        );
+
+        let buttons = ButtonPins {
+            up: pins.joystick_up.into_pull_up_input(),
+            down: pins.joystick_down.into_pull_up_input(),
+            left: pins.joystick_left.into_pull_up_input(),
+            right: pins.joystick_right.into_pull_up_input(),
+            center: pins.joystick_center.into_pull_up_input(),
+            a: pins.button_right.into_pull_up_input(),
+            b: pins.button_left.into_pull_up_input(),
+        };
+
+        // Configure speaker
+        let mut speaker_pwm = pwm.pwm7;
+        let speaker_pin = speaker_pwm.channel_b.output_to(pins.speaker);
+        let alarm = timer.alarm_0().ok_or(Error::AlreadyTaken)?;
+        let queue = cortex_m::singleton!(: SampleQueue<AUDIO_QUEUE_LEN> = SampleQueue::new())
+            .ok_or(Error::AlreadyTaken)?;
+        let (audio, samples) = queue.split();
+        let speaker = Speaker::new(speaker_pwm, speaker_pin, alarm, timer, samples);
+
+        // Mount save data, formatting the region on first use
+        let storage = match Store::mount(RomFlash::new()) {
+            Ok(storage) => Some(storage),
+            Err(error) => {
+                defmt::warn!("Save data not mounted: {}", Error::Storage(error));
+                None
+            }
+        };
+
+        let core1 = Core1::new(pac.PSM, pac.PPB, sio.fifo);
+
+        // Configure sleeping, the alarm ends waits between frames
+        let alarm = timer.alarm_2().ok_or(Error::AlreadyTaken)?;
+        let power = Power::new(timer, alarm, &mut core.SCB);
+
+        // Configure the ADC to measure the supply voltage
+        let adc = Adc::new(pac.ADC, &mut pac.RESETS);
+        // GPIO29 is one of the ADC inputs, so this cannot fail
+        let Ok(voltage_pin) = AdcPin::new(pins.voltage_monitor.into_floating_input()) else {
+            unreachable!()
+        };
+        let voltage = VoltageMonitor::new(adc, voltage_pin);
+
+        // Configure USB, the clock runs from PLL_USB
+        #[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+        let usb = {
+            use bsp::hal::usb::UsbBus;
+            use usb_device::bus::UsbBusAllocator;
+
+            let bus = UsbBus::new(
+                pac.USBCTRL_REGS,
+                pac.USBCTRL_DPRAM,
+                clocks.usb_clock,
+                true,
+                &mut pac.RESETS,
+            );
+            let bus = cortex_m::singleton!(: UsbBusAllocator<UsbBus> = UsbBusAllocator::new(bus))
+                .ok_or(Error::AlreadyTaken)?;
+            Usb::new(bus)
+        };
+
+        Ok(Self {
+            display,
+            display_status,
+            buttons,
+            leds,
+            delay,
+            timer,
+            backlight,
+            speaker,
+            audio,
+            storage,
+            core1,
+            power,
+            voltage,
+            #[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+            usb,
+            watchdog: Supervisor::new(watchdog),
+            reset_reason,
+        })
+    }
+}
@@ -0,0 +1,111 @@
+//! Rebooting into the USB boot loader of the ROM and starting apps in flash slots.
+//!
+//! [`reboot_to_bootsel`] does what holding BOOTSEL while plugging in does:
+//! the PicoBoy shows up as a USB drive and takes a UF2 file.
+//!
+//! The launcher starts an app with [`request_launch`], which stores the slot
+//! in watchdog scratch registers and resets the chip. Right after the reset
+//! the launcher finds the request with [`take_launch_request`] and jumps into
+//! the app with [`launch`], before it touches any peripheral. This way every
+//! app starts from a freshly reset chip, as if it had been flashed alone.
+//! The boot ROM uses scratch registers 4 to 7, the launcher uses 0 and 1.
+//! Scratch registers 2 and 3 tell the firmware after the reset who reset the
+//! chip, see [`watchdog`](crate::watchdog).
+
+use picoboy_color::hal::{pac, rom_data};
+
+use crate::slots::{self, AppHeader, SlotError, SLOT_COUNT, SLOT_PREFIX_LEN};
+use crate::supervisor::{ResetReason, SoftwareReset};
+use crate::watchdog;
+
+/// Marks a launch request in scratch register 0
+const LAUNCH_MAGIC: u32 = 0x5042_4c41;
+
+/// Reboots into the USB mass storage and PICOBOOT boot loader of the ROM
+pub fn reboot_to_bootsel() -> ! {
+    // Kept until the boot loader starts the new firmware
+    watchdog::record(ResetReason::Software(SoftwareReset::Bootsel));
+    // No activity LED, both interfaces enabled
+    rom_data::reset_to_usb_boot(0, 0);
+
+    // The ROM resets the chip and does not come back
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Returns the header of the app in `slot`, read through the memory mapped flash
+pub fn app(slot: usize) -> Result<AppHeader, SlotError> {
+    if slot >= SLOT_COUNT {
+        return Err(SlotError::NoSlot);
+    }
+
+    // SAFETY: the whole flash is mapped and nothing writes to the slots while the firmware runs
+    let bytes = unsafe {
+        core::slice::from_raw_parts(slots::slot_address(slot) as *const u8, SLOT_PREFIX_LEN)
+    };
+    slots::parse(slot, bytes)
+}
+
+/// Resets the chip to start the app in `slot` through the launcher
+pub fn request_launch(slot: usize) -> ! {
+    // SAFETY: the registers are only written here, right before the reset
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+
+    watchdog
+        .scratch0()
+        .write(|w| unsafe { w.bits(LAUNCH_MAGIC) });
+    watchdog
+        .scratch1()
+        .write(|w| unsafe { w.bits(slot as u32) });
+    reset(SoftwareReset::Launch)
+}
+
+/// Resets the whole chip, a firmware started by the launcher returns to its menu.
+///
+/// The firmware finds `reason` with
+/// [`take_reset_reason`](crate::watchdog::take_reset_reason) after the reset.
+pub fn reset(reason: SoftwareReset) -> ! {
+    watchdog::record(ResetReason::Software(reason));
+
+    // SAFETY: nothing runs after the reset is triggered
+    let (watchdog, psm) = unsafe { (&*pac::WATCHDOG::ptr(), &*pac::PSM::ptr()) };
+
+    // Reset everything but the oscillators, like the SDK's watchdog_reboot
+    psm.wdsel().write(|w| unsafe { w.bits(0x0001_FFFF) });
+    psm.wdsel()
+        .modify(|_, w| w.rosc().clear_bit().xosc().clear_bit());
+    watchdog.ctrl().write(|w| w.trigger().set_bit());
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Returns the slot requested by [`request_launch`] before the last reset, once
+pub fn take_launch_request() -> Option<usize> {
+    // SAFETY: reads and clears two scratch registers nothing else uses
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+
+    let requested = watchdog.scratch0().read().bits() == LAUNCH_MAGIC;
+    let slot = watchdog.scratch1().read().bits() as usize;
+    // A reset in the app leads back to the launcher
+    watchdog.scratch0().write(|w| unsafe { w.bits(0) });
+
+    requested.then_some(slot)
+}
+
+/// Starts the app in `slot`, if it passes the checks of [`app`]
+///
+/// # Safety
+///
+/// Must be called right after a reset, before any peripheral is configured
+/// or an interrupt is enabled, since the app expects a freshly reset chip.
+pub unsafe fn launch(slot: usize) -> Result<core::convert::Infallible, SlotError> {
+    app(slot)?;
+
+    // Interrupts stay enabled in PRIMASK, the app expects them to be after a reset
+    let vector_table = slots::vector_table_address(slot);
+    (*cortex_m::peripheral::SCB::PTR).vtor.write(vector_table);
+    cortex_m::asm::bootload(vector_table as *const u32)
+}
@@ -0,0 +1,46 @@
+//! Button changes as futures.
+//!
+//! [`ButtonPins::listen`](crate::board::ButtonPins::listen) enables interrupts
+//! on both edges of every button. The `IO_IRQ_BANK0` interrupt, which the
+//! application forwards to [`on_gpio_interrupt`], acknowledges them and wakes
+//! the [`Executor`](crate::executor::Executor), so [`changed`] completes
+//! without polling the pins in between.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use picoboy_color::hal::pac;
+
+use crate::executor::wait_for;
+
+/// Number of GPIOs sharing one interrupt register
+const PINS_PER_REGISTER: usize = 8;
+
+// Set by the interrupt, cleared by `changed`
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Handles the edges, call this from `IO_IRQ_BANK0`
+pub fn on_gpio_interrupt() {
+    // SAFETY: only the edge flags of core 0 are read and acknowledged
+    let io = unsafe { &*pac::IO_BANK0::ptr() };
+    for register in 0..30usize.div_ceil(PINS_PER_REGISTER) {
+        let pending = io.proc0_ints(register).read().bits();
+        if pending != 0 {
+            // SAFETY: the edge flags are cleared by writing ones, level flags ignore writes
+            io.intr(register).write(|w| unsafe { w.bits(pending) });
+            CHANGED.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Waits until a button is pressed or released
+pub async fn changed() {
+    wait_for(|| {
+        // The Cortex-M0+ has no atomic swap, keep the interrupt from setting the flag in between
+        cortex_m::interrupt::free(|_| {
+            let changed = CHANGED.load(Ordering::Acquire);
+            CHANGED.store(false, Ordering::Release);
+            changed.then_some(())
+        })
+    })
+    .await
+}
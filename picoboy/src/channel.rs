@@ -0,0 +1,213 @@
+//! Lock-free channel between two cores or a core and an interrupt.
+//!
+//! Messages of any type move from one [`Sender`] to one [`Receiver`] through
+//! a ring buffer. Like the [`SampleQueue`](crate::sample_queue::SampleQueue),
+//! both ends only load and store atomics, which the Cortex-M0+ supports.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Ring buffer holding up to `N - 1` messages, one slot always stays empty
+pub struct Channel<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // Index of the next message to receive, only written by the receiver
+    read: AtomicUsize,
+    // Index of the next message to send, only written by the sender
+    write: AtomicUsize,
+}
+
+// SAFETY: a message is only accessed by the sender before and by the receiver
+// after it was handed over through the indices, so sending `T` is enough.
+unsafe impl<T: Send, const N: usize> Sync for Channel<T, N> {}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Creates an empty channel, `N` has to be at least 2
+    pub const fn new() -> Self {
+        assert!(N >= 2);
+
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the channel into its two ends
+    pub fn split(&mut self) -> (Sender<'_, T, N>, Receiver<'_, T, N>) {
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+
+    fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+        (write + N - read) % N
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Channel<T, N> {
+    fn drop(&mut self) {
+        let (_, mut receiver) = self.split();
+        while receiver.recv().is_some() {}
+    }
+}
+
+/// Sending end of a [`Channel`]
+pub struct Sender<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+}
+
+impl<T, const N: usize> Sender<'_, T, N> {
+    /// Sends a message, giving it back if the channel is full
+    pub fn send(&mut self, message: T) -> Result<(), T> {
+        let write = self.channel.write.load(Ordering::Relaxed);
+        let read = self.channel.read.load(Ordering::Acquire);
+        let next = (write + 1) % N;
+        if next == read {
+            return Err(message);
+        }
+
+        // SAFETY: the receiver does not touch this slot until the index moves on
+        unsafe { (*self.channel.slots[write].get()).write(message) };
+        self.channel.write.store(next, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Returns `true` if no more messages fit
+    pub fn is_full(&self) -> bool {
+        self.channel.len() == N - 1
+    }
+}
+
+/// Receiving end of a [`Channel`]
+pub struct Receiver<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+}
+
+impl<T, const N: usize> Receiver<'_, T, N> {
+    /// Takes the oldest message, `None` if there is none
+    pub fn recv(&mut self) -> Option<T> {
+        let read = self.channel.read.load(Ordering::Relaxed);
+        let write = self.channel.write.load(Ordering::Acquire);
+        if read == write {
+            return None;
+        }
+
+        // SAFETY: the sender initialised the slot before publishing it and does not reuse it
+        // before the index moves on
+        let message = unsafe { (*self.channel.slots[read].get()).assume_init_read() };
+        self.channel.read.store((read + 1) % N, Ordering::Release);
+
+        Some(message)
+    }
+
+    /// Returns the number of waiting messages
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns `true` if no messages are waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// Counts how often it was dropped
+    struct Counted<'a>(&'a Cell<usize>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn holds_one_message_less_than_slots() {
+        let mut channel = Channel::<u8, 4>::new();
+        let (mut sender, mut receiver) = channel.split();
+        assert!(receiver.is_empty());
+        assert_eq!(receiver.recv(), None);
+
+        for message in 1..=3 {
+            assert!(!sender.is_full());
+            assert_eq!(sender.send(message), Ok(()));
+        }
+        assert!(sender.is_full());
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(sender.send(4), Err(4));
+
+        assert_eq!(receiver.recv(), Some(1));
+        assert!(!sender.is_full());
+        assert_eq!(sender.send(4), Ok(()));
+        assert_eq!(receiver.len(), 3);
+    }
+
+    #[test]
+    fn keeps_order_across_the_wrap() {
+        let mut channel = Channel::<u32, 3>::new();
+        let (mut sender, mut receiver) = channel.split();
+
+        // Each round moves the indices by two, so they wrap at every slot
+        for round in 0..10 {
+            assert_eq!(sender.send(2 * round), Ok(()));
+            assert_eq!(sender.send(2 * round + 1), Ok(()));
+            assert!(sender.is_full());
+            assert_eq!(receiver.len(), 2);
+            assert_eq!(receiver.recv(), Some(2 * round));
+            assert_eq!(receiver.recv(), Some(2 * round + 1));
+            assert!(receiver.is_empty());
+        }
+
+        // A message at a time wraps as well
+        for message in 0..7 {
+            assert_eq!(sender.send(message), Ok(()));
+            assert_eq!(receiver.recv(), Some(message));
+        }
+    }
+
+    #[test]
+    fn drops_pending_messages() {
+        let drops = Cell::new(0);
+        {
+            let mut channel = Channel::<Counted<'_>, 4>::new();
+            let (mut sender, mut receiver) = channel.split();
+            for _ in 0..3 {
+                assert!(sender.send(Counted(&drops)).is_ok());
+            }
+            // A message that does not fit comes back and is dropped by the caller
+            assert!(sender.send(Counted(&drops)).is_err());
+            assert_eq!(drops.get(), 1);
+
+            drop(receiver.recv());
+            assert_eq!(drops.get(), 2);
+        }
+        assert_eq!(drops.get(), 4);
+
+        // Taken slots are not dropped a second time, also after a wrap
+        let drops = Cell::new(0);
+        {
+            let mut channel = Channel::<Counted<'_>, 2>::new();
+            let (mut sender, mut receiver) = channel.split();
+            for _ in 0..3 {
+                assert!(sender.send(Counted(&drops)).is_ok());
+                drop(receiver.recv());
+            }
+            assert!(sender.send(Counted(&drops)).is_ok());
+            assert_eq!(drops.get(), 3);
+        }
+        assert_eq!(drops.get(), 4);
+    }
+}
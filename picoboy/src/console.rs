@@ -0,0 +1,345 @@
+//! Text commands of the serial console.
+//!
+//! A [`LineBuffer`] collects the characters typed in a terminal and parses
+//! each finished line into a [`Command`]. Executing the commands is left to
+//! the application, which knows what to report and what to change.
+//!
+//! ```text
+//! > status
+//! > brightness 80
+//! > screenshot 0 0 120 140
+//! ```
+
+use core::fmt;
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::dimmer::MAX_BRIGHTNESS;
+
+/// Longest line in bytes, longer ones are rejected as a whole
+pub const MAX_LINE_LEN: usize = 64;
+
+/// Commands and their arguments, shown by `help`
+pub const HELP: &str = "\
+help                   list the commands
+status                 show battery, backlight and power mode
+brightness [0-100]     show or set the brightness of the backlight in percent
+screenshot [x y w h]   send the screen or a region of it
+log [on|off]           show or set whether statistics are logged here
+";
+
+/// A command typed into the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Lists the commands
+    Help,
+    /// Shows the state of the device
+    Status,
+    /// Shows the brightness of the backlight or sets it, in percent
+    Brightness(Option<u8>),
+    /// Sends the whole screen or a region, see [`crate::screenshot`]
+    Screenshot(Option<Rectangle>),
+    /// Shows whether statistics are logged to the console or turns them on or off
+    Log(Option<bool>),
+}
+
+/// Reasons a line is not a valid command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// The line holds nothing but whitespace
+    Empty,
+    /// The line is longer than [`MAX_LINE_LEN`] bytes or not valid UTF-8
+    Invalid,
+    /// The first word is no known command
+    UnknownCommand,
+    /// An argument is missing
+    MissingArgument,
+    /// An argument is out of range or not a number
+    InvalidArgument,
+    /// There are more arguments than the command takes
+    TooManyArguments,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::Empty => "empty line",
+            ParseError::Invalid => "line too long or not UTF-8",
+            ParseError::UnknownCommand => "unknown command, try `help`",
+            ParseError::MissingArgument => "missing argument",
+            ParseError::InvalidArgument => "invalid argument",
+            ParseError::TooManyArguments => "too many arguments",
+        })
+    }
+}
+
+impl Command {
+    /// Parses a line without its line ending
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or(ParseError::Empty)?;
+
+        let command = match name {
+            "help" | "?" => Command::Help,
+            "status" => Command::Status,
+            "brightness" => Command::Brightness(match words.next().map(number).transpose()? {
+                Some(percent) if percent > MAX_BRIGHTNESS => {
+                    return Err(ParseError::InvalidArgument)
+                }
+                percent => percent,
+            }),
+            "screenshot" => match words.next() {
+                Some(x) => {
+                    let mut next = || words.next().ok_or(ParseError::MissingArgument);
+                    let (y, width, height) = (next()?, next()?, next()?);
+                    Command::Screenshot(Some(Rectangle::new(
+                        Point::new(number::<u16>(x)?.into(), number::<u16>(y)?.into()),
+                        Size::new(number::<u16>(width)?.into(), number::<u16>(height)?.into()),
+                    )))
+                }
+                None => Command::Screenshot(None),
+            },
+            "log" => Command::Log(match words.next() {
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                Some(_) => return Err(ParseError::InvalidArgument),
+                None => None,
+            }),
+            _ => return Err(ParseError::UnknownCommand),
+        };
+
+        if words.next().is_some() {
+            return Err(ParseError::TooManyArguments);
+        }
+
+        Ok(command)
+    }
+}
+
+fn number<T: core::str::FromStr>(word: &str) -> Result<T, ParseError> {
+    word.parse().map_err(|_| ParseError::InvalidArgument)
+}
+
+/// Collects typed characters until the end of a line.
+///
+/// Lines end with a carriage return, a line feed or both, as sent by
+/// different terminals. Backspace and delete remove the last character.
+#[derive(Debug, Clone)]
+pub struct LineBuffer {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+    overflow: bool,
+    // A line feed right after a carriage return ends no second line
+    after_return: bool,
+}
+
+impl LineBuffer {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_LINE_LEN],
+            len: 0,
+            overflow: false,
+            after_return: false,
+        }
+    }
+
+    /// Adds a received byte, returns the parsed command at the end of a line
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command, ParseError>> {
+        let after_return = core::mem::replace(&mut self.after_return, byte == b'\r');
+
+        match byte {
+            b'\n' if after_return => None,
+            b'\r' | b'\n' => Some(self.finish()),
+            // Backspace and delete
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len == MAX_LINE_LEN => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns the characters of the unfinished line
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Drops the unfinished line
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+
+    fn finish(&mut self) -> Result<Command, ParseError> {
+        let result = match core::str::from_utf8(self.as_bytes()) {
+            Ok(_) if self.overflow => Err(ParseError::Invalid),
+            Ok(line) => Command::parse(line),
+            Err(_) => Err(ParseError::Invalid),
+        };
+        self.clear();
+        result
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes `text` and returns the result of the last finished line
+    fn type_in(buffer: &mut LineBuffer, text: &[u8]) -> Option<Result<Command, ParseError>> {
+        text.iter()
+            .fold(None, |last, &byte| buffer.push(byte).or(last))
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("help"), Ok(Command::Help));
+        assert_eq!(Command::parse("?"), Ok(Command::Help));
+        assert_eq!(Command::parse("  status  "), Ok(Command::Status));
+        assert_eq!(Command::parse("brightness"), Ok(Command::Brightness(None)));
+        assert_eq!(
+            Command::parse("brightness\t80"),
+            Ok(Command::Brightness(Some(80)))
+        );
+        assert_eq!(Command::parse("screenshot"), Ok(Command::Screenshot(None)));
+        assert_eq!(
+            Command::parse("screenshot 0 10 120 140"),
+            Ok(Command::Screenshot(Some(Rectangle::new(
+                Point::new(0, 10),
+                Size::new(120, 140)
+            ))))
+        );
+        assert_eq!(Command::parse("log"), Ok(Command::Log(None)));
+        assert_eq!(Command::parse("log on"), Ok(Command::Log(Some(true))));
+        assert_eq!(Command::parse("log off"), Ok(Command::Log(Some(false))));
+    }
+
+    #[test]
+    fn takes_brightness_in_percent() {
+        assert_eq!(
+            Command::parse("brightness 0"),
+            Ok(Command::Brightness(Some(0)))
+        );
+        assert_eq!(
+            Command::parse("brightness 100"),
+            Ok(Command::Brightness(Some(100)))
+        );
+        for argument in ["101", "128", "256", "-1", "half"] {
+            assert_eq!(
+                Command::parse(&["brightness ", argument].concat()),
+                Err(ParseError::InvalidArgument),
+                "{argument}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert_eq!(Command::parse(""), Err(ParseError::Empty));
+        assert_eq!(Command::parse(" \t "), Err(ParseError::Empty));
+        assert_eq!(Command::parse("reboot"), Err(ParseError::UnknownCommand));
+        assert_eq!(Command::parse("Status"), Err(ParseError::UnknownCommand));
+        assert_eq!(
+            Command::parse("status now"),
+            Err(ParseError::TooManyArguments)
+        );
+        assert_eq!(
+            Command::parse("log on off"),
+            Err(ParseError::TooManyArguments)
+        );
+        assert_eq!(Command::parse("log yes"), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            Command::parse("screenshot 0 0 120"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(
+            Command::parse("screenshot 0 0 120 70000"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            Command::parse("screenshot 0 0 1 1 1"),
+            Err(ParseError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        for line in HELP.lines() {
+            let name = line.split_whitespace().next().unwrap();
+            assert_ne!(Command::parse(name), Err(ParseError::UnknownCommand));
+        }
+    }
+
+    #[test]
+    fn ends_lines_with_any_line_ending() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(type_in(&mut buffer, b"status\r"), Some(Ok(Command::Status)));
+        assert_eq!(type_in(&mut buffer, b"help\n"), Some(Ok(Command::Help)));
+
+        // The line feed of a CR LF ends no second, empty line
+        assert_eq!(buffer.push(b'l'), None);
+        assert_eq!(type_in(&mut buffer, b"og\r"), Some(Ok(Command::Log(None))));
+        assert_eq!(buffer.push(b'\n'), None);
+
+        // Empty lines are reported, so the prompt can be shown again
+        assert_eq!(buffer.push(b'\n'), Some(Err(ParseError::Empty)));
+        assert_eq!(type_in(&mut buffer, b"\r\r"), Some(Err(ParseError::Empty)));
+    }
+
+    #[test]
+    fn edits_with_backspace() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(type_in(&mut buffer, b"statux"), None);
+        buffer.push(0x08);
+        assert_eq!(buffer.as_bytes(), b"statu");
+        assert_eq!(type_in(&mut buffer, b"s\r"), Some(Ok(Command::Status)));
+
+        // Deleting beyond the start does nothing
+        type_in(&mut buffer, b"a\x7f\x7f\x7f");
+        assert_eq!(buffer.as_bytes(), b"");
+        assert_eq!(type_in(&mut buffer, b"help\r"), Some(Ok(Command::Help)));
+    }
+
+    #[test]
+    fn rejects_long_lines_as_a_whole() {
+        let mut buffer = LineBuffer::new();
+        type_in(&mut buffer, b"help");
+        for _ in 0..MAX_LINE_LEN {
+            assert_eq!(buffer.push(b' '), None);
+        }
+        assert_eq!(buffer.as_bytes().len(), MAX_LINE_LEN);
+        assert_eq!(buffer.push(b'\r'), Some(Err(ParseError::Invalid)));
+
+        // The next line starts over
+        assert_eq!(type_in(&mut buffer, b"help\r"), Some(Ok(Command::Help)));
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(
+            type_in(&mut buffer, b"log \xff\r"),
+            Some(Err(ParseError::Invalid))
+        );
+
+        type_in(&mut buffer, b"status");
+        buffer.clear();
+        assert_eq!(type_in(&mut buffer, b"help\r"), Some(Ok(Command::Help)));
+    }
+}
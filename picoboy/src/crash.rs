@@ -0,0 +1,268 @@
+//! Crash screen shown by the panic and `HardFault` handlers.
+//!
+//! A [`CrashScreen`] draws what went wrong: the panic message with its
+//! location, or the registers the core pushed when the fault hit. Drawing
+//! needs no framebuffer, so it works on the display driver directly, see
+//! [`crash_screen`](crate::crash_screen) for the handlers on the device.
+
+use core::fmt::{self, Write};
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::text::{fonts, FontStyle, TextBox, COLOR_MARK};
+
+/// Longest panic message kept in bytes, longer ones are cut off
+pub const MAX_MESSAGE_LEN: usize = 192;
+
+/// Appended to a message that was cut off
+const ELLIPSIS: char = '…';
+
+/// Space between the edges of the screen and the text
+const MARGIN: i32 = 8;
+
+/// Background of the whole screen
+const BACKGROUND: Rgb565 = Rgb565::CSS_DARK_RED;
+
+/// Registers the core pushes onto the stack when an exception is taken
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Registers {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+impl Registers {
+    /// Returns the names and values in the order they are shown
+    pub fn named(&self) -> [(&'static str, u32); 8] {
+        [
+            ("r0", self.r0),
+            ("r1", self.r1),
+            ("r2", self.r2),
+            ("r3", self.r3),
+            ("r12", self.r12),
+            ("lr", self.lr),
+            ("pc", self.pc),
+            ("xpsr", self.xpsr),
+        ]
+    }
+}
+
+/// What brought the firmware down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause<'a> {
+    /// A panic with its message, file and line
+    Panic {
+        message: &'a str,
+        location: Option<(&'a str, u32)>,
+    },
+    /// A fault the core could not handle, e.g. an invalid memory access
+    HardFault(Registers),
+}
+
+/// Panic message formatted into a fixed buffer.
+///
+/// Text beyond [`MAX_MESSAGE_LEN`] bytes is replaced by an ellipsis, color
+/// marks are doubled so [`TextBox`] shows them as they are.
+#[derive(Debug, Clone)]
+pub struct Message {
+    bytes: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+    truncated: bool,
+}
+
+impl Message {
+    /// Creates an empty message
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_MESSAGE_LEN],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Returns the message, ending in an ellipsis if it was cut off
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are copied in
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    /// Appends `c` if it fits with `reserved` bytes left over
+    fn push(&mut self, c: char, reserved: usize) -> bool {
+        let mut encoded = [0; 4];
+        let encoded = c.encode_utf8(&mut encoded).as_bytes();
+        if self.len + encoded.len() + reserved > MAX_MESSAGE_LEN {
+            return false;
+        }
+        self.bytes[self.len..self.len + encoded.len()].copy_from_slice(encoded);
+        self.len += encoded.len();
+        true
+    }
+}
+
+impl fmt::Write for Message {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        // Room for the ellipsis has to stay
+        let reserved = ELLIPSIS.len_utf8();
+        for c in text.chars() {
+            let fits = match c {
+                // Both marks or none, a single one would start a color
+                COLOR_MARK => {
+                    self.len + 2 + reserved <= MAX_MESSAGE_LEN && {
+                        self.push(COLOR_MARK, reserved);
+                        self.push(COLOR_MARK, reserved)
+                    }
+                }
+                c => self.push(c, reserved),
+            };
+            if !fits {
+                self.truncated = true;
+                self.push(ELLIPSIS, 0);
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Full screen report of a crash, in portrait orientation
+#[derive(Debug, Clone, Copy)]
+pub struct CrashScreen<'a> {
+    cause: Cause<'a>,
+    core: u8,
+    reset_after_s: Option<u32>,
+}
+
+impl<'a> CrashScreen<'a> {
+    /// Creates the report of a crash on `core`
+    pub fn new(cause: Cause<'a>, core: u8) -> Self {
+        Self {
+            cause,
+            core,
+            reset_after_s: None,
+        }
+    }
+
+    /// Announces that the firmware restarts after `seconds`
+    pub fn with_reset_after(mut self, seconds: u32) -> Self {
+        self.reset_after_s = Some(seconds);
+        self
+    }
+}
+
+impl Drawable for CrashScreen<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let area = target.bounding_box();
+        let width = area.size.width.saturating_sub(2 * MARGIN as u32);
+        area.into_styled(PrimitiveStyle::with_fill(BACKGROUND))
+            .draw(target)?;
+
+        let normal = FontStyle::new(&fonts::NORMAL, Rgb565::WHITE);
+        let line_height = fonts::NORMAL.line_height() as i32;
+        let mut y = MARGIN;
+
+        let title = match self.cause {
+            Cause::Panic { .. } => "Panic",
+            Cause::HardFault(_) => "HardFault",
+        };
+        Text::with_baseline(
+            title,
+            Point::new(MARGIN, y),
+            FontStyle::new(&fonts::LARGE, Rgb565::WHITE),
+            Baseline::Top,
+        )
+        .draw(target)?;
+        y += fonts::LARGE.line_height() as i32;
+
+        let mut core = Message::new();
+        let _ = write!(core, "on core {}", self.core);
+        Text::with_baseline(core.as_str(), Point::new(MARGIN, y), normal, Baseline::Top)
+            .draw(target)?;
+        y += 2 * line_height;
+
+        match self.cause {
+            Cause::Panic { message, location } => {
+                if let Some((file, line)) = location {
+                    let mut text = Message::new();
+                    let _ = write!(text, "{file}:{line}");
+                    let location = TextBox::new(
+                        text.as_str(),
+                        Rectangle::new(Point::new(MARGIN, y), Size::new(width, 1000)),
+                        FontStyle::new(&fonts::NORMAL, Rgb565::CSS_LIGHT_GRAY),
+                    );
+                    let height = location.text_height();
+                    location.draw(target)?;
+                    y += height as i32 + line_height / 2;
+                }
+
+                // Leaves room for the last line at the bottom
+                let bottom = area.size.height as i32 - MARGIN - line_height;
+                TextBox::new(
+                    message,
+                    Rectangle::with_corners(
+                        Point::new(MARGIN, y),
+                        Point::new(MARGIN + width as i32 - 1, bottom - 1),
+                    ),
+                    normal,
+                )
+                .draw(target)?;
+            }
+            Cause::HardFault(registers) => {
+                let column_width = width as i32 / 2;
+                for (i, (name, value)) in registers.named().into_iter().enumerate() {
+                    let mut text = Message::new();
+                    let _ = write!(text, "{name:<4} {value:08x}");
+                    let position = Point::new(
+                        MARGIN + (i as i32 % 2) * column_width,
+                        y + (i as i32 / 2) * line_height,
+                    );
+                    Text::with_baseline(text.as_str(), position, normal, Baseline::Top)
+                        .draw(target)?;
+                }
+            }
+        }
+
+        let mut footer = Message::new();
+        let _ = match self.reset_after_s {
+            Some(seconds) => {
+                write!(footer, "Restarting in {seconds} s")
+            }
+            None => footer.write_str("Switch off and on to restart"),
+        };
+        Text::with_baseline(
+            footer.as_str(),
+            Point::new(MARGIN, area.size.height as i32 - MARGIN),
+            FontStyle::new(&fonts::NORMAL, Rgb565::CSS_LIGHT_GRAY),
+            Baseline::Bottom,
+        )
+        .draw(target)?;
+
+        Ok(())
+    }
+}
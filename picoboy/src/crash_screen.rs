@@ -0,0 +1,247 @@
+//! Panic and `HardFault` handlers showing a [`CrashScreen`] on the display.
+//!
+//! The firmware may crash anywhere, with a display transfer half done or
+//! core 1 in the middle of a flush. The handlers therefore take the hardware
+//! over: interrupts stay off, core 1 is stopped, the display DMA is aborted,
+//! and SPI0 and the ST7789 are set up again for blocking transfers. Before
+//! that, everything goes out through defmt, so an attached probe still shows
+//! the crash. A running watchdog is stopped. Without configured clocks, e.g.
+//! for a crash before [`Board::take`](crate::board::Board::take), only the red
+//! LED blinks.
+//!
+//! The screen appears in the orientation the display was last set to, and
+//! SPI and delays run from the system clock the board recorded at start-up.
+//!
+//! ```ignore
+//! #[panic_handler]
+//! fn panic(info: &core::panic::PanicInfo) -> ! {
+//!     crash_screen::panic(info, Some(10))
+//! }
+//!
+//! #[exception]
+//! unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+//!     crash_screen::hard_fault(frame, Some(10))
+//! }
+//! ```
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use cortex_m::delay::Delay;
+use cortex_m_rt::ExceptionFrame;
+use display_interface_spi::SPIInterface;
+use embedded_graphics::{draw_target::DrawTargetExt, prelude::*};
+use embedded_hal::digital::OutputPin;
+use st7789::ST7789;
+
+use picoboy_color as bsp;
+
+use bsp::hal::{fugit::RateExtU32, gpio::FunctionSpi, pac, sio::Sio, Spi};
+
+use crate::board::DISPLAY_SPI_FREQ_HZ;
+use crate::boot;
+use crate::crash::{Cause, CrashScreen, Message, Registers};
+use crate::display::{DisplayConfig, Orientation};
+use crate::led_pattern::Pattern;
+use crate::leds;
+use crate::supervisor::SoftwareReset;
+use crate::watchdog;
+
+/// Orientations in the order of their discriminants, see [`record_display`]
+const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::Portrait,
+    Orientation::Landscape,
+    Orientation::PortraitSwapped,
+    Orientation::LandscapeSwapped,
+];
+
+// Orientation of the display, recorded by the panel so the crash screen matches the firmware
+static ORIENTATION: AtomicU8 = AtomicU8::new(Orientation::PortraitSwapped as u8);
+
+// Full speed of the system clock, recorded by `Board::take`, 0 before
+static SYSTEM_CLOCK_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// Shown on the red LED when there is no display to show the crash on
+const NO_SCREEN: Pattern = Pattern::SOS;
+
+// Set by the first crash, a second one while drawing only blinks the LED
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// Records the configuration the display was set to, the crash screen draws in the same
+pub(crate) fn record_display(config: DisplayConfig) {
+    ORIENTATION.store(config.orientation as u8, Ordering::Relaxed);
+}
+
+/// Records the frequency of the system clock set up by the board, before any slow down
+pub(crate) fn record_system_clock(hz: u32) {
+    SYSTEM_CLOCK_HZ.store(hz, Ordering::Relaxed);
+}
+
+/// Returns the frequency recorded by [`record_system_clock`], `None` before the board is up
+pub(crate) fn system_clock_hz() -> Option<u32> {
+    match SYSTEM_CLOCK_HZ.load(Ordering::Relaxed) {
+        0 => None,
+        hz => Some(hz),
+    }
+}
+
+fn display() -> DisplayConfig {
+    let orientation = ORIENTATIONS
+        .get(usize::from(ORIENTATION.load(Ordering::Relaxed)))
+        .copied()
+        .unwrap_or_default();
+    DisplayConfig::new(orientation)
+}
+
+/// Logs a panic, shows it on the display and resets after `reset_after_s`, if given
+pub fn panic(info: &PanicInfo, reset_after_s: Option<u32>) -> ! {
+    cortex_m::interrupt::disable();
+    defmt::error!("{}", defmt::Display2Format(info));
+
+    let mut message = Message::new();
+    let _ = write!(message, "{}", info.message());
+    let location = info
+        .location()
+        .map(|location| (location.file(), location.line()));
+
+    show(
+        Cause::Panic {
+            message: message.as_str(),
+            location,
+        },
+        reset_after_s,
+    )
+}
+
+/// Logs a hard fault, shows it on the display and resets after `reset_after_s`, if given
+pub fn hard_fault(frame: &ExceptionFrame, reset_after_s: Option<u32>) -> ! {
+    let registers = Registers {
+        r0: frame.r0(),
+        r1: frame.r1(),
+        r2: frame.r2(),
+        r3: frame.r3(),
+        r12: frame.r12(),
+        lr: frame.lr(),
+        pc: frame.pc(),
+        xpsr: frame.xpsr(),
+    };
+    defmt::error!("HardFault at {=u32:#010x}: {}", registers.pc, registers);
+
+    show(Cause::HardFault(registers), reset_after_s)
+}
+
+fn show(cause: Cause, reset_after_s: Option<u32>) -> ! {
+    // Interrupts are off, only the other core could come in between
+    if CRASHED.load(Ordering::Relaxed) {
+        leds::show_fault(NO_SCREEN);
+    }
+    CRASHED.store(true, Ordering::Relaxed);
+
+    // The screen stays as long as configured, not as long as the watchdog allows
+    watchdog::disable();
+
+    let mut screen = CrashScreen::new(cause, core_number() as u8);
+    if let Some(seconds) = reset_after_s {
+        screen = screen.with_reset_after(seconds);
+    }
+
+    if !draw(&screen) {
+        defmt::warn!("The crash screen cannot be shown");
+        leds::show_fault(NO_SCREEN);
+    }
+
+    let Some(seconds) = reset_after_s else {
+        loop {
+            cortex_m::asm::wfi();
+        }
+    };
+    // Drawing brought the clock back to full speed
+    let system_clock_hz = system_clock_hz().unwrap_or_default();
+    for _ in 0..seconds {
+        cortex_m::asm::delay(system_clock_hz);
+    }
+    boot::reset(SoftwareReset::Crash)
+}
+
+/// Sets up the display from scratch and draws `screen`, returns `false` if that is not possible
+fn draw(screen: &CrashScreen) -> bool {
+    // SAFETY: the application has stopped, the handler takes the hardware over
+    let (mut pac, core) = unsafe { (pac::Peripherals::steal(), pac::CorePeripherals::steal()) };
+
+    // Without clocks neither SPI nor the delay would work
+    let Some(system_clock_hz) = system_clock_hz() else {
+        return false;
+    };
+    if pac.CLOCKS.clk_peri_ctrl().read().enable().bit_is_clear() {
+        return false;
+    }
+    // Full speed, in case the power manager slowed the clock down
+    pac.CLOCKS
+        .clk_sys_div()
+        .write(|w| unsafe { w.int().bits(1) });
+
+    // Core 1 would go on flushing frames, core 0 only waits for it
+    if core_number() == 0 {
+        pac.PSM.frce_off().modify(|_, w| w.proc1().set_bit());
+    }
+
+    // Channel 0 streams to the display, see `DmaInterface`
+    pac.DMA.chan_abort().write(|w| unsafe { w.bits(1) });
+    while pac.DMA.chan_abort().read().bits() != 0 {}
+
+    let sio = Sio::new(pac.SIO);
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let spi = Spi::<_, _, _, 8>::new(
+        pac.SPI0,
+        (
+            pins.mosi.into_function::<FunctionSpi>(),
+            pins.gpio16.into_function::<FunctionSpi>(),
+            pins.sck.into_function::<FunctionSpi>(),
+        ),
+    )
+    .init(
+        &mut pac.RESETS,
+        system_clock_hz.Hz(),
+        DISPLAY_SPI_FREQ_HZ.Hz(),
+        embedded_hal::spi::MODE_3,
+    );
+    let di = SPIInterface::new(
+        spi,
+        pins.dc.into_push_pull_output(),
+        pins.cs.into_push_pull_output(),
+    );
+    let mut delay = Delay::new(core.SYST, system_clock_hz);
+
+    // The driver draws in RAM coordinates, the picture is cut out of them like a `Panel` does
+    let display = display();
+    let picture = display.bounding_box().translate(display.offset());
+    let ram = picture.bottom_right().unwrap_or_default() + Point::new(1, 1);
+    let mut driver = ST7789::new(
+        di,
+        pins.reset.into_push_pull_output(),
+        ram.x as u16,
+        ram.y as u16,
+    );
+
+    let drawn = driver.init(&mut delay).is_ok()
+        && driver.set_orientation(display.orientation.into()).is_ok()
+        && screen.draw(&mut driver.cropped(&picture)).is_ok();
+
+    // Full brightness, whatever the dimmer was at
+    let mut backlight = pins.backlight.into_push_pull_output();
+    let _ = backlight.set_high();
+
+    drawn
+}
+
+fn core_number() -> u32 {
+    // SAFETY: reading the number of the running core has no side effects
+    unsafe { (*pac::SIO::ptr()).cpuid().read().bits() }
+}
@@ -0,0 +1,410 @@
+//! Backlight brightness, fades and idle dimming.
+//!
+//! Brightness is given in percent of perceived lightness. [`duty`] maps it to
+//! a PWM duty cycle with the CIE 1931 lightness curve, so 50% looks half as
+//! bright as 100% and fades appear even. A [`Dimmer`] fades the brightness
+//! down after a period without input, switches the backlight off after a
+//! longer one and brings it back as soon as a button is pressed.
+
+use crate::input::Input;
+
+/// Duty cycle of a fully lit backlight
+pub const MAX_DUTY: u16 = u16::MAX;
+
+/// Full brightness in percent
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+/// Returns the duty cycle showing `brightness` percent, clamped to 100
+pub fn duty(brightness: u8) -> u16 {
+    let lightness = u64::from(brightness.min(MAX_BRIGHTNESS));
+
+    // Luminance relative to the maximum, the curve is linear near black
+    let (numerator, denominator) = if lightness > 8 {
+        ((lightness + 16).pow(3), 116u64.pow(3))
+    } else {
+        (lightness * 10, 9033)
+    };
+
+    (numerator * u64::from(MAX_DUTY) / denominator) as u16
+}
+
+/// Linear change of the brightness over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fade {
+    from: u8,
+    to: u8,
+    duration_ms: u32,
+    elapsed_ms: u32,
+}
+
+impl Fade {
+    /// Creates a fade from `from` to `to` percent, a zero duration jumps right to `to`
+    pub fn new(from: u8, to: u8, duration_ms: u32) -> Self {
+        Self {
+            from: from.min(MAX_BRIGHTNESS),
+            to: to.min(MAX_BRIGHTNESS),
+            duration_ms,
+            elapsed_ms: 0,
+        }
+    }
+
+    /// Creates a finished fade staying at `brightness`
+    pub fn constant(brightness: u8) -> Self {
+        Self::new(brightness, brightness, 0)
+    }
+
+    /// Advances the fade by `dt_ms` and returns the brightness reached
+    pub fn advance(&mut self, dt_ms: u32) -> u8 {
+        self.elapsed_ms = self.elapsed_ms.saturating_add(dt_ms).min(self.duration_ms);
+        self.brightness()
+    }
+
+    /// Returns the current brightness in percent
+    pub fn brightness(&self) -> u8 {
+        if self.is_done() {
+            return self.to;
+        }
+
+        let from = i64::from(self.from);
+        let change = i64::from(self.to) - from;
+        let brightness = from + change * i64::from(self.elapsed_ms) / i64::from(self.duration_ms);
+        brightness as u8
+    }
+
+    /// Returns the brightness the fade ends at
+    pub fn target(&self) -> u8 {
+        self.to
+    }
+
+    /// Returns `true` once the target is reached
+    pub fn is_done(&self) -> bool {
+        self.elapsed_ms >= self.duration_ms
+    }
+}
+
+/// Timing and levels of a [`Dimmer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DimmerConfig {
+    /// Brightness while the player is active in percent
+    pub brightness: u8,
+    /// Brightness after a while without input in percent
+    pub dimmed_brightness: u8,
+    /// Time without input until the backlight dims in milliseconds, `None` never dims
+    pub dim_after_ms: Option<u32>,
+    /// Time without input until the backlight is off in milliseconds, `None` keeps it on
+    pub off_after_ms: Option<u32>,
+    /// Duration of a fade between two levels in milliseconds
+    pub fade_ms: u32,
+}
+
+impl Default for DimmerConfig {
+    fn default() -> Self {
+        Self {
+            brightness: MAX_BRIGHTNESS,
+            dimmed_brightness: 20,
+            dim_after_ms: Some(30_000),
+            off_after_ms: Some(60_000),
+            fade_ms: 500,
+        }
+    }
+}
+
+/// Stage of a [`Dimmer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DimmerState {
+    /// Lit at the configured brightness
+    Active,
+    /// Lit at the dimmed brightness after a while without input
+    Dimmed,
+    /// Switched off after a longer while without input
+    Off,
+}
+
+/// Idle timer fading the backlight down and back up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Dimmer {
+    config: DimmerConfig,
+    state: DimmerState,
+    idle_ms: u32,
+    fade: Fade,
+}
+
+impl Dimmer {
+    /// Creates an active dimmer at the configured brightness
+    pub fn new(config: DimmerConfig) -> Self {
+        Self {
+            config,
+            state: DimmerState::Active,
+            idle_ms: 0,
+            fade: Fade::constant(config.brightness),
+        }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> DimmerConfig {
+        self.config
+    }
+
+    /// Changes the configuration, fading to the new level of the current state
+    pub fn configure(&mut self, config: DimmerConfig) {
+        self.config = config;
+        self.fade_to_state();
+    }
+
+    /// Fades to `brightness` percent while the player is active
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.configure(DimmerConfig {
+            brightness,
+            ..self.config
+        });
+    }
+
+    /// Returns the current stage
+    pub fn state(&self) -> DimmerState {
+        self.state
+    }
+
+    /// Returns `true` while the backlight is off or fading out, input is then only meant to wake
+    pub fn is_off(&self) -> bool {
+        self.state == DimmerState::Off
+    }
+
+    /// Returns the current brightness in percent
+    pub fn brightness(&self) -> u8 {
+        self.fade.brightness()
+    }
+
+    /// Restarts the idle timer, fading back to full brightness if needed
+    pub fn wake(&mut self) {
+        self.idle_ms = 0;
+        if self.state != DimmerState::Active {
+            self.state = DimmerState::Active;
+            self.fade_to_state();
+        }
+    }
+
+    /// Advances the dimmer by `dt_ms` and returns the brightness to show.
+    ///
+    /// Any held button counts as activity, so holding the joystick keeps the
+    /// backlight on.
+    pub fn update(&mut self, input: &Input, dt_ms: u32) -> u8 {
+        if !input.buttons().is_empty() {
+            self.wake();
+        } else {
+            self.idle_ms = self.idle_ms.saturating_add(dt_ms);
+
+            let idle_ms = self.idle_ms;
+            let expired = |limit: Option<u32>| limit.is_some_and(|ms| idle_ms >= ms);
+            let state = if expired(self.config.off_after_ms) {
+                DimmerState::Off
+            } else if expired(self.config.dim_after_ms) {
+                DimmerState::Dimmed
+            } else {
+                DimmerState::Active
+            };
+
+            if state != self.state {
+                self.state = state;
+                self.fade_to_state();
+            }
+        }
+
+        self.fade.advance(dt_ms)
+    }
+
+    fn fade_to_state(&mut self) {
+        let target = match self.state {
+            DimmerState::Active => self.config.brightness,
+            DimmerState::Dimmed => self.config.dimmed_brightness,
+            DimmerState::Off => 0,
+        };
+
+        if target != self.fade.target() {
+            self.fade = Fade::new(self.fade.brightness(), target, self.config.fade_ms);
+        }
+    }
+}
+
+impl Default for Dimmer {
+    fn default() -> Self {
+        Self::new(DimmerConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Button, Buttons};
+
+    const TICK_MS: u32 = 10;
+
+    const CONFIG: DimmerConfig = DimmerConfig {
+        brightness: 80,
+        dimmed_brightness: 20,
+        dim_after_ms: Some(100),
+        off_after_ms: Some(200),
+        fade_ms: 50,
+    };
+
+    /// Returns an input with A held down
+    fn holding() -> Input {
+        let mut input = Input::new();
+        let a = Buttons::from(Button::A);
+        input.update(a, TICK_MS);
+        input.update(a, TICK_MS);
+        assert!(input.is_down(Button::A));
+        input
+    }
+
+    /// Updates the dimmer for `ms` in ticks and returns the last brightness
+    fn run(dimmer: &mut Dimmer, input: &Input, ms: u32) -> u8 {
+        let mut brightness = dimmer.brightness();
+        for _ in 0..ms / TICK_MS {
+            brightness = dimmer.update(input, TICK_MS);
+        }
+        brightness
+    }
+
+    #[test]
+    fn duty_follows_lightness_curve() {
+        assert_eq!(duty(0), 0);
+        assert_eq!(duty(MAX_BRIGHTNESS), MAX_DUTY);
+        assert_eq!(duty(255), MAX_DUTY);
+
+        // Half the perceived lightness is less than a fifth of the light
+        assert_eq!(duty(50), 12070);
+        assert_eq!(duty(1), 72);
+
+        // Rising, also where the linear part meets the cubic one
+        assert_eq!((duty(8), duty(9)), (580, 656));
+        for brightness in 0..MAX_BRIGHTNESS {
+            assert!(duty(brightness) < duty(brightness + 1), "at {brightness}");
+        }
+    }
+
+    #[test]
+    fn fades_linearly() {
+        let mut fade = Fade::new(0, 100, 100);
+        assert_eq!(fade.brightness(), 0);
+        assert_eq!(fade.advance(25), 25);
+        assert_eq!(fade.advance(50), 75);
+        assert!(!fade.is_done());
+        assert_eq!(fade.advance(1000), 100);
+        assert!(fade.is_done());
+
+        let mut fade = Fade::new(80, 20, 60);
+        assert_eq!(fade.advance(30), 50);
+        assert_eq!(fade.advance(30), 20);
+        assert_eq!(fade.target(), 20);
+    }
+
+    #[test]
+    fn fade_clamps_and_jumps() {
+        let fade = Fade::new(150, 200, 0);
+        assert!(fade.is_done());
+        assert_eq!(fade.brightness(), MAX_BRIGHTNESS);
+        assert_eq!(Fade::constant(42).brightness(), 42);
+    }
+
+    #[test]
+    fn dims_then_switches_off_when_idle() {
+        let mut dimmer = Dimmer::new(CONFIG);
+        let idle = Input::new();
+        assert_eq!(dimmer.brightness(), 80);
+
+        assert_eq!(run(&mut dimmer, &idle, 90), 80);
+        assert_eq!(dimmer.state(), DimmerState::Active);
+
+        // The fade to the dimmed level starts with the tick reaching the limit
+        assert_eq!(dimmer.update(&idle, TICK_MS), 68);
+        assert_eq!(dimmer.state(), DimmerState::Dimmed);
+        assert_eq!(run(&mut dimmer, &idle, 40), 20);
+        assert_eq!(run(&mut dimmer, &idle, 50), 20);
+        assert!(!dimmer.is_off());
+
+        assert_eq!(dimmer.update(&idle, TICK_MS), 16);
+        assert_eq!(dimmer.state(), DimmerState::Off);
+        assert!(dimmer.is_off());
+        assert_eq!(run(&mut dimmer, &idle, 1000), 0);
+    }
+
+    #[test]
+    fn wakes_on_input() {
+        let mut dimmer = Dimmer::new(CONFIG);
+        run(&mut dimmer, &Input::new(), 300);
+        assert!(dimmer.is_off());
+
+        let input = holding();
+        assert_eq!(dimmer.update(&input, TICK_MS), 16);
+        assert_eq!(dimmer.state(), DimmerState::Active);
+        assert_eq!(run(&mut dimmer, &input, 40), 80);
+
+        // The idle time starts over after the release
+        let idle = Input::new();
+        assert_eq!(run(&mut dimmer, &idle, 90), 80);
+        assert_eq!(dimmer.state(), DimmerState::Active);
+    }
+
+    #[test]
+    fn held_buttons_keep_the_backlight_on() {
+        let mut dimmer = Dimmer::new(CONFIG);
+        assert_eq!(run(&mut dimmer, &holding(), 1000), 80);
+        assert_eq!(dimmer.state(), DimmerState::Active);
+    }
+
+    #[test]
+    fn wakes_from_the_middle_of_a_fade() {
+        let mut dimmer = Dimmer::new(CONFIG);
+        let idle = Input::new();
+        run(&mut dimmer, &idle, 110);
+        assert_eq!(dimmer.brightness(), 56);
+
+        dimmer.wake();
+        assert_eq!(dimmer.state(), DimmerState::Active);
+        // From 56 back to 80 within the fade time
+        assert_eq!(dimmer.update(&idle, 25), 68);
+        assert_eq!(dimmer.update(&idle, 25), 80);
+    }
+
+    #[test]
+    fn without_limits_stays_active() {
+        let mut dimmer = Dimmer::new(DimmerConfig {
+            dim_after_ms: None,
+            off_after_ms: None,
+            ..CONFIG
+        });
+        assert_eq!(run(&mut dimmer, &Input::new(), 10_000), 80);
+        assert_eq!(dimmer.state(), DimmerState::Active);
+
+        // Switching off without dimming first
+        let mut dimmer = Dimmer::new(DimmerConfig {
+            dim_after_ms: None,
+            ..CONFIG
+        });
+        run(&mut dimmer, &Input::new(), 250);
+        assert_eq!(dimmer.state(), DimmerState::Off);
+        assert_eq!(dimmer.brightness(), 0);
+    }
+
+    #[test]
+    fn fades_to_a_new_brightness() {
+        let mut dimmer = Dimmer::new(CONFIG);
+        let input = holding();
+        dimmer.set_brightness(30);
+        assert_eq!(dimmer.config().brightness, 30);
+        assert_eq!(dimmer.update(&input, 25), 55);
+        assert_eq!(dimmer.update(&input, 25), 30);
+
+        // Changing the dimmed level while active keeps the brightness
+        dimmer.configure(DimmerConfig {
+            brightness: 30,
+            dimmed_brightness: 5,
+            ..CONFIG
+        });
+        assert_eq!(dimmer.update(&input, TICK_MS), 30);
+    }
+}
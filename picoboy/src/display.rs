@@ -0,0 +1,125 @@
+//! Properties of the 240x280 ST7789 panel shared by the hardware and the simulator.
+//!
+//! The panel can show its picture in four orientations. A [`DisplayConfig`]
+//! knows the size of the picture in each of them, where it lies in the 240x320
+//! RAM of the ST7789 and how the joystick has to be turned to match it.
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+
+use crate::input::{Button, Buttons};
+
+/// Visible display width in pixels, in the default portrait orientation
+pub const DISPLAY_WIDTH: i32 = 240;
+
+/// Visible display height in pixels, in the default portrait orientation
+pub const DISPLAY_HEIGHT: i32 = 280;
+
+/// Visible display size, in the default portrait orientation
+pub const DISPLAY_SIZE: Size = Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
+/// RAM rows of the ST7789 without pixels, the panel shows rows 40 to 319
+const HIDDEN_ROWS: i32 = 40;
+
+/// Anything the application can draw on, the real display or a simulated one
+pub trait Display: DrawTarget<Color = Rgb565> {}
+
+impl<T> Display for T where T: DrawTarget<Color = Rgb565> {}
+
+/// Direction of the picture, named like `st7789::Orientation`.
+///
+/// Turns are given as seen on a PicoBoy held upright, with the joystick below
+/// the display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Orientation {
+    /// Upside down
+    Portrait,
+    /// Turned a quarter counterclockwise, hold the device turned clockwise
+    Landscape,
+    /// Upright
+    #[default]
+    PortraitSwapped,
+    /// Turned a quarter clockwise, hold the device turned counterclockwise
+    LandscapeSwapped,
+}
+
+impl Orientation {
+    /// Returns `true` for the two landscape orientations
+    pub const fn is_landscape(self) -> bool {
+        matches!(self, Orientation::Landscape | Orientation::LandscapeSwapped)
+    }
+}
+
+/// Orientation of the display and everything that follows from it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DisplayConfig {
+    pub orientation: Orientation,
+}
+
+impl DisplayConfig {
+    /// Creates a configuration for `orientation`
+    pub const fn new(orientation: Orientation) -> Self {
+        Self { orientation }
+    }
+
+    /// Returns the width of the picture in pixels
+    pub const fn width(&self) -> i32 {
+        if self.orientation.is_landscape() {
+            DISPLAY_HEIGHT
+        } else {
+            DISPLAY_WIDTH
+        }
+    }
+
+    /// Returns the height of the picture in pixels
+    pub const fn height(&self) -> i32 {
+        if self.orientation.is_landscape() {
+            DISPLAY_WIDTH
+        } else {
+            DISPLAY_HEIGHT
+        }
+    }
+
+    /// Returns the size of the picture
+    pub const fn size(&self) -> Size {
+        Size::new(self.width() as u32, self.height() as u32)
+    }
+
+    /// Returns the visible area in picture coordinates
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size())
+    }
+
+    /// Returns where the top left corner of the picture lies in the RAM of the ST7789.
+    ///
+    /// The hidden rows only come first in the orientations which do not
+    /// reverse the direction of the RAM rows.
+    pub const fn offset(&self) -> Point {
+        match self.orientation {
+            Orientation::Portrait => Point::new(0, HIDDEN_ROWS),
+            Orientation::Landscape => Point::new(HIDDEN_ROWS, 0),
+            Orientation::PortraitSwapped | Orientation::LandscapeSwapped => Point::zero(),
+        }
+    }
+
+    /// Turns the joystick directions of a sample, so pushing towards the top of the picture means up
+    pub fn remap(&self, buttons: Buttons) -> Buttons {
+        // Direction the joystick is pushed towards for up, down, left and right of the picture
+        let [up, down, left, right] = match self.orientation {
+            Orientation::PortraitSwapped => return buttons,
+            Orientation::Portrait => [Button::Down, Button::Up, Button::Right, Button::Left],
+            Orientation::Landscape => [Button::Left, Button::Right, Button::Down, Button::Up],
+            Orientation::LandscapeSwapped => {
+                [Button::Right, Button::Left, Button::Up, Button::Down]
+            }
+        };
+
+        let mut remapped = buttons;
+        remapped.set(Button::Up, buttons.contains(up));
+        remapped.set(Button::Down, buttons.contains(down));
+        remapped.set(Button::Left, buttons.contains(left));
+        remapped.set(Button::Right, buttons.contains(right));
+        remapped
+    }
+}
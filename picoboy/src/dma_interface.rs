@@ -0,0 +1,195 @@
+//! DMA-driven SPI display interface.
+//!
+//! [`DmaInterface`] implements [`WriteOnlyDataCommand`], so the `ST7789` driver
+//! uses it just like `SPIInterface`. Outgoing data is copied into one of two
+//! chunk buffers and streamed to SPI0 by a DMA channel while the next chunk is
+//! prepared. A call returns as soon as its last chunk is on its way, so the
+//! game continues with the next frame while the display still receives the
+//! previous one. The next command waits for the transfer to complete.
+
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_dma::ReadBuffer;
+use embedded_hal::digital::{OutputPin, PinState};
+
+use crate::board::{DisplaySpi, Output};
+
+use picoboy_color::hal::{
+    dma::{single_buffer, Channel, CH0},
+    gpio::bank0::{Gpio10, Gpio8},
+    pac,
+};
+
+/// Size of each of the two chunk buffers in bytes
+pub const CHUNK_SIZE: usize = 4096;
+
+/// DMA channel used for display transfers
+pub type DisplayChannel = Channel<CH0>;
+
+/// Statically allocated chunk buffer
+pub type ChunkBuffer = &'static mut [u8; CHUNK_SIZE];
+
+/// The filled part of a chunk buffer
+struct Chunk {
+    buffer: ChunkBuffer,
+    len: usize,
+}
+
+// SAFETY: the buffer is `'static` and owned by the chunk, so it stays valid and
+// unaliased for the whole transfer.
+unsafe impl ReadBuffer for Chunk {
+    type Word = u8;
+
+    unsafe fn read_buffer(&self) -> (*const u8, usize) {
+        (self.buffer.as_ptr(), self.len)
+    }
+}
+
+type Transfer = single_buffer::Transfer<DisplayChannel, Chunk, DisplaySpi>;
+
+enum Link {
+    Idle(DisplayChannel, DisplaySpi),
+    Busy(Transfer),
+}
+
+/// Completion flag of the display transfers.
+///
+/// The `ST7789` driver owns the [`DmaInterface`], this handle allows the game
+/// loop to check on the transfer anyway.
+#[derive(Clone, Copy)]
+pub struct TransferStatus {
+    _private: (),
+}
+
+impl TransferStatus {
+    /// Returns `true` while data is still being sent to the display
+    pub fn is_busy(&self) -> bool {
+        // SAFETY: only reads status registers of the channel and SPI owned by the interface
+        let (dma, spi) = unsafe { (&*pac::DMA::ptr(), &*pac::SPI0::ptr()) };
+        dma.ch(0).ch_ctrl_trig().read().busy().bit_is_set() || spi.sspsr().read().bsy().bit_is_set()
+    }
+}
+
+/// Display interface streaming data over SPI0 with DMA
+pub struct DmaInterface {
+    // Only `None` while switching between idle and busy
+    link: Option<Link>,
+    // Chunk buffers not used by the running transfer
+    spare: [Option<ChunkBuffer>; 2],
+    dc: Output<Gpio8>,
+    _cs: Output<Gpio10>,
+}
+
+impl DmaInterface {
+    /// Creates the interface, the display stays selected from now on
+    pub fn new(
+        spi: DisplaySpi,
+        channel: DisplayChannel,
+        buffers: &'static mut [[u8; CHUNK_SIZE]; 2],
+        dc: Output<Gpio8>,
+        mut cs: Output<Gpio10>,
+    ) -> Self {
+        // The display is the only device on SPI0
+        let _ = cs.set_low();
+
+        let [first, second] = buffers;
+        Self {
+            link: Some(Link::Idle(channel, spi)),
+            spare: [Some(first), Some(second)],
+            dc,
+            _cs: cs,
+        }
+    }
+
+    /// Returns a handle to check on the transfers after the interface moved into the driver
+    pub fn status(&self) -> TransferStatus {
+        TransferStatus { _private: () }
+    }
+
+    /// Returns `true` while data is still being sent to the display
+    pub fn is_busy(&self) -> bool {
+        self.status().is_busy()
+    }
+
+    /// Blocks until all data has been sent to the display
+    pub fn wait(&mut self) {
+        let (channel, spi) = self.finish();
+        while spi.is_busy() {}
+        self.link = Some(Link::Idle(channel, spi));
+    }
+
+    /// Waits for the running transfer and reclaims its buffer
+    fn finish(&mut self) -> (DisplayChannel, DisplaySpi) {
+        match self.link.take() {
+            Some(Link::Busy(transfer)) => {
+                let (channel, chunk, spi) = transfer.wait();
+                self.put_back(chunk.buffer);
+                (channel, spi)
+            }
+            Some(Link::Idle(channel, spi)) => (channel, spi),
+            None => unreachable!(),
+        }
+    }
+
+    fn put_back(&mut self, buffer: ChunkBuffer) {
+        if let Some(slot) = self.spare.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(buffer);
+        }
+    }
+
+    fn start(&mut self, chunk: Chunk) {
+        let (channel, spi) = self.finish();
+        let transfer = single_buffer::Config::new(channel, chunk, spi).start();
+        self.link = Some(Link::Busy(transfer));
+    }
+
+    fn stream(&mut self, mut bytes: impl Iterator<Item = u8>) {
+        // One chunk is in flight at most, so there is always a spare one to fill meanwhile
+        while let Some(buffer) = self.spare.iter_mut().find_map(Option::take) {
+            let mut len = 0;
+            for (slot, byte) in buffer.iter_mut().zip(&mut bytes) {
+                *slot = byte;
+                len += 1;
+            }
+
+            if len == 0 {
+                self.put_back(buffer);
+                return;
+            }
+
+            self.start(Chunk { buffer, len });
+
+            if len < CHUNK_SIZE {
+                return;
+            }
+        }
+    }
+
+    fn send(&mut self, dc: PinState, data: DataFormat<'_>) -> Result<(), DisplayError> {
+        // The level of the data/command pin must not change while bytes are in flight
+        self.wait();
+        let _ = self.dc.set_state(dc);
+
+        match data {
+            DataFormat::U8(bytes) => self.stream(bytes.iter().copied()),
+            DataFormat::U16(words) => self.stream(words.iter().flat_map(|w| w.to_ne_bytes())),
+            DataFormat::U16BE(words) => self.stream(words.iter().flat_map(|w| w.to_be_bytes())),
+            DataFormat::U16LE(words) => self.stream(words.iter().flat_map(|w| w.to_le_bytes())),
+            DataFormat::U8Iter(bytes) => self.stream(bytes),
+            DataFormat::U16BEIter(words) => self.stream(words.flat_map(u16::to_be_bytes)),
+            DataFormat::U16LEIter(words) => self.stream(words.flat_map(u16::to_le_bytes)),
+            _ => return Err(DisplayError::DataFormatNotImplemented),
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteOnlyDataCommand for DmaInterface {
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+        self.send(PinState::Low, cmd)
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+        self.send(PinState::High, buf)
+    }
+}
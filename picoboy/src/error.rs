@@ -0,0 +1,83 @@
+//! Errors of the hardware drivers, shared by the whole crate.
+//!
+//! An [`Error`] names the part that failed and, for the display, what it was
+//! doing at the time, so the log tells more than the location of a panic.
+//! The display recovers from most failures on its own, see
+//! [`Panel::retry`](crate::panel::Panel::retry).
+
+use core::fmt;
+
+use crate::storage;
+
+/// What the display was doing when it failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisplayOp {
+    /// Resetting the controller and sending its initialisation sequence
+    Init,
+    /// Turning the picture
+    Orientation,
+    /// Sending pixels
+    Draw,
+}
+
+impl fmt::Display for DisplayOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DisplayOp::Init => "init",
+            DisplayOp::Orientation => "orientation",
+            DisplayOp::Draw => "draw",
+        })
+    }
+}
+
+/// Errors of the hardware drivers.
+///
+/// There are no separate variants for the SPI bus and the control pins of
+/// the display: SPI and GPIO of rp2040-hal cannot fail, and the ST7789 driver
+/// folds any error of its interface, also of the `DmaInterface`, into
+/// `DisplayError`. A failing display therefore always shows up as
+/// [`Display`](Error::Display) with the operation it was doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The peripherals have already been taken
+    AlreadyTaken,
+    /// Crystal oscillator, PLLs or clocks could not be configured
+    Clocks,
+    /// The display did not take a command or data
+    Display(DisplayOp),
+    /// The save data could not be read or written
+    Storage(storage::Error),
+}
+
+impl From<storage::Error> for Error {
+    fn from(error: storage::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AlreadyTaken => f.write_str("peripherals already taken"),
+            Error::Clocks => f.write_str("clocks not configured"),
+            Error::Display(op) => write!(f, "display failed during {op}"),
+            Error::Storage(error) => write!(f, "save data: {error:?}"),
+        }
+    }
+}
+
+#[cfg(feature = "rp2040")]
+impl Error {
+    /// Converts an error of the ST7789 driver, whose reset pin cannot fail
+    pub(crate) fn from_driver(
+        op: DisplayOp,
+        error: st7789::Error<core::convert::Infallible>,
+    ) -> Self {
+        match error {
+            st7789::Error::DisplayError => Error::Display(op),
+            st7789::Error::Pin(never) => match never {},
+        }
+    }
+}
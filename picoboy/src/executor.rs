@@ -0,0 +1,93 @@
+//! Minimal executor for a fixed set of async tasks.
+//!
+//! [`Executor::run`] polls every task, then sleeps until something happens and
+//! polls them all again. Wakers only make sure the next sleep ends right away,
+//! so futures may also rely on the interrupt or event that ends the sleep
+//! instead of storing a waker. Polling all tasks on every wake-up costs little
+//! for the handful of tasks a game has, and needs neither allocation nor task
+//! queues.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// A task, pinned by the caller, e.g. with [`core::pin::pin!`]
+pub type Task<'a> = Pin<&'a mut dyn Future<Output = ()>>;
+
+// The waker data is the `wake` function of the executor
+const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+fn clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &VTABLE)
+}
+
+fn wake(data: *const ()) {
+    // SAFETY: the executor stores a `fn()` as data
+    let wake = unsafe { core::mem::transmute::<*const (), fn()>(data) };
+    wake();
+}
+
+fn drop(_: *const ()) {}
+
+/// Runs tasks until all of them are finished
+pub struct Executor {
+    sleep: fn(),
+    wake: fn(),
+}
+
+impl Executor {
+    /// Creates an executor which calls `sleep` when all tasks are pending.
+    ///
+    /// `sleep` has to return once `wake` was called or an interrupt occurred,
+    /// even if that happened before, like `wfe` and `sev` on the RP2040.
+    pub const fn new(sleep: fn(), wake: fn()) -> Self {
+        Self { sleep, wake }
+    }
+
+    /// Polls the tasks until all of them are finished
+    pub fn run<const N: usize>(&self, mut tasks: [Task<'_>; N]) {
+        // SAFETY: the vtable functions only call `self.wake`, which lives as long as the program
+        let waker = unsafe { Waker::from_raw(RawWaker::new(self.wake as *const (), &VTABLE)) };
+        let mut context = Context::from_waker(&waker);
+
+        let mut pending = N;
+        let mut finished = [false; N];
+
+        while pending > 0 {
+            for (task, finished) in tasks.iter_mut().zip(finished.iter_mut()) {
+                if !*finished && task.as_mut().poll(&mut context).is_ready() {
+                    *finished = true;
+                    pending -= 1;
+                }
+            }
+
+            if pending > 0 {
+                (self.sleep)();
+            }
+        }
+    }
+}
+
+/// Waits until `poll` returns a value, checking it whenever the executor wakes up
+pub async fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+    core::future::poll_fn(|_| match poll() {
+        Some(value) => Poll::Ready(value),
+        None => Poll::Pending,
+    })
+    .await
+}
+
+/// Lets the other tasks run before continuing
+pub async fn yield_now() {
+    let mut yielded = false;
+    core::future::poll_fn(|context| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
@@ -0,0 +1,141 @@
+//! Flash in RAM to run the save data code on the host.
+//!
+//! [`FlashEmulator`] behaves like NOR flash: programming can only clear bits
+//! and only an erase sets them again. It counts the erases of every sector and
+//! can simulate a power loss in the middle of a program or erase, to check that
+//! the [`Store`](crate::storage::Store) recovers from it.
+
+use crate::storage::{Flash, FlashError};
+
+/// Sector size of the emulated flash, the same as the RP2040's flash chip
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Emulated flash with `SECTORS` sectors, all erased at first
+#[derive(Clone)]
+pub struct FlashEmulator<const SECTORS: usize> {
+    sectors: [[u8; SECTOR_SIZE]; SECTORS],
+    erase_counts: [u32; SECTORS],
+    // Bytes which can still be programmed or erased before the power fails
+    budget: Option<usize>,
+    powered: bool,
+}
+
+impl<const SECTORS: usize> FlashEmulator<SECTORS> {
+    /// Creates an erased flash
+    pub const fn new() -> Self {
+        Self {
+            sectors: [[0xFF; SECTOR_SIZE]; SECTORS],
+            erase_counts: [0; SECTORS],
+            budget: None,
+            powered: true,
+        }
+    }
+
+    /// Cuts the power after `bytes` more bytes have been programmed or erased.
+    ///
+    /// The operation in progress stops halfway and fails with
+    /// [`FlashError::Interrupted`], as does everything else until
+    /// [`power_cycle`](Self::power_cycle) is called.
+    pub fn fail_after(&mut self, bytes: usize) {
+        self.budget = Some(bytes);
+    }
+
+    /// Restores the power, the content stays as it was left
+    pub fn power_cycle(&mut self) {
+        self.budget = None;
+        self.powered = true;
+    }
+
+    /// Returns `true` if the power was cut by [`fail_after`](Self::fail_after)
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Returns how often a sector was erased
+    pub fn erase_count(&self, sector: usize) -> u32 {
+        self.erase_counts[sector]
+    }
+
+    /// Returns the content of a sector
+    pub fn sector(&self, sector: usize) -> &[u8; SECTOR_SIZE] {
+        &self.sectors[sector]
+    }
+
+    /// Takes one byte of the budget, `false` once the power is gone
+    fn consume(&mut self) -> bool {
+        match &mut self.budget {
+            _ if !self.powered => false,
+            Some(0) => {
+                self.powered = false;
+                false
+            }
+            Some(left) => {
+                *left -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result<(), FlashError> {
+        if !self.powered {
+            return Err(FlashError::Interrupted);
+        }
+        match offset.checked_add(len) {
+            Some(end) if end <= self.capacity() => Ok(()),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+}
+
+impl<const SECTORS: usize> Default for FlashEmulator<SECTORS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SECTORS: usize> Flash for FlashEmulator<SECTORS> {
+    const SECTOR_SIZE: usize = SECTOR_SIZE;
+
+    fn capacity(&self) -> usize {
+        SECTORS * SECTOR_SIZE
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), FlashError> {
+        self.check(offset, buffer.len())?;
+        for (index, byte) in buffer.iter_mut().enumerate() {
+            let position = offset + index;
+            *byte = self.sectors[position / SECTOR_SIZE][position % SECTOR_SIZE];
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, offset: usize) -> Result<(), FlashError> {
+        self.check(offset, SECTOR_SIZE)?;
+        if !offset.is_multiple_of(SECTOR_SIZE) {
+            return Err(FlashError::NotAligned);
+        }
+
+        let sector = offset / SECTOR_SIZE;
+        self.erase_counts[sector] += 1;
+        for index in 0..SECTOR_SIZE {
+            if !self.consume() {
+                return Err(FlashError::Interrupted);
+            }
+            self.sectors[sector][index] = 0xFF;
+        }
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.check(offset, data.len())?;
+        for (index, &byte) in data.iter().enumerate() {
+            if !self.consume() {
+                return Err(FlashError::Interrupted);
+            }
+            let position = offset + index;
+            self.sectors[position / SECTOR_SIZE][position % SECTOR_SIZE] &= byte;
+        }
+        Ok(())
+    }
+}
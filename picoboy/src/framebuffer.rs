@@ -0,0 +1,500 @@
+//! Off-screen framebuffer with dirty-rectangle tracking.
+//!
+//! The game draws the complete scene into the [`FrameBuffer`] every frame. Only
+//! pixels which actually changed are recorded as dirty, and [`FrameBuffer::flush`]
+//! sends each dirty region to the display with a single `fill_contiguous` call.
+//! On the ST7789 this is one address window and one SPI burst per region, so
+//! nothing half drawn ever reaches the panel.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_SIZE, DISPLAY_WIDTH};
+
+/// Number of pixels of the display
+pub const PIXEL_COUNT: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize;
+
+/// Maximum number of separately flushed regions, more are merged
+pub const MAX_DIRTY_REGIONS: usize = 8;
+
+/// Set of rectangles which need to be sent to the display.
+///
+/// Overlapping or touching rectangles are merged as long as the merged
+/// rectangle is not larger than both of them together. If all slots are used,
+/// the new rectangle is merged with the one growing the least.
+#[derive(Debug, Clone)]
+pub struct DirtyRegions<const N: usize> {
+    regions: [Rectangle; N],
+    len: usize,
+}
+
+impl<const N: usize> DirtyRegions<N> {
+    /// Creates an empty set
+    pub const fn new() -> Self {
+        Self {
+            regions: [Rectangle::new(Point::new(0, 0), Size::new(0, 0)); N],
+            len: 0,
+        }
+    }
+
+    /// Returns the regions in no particular order
+    pub fn regions(&self) -> &[Rectangle] {
+        &self.regions[..self.len]
+    }
+
+    /// Returns `true` if nothing is dirty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all regions
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Adds a region, merging it with existing ones where worthwhile
+    pub fn add(&mut self, area: Rectangle) {
+        if area.is_zero_sized() || N == 0 {
+            return;
+        }
+
+        let mut area = area;
+
+        // A merged region may now overlap regions checked before, so start over after each merge
+        let mut index = 0;
+        while index < self.len {
+            let merged = union(&self.regions[index], &area);
+            if pixels(&merged) <= pixels(&self.regions[index]) + pixels(&area) {
+                area = merged;
+                self.remove(index);
+                index = 0;
+            } else {
+                index += 1;
+            }
+        }
+
+        if self.len == N {
+            let cheapest = (0..self.len)
+                .min_by_key(|&index| {
+                    let region = &self.regions[index];
+                    pixels(&union(region, &area)) - pixels(region)
+                })
+                .unwrap_or(0);
+
+            let merged = union(&self.regions[cheapest], &area);
+            self.remove(cheapest);
+            self.add(merged);
+            return;
+        }
+
+        self.regions[self.len] = area;
+        self.len += 1;
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.len -= 1;
+        self.regions[index] = self.regions[self.len];
+    }
+}
+
+impl<const N: usize> Default for DirtyRegions<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Smallest rectangle containing both rectangles
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    match (a.bottom_right(), b.bottom_right()) {
+        (Some(a_end), Some(b_end)) => Rectangle::with_corners(
+            a.top_left.component_min(b.top_left),
+            a_end.component_max(b_end),
+        ),
+        (Some(_), None) => *a,
+        _ => *b,
+    }
+}
+
+fn pixels(area: &Rectangle) -> u32 {
+    area.size.width * area.size.height
+}
+
+/// Bounding box of the pixels changed by a single drawing operation
+struct Changes {
+    top_left: Point,
+    bottom_right: Point,
+    any: bool,
+}
+
+impl Changes {
+    fn new() -> Self {
+        Self {
+            top_left: Point::zero(),
+            bottom_right: Point::zero(),
+            any: false,
+        }
+    }
+
+    fn include(&mut self, point: Point) {
+        if self.any {
+            self.top_left = self.top_left.component_min(point);
+            self.bottom_right = self.bottom_right.component_max(point);
+        } else {
+            self.top_left = point;
+            self.bottom_right = point;
+            self.any = true;
+        }
+    }
+
+    fn area(&self) -> Option<Rectangle> {
+        self.any
+            .then(|| Rectangle::with_corners(self.top_left, self.bottom_right))
+    }
+}
+
+/// Full-frame RGB565 framebuffer, 134 400 bytes.
+///
+/// Too large for the stack, so keep it in a `static`. It starts out with the
+/// size of the display in portrait orientation, [`FrameBuffer::set_size`]
+/// turns it for landscape.
+pub struct FrameBuffer {
+    pixels: [u16; PIXEL_COUNT],
+    size: Size,
+    dirty: DirtyRegions<MAX_DIRTY_REGIONS>,
+}
+
+impl FrameBuffer {
+    /// Creates a black framebuffer without dirty regions, matching a cleared display
+    pub const fn new() -> Self {
+        Self {
+            pixels: [0; PIXEL_COUNT],
+            size: DISPLAY_SIZE,
+            dirty: DirtyRegions::new(),
+        }
+    }
+
+    /// Changes the size, e.g. to the one of a [`DisplayConfig`](crate::display::DisplayConfig).
+    ///
+    /// The pixels are cleared to black without being marked dirty, matching a
+    /// cleared display. Heights beyond [`PIXEL_COUNT`] pixels are cut off.
+    pub fn set_size(&mut self, size: Size) {
+        let width = size.width.clamp(1, PIXEL_COUNT as u32);
+        let height = size.height.min(PIXEL_COUNT as u32 / width);
+
+        self.size = Size::new(width, height);
+        self.pixels.fill(0);
+        self.dirty.clear();
+    }
+
+    /// Returns the color of a pixel, `None` outside of the display
+    pub fn pixel(&self, point: Point) -> Option<Rgb565> {
+        self.index(point)
+            .map(|index| RawU16::new(self.pixels[index]).into())
+    }
+
+    /// Returns the regions changed since the last flush
+    pub fn dirty(&self) -> &[Rectangle] {
+        self.dirty.regions()
+    }
+
+    /// Forces a region to be sent on the next flush, e.g. after the display was reset
+    pub fn mark_dirty(&mut self, area: Rectangle) {
+        self.dirty.add(area.intersection(&self.bounding_box()));
+    }
+
+    /// Sends all dirty regions to the display
+    pub fn flush<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let width = self.size.width as usize;
+        for area in self.dirty.regions() {
+            let pixels = &self.pixels;
+            let colors = area.rows().flat_map(|y| {
+                let start = y as usize * width + area.top_left.x as usize;
+                pixels[start..start + area.size.width as usize]
+                    .iter()
+                    .map(|&raw| Rgb565::from(RawU16::new(raw)))
+            });
+
+            display.fill_contiguous(area, colors)?;
+        }
+
+        self.dirty.clear();
+
+        Ok(())
+    }
+
+    fn record(&mut self, changes: Changes) {
+        if let Some(area) = changes.area() {
+            self.dirty.add(area);
+        }
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        let (width, height) = (self.size.width as i32, self.size.height as i32);
+        let inside = (0..width).contains(&point.x) && (0..height).contains(&point.y);
+        inside.then(|| point.y as usize * width as usize + point.x as usize)
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let mut changes = Changes::new();
+
+        for Pixel(point, color) in pixels {
+            if let Some(index) = self.index(point) {
+                let raw = color.into_storage();
+                if self.pixels[index] != raw {
+                    self.pixels[index] = raw;
+                    changes.include(point);
+                }
+            }
+        }
+
+        self.record(changes);
+
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let raw = color.into_storage();
+        let mut changes = Changes::new();
+
+        for y in area.rows() {
+            let start = y as usize * self.size.width as usize;
+            for x in area.columns() {
+                let pixel = &mut self.pixels[start + x as usize];
+                if *pixel != raw {
+                    *pixel = raw;
+                    changes.include(Point::new(x, y));
+                }
+            }
+        }
+
+        self.record(changes);
+
+        Ok(())
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{boxed::Box, vec::Vec};
+
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    /// Display recording what each flush sends
+    #[derive(Default)]
+    struct Recorder {
+        areas: Vec<Rectangle>,
+        colors: Vec<Rgb565>,
+    }
+
+    impl DrawTarget for Recorder {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            unreachable!("flush sends whole regions")
+        }
+
+        fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Self::Color>,
+        {
+            self.areas.push(*area);
+            self.colors.extend(colors);
+            Ok(())
+        }
+    }
+
+    impl OriginDimensions for Recorder {
+        fn size(&self) -> Size {
+            DISPLAY_SIZE
+        }
+    }
+
+    #[test]
+    fn ignores_empty_regions() {
+        let mut dirty = DirtyRegions::<4>::new();
+        dirty.add(rect(5, 5, 0, 10));
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn merges_overlapping_regions() {
+        let mut dirty = DirtyRegions::<4>::new();
+        dirty.add(rect(0, 0, 10, 10));
+        dirty.add(rect(5, 0, 10, 10));
+        assert_eq!(dirty.regions(), [rect(0, 0, 15, 10)]);
+
+        // Contained in the existing region
+        dirty.add(rect(2, 2, 3, 3));
+        assert_eq!(dirty.regions(), [rect(0, 0, 15, 10)]);
+    }
+
+    #[test]
+    fn merges_touching_regions() {
+        let mut dirty = DirtyRegions::<4>::new();
+        dirty.add(rect(0, 0, 10, 10));
+        dirty.add(rect(10, 0, 10, 10));
+        assert_eq!(dirty.regions(), [rect(0, 0, 20, 10)]);
+    }
+
+    #[test]
+    fn keeps_distant_regions_apart() {
+        let mut dirty = DirtyRegions::<4>::new();
+        dirty.add(rect(0, 0, 10, 10));
+        dirty.add(rect(100, 100, 10, 10));
+        assert_eq!(dirty.regions().len(), 2);
+    }
+
+    #[test]
+    fn merges_chains_of_regions() {
+        let mut dirty = DirtyRegions::<4>::new();
+        dirty.add(rect(0, 0, 10, 10));
+        dirty.add(rect(20, 0, 10, 10));
+        // Bridges both regions, the merge with the first one then overlaps the second
+        dirty.add(rect(5, 0, 20, 10));
+        assert_eq!(dirty.regions(), [rect(0, 0, 30, 10)]);
+    }
+
+    #[test]
+    fn merges_cheapest_when_full() {
+        let mut dirty = DirtyRegions::<2>::new();
+        dirty.add(rect(0, 0, 10, 10));
+        dirty.add(rect(200, 200, 10, 10));
+        dirty.add(rect(0, 30, 10, 10));
+
+        let mut regions = dirty.regions().to_vec();
+        regions.sort_by_key(|region| region.top_left.x);
+        assert_eq!(regions, [rect(0, 0, 10, 40), rect(200, 200, 10, 10)]);
+    }
+
+    #[test]
+    fn never_exceeds_capacity() {
+        let mut dirty = DirtyRegions::<3>::new();
+        for i in 0..20 {
+            dirty.add(rect(i * 12 % 230, i * 37 % 270, 5, 5));
+            assert!(dirty.regions().len() <= 3);
+        }
+        for i in 0..20 {
+            let area = rect(i * 12 % 230, i * 37 % 270, 5, 5);
+            let covered = dirty
+                .regions()
+                .iter()
+                .any(|region| region.intersection(&area) == area);
+            assert!(covered, "{area:?} lost");
+        }
+    }
+
+    #[test]
+    fn records_only_changed_pixels() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+
+        // Black on black changes nothing
+        let Ok(()) = rect(0, 0, 20, 20)
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(framebuffer.as_mut());
+        assert!(framebuffer.dirty().is_empty());
+
+        let Ok(()) = rect(10, 20, 5, 6)
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+            .draw(framebuffer.as_mut());
+        assert_eq!(framebuffer.dirty(), [rect(10, 20, 5, 6)]);
+        assert_eq!(framebuffer.pixel(Point::new(10, 20)), Some(Rgb565::RED));
+        assert_eq!(framebuffer.pixel(Point::new(-1, 0)), None);
+    }
+
+    #[test]
+    fn flushes_dirty_regions() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        let Ok(()) = Pixel(Point::new(3, 4), Rgb565::GREEN).draw(framebuffer.as_mut());
+        let Ok(()) = rect(100, 200, 2, 2)
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLUE))
+            .draw(framebuffer.as_mut());
+
+        let mut display = Recorder::default();
+        let Ok(()) = framebuffer.flush(&mut display);
+        let mut areas = display.areas.clone();
+        areas.sort_by_key(|area| area.top_left.x);
+        assert_eq!(areas, [rect(3, 4, 1, 1), rect(100, 200, 2, 2)]);
+        assert_eq!(display.colors.len(), 5);
+        assert!(framebuffer.dirty().is_empty());
+
+        // Nothing changed since
+        let mut display = Recorder::default();
+        let Ok(()) = framebuffer.flush(&mut display);
+        assert!(display.areas.is_empty());
+    }
+
+    #[test]
+    fn flushes_rows_of_the_region() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        // One drawing operation, so one region around both pixels
+        let Ok(()) = framebuffer.draw_iter([
+            Pixel(Point::new(10, 10), Rgb565::RED),
+            Pixel(Point::new(11, 11), Rgb565::BLUE),
+        ]);
+
+        let mut display = Recorder::default();
+        let Ok(()) = framebuffer.flush(&mut display);
+        assert_eq!(display.areas, [rect(10, 10, 2, 2)]);
+        assert_eq!(
+            display.colors,
+            [Rgb565::RED, Rgb565::BLACK, Rgb565::BLACK, Rgb565::BLUE]
+        );
+    }
+
+    #[test]
+    fn clips_mark_dirty_to_the_display() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        framebuffer.mark_dirty(rect(-10, -10, 1000, 1000));
+        assert_eq!(framebuffer.dirty(), [framebuffer.bounding_box()]);
+    }
+
+    #[test]
+    fn turns_for_landscape() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        framebuffer.set_size(Size::new(280, 240));
+        let Ok(()) = Pixel(Point::new(279, 239), Rgb565::WHITE).draw(framebuffer.as_mut());
+        assert_eq!(framebuffer.pixel(Point::new(279, 239)), Some(Rgb565::WHITE));
+        assert_eq!(framebuffer.pixel(Point::new(0, 240)), None);
+    }
+}
@@ -0,0 +1,371 @@
+//! Fixed-timestep game loop.
+//!
+//! [`GameLoop::frame`] runs the update as often as the elapsed time requires,
+//! always with the same step, and renders once if anything was updated. Game
+//! speed therefore no longer depends on how long drawing takes. If the loop
+//! falls too far behind, surplus updates are skipped instead of stalling.
+
+/// Monotonic time source
+pub trait Clock {
+    /// Returns the time since an arbitrary point in the past in microseconds
+    fn now_us(&self) -> u64;
+}
+
+/// Work requested by [`GameLoop::frame`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Step {
+    /// Advance the game by the given time in milliseconds
+    Update(u32),
+    /// Draw the current state
+    Render,
+}
+
+/// Default limit of updates run before the next render
+pub const MAX_UPDATES_PER_FRAME: u32 = 5;
+
+/// Length of the window statistics are collected over in microseconds
+const STATS_WINDOW_US: u64 = 1_000_000;
+
+/// What happened during a single call to [`GameLoop::frame`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    /// Number of updates run
+    pub updates: u32,
+    /// Number of updates dropped because the loop fell behind
+    pub skipped: u32,
+    /// `true` if the frame has been rendered
+    pub rendered: bool,
+}
+
+/// Frame timing measured over the last second
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stats {
+    /// Rendered frames per second
+    pub fps: u32,
+    /// Updates per second
+    pub ups: u32,
+    /// Skipped updates
+    pub skipped: u32,
+    /// Average time spent updating and rendering a frame in microseconds
+    pub average_frame_us: u32,
+    /// Longest time spent updating and rendering a frame in microseconds
+    pub max_frame_us: u32,
+}
+
+/// Runs updates at a fixed rate and renders in between
+pub struct GameLoop {
+    step_us: u64,
+    max_updates: u32,
+    last_us: Option<u64>,
+    accumulator_us: u64,
+    window_start_us: u64,
+    window: Stats,
+    frame_time_total_us: u64,
+    stats: Option<Stats>,
+}
+
+impl GameLoop {
+    /// Creates a loop running an update every `step_us` microseconds
+    pub fn new(step_us: u32) -> Self {
+        Self {
+            step_us: u64::from(step_us.max(1)),
+            max_updates: MAX_UPDATES_PER_FRAME,
+            last_us: None,
+            accumulator_us: 0,
+            window_start_us: 0,
+            window: Stats::default(),
+            frame_time_total_us: 0,
+            stats: None,
+        }
+    }
+
+    /// Changes the limit of updates run before the next render
+    pub fn set_max_updates_per_frame(&mut self, max_updates: u32) {
+        self.max_updates = max_updates.max(1);
+    }
+
+    /// Returns the fixed update step in microseconds
+    pub fn step_us(&self) -> u32 {
+        self.step_us as u32
+    }
+
+    /// Returns the fixed update step in milliseconds
+    pub fn step_ms(&self) -> u32 {
+        (self.step_us / 1000) as u32
+    }
+
+    /// Returns the time until the next update is due in microseconds
+    pub fn time_to_next_update_us(&self, now_us: u64) -> u64 {
+        match self.last_us {
+            Some(last_us) => {
+                let pending = self.accumulator_us + now_us.saturating_sub(last_us);
+                self.step_us.saturating_sub(pending)
+            }
+            None => 0,
+        }
+    }
+
+    /// Advances the loop to `now_us` and returns how many updates are due.
+    ///
+    /// This is the scheduling part of [`GameLoop::frame`] without running anything.
+    pub fn advance(&mut self, now_us: u64) -> Frame {
+        let elapsed_us = match self.last_us {
+            Some(last_us) => now_us.saturating_sub(last_us),
+            None => {
+                // Update and render right away on the very first frame
+                self.window_start_us = now_us;
+                self.step_us
+            }
+        };
+        self.last_us = Some(now_us);
+        self.accumulator_us += elapsed_us;
+
+        let due = self.accumulator_us / self.step_us;
+        self.accumulator_us %= self.step_us;
+
+        let updates = due.min(u64::from(self.max_updates)) as u32;
+        let skipped = (due - u64::from(updates)).min(u64::from(u32::MAX)) as u32;
+
+        Frame {
+            updates,
+            skipped,
+            rendered: updates > 0,
+        }
+    }
+
+    /// Calls `run` with all due updates, followed by a single render
+    pub fn frame<C, F>(&mut self, clock: &C, mut run: F) -> Frame
+    where
+        C: Clock,
+        F: FnMut(Step),
+    {
+        let start_us = clock.now_us();
+        let frame = self.advance(start_us);
+
+        let step_ms = self.step_ms();
+        for _ in 0..frame.updates {
+            run(Step::Update(step_ms));
+        }
+
+        if frame.rendered {
+            run(Step::Render);
+        }
+
+        self.record(&frame, start_us, clock.now_us());
+
+        frame
+    }
+
+    /// Adds a frame to the statistics, `start_us` and `end_us` enclose update and render
+    pub fn record(&mut self, frame: &Frame, start_us: u64, end_us: u64) {
+        self.window.ups += frame.updates;
+        self.window.skipped = self.window.skipped.saturating_add(frame.skipped);
+
+        if frame.rendered {
+            let frame_us = end_us.saturating_sub(start_us).min(u64::from(u32::MAX)) as u32;
+            self.window.fps += 1;
+            self.window.max_frame_us = self.window.max_frame_us.max(frame_us);
+            self.frame_time_total_us += u64::from(frame_us);
+        }
+
+        if end_us.saturating_sub(self.window_start_us) >= STATS_WINDOW_US {
+            if self.window.fps > 0 {
+                self.window.average_frame_us =
+                    (self.frame_time_total_us / u64::from(self.window.fps)) as u32;
+            }
+
+            self.stats = Some(self.window);
+            self.window = Stats::default();
+            self.frame_time_total_us = 0;
+            self.window_start_us = end_us;
+        }
+    }
+
+    /// Returns the statistics of the last second once, as soon as they are complete
+    pub fn take_stats(&mut self) -> Option<Stats> {
+        self.stats.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    const STEP_US: u32 = 50_000;
+
+    /// Clock which only moves when told to
+    #[derive(Default)]
+    struct MockClock {
+        now_us: Cell<u64>,
+    }
+
+    impl MockClock {
+        fn advance(&self, us: u64) {
+            self.now_us.set(self.now_us.get() + us);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now_us(&self) -> u64 {
+            self.now_us.get()
+        }
+    }
+
+    fn frame(updates: u32, skipped: u32) -> Frame {
+        Frame {
+            updates,
+            skipped,
+            rendered: updates > 0,
+        }
+    }
+
+    #[test]
+    fn updates_on_the_first_frame() {
+        let mut game_loop = GameLoop::new(STEP_US);
+        assert_eq!(game_loop.advance(123_456), frame(1, 0));
+        assert_eq!(game_loop.advance(123_456), frame(0, 0));
+    }
+
+    #[test]
+    fn runs_fixed_steps() {
+        let mut game_loop = GameLoop::new(STEP_US);
+        game_loop.advance(0);
+
+        assert_eq!(game_loop.advance(49_999), frame(0, 0));
+        assert_eq!(game_loop.time_to_next_update_us(49_999), 1);
+        assert_eq!(game_loop.advance(50_000), frame(1, 0));
+        // The remainder carries over
+        assert_eq!(game_loop.advance(130_000), frame(1, 0));
+        assert_eq!(game_loop.time_to_next_update_us(130_000), 20_000);
+        assert_eq!(game_loop.advance(150_000), frame(1, 0));
+        assert_eq!(game_loop.advance(250_000), frame(2, 0));
+    }
+
+    #[test]
+    fn caps_catch_up_and_skips_the_rest() {
+        let mut game_loop = GameLoop::new(STEP_US);
+        game_loop.advance(0);
+
+        // Ten steps late
+        assert_eq!(game_loop.advance(500_000), frame(5, 5));
+        // Skipped updates are gone, not postponed
+        assert_eq!(game_loop.advance(510_000), frame(0, 0));
+
+        game_loop.set_max_updates_per_frame(2);
+        assert_eq!(game_loop.advance(700_000), frame(2, 2));
+
+        // At least one update per frame
+        game_loop.set_max_updates_per_frame(0);
+        assert_eq!(game_loop.advance(800_000), frame(1, 1));
+    }
+
+    #[test]
+    fn survives_a_clock_going_backwards() {
+        let mut game_loop = GameLoop::new(STEP_US);
+        game_loop.advance(1_000_000);
+        assert_eq!(game_loop.advance(0), frame(0, 0));
+        assert_eq!(game_loop.advance(50_000), frame(1, 0));
+    }
+
+    #[test]
+    fn converts_the_step() {
+        let game_loop = GameLoop::new(STEP_US);
+        assert_eq!(game_loop.step_us(), 50_000);
+        assert_eq!(game_loop.step_ms(), 50);
+        assert_eq!(GameLoop::new(0).step_us(), 1);
+    }
+
+    #[test]
+    fn runs_updates_then_renders_once() {
+        let clock = MockClock::default();
+        let mut game_loop = GameLoop::new(STEP_US);
+        game_loop.advance(0);
+
+        clock.advance(3 * u64::from(STEP_US));
+        let mut steps = [None; 5];
+        let mut count = 0;
+        let result = game_loop.frame(&clock, |step| {
+            steps[count] = Some(step);
+            count += 1;
+        });
+
+        assert_eq!(result, frame(3, 0));
+        assert_eq!(
+            steps,
+            [
+                Some(Step::Update(50)),
+                Some(Step::Update(50)),
+                Some(Step::Update(50)),
+                Some(Step::Render),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_render_without_update() {
+        let clock = MockClock::default();
+        let mut game_loop = GameLoop::new(STEP_US);
+        game_loop.frame(&clock, |_| {});
+
+        clock.advance(10_000);
+        let mut called = false;
+        let result = game_loop.frame(&clock, |_| called = true);
+        assert_eq!(result, frame(0, 0));
+        assert!(!called);
+    }
+
+    #[test]
+    fn collects_stats_per_second() {
+        let clock = MockClock::default();
+        let mut game_loop = GameLoop::new(STEP_US);
+
+        // 20 frames, each rendering in 10 ms and waiting 40 ms for the next
+        for _ in 0..20 {
+            game_loop.frame(&clock, |step| {
+                if step == Step::Render {
+                    clock.advance(10_000);
+                }
+            });
+            assert_eq!(game_loop.take_stats(), None);
+            clock.advance(40_000);
+        }
+
+        // One long frame completes the second
+        game_loop.frame(&clock, |step| {
+            if step == Step::Render {
+                clock.advance(30_000);
+            }
+        });
+        let stats = game_loop.take_stats().expect("a second has passed");
+        assert_eq!(stats.fps, 21);
+        assert_eq!(stats.ups, 21);
+        assert_eq!(stats.skipped, 0);
+        assert_eq!(stats.max_frame_us, 30_000);
+        assert_eq!(stats.average_frame_us, (20 * 10_000 + 30_000) / 21);
+
+        // Taken only once
+        assert_eq!(game_loop.take_stats(), None);
+    }
+
+    #[test]
+    fn counts_skipped_updates_in_stats() {
+        let mut game_loop = GameLoop::new(STEP_US);
+        let first = game_loop.advance(0);
+        game_loop.record(&first, 0, 0);
+
+        let late = game_loop.advance(1_000_000);
+        assert_eq!(late, frame(5, 15));
+        game_loop.record(&late, 1_000_000, 1_000_000);
+
+        let stats = game_loop.take_stats().expect("a second has passed");
+        assert_eq!(stats.ups, 6);
+        assert_eq!(stats.skipped, 15);
+        assert_eq!(stats.fps, 2);
+    }
+}
@@ -0,0 +1,177 @@
+//! Buffers passed back and forth between a producer and a consumer.
+//!
+//! The [`Producer`] acquires a free buffer, fills it and presents it. The
+//! [`Consumer`] takes the presented buffer, e.g. to flush it to the display
+//! on the other core, and releases it again. With two buffers the producer
+//! fills one while the consumer works on the other, with a single buffer the
+//! two sides take turns but still run in parallel with other work.
+//!
+//! A [`FrameBuffer`](crate::framebuffer::FrameBuffer) takes more than half of
+//! the RAM, so the firmware hands over a single one. Drawing a frame and
+//! flushing the previous one are serialised then, the producer waits in
+//! [`Producer::acquire`] until the consumer has released the buffer.
+
+use crate::channel::{Channel, Receiver, Sender};
+
+/// Room for two buffers, plus the slot a channel keeps empty
+const SLOTS: usize = 3;
+
+/// Two channels carrying buffers in opposite directions
+pub struct Handoff<T: 'static> {
+    presented: Channel<&'static mut T, SLOTS>,
+    free: Channel<&'static mut T, SLOTS>,
+}
+
+impl<T: 'static> Handoff<T> {
+    /// Creates a handoff without buffers
+    pub const fn new() -> Self {
+        Self {
+            presented: Channel::new(),
+            free: Channel::new(),
+        }
+    }
+
+    /// Splits the handoff into its two ends, `first` and `second` start out free
+    pub fn split(
+        &mut self,
+        first: &'static mut T,
+        second: Option<&'static mut T>,
+    ) -> (Producer<'_, T>, Consumer<'_, T>) {
+        let (present, presented) = self.presented.split();
+        let (mut release, free) = self.free.split();
+
+        for buffer in core::iter::once(first).chain(second) {
+            // Two buffers always fit
+            let _ = release.send(buffer);
+        }
+
+        (Producer { present, free }, Consumer { presented, release })
+    }
+}
+
+impl<T: 'static> Default for Handoff<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// End filling the buffers
+pub struct Producer<'a, T: 'static> {
+    present: Sender<'a, &'static mut T, SLOTS>,
+    free: Receiver<'a, &'static mut T, SLOTS>,
+}
+
+impl<T: 'static> Producer<'_, T> {
+    /// Takes a free buffer, `None` while the consumer still holds all of them
+    pub fn acquire(&mut self) -> Option<&'static mut T> {
+        self.free.recv()
+    }
+
+    /// Hands a filled buffer to the consumer
+    pub fn present(&mut self, buffer: &'static mut T) {
+        // Only the buffers given to `split` circulate, so there is always room
+        let _ = self.present.send(buffer);
+    }
+}
+
+/// End using the filled buffers
+pub struct Consumer<'a, T: 'static> {
+    presented: Receiver<'a, &'static mut T, SLOTS>,
+    release: Sender<'a, &'static mut T, SLOTS>,
+}
+
+impl<T: 'static> Consumer<'_, T> {
+    /// Takes the oldest presented buffer, `None` if there is none
+    pub fn take(&mut self) -> Option<&'static mut T> {
+        self.presented.recv()
+    }
+
+    /// Gives a buffer back to the producer
+    pub fn release(&mut self, buffer: &'static mut T) {
+        let _ = self.release.send(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use super::*;
+
+    /// Returns a buffer living as long as the test process
+    fn buffer(value: u32) -> &'static mut u32 {
+        Box::leak(Box::new(value))
+    }
+
+    #[test]
+    fn takes_turns_with_one_buffer() {
+        let mut handoff = Handoff::new();
+        let (mut producer, mut consumer) = handoff.split(buffer(0), None);
+        assert_eq!(consumer.take(), None);
+
+        for frame in 1..=5 {
+            let buffer = producer.acquire().unwrap();
+            *buffer = frame;
+            producer.present(buffer);
+
+            // The producer waits until the consumer is done
+            assert_eq!(producer.acquire(), None);
+            let buffer = consumer.take().unwrap();
+            assert_eq!(*buffer, frame);
+            assert_eq!(consumer.take(), None);
+            consumer.release(buffer);
+        }
+    }
+
+    #[test]
+    fn fills_one_buffer_while_the_other_is_used() {
+        let mut handoff = Handoff::new();
+        let (mut producer, mut consumer) = handoff.split(buffer(0), Some(buffer(0)));
+
+        let first = producer.acquire().unwrap();
+        *first = 1;
+        producer.present(first);
+
+        // The consumer works on the first frame while the second one is drawn
+        let shown = consumer.take().unwrap();
+        let second = producer.acquire().unwrap();
+        *second = 2;
+        producer.present(second);
+        assert_eq!(producer.acquire(), None);
+
+        assert_eq!(*shown, 1);
+        consumer.release(shown);
+        let third = producer.acquire().unwrap();
+        assert_eq!(*third, 1);
+        *third = 3;
+
+        // Frames arrive in the order they were presented
+        assert_eq!(consumer.take().map(|buffer| *buffer), Some(2));
+        producer.present(third);
+        assert_eq!(consumer.take().map(|buffer| *buffer), Some(3));
+        assert_eq!(consumer.take(), None);
+    }
+
+    #[test]
+    fn presents_both_buffers_at_once() {
+        let mut handoff = Handoff::new();
+        let (mut producer, mut consumer) = handoff.split(buffer(1), Some(buffer(2)));
+
+        // Both buffers fit into either channel, so none is lost
+        for _ in 0..3 {
+            let first = producer.acquire().unwrap();
+            let second = producer.acquire().unwrap();
+            assert_eq!(producer.acquire(), None);
+            producer.present(first);
+            producer.present(second);
+
+            let first = consumer.take().unwrap();
+            let second = consumer.take().unwrap();
+            assert_eq!((*first, *second), (1, 2));
+            consumer.release(first);
+            consumer.release(second);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Power modes chosen by idle time, and statistics about sleeping.
+//!
+//! An [`IdleTimer`] tracks the time since the last input, like the
+//! [`Dimmer`](crate::dimmer::Dimmer), and picks a [`PowerMode`]: full speed
+//! while the player is around, a slower system clock once nobody has pressed a
+//! button for a while and dormant mode after a long time. [`SleepStats`]
+//! collects how long the core slept between frames.
+
+use crate::input::Input;
+
+/// Length of the window statistics are collected over in microseconds
+const STATS_WINDOW_US: u64 = 1_000_000;
+
+/// How much power the device may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerMode {
+    /// Full system clock
+    Run,
+    /// Reduced system clock, the game keeps running but is not drawn
+    Slow,
+    /// All clocks stopped until a button is pressed
+    Dormant,
+}
+
+/// Idle times after which the power mode drops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerConfig {
+    /// Time without input until the clock slows down in milliseconds, `None` never slows down.
+    ///
+    /// Nothing is drawn meanwhile, so this should not be shorter than the
+    /// time until the dimmer switches the backlight off.
+    pub slow_after_ms: Option<u32>,
+    /// Time without input until the device goes dormant in milliseconds, `None` never does
+    pub dormant_after_ms: Option<u32>,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            slow_after_ms: Some(60_000),
+            dormant_after_ms: Some(300_000),
+        }
+    }
+}
+
+/// Time since the last input, mapped to a [`PowerMode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdleTimer {
+    config: PowerConfig,
+    idle_ms: u32,
+}
+
+impl IdleTimer {
+    /// Creates a timer which starts out in [`PowerMode::Run`]
+    pub fn new(config: PowerConfig) -> Self {
+        Self { config, idle_ms: 0 }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> PowerConfig {
+        self.config
+    }
+
+    /// Changes the configuration
+    pub fn configure(&mut self, config: PowerConfig) {
+        self.config = config;
+    }
+
+    /// Restarts the idle time, e.g. after waking from dormant mode
+    pub fn wake(&mut self) {
+        self.idle_ms = 0;
+    }
+
+    /// Advances the timer by `dt_ms`, any held button restarts it
+    pub fn update(&mut self, input: &Input, dt_ms: u32) -> PowerMode {
+        if input.buttons().is_empty() {
+            self.idle_ms = self.idle_ms.saturating_add(dt_ms);
+        } else {
+            self.idle_ms = 0;
+        }
+        self.mode()
+    }
+
+    /// Returns the mode for the current idle time
+    pub fn mode(&self) -> PowerMode {
+        let expired = |limit: Option<u32>| limit.is_some_and(|ms| self.idle_ms >= ms);
+        if expired(self.config.dormant_after_ms) {
+            PowerMode::Dormant
+        } else if expired(self.config.slow_after_ms) {
+            PowerMode::Slow
+        } else {
+            PowerMode::Run
+        }
+    }
+}
+
+impl Default for IdleTimer {
+    fn default() -> Self {
+        Self::new(PowerConfig::default())
+    }
+}
+
+/// Sleep measured over the last second
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerStats {
+    /// Time spent running in microseconds
+    pub awake_us: u32,
+    /// Time spent waiting for the next frame in microseconds
+    pub asleep_us: u32,
+    /// Part of the time, awake or asleep, with the slow clock in microseconds
+    pub slow_us: u32,
+    /// Times the device went dormant, the timer stops meanwhile so it is not measured
+    pub dormant: u32,
+}
+
+impl PowerStats {
+    /// Returns the share of the time spent asleep in percent
+    pub fn asleep_percent(&self) -> u32 {
+        let total = u64::from(self.awake_us) + u64::from(self.asleep_us);
+        if total == 0 {
+            return 0;
+        }
+        (u64::from(self.asleep_us) * 100 / total) as u32
+    }
+}
+
+/// Collects [`PowerStats`] in windows of one second
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SleepStats {
+    window_start_us: Option<u64>,
+    // End of the previous sleep, the core is awake from here to the next one
+    last_us: u64,
+    window: PowerStats,
+    stats: Option<PowerStats>,
+}
+
+impl SleepStats {
+    /// Creates empty statistics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a sleep from `start_us` to `end_us`, the time since the previous one counts as awake.
+    ///
+    /// `slow` tells whether the clock was slow since the previous sleep, it
+    /// only changes between sleeps.
+    pub fn record_sleep(&mut self, start_us: u64, end_us: u64, slow: bool) {
+        let window_start_us = match self.window_start_us {
+            Some(window_start_us) => window_start_us,
+            None => {
+                self.last_us = start_us;
+                *self.window_start_us.insert(start_us)
+            }
+        };
+
+        let awake_us = start_us.saturating_sub(self.last_us);
+        let asleep_us = end_us.saturating_sub(start_us);
+        self.window.awake_us = add(self.window.awake_us, awake_us);
+        self.window.asleep_us = add(self.window.asleep_us, asleep_us);
+        if slow {
+            self.window.slow_us = add(self.window.slow_us, awake_us + asleep_us);
+        }
+        self.last_us = end_us;
+
+        if end_us.saturating_sub(window_start_us) >= STATS_WINDOW_US {
+            self.stats = Some(self.window);
+            self.window = PowerStats::default();
+            self.window_start_us = Some(end_us);
+        }
+    }
+
+    /// Records that the device went dormant at `now_us`, the time asleep is not known
+    pub fn record_dormant(&mut self, now_us: u64) {
+        self.window.dormant += 1;
+        self.window.awake_us = add(self.window.awake_us, now_us.saturating_sub(self.last_us));
+        // The timer stands still while dormant, so the device is awake again from here
+        self.last_us = now_us;
+    }
+
+    /// Returns the statistics of the last second once, as soon as they are complete
+    pub fn take_stats(&mut self) -> Option<PowerStats> {
+        self.stats.take()
+    }
+}
+
+fn add(total_us: u32, us: u64) -> u32 {
+    total_us.saturating_add(us.min(u64::from(u32::MAX)) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Button, Buttons};
+
+    const TICK_MS: u32 = 10;
+
+    /// Returns an input with A held down
+    fn holding() -> Input {
+        let mut input = Input::new();
+        let a = Buttons::from(Button::A);
+        input.update(a, TICK_MS);
+        input.update(a, TICK_MS);
+        assert!(input.is_down(Button::A));
+        input
+    }
+
+    /// Updates the timer without input for `ms` in ticks and returns the last mode
+    fn idle(timer: &mut IdleTimer, ms: u32) -> PowerMode {
+        let input = Input::new();
+        let mut mode = timer.mode();
+        for _ in 0..ms / TICK_MS {
+            mode = timer.update(&input, TICK_MS);
+        }
+        mode
+    }
+
+    #[test]
+    fn drops_power_mode_at_the_limits() {
+        let mut timer = IdleTimer::new(PowerConfig {
+            slow_after_ms: Some(100),
+            dormant_after_ms: Some(300),
+        });
+        assert_eq!(timer.mode(), PowerMode::Run);
+        assert_eq!(idle(&mut timer, 90), PowerMode::Run);
+        assert_eq!(idle(&mut timer, 10), PowerMode::Slow);
+        assert_eq!(idle(&mut timer, 190), PowerMode::Slow);
+        assert_eq!(idle(&mut timer, 10), PowerMode::Dormant);
+    }
+
+    #[test]
+    fn restarts_on_input_and_wake() {
+        let mut timer = IdleTimer::new(PowerConfig {
+            slow_after_ms: Some(100),
+            dormant_after_ms: Some(300),
+        });
+        assert_eq!(idle(&mut timer, 200), PowerMode::Slow);
+        assert_eq!(timer.update(&holding(), TICK_MS), PowerMode::Run);
+        assert_eq!(idle(&mut timer, 90), PowerMode::Run);
+
+        assert_eq!(idle(&mut timer, 300), PowerMode::Dormant);
+        timer.wake();
+        assert_eq!(timer.mode(), PowerMode::Run);
+    }
+
+    #[test]
+    fn never_drops_without_limits() {
+        let mut timer = IdleTimer::new(PowerConfig {
+            slow_after_ms: None,
+            dormant_after_ms: None,
+        });
+        assert_eq!(timer.update(&Input::new(), u32::MAX), PowerMode::Run);
+        // The idle time saturates instead of wrapping to zero
+        assert_eq!(timer.update(&Input::new(), u32::MAX), PowerMode::Run);
+
+        timer.configure(PowerConfig {
+            slow_after_ms: Some(1000),
+            dormant_after_ms: None,
+        });
+        assert_eq!(timer.mode(), PowerMode::Slow);
+
+        // Without a slow limit the timer goes straight to dormant
+        timer.configure(PowerConfig {
+            slow_after_ms: None,
+            dormant_after_ms: Some(1000),
+        });
+        timer.wake();
+        assert_eq!(idle(&mut timer, 990), PowerMode::Run);
+        assert_eq!(idle(&mut timer, 10), PowerMode::Dormant);
+        assert_eq!(timer.config().dormant_after_ms, Some(1000));
+    }
+
+    #[test]
+    fn measures_awake_time_since_the_last_sleep() {
+        let mut stats = SleepStats::new();
+        // The first sleep starts the window, nothing before it is known
+        stats.record_sleep(5_000, 9_000, false);
+        stats.record_sleep(12_000, 20_000, false);
+        stats.record_sleep(20_000, 21_000, false);
+        assert_eq!(stats.take_stats(), None);
+
+        // The window ends with the first sleep ending a second after it started
+        stats.record_sleep(1_000_000, 1_005_000, false);
+        let window = stats.take_stats().unwrap();
+        assert_eq!(
+            window,
+            PowerStats {
+                awake_us: 3_000 + 979_000,
+                asleep_us: 4_000 + 8_000 + 1_000 + 5_000,
+                slow_us: 0,
+                dormant: 0,
+            }
+        );
+        assert_eq!(window.asleep_percent(), 1);
+        assert_eq!(stats.take_stats(), None);
+    }
+
+    #[test]
+    fn starts_the_next_window_where_the_last_ended() {
+        let mut stats = SleepStats::new();
+        stats.record_sleep(0, 1_000_000, false);
+        assert_eq!(
+            stats.take_stats().map(|stats| stats.asleep_percent()),
+            Some(100)
+        );
+
+        stats.record_sleep(1_250_000, 1_500_000, false);
+        stats.record_sleep(1_750_000, 1_999_999, false);
+        assert_eq!(stats.take_stats(), None);
+        stats.record_sleep(1_999_999, 2_000_000, false);
+        let window = stats.take_stats().unwrap();
+        assert_eq!((window.awake_us, window.asleep_us), (500_000, 500_000));
+        assert_eq!(window.asleep_percent(), 50);
+
+        // A window not taken in time is replaced by the next one
+        stats.record_sleep(2_000_000, 3_000_000, false);
+        stats.record_sleep(3_000_000, 4_000_000, true);
+        assert_eq!(
+            stats.take_stats().map(|stats| stats.slow_us),
+            Some(1_000_000)
+        );
+    }
+
+    #[test]
+    fn counts_slow_time_awake_and_asleep() {
+        let mut stats = SleepStats::new();
+        stats.record_sleep(0, 100_000, false);
+        stats.record_sleep(300_000, 400_000, true);
+        stats.record_sleep(500_000, 1_000_000, false);
+        let window = stats.take_stats().unwrap();
+        assert_eq!(window.slow_us, 200_000 + 100_000);
+        assert_eq!(window.awake_us, 300_000);
+    }
+
+    #[test]
+    fn counts_dormant_time_as_awake_until_it_starts() {
+        let mut stats = SleepStats::new();
+        stats.record_sleep(0, 10_000, false);
+        stats.record_dormant(30_000);
+        // The timer stood still, so the next sleep follows right after
+        stats.record_sleep(40_000, 1_000_000, false);
+        assert_eq!(
+            stats.take_stats(),
+            Some(PowerStats {
+                awake_us: 20_000 + 10_000,
+                asleep_us: 10_000 + 960_000,
+                slow_us: 0,
+                dormant: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn saturates_long_times() {
+        let mut stats = SleepStats::new();
+        stats.record_sleep(0, 10, false);
+        stats.record_sleep(1 << 40, 1 << 41, true);
+        let window = stats.take_stats().unwrap();
+        assert_eq!(window.awake_us, u32::MAX);
+        assert_eq!(window.asleep_us, u32::MAX);
+        assert_eq!(window.slow_us, u32::MAX);
+        assert_eq!(window.asleep_percent(), 50);
+
+        // Times running backwards count as zero
+        let mut stats = SleepStats::new();
+        stats.record_sleep(500, 400, false);
+        stats.record_dormant(0);
+        stats.record_sleep(1_000_500, 1_000_500, false);
+        let window = stats.take_stats().unwrap();
+        assert_eq!((window.awake_us, window.asleep_us), (1_000_500, 0));
+        assert_eq!(PowerStats::default().asleep_percent(), 0);
+    }
+}
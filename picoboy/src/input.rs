@@ -0,0 +1,514 @@
+//! Portable access to the player controls.
+//!
+//! A [`Controls`] implementation samples the raw state of all buttons into a
+//! [`Buttons`] bitmask once per tick. [`Input`] debounces these samples and turns
+//! them into [`Event`]s, so the game never has to deal with bouncing contacts.
+
+use core::ops::BitOr;
+
+/// Number of buttons on the Picoboy Color
+pub const BUTTON_COUNT: usize = 7;
+
+/// A single button, the joystick counts as five buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Button {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Joystick pressed down
+    Center,
+    /// Right action button
+    A,
+    /// Left action button
+    B,
+}
+
+impl Button {
+    /// All buttons in bitmask order
+    pub const ALL: [Button; BUTTON_COUNT] = [
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+        Button::Center,
+        Button::A,
+        Button::B,
+    ];
+
+    const fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Set of buttons, stored as bitmask
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Buttons(u8);
+
+impl Buttons {
+    /// No button at all
+    pub const NONE: Buttons = Buttons(0);
+
+    /// Creates a set from its bitmask, bits without a button are ignored
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & ((1 << BUTTON_COUNT) - 1))
+    }
+
+    /// Returns the bitmask, bit `n` belongs to `Button::ALL[n]`
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns a copy of the set with `button` added
+    pub const fn with(self, button: Button) -> Self {
+        Self(self.0 | button.mask())
+    }
+
+    /// Returns `true` if `button` is part of the set
+    pub const fn contains(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    /// Returns `true` if no button is part of the set
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Adds or removes `button`
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.0 |= button.mask();
+        } else {
+            self.0 &= !button.mask();
+        }
+    }
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Self {
+        Buttons::NONE.with(button)
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl BitOr<Button> for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Button) -> Buttons {
+        self.with(rhs)
+    }
+}
+
+/// Source of raw button samples, the GPIO pins or a simulator script
+pub trait Controls {
+    /// Samples the current state of all buttons, a set bit means pressed
+    fn sample(&mut self) -> Buttons;
+}
+
+/// Auto-repeat timing of a button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Repeat {
+    /// Time between the press and the first repeat in milliseconds
+    pub delay_ms: u32,
+    /// Time between two repeats in milliseconds
+    pub interval_ms: u32,
+}
+
+/// Per-button configuration of [`Input`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonConfig {
+    /// Time a changed sample has to be stable before it is accepted in milliseconds
+    pub debounce_ms: u32,
+    /// Auto-repeat while the button is held, `None` disables it
+    pub repeat: Option<Repeat>,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 20,
+            repeat: None,
+        }
+    }
+}
+
+/// Something that happened to a button during the last tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The button has been pressed
+    Pressed(Button),
+    /// The button has been released
+    Released(Button),
+    /// The button is still held, with the time since it was pressed in milliseconds
+    Held(Button, u32),
+    /// The auto-repeat of a held button fired
+    Repeat(Button),
+}
+
+#[derive(Default, Clone, Copy)]
+struct ButtonState {
+    config: ButtonConfig,
+    // Time the raw sample differs from the debounced state, `None` while they match
+    bouncing_ms: Option<u32>,
+    // Time since the debounced press
+    held_ms: u32,
+    // Time until the next repeat
+    repeat_in_ms: u32,
+    repeated: bool,
+}
+
+impl ButtonState {
+    fn hold(&mut self, dt_ms: u32) {
+        self.held_ms = self.held_ms.saturating_add(dt_ms);
+
+        if let Some(repeat) = self.config.repeat {
+            if dt_ms >= self.repeat_in_ms {
+                self.repeated = true;
+                // Fire at most once per update, even if the interval is shorter than a tick
+                self.repeat_in_ms = (self.repeat_in_ms + repeat.interval_ms).saturating_sub(dt_ms);
+            } else {
+                self.repeat_in_ms -= dt_ms;
+            }
+        }
+    }
+}
+
+/// Debounced button state and edge detection
+pub struct Input {
+    buttons: [ButtonState; BUTTON_COUNT],
+    pressed: Buttons,
+    previous: Buttons,
+}
+
+impl Input {
+    /// Creates an input with all buttons released and default configuration
+    pub fn new() -> Self {
+        Self {
+            buttons: [ButtonState::default(); BUTTON_COUNT],
+            pressed: Buttons::NONE,
+            previous: Buttons::NONE,
+        }
+    }
+
+    /// Changes the configuration of a single button
+    pub fn configure(&mut self, button: Button, config: ButtonConfig) {
+        self.buttons[button as usize].config = config;
+    }
+
+    /// Returns the configuration of a single button
+    pub fn config(&self, button: Button) -> ButtonConfig {
+        self.buttons[button as usize].config
+    }
+
+    /// Feeds a raw sample taken `dt_ms` milliseconds after the previous one
+    pub fn update(&mut self, raw: Buttons, dt_ms: u32) {
+        self.previous = self.pressed;
+
+        for button in Button::ALL {
+            let state = &mut self.buttons[button as usize];
+            let was_pressed = self.previous.contains(button);
+            let mut is_pressed = was_pressed;
+            state.repeated = false;
+
+            if raw.contains(button) != was_pressed {
+                // Keep the previous state until the change proved to be stable
+                let bouncing_ms = state
+                    .bouncing_ms
+                    .map_or(dt_ms, |ms| ms.saturating_add(dt_ms));
+                if bouncing_ms >= state.config.debounce_ms {
+                    state.bouncing_ms = None;
+                    is_pressed = !was_pressed;
+                } else {
+                    state.bouncing_ms = Some(bouncing_ms);
+                }
+            } else {
+                state.bouncing_ms = None;
+            }
+
+            self.pressed.set(button, is_pressed);
+
+            if is_pressed && !was_pressed {
+                state.held_ms = 0;
+                state.repeat_in_ms = state.config.repeat.map_or(0, |repeat| repeat.delay_ms);
+            } else if is_pressed {
+                state.hold(dt_ms);
+            }
+        }
+    }
+
+    /// Returns all debounced buttons which are currently held
+    pub fn buttons(&self) -> Buttons {
+        self.pressed
+    }
+
+    /// Returns `true` while the button is held
+    pub fn is_down(&self, button: Button) -> bool {
+        self.pressed.contains(button)
+    }
+
+    /// Returns `true` if the button has been pressed during the last update
+    pub fn pressed(&self, button: Button) -> bool {
+        self.pressed.contains(button) && !self.previous.contains(button)
+    }
+
+    /// Returns `true` if the button has been released during the last update
+    pub fn released(&self, button: Button) -> bool {
+        !self.pressed.contains(button) && self.previous.contains(button)
+    }
+
+    /// Returns the time the button has been held in milliseconds
+    pub fn held(&self, button: Button) -> Option<u32> {
+        self.is_down(button)
+            .then(|| self.buttons[button as usize].held_ms)
+    }
+
+    /// Returns `true` if the button has just been pressed or its auto-repeat fired
+    pub fn pressed_or_repeated(&self, button: Button) -> bool {
+        self.pressed(button) || self.buttons[button as usize].repeated
+    }
+
+    /// Returns the events of the last update, ordered by button
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        Button::ALL.into_iter().flat_map(move |button| {
+            let state = &self.buttons[button as usize];
+
+            let edge = if self.pressed(button) {
+                Some(Event::Pressed(button))
+            } else if self.released(button) {
+                Some(Event::Released(button))
+            } else if self.is_down(button) {
+                Some(Event::Held(button, state.held_ms))
+            } else {
+                None
+            };
+            let repeat = state.repeated.then_some(Event::Repeat(button));
+
+            edge.into_iter().chain(repeat)
+        })
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_MS: u32 = 10;
+
+    /// Feeds `samples` one tick apart and returns the input afterwards
+    fn feed(input: &mut Input, samples: &[Buttons]) {
+        for &raw in samples {
+            input.update(raw, TICK_MS);
+        }
+    }
+
+    fn events(input: &Input) -> ([Option<Event>; 4], usize) {
+        let mut events = [None; 4];
+        let mut count = 0;
+        for event in input.events() {
+            events[count] = Some(event);
+            count += 1;
+        }
+        (events, count)
+    }
+
+    #[test]
+    fn buttons_mask() {
+        let buttons = Buttons::from(Button::A) | Button::Up;
+        assert!(buttons.contains(Button::A));
+        assert!(buttons.contains(Button::Up));
+        assert!(!buttons.contains(Button::B));
+        assert_eq!(buttons.bits(), 0b10_0001);
+        assert_eq!(Buttons::from_bits(0xff).bits(), 0x7f);
+        assert!(Buttons::NONE.is_empty());
+    }
+
+    #[test]
+    fn accepts_change_after_debounce_time() {
+        let mut input = Input::new();
+        let a = Buttons::from(Button::A);
+
+        // 20 ms at 10 ms per tick
+        input.update(a, TICK_MS);
+        assert!(!input.is_down(Button::A));
+        input.update(a, TICK_MS);
+        assert!(input.pressed(Button::A));
+
+        input.update(Buttons::NONE, TICK_MS);
+        assert!(input.is_down(Button::A));
+        input.update(Buttons::NONE, TICK_MS);
+        assert!(input.released(Button::A));
+        assert!(!input.is_down(Button::A));
+    }
+
+    #[test]
+    fn ignores_bouncing_contacts() {
+        let mut input = Input::new();
+        let a = Buttons::from(Button::A);
+
+        feed(&mut input, &[a, Buttons::NONE, a, Buttons::NONE, a]);
+        assert!(!input.is_down(Button::A));
+
+        input.update(a, TICK_MS);
+        assert!(input.pressed(Button::A));
+
+        // A short glitch while held does not release the button
+        feed(&mut input, &[Buttons::NONE, a, Buttons::NONE, a]);
+        assert!(input.is_down(Button::A));
+        assert!(!input.released(Button::A));
+    }
+
+    #[test]
+    fn without_debounce_follows_samples() {
+        let mut input = Input::new();
+        input.configure(
+            Button::B,
+            ButtonConfig {
+                debounce_ms: 0,
+                repeat: None,
+            },
+        );
+
+        input.update(Button::B.into(), TICK_MS);
+        assert!(input.pressed(Button::B));
+        input.update(Buttons::NONE, TICK_MS);
+        assert!(input.released(Button::B));
+    }
+
+    #[test]
+    fn edges_last_one_update() {
+        let mut input = Input::new();
+        let up = Buttons::from(Button::Up);
+
+        feed(&mut input, &[up, up]);
+        assert!(input.pressed(Button::Up));
+        input.update(up, TICK_MS);
+        assert!(!input.pressed(Button::Up));
+        assert!(input.is_down(Button::Up));
+        assert_eq!(input.buttons(), up);
+    }
+
+    #[test]
+    fn held_counts_time_since_press() {
+        let mut input = Input::new();
+        let left = Buttons::from(Button::Left);
+
+        feed(&mut input, &[left, left]);
+        assert_eq!(input.held(Button::Left), Some(0));
+        assert_eq!(
+            events(&input),
+            ([Some(Event::Pressed(Button::Left)), None, None, None], 1)
+        );
+
+        feed(&mut input, &[left; 5]);
+        assert_eq!(input.held(Button::Left), Some(50));
+        assert_eq!(
+            events(&input),
+            ([Some(Event::Held(Button::Left, 50)), None, None, None], 1)
+        );
+
+        feed(&mut input, &[Buttons::NONE, Buttons::NONE]);
+        assert_eq!(input.held(Button::Left), None);
+        assert_eq!(
+            events(&input),
+            ([Some(Event::Released(Button::Left)), None, None, None], 1)
+        );
+    }
+
+    #[test]
+    fn repeats_after_delay_and_interval() {
+        let mut input = Input::new();
+        input.configure(
+            Button::Down,
+            ButtonConfig {
+                debounce_ms: 0,
+                repeat: Some(Repeat {
+                    delay_ms: 30,
+                    interval_ms: 20,
+                }),
+            },
+        );
+        let down = Buttons::from(Button::Down);
+
+        let mut fired = [false; 10];
+        for fired in &mut fired {
+            input.update(down, TICK_MS);
+            *fired = input.buttons[Button::Down as usize].repeated;
+        }
+        // Pressed at 0 ms, repeats at 30, 50, 70 and 90 ms
+        assert_eq!(
+            fired,
+            [false, false, false, true, false, true, false, true, false, true]
+        );
+
+        input.update(down, TICK_MS);
+        assert!(!input.pressed_or_repeated(Button::Down));
+        input.update(down, TICK_MS);
+        assert!(input.pressed_or_repeated(Button::Down));
+        assert_eq!(
+            events(&input),
+            (
+                [
+                    Some(Event::Held(Button::Down, 110)),
+                    Some(Event::Repeat(Button::Down)),
+                    None,
+                    None
+                ],
+                2
+            )
+        );
+    }
+
+    #[test]
+    fn repeats_once_per_update_with_short_interval() {
+        let mut input = Input::new();
+        input.configure(
+            Button::Right,
+            ButtonConfig {
+                debounce_ms: 0,
+                repeat: Some(Repeat {
+                    delay_ms: 0,
+                    interval_ms: 1,
+                }),
+            },
+        );
+        let right = Buttons::from(Button::Right);
+
+        input.update(right, TICK_MS);
+        for _ in 0..5 {
+            input.update(right, TICK_MS);
+            assert!(input.pressed_or_repeated(Button::Right));
+        }
+    }
+
+    #[test]
+    fn buttons_are_independent() {
+        let mut input = Input::new();
+        let a = Buttons::from(Button::A);
+        let both = a | Button::B;
+
+        feed(&mut input, &[a, a, both]);
+        assert!(input.is_down(Button::A));
+        assert!(!input.is_down(Button::B));
+        input.update(both, TICK_MS);
+        assert!(input.pressed(Button::B));
+        assert!(!input.pressed(Button::A));
+    }
+}
@@ -0,0 +1,437 @@
+//! Blink patterns for the status LEDs.
+//!
+//! A [`Player`] turns a [`Pattern`] into a brightness over time. It does not
+//! block: the main loop advances it by the elapsed time, like the game, and
+//! outputs the returned brightness. Patterns repeat until another one is
+//! played, so a status stays visible without a debug probe.
+
+use crate::dimmer::MAX_BRIGHTNESS;
+
+/// Length of a Morse dot, dashes and gaps are multiples of it
+pub const MORSE_UNIT_MS: u32 = 150;
+
+/// Units between two repetitions of a Morse message
+const MORSE_PAUSE_UNITS: u32 = 14;
+
+/// Units a space adds to the gap after the previous character
+const SPACE_UNITS: u32 = 4;
+
+/// What an LED shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pattern {
+    /// Constantly lit at a brightness in percent, 0 is off
+    Steady(u8),
+    /// On and off for the given times in milliseconds
+    Blink { on_ms: u32, off_ms: u32 },
+    /// Fades in and out again over the given period in milliseconds
+    Breathe { period_ms: u32 },
+    /// Two short beats followed by a pause, over the given period in milliseconds
+    Heartbeat { period_ms: u32 },
+    /// Letters, digits and spaces in Morse code, other characters are skipped
+    Morse(&'static str),
+    /// A number in Morse code, one digit after the other
+    ErrorCode(u16),
+}
+
+impl Pattern {
+    /// Switched off
+    pub const OFF: Pattern = Pattern::Steady(0);
+    /// Lit at full brightness
+    pub const ON: Pattern = Pattern::Steady(MAX_BRIGHTNESS);
+    /// "SOS" in Morse code, shown after a crash
+    pub const SOS: Pattern = Pattern::Morse("SOS");
+
+    /// Returns the brightness `time_ms` after the start, which wraps at the end of the pattern
+    pub fn brightness_at(&self, time_ms: u32) -> u8 {
+        match *self {
+            Pattern::Steady(brightness) => brightness.min(MAX_BRIGHTNESS),
+            Pattern::Blink { on_ms, off_ms } => {
+                let time_ms = time_ms % (on_ms + off_ms).max(1);
+                lit(time_ms < on_ms)
+            }
+            Pattern::Breathe { period_ms } => {
+                let period_ms = period_ms.max(2);
+                let half_ms = period_ms / 2;
+                let time_ms = time_ms % period_ms;
+                let rise_ms = if time_ms < half_ms {
+                    time_ms
+                } else {
+                    period_ms - time_ms
+                };
+                (u32::from(MAX_BRIGHTNESS) * rise_ms.min(half_ms) / half_ms) as u8
+            }
+            Pattern::Heartbeat { period_ms } => {
+                // Beats of an eighth of the period, a gap of the same length in between
+                let beat_ms = (period_ms / 8).max(1);
+                let time_ms = time_ms % (beat_ms * 8);
+                lit(time_ms < beat_ms || (2 * beat_ms..3 * beat_ms).contains(&time_ms))
+            }
+            Pattern::Morse(text) => morse_at(text.bytes(), time_ms),
+            Pattern::ErrorCode(code) => morse_at(Digits::new(code), time_ms),
+        }
+    }
+}
+
+fn lit(on: bool) -> u8 {
+    if on {
+        MAX_BRIGHTNESS
+    } else {
+        0
+    }
+}
+
+/// Returns Morse code of a letter or digit as dots (`false`) and dashes (`true`)
+fn morse_code(character: u8) -> &'static [bool] {
+    const O: bool = false;
+    const I: bool = true;
+
+    match character.to_ascii_uppercase() {
+        b'A' => &[O, I],
+        b'B' => &[I, O, O, O],
+        b'C' => &[I, O, I, O],
+        b'D' => &[I, O, O],
+        b'E' => &[O],
+        b'F' => &[O, O, I, O],
+        b'G' => &[I, I, O],
+        b'H' => &[O, O, O, O],
+        b'I' => &[O, O],
+        b'J' => &[O, I, I, I],
+        b'K' => &[I, O, I],
+        b'L' => &[O, I, O, O],
+        b'M' => &[I, I],
+        b'N' => &[I, O],
+        b'O' => &[I, I, I],
+        b'P' => &[O, I, I, O],
+        b'Q' => &[I, I, O, I],
+        b'R' => &[O, I, O],
+        b'S' => &[O, O, O],
+        b'T' => &[I],
+        b'U' => &[O, O, I],
+        b'V' => &[O, O, O, I],
+        b'W' => &[O, I, I],
+        b'X' => &[I, O, O, I],
+        b'Y' => &[I, O, I, I],
+        b'Z' => &[I, I, O, O],
+        b'0' => &[I, I, I, I, I],
+        b'1' => &[O, I, I, I, I],
+        b'2' => &[O, O, I, I, I],
+        b'3' => &[O, O, O, I, I],
+        b'4' => &[O, O, O, O, I],
+        b'5' => &[O, O, O, O, O],
+        b'6' => &[I, O, O, O, O],
+        b'7' => &[I, I, O, O, O],
+        b'8' => &[I, I, I, O, O],
+        b'9' => &[I, I, I, I, O],
+        _ => &[],
+    }
+}
+
+/// Returns the brightness of a Morse message `time_ms` after its start
+fn morse_at(characters: impl Iterator<Item = u8> + Clone, time_ms: u32) -> u8 {
+    // A dot is one unit, a dash three, with one unit between the signals of a character,
+    // three between characters and seven between words
+    let mut units = characters.clone().map(character_units).sum::<u32>();
+    units += MORSE_PAUSE_UNITS;
+    let mut time = time_ms / MORSE_UNIT_MS % units;
+
+    for character in characters {
+        if character == b' ' {
+            if time < SPACE_UNITS {
+                return 0;
+            }
+            time -= SPACE_UNITS;
+            continue;
+        }
+
+        // Skipped characters take no time, see `character_units`
+        let code = morse_code(character);
+        if code.is_empty() {
+            continue;
+        }
+
+        for &dash in code {
+            let signal = if dash { 3 } else { 1 };
+            if time < signal {
+                return MAX_BRIGHTNESS;
+            }
+            if time < signal + 1 {
+                return 0;
+            }
+            time -= signal + 1;
+        }
+
+        // The gap after the last signal is one unit already
+        if time < 2 {
+            return 0;
+        }
+        time -= 2;
+    }
+
+    0
+}
+
+/// Units a character takes including the gap after it
+fn character_units(character: u8) -> u32 {
+    if character == b' ' {
+        return SPACE_UNITS;
+    }
+
+    let code = morse_code(character);
+    if code.is_empty() {
+        return 0;
+    }
+    code.iter()
+        .map(|&dash| if dash { 4 } else { 2 })
+        .sum::<u32>()
+        + 2
+}
+
+/// Decimal digits of a number as ASCII, most significant first
+#[derive(Clone)]
+struct Digits {
+    value: u16,
+    divisor: u16,
+}
+
+impl Digits {
+    fn new(value: u16) -> Self {
+        let mut divisor = 1;
+        while value / divisor >= 10 {
+            divisor *= 10;
+        }
+        Self { value, divisor }
+    }
+}
+
+impl Iterator for Digits {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.divisor == 0 {
+            return None;
+        }
+        let digit = (self.value / self.divisor % 10) as u8;
+        self.divisor /= 10;
+        Some(b'0' + digit)
+    }
+}
+
+/// Plays a [`Pattern`] over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Player {
+    pattern: Pattern,
+    elapsed_ms: u32,
+}
+
+impl Player {
+    /// Creates a player starting `pattern`
+    pub const fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            elapsed_ms: 0,
+        }
+    }
+
+    /// Starts `pattern` from the beginning, unless it is already playing
+    pub fn play(&mut self, pattern: Pattern) {
+        if pattern != self.pattern {
+            *self = Self::new(pattern);
+        }
+    }
+
+    /// Returns the pattern being played
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Advances the pattern by `dt_ms` and returns the brightness to show
+    pub fn advance(&mut self, dt_ms: u32) -> u8 {
+        self.elapsed_ms = self.elapsed_ms.wrapping_add(dt_ms);
+        self.brightness()
+    }
+
+    /// Returns the current brightness in percent
+    pub fn brightness(&self) -> u8 {
+        self.pattern.brightness_at(self.elapsed_ms)
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new(Pattern::OFF)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    /// Returns `#` for each lit and `.` for each dark Morse unit of the first `count` units
+    fn units(pattern: Pattern, count: u32) -> String {
+        (0..count)
+            .map(|unit| match pattern.brightness_at(unit * MORSE_UNIT_MS) {
+                0 => '.',
+                _ => '#',
+            })
+            .collect()
+    }
+
+    /// Returns the units of a character written in dots and dashes, including the gap after it
+    fn code(signals: &str) -> String {
+        let mut units: String = signals
+            .chars()
+            .map(|signal| if signal == '-' { "###." } else { "#." })
+            .collect();
+        units.push_str("..");
+        units
+    }
+
+    /// Returns the units between two repetitions
+    fn pause() -> String {
+        ".".repeat(MORSE_PAUSE_UNITS as usize)
+    }
+
+    /// Asserts that `pattern` shows `cycle` and then starts over
+    fn assert_cycle(pattern: Pattern, cycle: &str) {
+        let count = cycle.len() as u32;
+        assert_eq!(units(pattern, 2 * count), cycle.repeat(2), "{pattern:?}");
+    }
+
+    #[test]
+    fn limits_steady_brightness() {
+        assert_eq!(Pattern::OFF.brightness_at(1234), 0);
+        assert_eq!(Pattern::ON.brightness_at(1234), MAX_BRIGHTNESS);
+        assert_eq!(Pattern::Steady(40).brightness_at(0), 40);
+        assert_eq!(Pattern::Steady(150).brightness_at(0), MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn blinks_on_then_off() {
+        let blink = Pattern::Blink {
+            on_ms: 100,
+            off_ms: 300,
+        };
+        assert_eq!(blink.brightness_at(0), MAX_BRIGHTNESS);
+        assert_eq!(blink.brightness_at(99), MAX_BRIGHTNESS);
+        assert_eq!(blink.brightness_at(100), 0);
+        assert_eq!(blink.brightness_at(399), 0);
+        assert_eq!(blink.brightness_at(400), MAX_BRIGHTNESS);
+        assert_eq!(blink.brightness_at(4100), 0);
+
+        // Without any time the LED stays off instead of dividing by zero
+        let empty = Pattern::Blink {
+            on_ms: 0,
+            off_ms: 0,
+        };
+        assert_eq!(empty.brightness_at(10), 0);
+    }
+
+    #[test]
+    fn breathes_in_a_triangle() {
+        let breathe = Pattern::Breathe { period_ms: 1000 };
+        let brightness =
+            [0, 250, 499, 500, 750, 999, 1000, 1250].map(|ms| breathe.brightness_at(ms));
+        assert_eq!(brightness, [0, 50, 99, 100, 50, 0, 0, 50]);
+
+        // Too short periods are stretched to the shortest one possible
+        let short = Pattern::Breathe { period_ms: 0 };
+        assert_eq!([0, 1, 2].map(|ms| short.brightness_at(ms)), [0, 100, 0]);
+    }
+
+    #[test]
+    fn beats_twice_per_period() {
+        let heartbeat = Pattern::Heartbeat { period_ms: 800 };
+        let lit: String = (0..16)
+            .map(|step| match heartbeat.brightness_at(step * 50) {
+                0 => '.',
+                _ => '#',
+            })
+            .collect();
+        assert_eq!(lit, "##..##..........");
+        assert_eq!(heartbeat.brightness_at(800), MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn signals_sos() {
+        let sos = [code("..."), code("---"), code("..."), pause()].concat();
+        assert_eq!(sos.len(), 44);
+        assert!(sos.starts_with("#.#.#...###.###.###...#.#.#..."));
+        assert_cycle(Pattern::SOS, &sos);
+
+        // The time within a unit does not matter
+        assert_eq!(
+            Pattern::SOS.brightness_at(MORSE_UNIT_MS - 1),
+            MAX_BRIGHTNESS
+        );
+        assert_eq!(Pattern::SOS.brightness_at(MORSE_UNIT_MS), 0);
+    }
+
+    #[test]
+    fn separates_words() {
+        let words = [code("."), "....".into(), code("-"), pause()].concat();
+        assert_cycle(Pattern::Morse("E T"), &words);
+        assert_cycle(Pattern::Morse("e t"), &words);
+    }
+
+    #[test]
+    fn signals_error_codes_digit_by_digit() {
+        assert_cycle(Pattern::ErrorCode(0), &[code("-----"), pause()].concat());
+        assert_cycle(
+            Pattern::ErrorCode(1203),
+            &[
+                code(".----"),
+                code("..---"),
+                code("-----"),
+                code("...--"),
+                pause(),
+            ]
+            .concat(),
+        );
+        assert_cycle(
+            Pattern::ErrorCode(10),
+            &[code(".----"), code("-----"), pause()].concat(),
+        );
+    }
+
+    #[test]
+    fn skips_other_characters() {
+        let seven = [code(".").repeat(7), pause()].concat();
+        assert_cycle(Pattern::Morse("EEEEEEE"), &seven);
+        assert_cycle(Pattern::Morse("E-E-E-E-E-E-E"), &seven);
+        assert_cycle(Pattern::Morse("!?SOS."), &units(Pattern::SOS, 44));
+        assert_cycle(
+            Pattern::Morse("E\u{e4}E"),
+            &[code("."), code("."), pause()].concat(),
+        );
+    }
+
+    #[test]
+    fn stays_dark_without_signals() {
+        assert_eq!(units(Pattern::Morse(""), 30), ".".repeat(30));
+        assert_eq!(units(Pattern::Morse("#"), 30), ".".repeat(30));
+    }
+
+    #[test]
+    fn restarts_only_for_another_pattern() {
+        let blink = Pattern::Blink {
+            on_ms: 100,
+            off_ms: 100,
+        };
+        let mut player = Player::new(blink);
+        assert_eq!(player.advance(150), 0);
+
+        player.play(blink);
+        assert_eq!(player.brightness(), 0);
+
+        player.play(Pattern::SOS);
+        assert_eq!(player.pattern(), Pattern::SOS);
+        assert_eq!(player.brightness(), MAX_BRIGHTNESS);
+        assert_eq!(player.advance(MORSE_UNIT_MS), 0);
+    }
+}
@@ -0,0 +1,212 @@
+//! PWM control of the red, yellow and green status LEDs.
+//!
+//! Each [`Led`] plays a [`Pattern`] from [`led_pattern`](crate::led_pattern),
+//! advanced by [`Leds::update`] from the main loop. The green and yellow LEDs
+//! use PWM slice 6. The red LED shares slice 7 with the speaker, which sets
+//! its period and owns the slice, so the red duty cycle is written through the
+//! atomic set and clear aliases without disturbing the speaker's channel.
+//!
+//! After a crash nothing ticks the patterns anymore. [`show_fault`] then takes
+//! over the red LED as a plain GPIO and plays a pattern by busy waiting.
+
+use picoboy_color::hal::{
+    gpio::{
+        bank0::{Gpio12, Gpio13, Gpio14},
+        FunctionPwm, Pin, PinId, PullDown,
+    },
+    pac,
+    pwm::{FreeRunning, Pwm6, Slice},
+};
+
+use crate::crash_screen;
+use crate::dimmer::{duty, MAX_BRIGHTNESS};
+use crate::led_pattern::{Pattern, Player};
+
+/// Top of the PWM counter, the speaker runs slice 7 with the same one
+const TOP: u16 = u8::MAX as u16;
+
+/// GPIO of the red LED
+const RED_PIN: usize = 14;
+
+/// Time between two steps of [`show_fault`]
+const FAULT_TICK_MS: u32 = 10;
+
+/// Rough frequency of the ring oscillator the RP2040 runs from until the clocks are set up
+const ROSC_HZ: u32 = 6_000_000;
+
+/// Offsets of the atomic register aliases
+const SET_ALIAS: usize = 0x2000;
+const CLEAR_ALIAS: usize = 0x3000;
+
+/// A single status LED
+pub struct Led<I: PinId> {
+    _pin: Pin<I, FunctionPwm, PullDown>,
+    slice: usize,
+    // Bit offset of the compare value in the CC register, 0 for channel A and 16 for B
+    shift: u32,
+    player: Player,
+    brightness: u8,
+}
+
+impl<I: PinId> Led<I> {
+    fn new(pin: Pin<I, FunctionPwm, PullDown>) -> Self {
+        // Every GPIO belongs to one of 8 slices, channel A on even and B on odd pins
+        let number = usize::from(pin.id().num);
+        let mut led = Self {
+            _pin: pin,
+            slice: number / 2 % 8,
+            shift: if number % 2 == 0 { 0 } else { 16 },
+            player: Player::default(),
+            brightness: 0,
+        };
+        led.output(0);
+        led
+    }
+
+    /// Plays `pattern` from its start, unless it is already playing
+    pub fn play(&mut self, pattern: Pattern) {
+        self.player.play(pattern);
+        self.output(self.player.brightness());
+    }
+
+    /// Returns the pattern being played
+    pub fn pattern(&self) -> Pattern {
+        self.player.pattern()
+    }
+
+    /// Lights the LED constantly at `brightness` percent
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.play(Pattern::Steady(brightness));
+    }
+
+    /// Switches the LED on or off
+    pub fn set(&mut self, on: bool) {
+        self.play(if on { Pattern::ON } else { Pattern::OFF });
+    }
+
+    /// Switches the LED on
+    pub fn on(&mut self) {
+        self.set(true);
+    }
+
+    /// Switches the LED off
+    pub fn off(&mut self) {
+        self.set(false);
+    }
+
+    /// Advances the pattern by `dt_ms`
+    pub fn update(&mut self, dt_ms: u32) {
+        let brightness = self.player.advance(dt_ms);
+        self.output(brightness);
+    }
+
+    fn output(&mut self, brightness: u8) {
+        if brightness == self.brightness {
+            return;
+        }
+        self.brightness = brightness;
+
+        // A compare value above the top keeps the output high
+        let level = if brightness >= MAX_BRIGHTNESS {
+            u32::from(TOP) + 1
+        } else {
+            u32::from(duty(brightness) >> 8)
+        };
+
+        // SAFETY: only the half of the register belonging to this LED changes, and each of the
+        // two writes is a single store, so the speaker interrupt cannot undo it
+        unsafe {
+            let cc = (*pac::PWM::ptr()).ch(self.slice).cc().as_ptr() as usize;
+            core::ptr::write_volatile((cc + CLEAR_ALIAS) as *mut u32, 0xFFFF << self.shift);
+            core::ptr::write_volatile((cc + SET_ALIAS) as *mut u32, level << self.shift);
+        }
+    }
+}
+
+/// The red, yellow and green status LEDs
+pub struct Leds {
+    pub red: Led<Gpio14>,
+    pub yellow: Led<Gpio13>,
+    pub green: Led<Gpio12>,
+    _pwm: Slice<Pwm6, FreeRunning>,
+}
+
+impl Leds {
+    pub(crate) fn new(
+        mut pwm: Slice<Pwm6, FreeRunning>,
+        red: Pin<Gpio14, FunctionPwm, PullDown>,
+        yellow: Pin<Gpio13, FunctionPwm, PullDown>,
+        green: Pin<Gpio12, FunctionPwm, PullDown>,
+    ) -> Self {
+        pwm.set_top(TOP);
+        pwm.set_div_int(1);
+        pwm.enable();
+
+        Self {
+            red: Led::new(red),
+            yellow: Led::new(yellow),
+            green: Led::new(green),
+            _pwm: pwm,
+        }
+    }
+
+    /// Advances the patterns of all LEDs by `dt_ms`
+    pub fn update(&mut self, dt_ms: u32) {
+        self.red.update(dt_ms);
+        self.yellow.update(dt_ms);
+        self.green.update(dt_ms);
+    }
+
+    /// Switches all LEDs off until the next [`update`](Self::update), keeping their patterns
+    pub fn blank(&mut self) {
+        self.red.output(0);
+        self.yellow.output(0);
+        self.green.output(0);
+    }
+}
+
+/// Plays `pattern` on the red LED forever, e.g. from the `HardFault` handler.
+///
+/// Works whatever state the LEDs were left in, even before `Board::take`. The
+/// timing uses the system clock recorded by the board, or an estimate of the
+/// ring oscillator before, so it is only roughly right in that case.
+pub fn show_fault(pattern: Pattern) -> ! {
+    // SAFETY: the application has stopped, nothing else uses these peripherals anymore
+    let (resets, io, sio, clocks) = unsafe {
+        (
+            &*pac::RESETS::ptr(),
+            &*pac::IO_BANK0::ptr(),
+            &*pac::SIO::ptr(),
+            &*pac::CLOCKS::ptr(),
+        )
+    };
+
+    // Full speed, in case the power manager slowed the clock down, 1 is also the reset value
+    // SAFETY: the divider of the system clock may change at any time
+    clocks.clk_sys_div().write(|w| unsafe { w.int().bits(1) });
+    let cycles_per_ms = crash_screen::system_clock_hz().unwrap_or(ROSC_HZ) / 1000;
+
+    // Bring the GPIOs out of reset in case the crash happened before
+    resets
+        .reset()
+        .modify(|_, w| w.io_bank0().clear_bit().pads_bank0().clear_bit());
+    while !resets.reset_done().read().io_bank0().bit() {}
+    while !resets.reset_done().read().pads_bank0().bit() {}
+
+    io.gpio(RED_PIN).gpio_ctrl().write(|w| w.funcsel().sio());
+    // SAFETY: only the bit of the red LED is set
+    sio.gpio_oe_set().write(|w| unsafe { w.bits(1 << RED_PIN) });
+
+    let mut player = Player::new(pattern);
+    loop {
+        // SAFETY: only the bit of the red LED is set
+        if player.advance(FAULT_TICK_MS) > 0 {
+            sio.gpio_out_set()
+                .write(|w| unsafe { w.bits(1 << RED_PIN) });
+        } else {
+            sio.gpio_out_clr()
+                .write(|w| unsafe { w.bits(1 << RED_PIN) });
+        }
+        cortex_m::asm::delay(FAULT_TICK_MS * cycles_per_ms);
+    }
+}
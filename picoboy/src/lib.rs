@@ -0,0 +1,71 @@
+//! Reusable building blocks for PicoBoy Color applications.
+//!
+//! Everything except the hardware drivers is portable and builds on the host.
+//! The drivers are enabled with the `rp2040` feature, the executor and the
+//! futures for timers and buttons with the `async` feature, the USB serial
+//! console and gamepad with the `usb-serial` and `usb-gamepad` features.
+#![no_std]
+
+pub mod audio;
+#[cfg(feature = "rp2040")]
+pub mod backlight;
+pub mod battery;
+#[cfg(feature = "rp2040")]
+pub mod board;
+#[cfg(feature = "rp2040")]
+pub mod boot;
+#[cfg(all(feature = "rp2040", feature = "async"))]
+pub mod button_edges;
+pub mod channel;
+pub mod console;
+pub mod crash;
+#[cfg(feature = "rp2040")]
+pub mod crash_screen;
+pub mod dimmer;
+pub mod display;
+#[cfg(feature = "rp2040")]
+pub mod dma_interface;
+pub mod error;
+#[cfg(feature = "async")]
+pub mod executor;
+pub mod flash_emulator;
+pub mod framebuffer;
+pub mod game_loop;
+pub mod handoff;
+pub mod idle;
+pub mod input;
+pub mod led_pattern;
+#[cfg(feature = "rp2040")]
+pub mod leds;
+#[cfg(feature = "rp2040")]
+pub mod multicore;
+#[cfg(feature = "rp2040")]
+pub mod panel;
+pub mod physics;
+#[cfg(feature = "rp2040")]
+pub mod power;
+#[cfg(feature = "rp2040")]
+pub mod rom_flash;
+pub mod sample_queue;
+pub mod scene;
+pub mod screenshot;
+#[cfg(all(feature = "rp2040", feature = "async"))]
+pub mod sleep;
+pub mod slots;
+#[cfg(feature = "rp2040")]
+pub mod speaker;
+pub mod sprite;
+pub mod storage;
+pub mod supervisor;
+pub mod text;
+pub mod tilemap;
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+pub mod usb;
+#[cfg(feature = "usb-gamepad")]
+pub mod usb_gamepad;
+#[cfg(feature = "usb-serial")]
+pub mod usb_serial;
+#[cfg(feature = "rp2040")]
+pub mod voltage_monitor;
+#[cfg(feature = "rp2040")]
+pub mod watchdog;
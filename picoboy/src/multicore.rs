@@ -0,0 +1,138 @@
+//! Second core of the RP2040.
+//!
+//! [`Core1::spawn`] starts a task on core 1 through the SIO FIFO launch
+//! sequence of the boot ROM. The task gets its own [`Stack`], whose lowest
+//! bytes are guarded by the MPU, while core 0 keeps the stack placed by
+//! flip-link. The cores exchange data through [`Channel`](crate::channel::Channel)s
+//! and [`Handoff`](crate::handoff::Handoff)s, [`wait`] sleeps until the other
+//! core calls [`notify`].
+//!
+//! While the flash is written, core 1 must not run code from it. [`lockout`]
+//! sends a request through the FIFO, upon which core 1 parks in RAM until the
+//! write is done. For this the application forwards `SIO_IRQ_PROC1` to
+//! [`on_fifo_interrupt`].
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use picoboy_color::hal::{multicore::Multicore, pac, sio::SioFifo};
+
+pub use picoboy_color::hal::multicore::{Error, Stack};
+
+/// Asks core 1 to park in RAM
+const LOCKOUT_REQUEST: u32 = 0x4C4F_434B;
+
+// Set once a task runs on core 1
+static CORE1_RUNNING: AtomicBool = AtomicBool::new(false);
+// Set by core 0 for the duration of a lockout
+static LOCKED: AtomicBool = AtomicBool::new(false);
+// Set by core 1 while it is parked
+static PARKED: AtomicBool = AtomicBool::new(false);
+
+/// Handle to start core 1
+pub struct Core1 {
+    psm: pac::PSM,
+    ppb: pac::PPB,
+    fifo: SioFifo,
+}
+
+impl Core1 {
+    pub(crate) fn new(psm: pac::PSM, ppb: pac::PPB, fifo: SioFifo) -> Self {
+        Self { psm, ppb, fifo }
+    }
+
+    /// Runs `task` on core 1, restarting the core if it already ran a task.
+    ///
+    /// The stack has to hold everything the task puts on it, an overflow ends
+    /// in a HardFault on core 1 instead of corrupting memory.
+    pub fn spawn<F, const WORDS: usize>(
+        &mut self,
+        stack: &'static mut Stack<WORDS>,
+        task: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut multicore = Multicore::new(&mut self.psm, &mut self.ppb, &mut self.fifo);
+        multicore.cores()[1].spawn(&mut stack.mem, move || {
+            // SAFETY: the handler only parks the core, which is safe at any point
+            unsafe { pac::NVIC::unmask(pac::Interrupt::SIO_IRQ_PROC1) };
+            task();
+        })?;
+
+        CORE1_RUNNING.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Sleeps until `poll` returns a value
+pub fn wait<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+    loop {
+        if let Some(value) = poll() {
+            return value;
+        }
+        // Events are latched, so one sent since the poll ends the wait right away
+        cortex_m::asm::wfe();
+    }
+}
+
+/// Wakes the other core if it is in [`wait`]
+pub fn notify() {
+    cortex_m::asm::sev();
+}
+
+/// Runs `f` on core 0 while core 1 is parked in RAM, e.g. to write the flash
+pub(crate) fn lockout<R>(f: impl FnOnce() -> R) -> R {
+    if !CORE1_RUNNING.load(Ordering::Acquire) {
+        return f();
+    }
+
+    LOCKED.store(true, Ordering::Release);
+
+    // SAFETY: only the FIFO towards core 1 is written, nothing else on core 0 uses it
+    let sio = unsafe { &*pac::SIO::ptr() };
+    while !sio.fifo_st().read().rdy().bit() {}
+    // SAFETY: any value can be written to the FIFO
+    sio.fifo_wr().write(|w| unsafe { w.bits(LOCKOUT_REQUEST) });
+    while !PARKED.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+
+    let result = f();
+
+    LOCKED.store(false, Ordering::Release);
+    while PARKED.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+
+    result
+}
+
+/// Handles requests from core 0, call this from `SIO_IRQ_PROC1`
+pub fn on_fifo_interrupt() {
+    // SAFETY: only core 1 receives this interrupt and reads its FIFO
+    let sio = unsafe { &*pac::SIO::ptr() };
+    while sio.fifo_st().read().vld().bit() {
+        if sio.fifo_rd().read().bits() == LOCKOUT_REQUEST {
+            park();
+        }
+    }
+
+    // Clear overflow and underflow flags, they would keep the interrupt pending
+    sio.fifo_st()
+        .write(|w| w.roe().clear_bit_by_one().wof().clear_bit_by_one());
+}
+
+/// Spins in RAM with interrupts disabled until the lockout ends
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+fn park() {
+    // SAFETY: interrupts are enabled again below
+    unsafe { core::arch::asm!("cpsid i") };
+    PARKED.store(true, Ordering::Release);
+    while LOCKED.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+    PARKED.store(false, Ordering::Release);
+    // SAFETY: the handler was entered with interrupts enabled
+    unsafe { core::arch::asm!("cpsie i") };
+}
@@ -0,0 +1,209 @@
+//! The ST7789 display in any of its four orientations.
+//!
+//! The `st7789` driver addresses the RAM of the controller directly, which is
+//! 240x320 pixels while the panel only shows 240x280 of them. [`Panel`] wraps
+//! it and draws in picture coordinates instead: it clips to the visible area
+//! of the current [`DisplayConfig`] and moves everything by its RAM offset.
+//!
+//! When the controller does not take a command, [`Panel::retry`] resets it,
+//! sends the initialisation sequence again and repeats what failed.
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+use st7789::ST7789;
+
+use picoboy_color::hal::{gpio::bank0::Gpio9, Timer};
+
+use crate::board::{DisplayInterface, Output};
+use crate::crash_screen;
+use crate::display::{DisplayConfig, Orientation};
+use crate::error::{DisplayOp, Error};
+use crate::framebuffer::FrameBuffer;
+
+/// ST7789 driver as wired on the PicoBoy Color
+pub type Driver = ST7789<DisplayInterface, Output<Gpio9>>;
+
+/// Attempts of [`Panel::retry`], each one after the first follows a reset of the display
+const MAX_ATTEMPTS: u32 = 3;
+
+impl From<Orientation> for st7789::Orientation {
+    fn from(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Portrait => st7789::Orientation::Portrait,
+            Orientation::Landscape => st7789::Orientation::Landscape,
+            Orientation::PortraitSwapped => st7789::Orientation::PortraitSwapped,
+            Orientation::LandscapeSwapped => st7789::Orientation::LandscapeSwapped,
+        }
+    }
+}
+
+/// Display drawing in the coordinates of its current orientation
+pub struct Panel {
+    driver: Driver,
+    config: DisplayConfig,
+    // Delays of the initialisation sequence
+    timer: Timer,
+    // The last initialisation failed, the next operation starts with another
+    uninitialised: bool,
+}
+
+impl Panel {
+    /// Initialises the display and applies `config`.
+    ///
+    /// A display which does not respond is logged and initialised again by
+    /// the next operation, so the rest of the board comes up regardless.
+    pub(crate) fn new(driver: Driver, config: DisplayConfig, timer: Timer) -> Self {
+        let mut panel = Self {
+            driver,
+            config,
+            timer,
+            uninitialised: true,
+        };
+        if let Err(error) = panel.retry(|_, _| Ok(())) {
+            defmt::warn!("Display not initialised: {}", error);
+        }
+        panel
+    }
+
+    /// Returns the current configuration
+    pub fn config(&self) -> DisplayConfig {
+        self.config
+    }
+
+    /// Turns the picture and clears the display.
+    ///
+    /// A [`FrameBuffer`](crate::framebuffer::FrameBuffer) drawing on it needs
+    /// the new size as well, see [`DisplayConfig::size`].
+    pub fn set_config(&mut self, config: DisplayConfig) -> Result<(), Error> {
+        self.retry(|panel, _| panel.apply(config))
+    }
+
+    /// Resets the controller and initialises it again with the current configuration.
+    ///
+    /// The display is cleared, a [`FrameBuffer`] has to send everything again.
+    pub fn reinit(&mut self) -> Result<(), Error> {
+        self.uninitialised = true;
+        self.driver
+            .init(&mut self.timer)
+            .map_err(|error| Error::from_driver(DisplayOp::Init, error))?;
+        self.apply(self.config)?;
+        self.uninitialised = false;
+        Ok(())
+    }
+
+    /// Runs `f` and, as long as it fails, resets the display and runs it again.
+    ///
+    /// `f` learns whether the display was reset before, which cleared it.
+    /// After [`MAX_ATTEMPTS`] the last error is returned. A display whose last
+    /// initialisation failed is reset before the first attempt.
+    pub fn retry<R>(
+        &mut self,
+        mut f: impl FnMut(&mut Self, bool) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let mut result = if self.uninitialised {
+            self.reinit().and_then(|()| f(self, true))
+        } else {
+            f(self, false)
+        };
+        for attempt in 2..=MAX_ATTEMPTS {
+            let Err(error) = result else {
+                break;
+            };
+            defmt::warn!(
+                "{}, resetting the display (attempt {} of {})",
+                error,
+                attempt,
+                MAX_ATTEMPTS
+            );
+            result = match self.reinit() {
+                Ok(()) => f(self, true),
+                Err(error) => Err(error),
+            };
+        }
+        result
+    }
+
+    /// Sends the dirty regions of `framebuffer`, all of it after a reset of the display
+    pub fn flush(&mut self, framebuffer: &mut FrameBuffer) -> Result<(), Error> {
+        self.retry(|panel, reset| {
+            if reset {
+                framebuffer.mark_dirty(framebuffer.bounding_box());
+            }
+            framebuffer.flush(panel)
+        })
+    }
+
+    fn apply(&mut self, config: DisplayConfig) -> Result<(), Error> {
+        self.driver
+            .set_orientation(config.orientation.into())
+            .map_err(|error| Error::from_driver(DisplayOp::Orientation, error))?;
+        self.config = config;
+        crash_screen::record_display(config);
+        // Clears the whole RAM, including the rows the panel does not show
+        self.driver
+            .clear(Rgb565::BLACK)
+            .map_err(|error| Error::from_driver(DisplayOp::Draw, error))
+    }
+
+    /// Returns the driver, which draws in RAM coordinates
+    pub fn driver(&mut self) -> &mut Driver {
+        &mut self.driver
+    }
+}
+
+impl DrawTarget for Panel {
+    type Color = Rgb565;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+        let offset = self.config.offset();
+        let pixels = pixels
+            .into_iter()
+            .filter(|Pixel(point, _)| bounds.contains(*point))
+            .map(|Pixel(point, color)| Pixel(point + offset, color));
+        self.driver.draw_iter(pixels).map_err(draw_error)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        // Areas within the picture go out in one burst, others pixel by pixel
+        if self.bounding_box().intersection(area) == *area {
+            let area = area.translate(self.config.offset());
+            self.driver
+                .fill_contiguous(&area, colors)
+                .map_err(draw_error)
+        } else {
+            let pixels = area
+                .points()
+                .zip(colors)
+                .map(|(point, color)| Pixel(point, color));
+            self.draw_iter(pixels)
+        }
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area
+            .intersection(&self.bounding_box())
+            .translate(self.config.offset());
+        self.driver.fill_solid(&area, color).map_err(draw_error)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill_solid(&self.bounding_box(), color)
+    }
+}
+
+fn draw_error(error: st7789::Error<core::convert::Infallible>) -> Error {
+    Error::from_driver(DisplayOp::Draw, error)
+}
+
+impl OriginDimensions for Panel {
+    fn size(&self) -> Size {
+        self.config.size()
+    }
+}
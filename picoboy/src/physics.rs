@@ -0,0 +1,993 @@
+//! Fixed-point 2D physics: moving bodies, collisions and screen bounds.
+//!
+//! The RP2040 has no FPU, so everything is computed with [`Fixed`], an
+//! integer with 8 fractional bits, a 256th of a pixel. A [`Body`] moves with
+//! its velocity in pixels per second, which its acceleration changes. Boxes
+//! and circles tell whether they overlap and how far one has to move to
+//! leave the other. A body either stays within the screen, comes back on the
+//! other side, or stops at the solid tiles of a [`TileMap`].
+//!
+//! All arithmetic saturates instead of overflowing.
+
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::tilemap::TileMap;
+
+/// Most steps of [`Body::move_in`], enough for 128 tiles per move
+const MAX_STEPS: i32 = 256;
+
+/// Number with [`Fixed::FRACTION_BITS`] fractional bits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fixed(i32);
+
+impl Fixed {
+    /// Number of fractional bits
+    pub const FRACTION_BITS: u32 = 8;
+    /// Raw value of one
+    const SCALE: i32 = 1 << Self::FRACTION_BITS;
+
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(Self::SCALE);
+    pub const MIN: Self = Self(i32::MIN);
+    pub const MAX: Self = Self(i32::MAX);
+
+    /// Converts an integer, saturating beyond about 8 million
+    pub const fn from_int(value: i32) -> Self {
+        Self::saturate(value as i64 * Self::SCALE as i64)
+    }
+
+    /// Returns `numerator / denominator`, rounded towards zero.
+    ///
+    /// A zero `denominator` saturates in the direction of the numerator.
+    pub const fn from_ratio(numerator: i32, denominator: i32) -> Self {
+        Self::from_int(numerator).scale(1, denominator)
+    }
+
+    /// Creates a number from its raw value, in units of a 256th
+    pub const fn from_raw(raw: i32) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw value, in units of a 256th
+    pub const fn raw(self) -> i32 {
+        self.0
+    }
+
+    /// Returns the largest integer not greater than the number
+    pub const fn floor(self) -> i32 {
+        self.0 >> Self::FRACTION_BITS
+    }
+
+    /// Returns the smallest integer not less than the number
+    pub const fn ceil(self) -> i32 {
+        ((self.0 as i64 + Self::SCALE as i64 - 1) >> Self::FRACTION_BITS) as i32
+    }
+
+    /// Returns the nearest integer, halves are rounded up
+    pub const fn round(self) -> i32 {
+        ((self.0 as i64 + Self::SCALE as i64 / 2) >> Self::FRACTION_BITS) as i32
+    }
+
+    /// Returns the absolute value
+    pub const fn abs(self) -> Self {
+        Self(self.0.saturating_abs())
+    }
+
+    /// Returns `self * numerator / denominator` without an intermediate overflow.
+    ///
+    /// The result is rounded towards zero, a zero `denominator` saturates in
+    /// the direction of the product.
+    pub const fn scale(self, numerator: i32, denominator: i32) -> Self {
+        let product = self.0 as i64 * numerator as i64;
+        if denominator == 0 {
+            return if product > 0 {
+                Self::MAX
+            } else if product < 0 {
+                Self::MIN
+            } else {
+                Self::ZERO
+            };
+        }
+        Self::saturate(product / denominator as i64)
+    }
+
+    /// Returns the smaller number
+    pub const fn min(self, other: Self) -> Self {
+        if self.0 <= other.0 {
+            self
+        } else {
+            other
+        }
+    }
+
+    /// Returns the larger number
+    pub const fn max(self, other: Self) -> Self {
+        if self.0 >= other.0 {
+            self
+        } else {
+            other
+        }
+    }
+
+    /// Limits the number to `min..=max`, `min` wins if the range is empty
+    pub const fn clamp(self, min: Self, max: Self) -> Self {
+        self.min(max).max(min)
+    }
+
+    const fn saturate(raw: i64) -> Self {
+        if raw > i32::MAX as i64 {
+            Self::MAX
+        } else if raw < i32::MIN as i64 {
+            Self::MIN
+        } else {
+            Self(raw as i32)
+        }
+    }
+}
+
+impl From<i32> for Fixed {
+    fn from(value: i32) -> Self {
+        Self::from_int(value)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        self.scale(rhs.0, Self::SCALE)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    /// Divides, a zero divisor saturates like [`Fixed::scale`]
+    fn div(self, rhs: Self) -> Self {
+        self.scale(Self::SCALE, rhs.0)
+    }
+}
+
+/// Point or distance in pixels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Vec2 {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+impl Vec2 {
+    pub const ZERO: Self = Self::new(Fixed::ZERO, Fixed::ZERO);
+
+    /// Creates a vector from its components
+    pub const fn new(x: Fixed, y: Fixed) -> Self {
+        Self { x, y }
+    }
+
+    /// Creates a vector from whole pixels
+    pub const fn from_int(x: i32, y: i32) -> Self {
+        Self::new(Fixed::from_int(x), Fixed::from_int(y))
+    }
+
+    /// Returns the pixel the vector points into, rounding down
+    pub const fn to_point(self) -> Point {
+        Point::new(self.x.floor(), self.y.floor())
+    }
+
+    /// Returns `self * numerator / denominator` for both components, see [`Fixed::scale`]
+    pub const fn scale(self, numerator: i32, denominator: i32) -> Self {
+        Self::new(
+            self.x.scale(numerator, denominator),
+            self.y.scale(numerator, denominator),
+        )
+    }
+
+    /// Returns the square of the length in raw units, which cannot overflow
+    pub const fn length_squared_raw(self) -> u64 {
+        let (x, y) = (
+            self.x.raw().unsigned_abs() as u64,
+            self.y.raw().unsigned_abs() as u64,
+        );
+        x * x + y * y
+    }
+
+    /// Returns the length, rounded down
+    pub const fn length(self) -> Fixed {
+        // At most about 2^31.5, which still fits after saturating
+        Fixed::saturate(self.length_squared_raw().isqrt() as i64)
+    }
+}
+
+impl From<Point> for Vec2 {
+    fn from(point: Point) -> Self {
+        Self::from_int(point.x, point.y)
+    }
+}
+
+impl From<Size> for Vec2 {
+    fn from(size: Size) -> Self {
+        Self::from_int(size.width as i32, size.height as i32)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+/// Axis-aligned box, the right and bottom edges are not part of it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Aabb {
+    /// Top left corner
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+impl Aabb {
+    /// Creates a box from its top left corner and size
+    pub const fn new(position: Vec2, size: Vec2) -> Self {
+        Self { position, size }
+    }
+
+    /// Returns the left edge
+    pub fn left(&self) -> Fixed {
+        self.position.x
+    }
+
+    /// Returns the right edge, just outside the box
+    pub fn right(&self) -> Fixed {
+        self.position.x + self.size.x
+    }
+
+    /// Returns the top edge
+    pub fn top(&self) -> Fixed {
+        self.position.y
+    }
+
+    /// Returns the bottom edge, just outside the box
+    pub fn bottom(&self) -> Fixed {
+        self.position.y + self.size.y
+    }
+
+    /// Returns the center
+    pub fn center(&self) -> Vec2 {
+        self.position + self.size.scale(1, 2)
+    }
+
+    /// Returns `true` if `point` lies within the box
+    pub fn contains(&self, point: Vec2) -> bool {
+        (self.left()..self.right()).contains(&point.x)
+            && (self.top()..self.bottom()).contains(&point.y)
+    }
+
+    /// Returns `true` if the boxes overlap, touching edges do not count
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.left() < other.right()
+            && other.left() < self.right()
+            && self.top() < other.bottom()
+            && other.top() < self.bottom()
+    }
+
+    /// Returns the shortest move out of `other`, `None` if the boxes do not overlap.
+    ///
+    /// The move is along the axis with the smaller overlap, away from the
+    /// center of `other`.
+    pub fn penetration(&self, other: &Aabb) -> Option<Vec2> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        // Moves to the left of and above `other`, or to its right and below
+        let left = other.left() - self.right();
+        let right = other.right() - self.left();
+        let up = other.top() - self.bottom();
+        let down = other.bottom() - self.top();
+
+        let (center, other_center) = (self.center(), other.center());
+        let x = if center.x < other_center.x {
+            left
+        } else {
+            right
+        };
+        let y = if center.y < other_center.y { up } else { down };
+
+        Some(if x.abs() <= y.abs() {
+            Vec2::new(x, Fixed::ZERO)
+        } else {
+            Vec2::new(Fixed::ZERO, y)
+        })
+    }
+
+    /// Returns the point of the box closest to `point`
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            point.x.clamp(self.left(), self.right()),
+            point.y.clamp(self.top(), self.bottom()),
+        )
+    }
+}
+
+impl From<Rectangle> for Aabb {
+    fn from(rectangle: Rectangle) -> Self {
+        Self::new(rectangle.top_left.into(), rectangle.size.into())
+    }
+}
+
+/// Circle, e.g. around a round sprite
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: Fixed,
+}
+
+impl Circle {
+    /// Creates a circle from its center and radius
+    pub const fn new(center: Vec2, radius: Fixed) -> Self {
+        Self { center, radius }
+    }
+
+    /// Returns `true` if the circles overlap, touching ones do not count
+    pub fn intersects(&self, other: &Circle) -> bool {
+        let reach = (self.radius.raw() as i64 + other.radius.raw() as i64).max(0) as u64;
+        (other.center - self.center).length_squared_raw() < reach.saturating_mul(reach)
+    }
+
+    /// Returns `true` if the circle overlaps `other`, touching does not count
+    pub fn intersects_aabb(&self, other: &Aabb) -> bool {
+        let closest = other.closest_point(self.center);
+        let radius = self.radius.raw().max(0) as u64;
+        (closest - self.center).length_squared_raw() < radius * radius
+    }
+
+    /// Returns the shortest move out of `other`, `None` if the circles do not overlap.
+    ///
+    /// Circles with the same center are moved up.
+    pub fn penetration(&self, other: &Circle) -> Option<Vec2> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        let apart = self.center - other.center;
+        let distance = apart.length();
+        let depth = self.radius + other.radius - distance;
+        if distance == Fixed::ZERO {
+            return Some(Vec2::new(Fixed::ZERO, -depth));
+        }
+        // Rounded away from zero, so the move always separates the circles
+        let away = |component: Fixed| {
+            let product = component.raw() as i64 * depth.raw() as i64;
+            let rounding = (distance.raw() as i64 - 1) * product.signum();
+            Fixed::saturate((product + rounding) / distance.raw() as i64)
+        };
+        Some(Vec2::new(away(apart.x), away(apart.y)))
+    }
+}
+
+/// Sides of a body which touched something during a move
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Contacts {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
+impl Contacts {
+    /// Returns `true` if any side touched something
+    pub fn any(&self) -> bool {
+        self.left || self.right || self.top || self.bottom
+    }
+}
+
+/// Something that moves, with its top left corner as position
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Body {
+    pub position: Vec2,
+    /// Velocity in pixels per second
+    pub velocity: Vec2,
+}
+
+impl Body {
+    /// Creates a body at rest
+    pub const fn new(position: Vec2) -> Self {
+        Self {
+            position,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    /// Returns the box covered by the body with `size`
+    pub fn aabb(&self, size: Vec2) -> Aabb {
+        Aabb::new(self.position, size)
+    }
+
+    /// Advances the body by `dt_ms`, `acceleration` in pixels per second squared.
+    ///
+    /// The velocity changes first and moves the body right away, which keeps
+    /// jumps and orbits stable.
+    pub fn integrate(&mut self, acceleration: Vec2, dt_ms: u32) {
+        let dt_ms = dt_ms.min(i32::MAX as u32) as i32;
+        self.velocity += acceleration.scale(dt_ms, 1000);
+        self.position += self.velocity.scale(dt_ms, 1000);
+    }
+
+    /// Moves the body with `size` back within `bounds`, stopping it at the edges.
+    ///
+    /// A body larger than `bounds` is kept at the left or top edge.
+    pub fn clamp_to(&mut self, size: Vec2, bounds: &Aabb) -> Contacts {
+        let mut contacts = Contacts::default();
+
+        if self.position.x + size.x > bounds.right() {
+            self.position.x = bounds.right() - size.x;
+            self.velocity.x = self.velocity.x.min(Fixed::ZERO);
+            contacts.right = true;
+        }
+        if self.position.x < bounds.left() {
+            self.position.x = bounds.left();
+            self.velocity.x = self.velocity.x.max(Fixed::ZERO);
+            contacts.left = true;
+        }
+        if self.position.y + size.y > bounds.bottom() {
+            self.position.y = bounds.bottom() - size.y;
+            self.velocity.y = self.velocity.y.min(Fixed::ZERO);
+            contacts.bottom = true;
+        }
+        if self.position.y < bounds.top() {
+            self.position.y = bounds.top();
+            self.velocity.y = self.velocity.y.max(Fixed::ZERO);
+            contacts.top = true;
+        }
+
+        contacts
+    }
+
+    /// Brings a body which left `bounds` back on the opposite side
+    pub fn wrap_to(&mut self, bounds: &Aabb) {
+        self.position.x = wrap(self.position.x, bounds.left(), bounds.size.x);
+        self.position.y = wrap(self.position.y, bounds.top(), bounds.size.y);
+    }
+
+    /// Moves the body with `size` by its velocity for `dt_ms`, stopping it at solid tiles.
+    ///
+    /// `solid` tells which tile numbers block the way, cells outside the map
+    /// and without a tile are free. The move is split into steps shorter
+    /// than a tile, so a fast body cannot pass through a wall, up to 128 tiles
+    /// per move. The body should not overlap a solid tile before the move.
+    pub fn move_in<F>(&mut self, size: Vec2, dt_ms: u32, map: &TileMap<'_>, solid: F) -> Contacts
+    where
+        F: Fn(u8) -> bool,
+    {
+        let mut contacts = Contacts::default();
+        let tile_size = map.tile_size();
+        if tile_size.width == 0 || tile_size.height == 0 {
+            return contacts;
+        }
+
+        let dt_ms = dt_ms.min(i32::MAX as u32) as i32;
+        let delta = self.velocity.scale(dt_ms, 1000);
+        let step =
+            Fixed::from_int(tile_size.width.min(tile_size.height) as i32 / 2).max(Fixed::ONE);
+        let longest = delta.x.abs().max(delta.y.abs());
+        let steps = (longest.raw() / step.raw() + 1).min(MAX_STEPS);
+
+        let grid = Grid { map, solid: &solid };
+        let mut moved = Vec2::ZERO;
+        for i in 1..=steps {
+            // Exact division of the whole move, the remainders do not add up
+            let next = delta.scale(i, steps);
+            let part = next - moved;
+            moved = next;
+
+            if part.x != Fixed::ZERO {
+                self.position.x += part.x;
+                if let Some(edge) = grid.blocking_x(self.aabb(size), part.x) {
+                    self.position.x = edge;
+                    self.velocity.x = Fixed::ZERO;
+                    contacts.left |= part.x < Fixed::ZERO;
+                    contacts.right |= part.x > Fixed::ZERO;
+                }
+            }
+            if part.y != Fixed::ZERO {
+                self.position.y += part.y;
+                if let Some(edge) = grid.blocking_y(self.aabb(size), part.y) {
+                    self.position.y = edge;
+                    self.velocity.y = Fixed::ZERO;
+                    contacts.top |= part.y < Fixed::ZERO;
+                    contacts.bottom |= part.y > Fixed::ZERO;
+                }
+            }
+        }
+
+        contacts
+    }
+}
+
+fn wrap(value: Fixed, start: Fixed, len: Fixed) -> Fixed {
+    if len <= Fixed::ZERO {
+        return value;
+    }
+    let offset = (value.raw() as i64 - start.raw() as i64).rem_euclid(len.raw() as i64);
+    Fixed::saturate(start.raw() as i64 + offset)
+}
+
+/// Solid cells of a tile map
+struct Grid<'a, 'm, F> {
+    map: &'a TileMap<'m>,
+    solid: &'a F,
+}
+
+impl<F: Fn(u8) -> bool> Grid<'_, '_, F> {
+    /// Returns where the box has to stop after moving by `dx`, `None` if nothing is in its way
+    fn blocking_x(&self, aabb: Aabb, dx: Fixed) -> Option<Fixed> {
+        let (columns, rows) = self.cells(&aabb);
+        let width = self.map.tile_size().width as i32;
+        let origin = self.map.position().x;
+
+        if dx > Fixed::ZERO {
+            // Left edge of the leftmost solid cell
+            let column = columns
+                .clone()
+                .find(|&column| self.any_solid(column..=column, rows.clone()))?;
+            Some(Fixed::from_int(origin + column * width) - aabb.size.x)
+        } else {
+            let column = columns
+                .rev()
+                .find(|&column| self.any_solid(column..=column, rows.clone()))?;
+            Some(Fixed::from_int(origin + (column + 1) * width))
+        }
+    }
+
+    /// Returns where the box has to stop after moving by `dy`, `None` if nothing is in its way
+    fn blocking_y(&self, aabb: Aabb, dy: Fixed) -> Option<Fixed> {
+        let (columns, rows) = self.cells(&aabb);
+        let height = self.map.tile_size().height as i32;
+        let origin = self.map.position().y;
+
+        if dy > Fixed::ZERO {
+            let row = rows
+                .clone()
+                .find(|&row| self.any_solid(columns.clone(), row..=row))?;
+            Some(Fixed::from_int(origin + row * height) - aabb.size.y)
+        } else {
+            let row = rows
+                .rev()
+                .find(|&row| self.any_solid(columns.clone(), row..=row))?;
+            Some(Fixed::from_int(origin + (row + 1) * height))
+        }
+    }
+
+    /// Returns the columns and rows of the cells the box overlaps
+    fn cells(
+        &self,
+        aabb: &Aabb,
+    ) -> (
+        core::ops::RangeInclusive<i32>,
+        core::ops::RangeInclusive<i32>,
+    ) {
+        let tile_size = self.map.tile_size();
+        let origin = Vec2::from(self.map.position());
+        let (width, height) = (
+            Fixed::from_int(tile_size.width as i32),
+            Fixed::from_int(tile_size.height as i32),
+        );
+
+        // The right and bottom edges are outside the box
+        let cell = |value: Fixed, len: Fixed| value.raw().div_euclid(len.raw());
+        let last = |value: Fixed, len: Fixed| (value.raw() - 1).div_euclid(len.raw());
+        (
+            cell(aabb.left() - origin.x, width)..=last(aabb.right() - origin.x, width),
+            cell(aabb.top() - origin.y, height)..=last(aabb.bottom() - origin.y, height),
+        )
+    }
+
+    fn any_solid(
+        &self,
+        columns: core::ops::RangeInclusive<i32>,
+        rows: core::ops::RangeInclusive<i32>,
+    ) -> bool {
+        rows.into_iter().any(|row| {
+            columns.clone().any(|column| {
+                let tile = u32::try_from(column)
+                    .ok()
+                    .zip(u32::try_from(row).ok())
+                    .and_then(|(column, row)| self.map.tile(column, row));
+                tile.is_some_and(self.solid)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sprite::Image;
+    use crate::tilemap::Tileset;
+
+    const TILE: i32 = 8;
+
+    /// A single black tile
+    const IMAGE: Image<'static> = Image::rgb565(8, 8, &[0; 64], None);
+
+    /// A wall in column 8 and a floor in row 5, both made of tile 1
+    #[rustfmt::skip]
+    const TILES: [u8; 60] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    ];
+
+    fn map() -> TileMap<'static> {
+        TileMap::new(Tileset::new(&IMAGE, Size::new(8, 8)), 10, &TILES)
+    }
+
+    fn solid(tile: u8) -> bool {
+        tile == 1
+    }
+
+    fn fixed(value: f32) -> Fixed {
+        Fixed::from_raw((value * 256.0) as i32)
+    }
+
+    fn aabb(x: i32, y: i32, width: i32, height: i32) -> Aabb {
+        Aabb::new(Vec2::from_int(x, y), Vec2::from_int(width, height))
+    }
+
+    fn body(x: i32, y: i32, vx: i32, vy: i32) -> Body {
+        Body {
+            position: Vec2::from_int(x, y),
+            velocity: Vec2::from_int(vx, vy),
+        }
+    }
+
+    const SIZE: Vec2 = Vec2::from_int(TILE, TILE);
+
+    #[test]
+    fn converts_and_rounds() {
+        assert_eq!(Fixed::from_int(3).raw(), 768);
+        assert_eq!(Fixed::from_ratio(1, 3).raw(), 85);
+        assert_eq!(Fixed::from_ratio(-1, 3).raw(), -85);
+
+        for (value, floor, ceil, round) in [
+            (2.25, 2, 3, 2),
+            (2.5, 2, 3, 3),
+            (2.75, 2, 3, 3),
+            (3.0, 3, 3, 3),
+            (-2.25, -3, -2, -2),
+            (-2.5, -3, -2, -2),
+            (-2.75, -3, -2, -3),
+        ] {
+            let fixed = fixed(value);
+            assert_eq!(
+                (fixed.floor(), fixed.ceil(), fixed.round()),
+                (floor, ceil, round),
+                "{value}"
+            );
+        }
+
+        assert_eq!(
+            Vec2::new(fixed(-0.5), fixed(1.5)).to_point(),
+            Point::new(-1, 1)
+        );
+    }
+
+    #[test]
+    fn multiplies_and_divides() {
+        assert_eq!(fixed(1.5) * fixed(-2.5), fixed(-3.75));
+        assert_eq!(fixed(7.5) / fixed(2.5), Fixed::from_int(3));
+        assert_eq!(Fixed::ONE / Fixed::from_int(3), Fixed::from_ratio(1, 3));
+        // Rounded towards zero
+        assert_eq!(Fixed::from_raw(5).scale(1, 2).raw(), 2);
+        assert_eq!(Fixed::from_raw(-5).scale(1, 2).raw(), -2);
+        assert_eq!(Vec2::from_int(3, 4).length(), Fixed::from_int(5));
+    }
+
+    #[test]
+    fn saturates() {
+        assert_eq!(Fixed::from_int(1 << 23), Fixed::MAX);
+        assert_eq!(Fixed::from_int(-(1 << 23) - 1), Fixed::MIN);
+        assert_eq!(Fixed::MAX + Fixed::ONE, Fixed::MAX);
+        assert_eq!(Fixed::MIN - Fixed::ONE, Fixed::MIN);
+        assert_eq!(-Fixed::MIN, Fixed::MAX);
+        assert_eq!(Fixed::MIN.abs(), Fixed::MAX);
+        assert_eq!(
+            Fixed::from_int(100_000) * Fixed::from_int(100_000),
+            Fixed::MAX
+        );
+        assert_eq!(
+            Fixed::from_int(-100_000) * Fixed::from_int(100_000),
+            Fixed::MIN
+        );
+
+        // Dividing by zero saturates towards the sign of the dividend
+        assert_eq!(Fixed::ONE / Fixed::ZERO, Fixed::MAX);
+        assert_eq!(-Fixed::ONE / Fixed::ZERO, Fixed::MIN);
+        assert_eq!(Fixed::ZERO / Fixed::ZERO, Fixed::ZERO);
+        assert_eq!(Fixed::from_ratio(-1, 0), Fixed::MIN);
+
+        assert_eq!(Vec2::new(Fixed::MAX, Fixed::MIN).length(), Fixed::MAX);
+        assert_eq!(
+            Fixed::from_int(5).clamp(Fixed::ONE, Fixed::ZERO),
+            Fixed::ONE
+        );
+    }
+
+    #[test]
+    fn boxes_intersect() {
+        let a = aabb(0, 0, 10, 10);
+        assert!(a.intersects(&aabb(9, 9, 5, 5)));
+        assert!(a.intersects(&aabb(2, 2, 2, 2)));
+        // Touching edges
+        assert!(!a.intersects(&aabb(10, 0, 5, 5)));
+        assert!(!a.intersects(&aabb(0, -5, 5, 5)));
+
+        assert!(a.contains(Vec2::ZERO));
+        assert!(!a.contains(Vec2::from_int(10, 5)));
+        assert_eq!(a.center(), Vec2::from_int(5, 5));
+        assert_eq!(
+            a.closest_point(Vec2::from_int(20, -3)),
+            Vec2::from_int(10, 0)
+        );
+    }
+
+    #[test]
+    fn box_penetration_takes_shortest_way_out() {
+        let wall = aabb(10, 0, 10, 20);
+        assert_eq!(aabb(0, 0, 5, 5).penetration(&wall), None);
+
+        // Two pixels into the left side, far from top and bottom
+        let player = aabb(2, 8, 10, 4);
+        assert_eq!(player.penetration(&wall), Some(Vec2::from_int(-2, 0)));
+
+        // Three pixels into the top
+        let player = aabb(12, -7, 4, 10);
+        assert_eq!(player.penetration(&wall), Some(Vec2::from_int(0, -3)));
+
+        // Out of the right side
+        let player = aabb(17, 5, 4, 4);
+        let out = player.penetration(&wall).unwrap();
+        assert_eq!(out, Vec2::from_int(3, 0));
+        let moved = Aabb::new(player.position + out, player.size);
+        assert!(!moved.intersects(&wall));
+    }
+
+    #[test]
+    fn circles_intersect() {
+        let a = Circle::new(Vec2::ZERO, Fixed::from_int(5));
+        assert!(a.intersects(&Circle::new(Vec2::from_int(6, 0), Fixed::from_int(2))));
+        // Touching at a distance of 7
+        assert!(!a.intersects(&Circle::new(Vec2::from_int(7, 0), Fixed::from_int(2))));
+        assert!(!a.intersects(&Circle::new(Vec2::from_int(6, 8), Fixed::from_int(4))));
+
+        assert!(a.intersects_aabb(&aabb(4, -1, 5, 2)));
+        assert!(!a.intersects_aabb(&aabb(5, -1, 5, 2)));
+        // The corner of the box is just out of reach
+        assert!(!a.intersects_aabb(&aabb(4, 4, 5, 5)));
+        assert!(a.intersects_aabb(&aabb(3, 3, 5, 5)));
+    }
+
+    #[test]
+    fn circle_penetration_separates() {
+        let other = Circle::new(Vec2::ZERO, Fixed::from_int(5));
+
+        let circle = Circle::new(Vec2::from_int(6, 0), Fixed::from_int(3));
+        assert_eq!(circle.penetration(&other), Some(Vec2::from_int(2, 0)));
+        assert_eq!(
+            Circle::new(Vec2::from_int(9, 0), Fixed::from_int(3)).penetration(&other),
+            None
+        );
+
+        // Diagonal moves are rounded away from the other circle
+        let circle = Circle::new(Vec2::from_int(-3, -4), Fixed::from_int(2));
+        let out = circle.penetration(&other).unwrap();
+        assert!(out.x < Fixed::ZERO && out.y < out.x);
+        let moved = Circle::new(circle.center + out, circle.radius);
+        assert!(!moved.intersects(&other));
+
+        // The same center
+        let circle = Circle::new(Vec2::ZERO, Fixed::from_int(1));
+        assert_eq!(circle.penetration(&other), Some(Vec2::from_int(0, -6)));
+    }
+
+    #[test]
+    fn integrates_velocity_first() {
+        let mut body = body(0, 0, 10, 0);
+        body.integrate(Vec2::from_int(0, 100), 100);
+        assert_eq!(body.velocity, Vec2::from_int(10, 10));
+        assert_eq!(body.position, Vec2::from_int(1, 1));
+    }
+
+    #[test]
+    fn clamps_to_bounds() {
+        let bounds = aabb(0, 0, 100, 50);
+
+        let mut inside = body(10, 10, 5, 5);
+        assert!(!inside.clamp_to(SIZE, &bounds).any());
+        assert_eq!(inside, body(10, 10, 5, 5));
+
+        let mut right = body(95, 45, 30, 30);
+        let contacts = right.clamp_to(SIZE, &bounds);
+        assert!(contacts.right && contacts.bottom && !contacts.left && !contacts.top);
+        assert_eq!(right, body(92, 42, 0, 0));
+
+        // Moving away from the edge keeps its speed
+        let mut left = body(-3, -1, 20, -20);
+        let contacts = left.clamp_to(SIZE, &bounds);
+        assert!(contacts.left && contacts.top);
+        assert_eq!(left, body(0, 0, 20, 0));
+
+        // Too large for the bounds
+        let mut large = body(5, 5, 0, 0);
+        large.clamp_to(Vec2::from_int(120, 8), &bounds);
+        assert_eq!(large.position, Vec2::from_int(0, 5));
+    }
+
+    #[test]
+    fn wraps_to_bounds() {
+        let bounds = aabb(10, 20, 100, 50);
+
+        let mut body = Body::new(Vec2::new(fixed(8.5), Fixed::from_int(75)));
+        body.wrap_to(&bounds);
+        assert_eq!(body.position, Vec2::new(fixed(108.5), Fixed::from_int(25)));
+
+        let mut far = Body::new(Vec2::from_int(-495, 20));
+        far.wrap_to(&bounds);
+        assert_eq!(far.position, Vec2::from_int(105, 20));
+
+        let mut inside = Body::new(Vec2::from_int(109, 69));
+        inside.wrap_to(&bounds);
+        assert_eq!(inside.position, Vec2::from_int(109, 69));
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let map = map();
+        let mut body = body(8, 8, 0, 1000);
+        let contacts = body.move_in(SIZE, 100, &map, solid);
+
+        assert!(contacts.bottom && !contacts.top && !contacts.left && !contacts.right);
+        assert_eq!(body, self::body(8, 32, 0, 0));
+
+        // Resting on the floor while running keeps the horizontal speed
+        body.velocity = Vec2::from_int(100, 50);
+        let contacts = body.move_in(SIZE, 100, &map, solid);
+        assert!(contacts.bottom);
+        assert_eq!(body, self::body(18, 32, 100, 0));
+    }
+
+    #[test]
+    fn stops_at_walls() {
+        let map = map();
+
+        let mut body = body(30, 20, 400, 0);
+        let contacts = body.move_in(SIZE, 100, &map, solid);
+        assert!(contacts.right);
+        assert_eq!(body, self::body(56, 20, 0, 0));
+
+        // From the other side of the wall
+        let mut body = self::body(76, 20, -300, 0);
+        let contacts = body.move_in(SIZE, 100, &map, solid);
+        assert!(contacts.left);
+        assert_eq!(body.position, Vec2::from_int(72, 20));
+
+        // Diagonally into the corner of wall and floor
+        let mut body = self::body(40, 20, 500, 500);
+        let contacts = body.move_in(SIZE, 100, &map, solid);
+        assert!(contacts.right && contacts.bottom);
+        assert_eq!(body, self::body(56, 32, 0, 0));
+    }
+
+    #[test]
+    fn cells_outside_the_map_are_free() {
+        let map = map();
+        let mut body = body(8, 8, 0, -500);
+        assert!(!body.move_in(SIZE, 100, &map, solid).any());
+        assert_eq!(body.position, Vec2::from_int(8, -42));
+
+        // A map moved on the screen moves its walls
+        let mut map = map;
+        map.set_position(Point::new(-16, 0));
+        let mut body = self::body(30, 20, 200, 0);
+        assert!(body.move_in(SIZE, 100, &map, solid).right);
+        assert_eq!(body.position, Vec2::from_int(40, 20));
+    }
+
+    #[test]
+    fn does_not_tunnel_at_high_speed() {
+        let map = map();
+
+        // 120 tiles in one move, through a wall a single tile thick
+        let mut body = body(0, 20, 60_000, 0);
+        assert!(body.move_in(SIZE, 16, &map, solid).right);
+        assert_eq!(body.position, Vec2::from_int(56, 20));
+
+        let mut body = self::body(0, 0, 0, 60_000);
+        assert!(body.move_in(SIZE, 16, &map, solid).bottom);
+        assert_eq!(body.position, Vec2::from_int(0, 32));
+
+        // A thin box with fractional steps
+        let thin = Vec2::new(fixed(0.5), fixed(0.5));
+        let mut body = Body {
+            position: Vec2::new(fixed(0.25), fixed(30.5)),
+            velocity: Vec2::from_int(7_777, 0),
+        };
+        assert!(body.move_in(thin, 100, &map, solid).right);
+        assert_eq!(body.position.x, fixed(63.5));
+    }
+}
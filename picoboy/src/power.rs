@@ -0,0 +1,146 @@
+//! Sleeping between frames, a slower clock and dormant mode.
+//!
+//! [`Power::wait_until`] sleeps with `wfe` until the next frame is due. Timer
+//! alarm 2 ends the sleep: its interrupt stays masked, but with `SEVONPEND`
+//! set it still wakes the core, so no handler is needed. Other interrupts,
+//! like the speaker's, wake the core too and are served as usual.
+//!
+//! [`Power::set_slow`] divides the system clock by 10. The timer runs from the
+//! crystal, so game speed and audio stay the same, but the SPI clock and
+//! therefore the display become slower, as does [`cortex_m::delay::Delay`].
+//!
+//! [`Power::dormant`] stops the crystal and with it every clock until a button
+//! is pressed. The timer stands still meanwhile.
+
+use cortex_m::peripheral::SCB;
+use picoboy_color::hal::{
+    pac,
+    timer::{Alarm, Alarm2, Instant},
+    Timer,
+};
+
+use crate::board::ButtonPins;
+use crate::idle::{PowerStats, SleepStats};
+
+/// Divider of the system clock while slow, 125 MHz become 12.5 MHz
+const SLOW_DIVIDER: u32 = 10;
+
+/// Sends the crystal oscillator to sleep when written to its `DORMANT` register
+const XOSC_DORMANT: u32 = 0x636f_6d61;
+
+/// Bits of `CLK_SYS_SELECTED` for the reference clock and the PLL
+const CLK_SYS_SELECTED_REF: u32 = 1 << 0;
+const CLK_SYS_SELECTED_AUX: u32 = 1 << 1;
+
+/// Sleep and clock control of core 0
+pub struct Power {
+    timer: Timer,
+    alarm: Alarm2,
+    slow: bool,
+    stats: SleepStats,
+}
+
+impl Power {
+    pub(crate) fn new(timer: Timer, mut alarm: Alarm2, scb: &mut SCB) -> Self {
+        // Let pending interrupts end `wfe` even while masked
+        scb.set_sevonpend();
+        alarm.enable_interrupt();
+
+        Self {
+            timer,
+            alarm,
+            slow: false,
+            stats: SleepStats::new(),
+        }
+    }
+
+    /// Sleeps until the timer reaches `deadline_us`
+    pub fn wait_until(&mut self, deadline_us: u64) {
+        let start_us = self.timer.get_counter().ticks();
+        if deadline_us <= start_us {
+            return;
+        }
+
+        let _ = self.alarm.schedule_at(Instant::from_ticks(deadline_us));
+        // Other interrupts end the sleep early, so check the time after each one
+        while self.timer.get_counter().ticks() < deadline_us {
+            cortex_m::asm::wfe();
+        }
+        self.alarm.clear_interrupt();
+        pac::NVIC::unpend(pac::Interrupt::TIMER_IRQ_2);
+
+        let end_us = self.timer.get_counter().ticks();
+        self.stats.record_sleep(start_us, end_us, self.slow);
+    }
+
+    /// Slows the system clock down or brings it back to full speed
+    pub fn set_slow(&mut self, slow: bool) {
+        if slow == self.slow {
+            return;
+        }
+        self.slow = slow;
+
+        let divider = if slow { SLOW_DIVIDER } else { 1 };
+        // SAFETY: the divider of the system clock may change at any time, and nothing else
+        // changes it after the board is up
+        let clocks = unsafe { &*pac::CLOCKS::ptr() };
+        clocks
+            .clk_sys_div()
+            .write(|w| unsafe { w.int().bits(divider) });
+    }
+
+    /// Returns `true` while the system clock is slowed down
+    pub fn is_slow(&self) -> bool {
+        self.slow
+    }
+
+    /// Stops all clocks until one of the buttons is pressed.
+    ///
+    /// Everything stops, so transfers like a display flush have to be
+    /// finished and outputs like the speaker silenced before.
+    pub fn dormant(&mut self, buttons: &mut ButtonPins) {
+        let start_us = self.timer.get_counter().ticks();
+
+        buttons.set_dormant_wake(true);
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: interrupts are off and core 0 is the only one changing the clocks
+            unsafe { sleep_dormant() }
+        });
+        buttons.set_dormant_wake(false);
+
+        self.stats.record_dormant(start_us);
+    }
+
+    /// Returns the statistics of the last second once, as soon as they are complete
+    pub fn take_stats(&mut self) -> Option<PowerStats> {
+        self.stats.take_stats()
+    }
+}
+
+/// Sends the crystal oscillator dormant and restores the system clock after waking up
+///
+/// # Safety
+///
+/// Nothing else may change the clocks meanwhile.
+unsafe fn sleep_dormant() {
+    let (clocks, xosc, pll) = (
+        &*pac::CLOCKS::ptr(),
+        &*pac::XOSC::ptr(),
+        &*pac::PLL_SYS::ptr(),
+    );
+
+    // Run the system clock directly from the crystal, the PLL loses its lock once it stops
+    clocks.clk_sys_ctrl().modify(|_, w| w.src().clk_ref());
+    while clocks.clk_sys_selected().read().bits() & CLK_SYS_SELECTED_REF == 0 {}
+
+    xosc.dormant().write(|w| w.bits(XOSC_DORMANT));
+
+    // A button edge starts the crystal again
+    while !xosc.status().read().stable().bit() {}
+    while !pll.cs().read().lock().bit() {}
+
+    clocks
+        .clk_sys_ctrl()
+        .modify(|_, w| w.src().clksrc_clk_sys_aux());
+    while clocks.clk_sys_selected().read().bits() & CLK_SYS_SELECTED_AUX == 0 {}
+}
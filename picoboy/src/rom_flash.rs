@@ -0,0 +1,192 @@
+//! Save data region of the onboard flash.
+//!
+//! The memory layout in `memory/standalone.x` reserves the last 64 KiB of the
+//! 2 MiB flash as `SAVE`, so the firmware never overlaps it. Slot builds for
+//! the launcher share the region. Reads go through the memory mapped XIP
+//! window, erasing and programming use the flash routines of the boot ROM.
+//!
+//! While the flash is written, code cannot run from it. The write routine is
+//! therefore placed in RAM and interrupts are disabled for its duration, which
+//! is tens of milliseconds for an erase. If core 1 runs, it is parked in RAM
+//! by [`multicore`](crate::multicore) meanwhile. Afterwards a copy of the
+//! second stage boot loader restores the fast read mode of the flash.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use picoboy_color::hal::rom_data;
+
+use crate::multicore;
+use crate::storage::{Flash, FlashError};
+
+/// Start of the memory mapped flash
+const XIP_BASE: usize = 0x1000_0000;
+
+/// Smallest unit the flash can program
+const PAGE_SIZE: usize = 256;
+
+/// Erase command for one 4 KiB sector
+const SECTOR_ERASE_COMMAND: u8 = 0x20;
+
+/// Size of the second stage boot loader at the start of the flash
+const BOOT2_SIZE: usize = 256;
+
+extern "C" {
+    // Bounds of the SAVE region, defined in the memory layout
+    static __save_start: u8;
+    static __save_end: u8;
+}
+
+// Copy of the boot loader, it cannot run from the flash while it is reconfigured
+static mut BOOT2: [u32; BOOT2_SIZE / 4] = [0; BOOT2_SIZE / 4];
+
+/// The `SAVE` region of the onboard flash
+pub struct RomFlash {
+    // Offset of the region from the start of the flash
+    start: usize,
+    len: usize,
+}
+
+impl RomFlash {
+    pub(crate) fn new() -> Self {
+        // SAFETY: only the board creates the flash, before anything writes to it
+        unsafe {
+            let boot2 = XIP_BASE as *const u32;
+            let copy = addr_of_mut!(BOOT2).cast::<u32>();
+            core::ptr::copy_nonoverlapping(boot2, copy, BOOT2_SIZE / 4);
+        }
+
+        // The symbols are only used for their addresses
+        let start = addr_of!(__save_start) as usize;
+        let end = addr_of!(__save_end) as usize;
+
+        Self {
+            start: start - XIP_BASE,
+            len: end - start,
+        }
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result<(), FlashError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => Ok(()),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+}
+
+impl Flash for RomFlash {
+    const SECTOR_SIZE: usize = 4096;
+
+    fn capacity(&self) -> usize {
+        self.len
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), FlashError> {
+        self.check(offset, buffer.len())?;
+
+        // SAFETY: the range lies within the mapped flash, which is not written at the same time
+        unsafe {
+            let source = (XIP_BASE + self.start + offset) as *const u8;
+            core::ptr::copy_nonoverlapping(source, buffer.as_mut_ptr(), buffer.len());
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, offset: usize) -> Result<(), FlashError> {
+        self.check(offset, Self::SECTOR_SIZE)?;
+        if !offset.is_multiple_of(Self::SECTOR_SIZE) {
+            return Err(FlashError::NotAligned);
+        }
+
+        write(Operation::Erase(self.start + offset));
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.check(offset, data.len())?;
+
+        // Whole pages are programmed, bytes outside of the data stay 0xFF and keep their value.
+        // The data is copied to RAM as it might come from the flash itself.
+        let mut written = 0;
+        while written < data.len() {
+            let position = offset + written;
+            let page_offset = position % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(data.len() - written);
+
+            let mut page = [0xFF; PAGE_SIZE];
+            page[page_offset..page_offset + len].copy_from_slice(&data[written..written + len]);
+            write(Operation::Program(
+                self.start + position - page_offset,
+                &page,
+            ));
+
+            written += len;
+        }
+        Ok(())
+    }
+}
+
+enum Operation<'a> {
+    Erase(usize),
+    Program(usize, &'a [u8; PAGE_SIZE]),
+}
+
+/// Boot ROM routines, looked up before the flash becomes unavailable
+struct Routines {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    boot2: unsafe extern "C" fn(),
+}
+
+fn write(operation: Operation) {
+    let routines = Routines {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        // SAFETY: the copy holds the boot loader's Thumb code, hence the set lowest bit
+        boot2: unsafe {
+            core::mem::transmute::<usize, unsafe extern "C" fn()>(addr_of!(BOOT2) as usize + 1)
+        },
+    };
+
+    let (address, data, len) = match operation {
+        Operation::Erase(address) => (address, core::ptr::null(), 0),
+        Operation::Program(address, page) => (address, page.as_ptr(), PAGE_SIZE),
+    };
+
+    multicore::lockout(|| {
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: interrupts are off, core 1 is parked in RAM and the routine only touches
+            // RAM and the boot ROM
+            unsafe { write_in_ram(&routines, address as u32, data, len) }
+        })
+    });
+}
+
+/// Erases a sector if `len` is 0, programs a page otherwise
+///
+/// # Safety
+///
+/// Nothing may access the flash while this runs, including interrupts and the other core.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_in_ram(routines: &Routines, address: u32, data: *const u8, len: usize) {
+    (routines.connect_internal_flash)();
+    (routines.flash_exit_xip)();
+    if len == 0 {
+        (routines.flash_range_erase)(
+            address,
+            RomFlash::SECTOR_SIZE,
+            RomFlash::SECTOR_SIZE as u32,
+            SECTOR_ERASE_COMMAND,
+        );
+    } else {
+        (routines.flash_range_program)(address, data, len);
+    }
+    (routines.flash_flush_cache)();
+    (routines.boot2)();
+}
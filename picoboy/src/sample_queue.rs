@@ -0,0 +1,204 @@
+//! Lock-free queue of audio samples.
+//!
+//! The main loop renders samples ahead of time with the [`Producer`], while
+//! the timer interrupt takes one sample at a time with the [`Consumer`]. Both
+//! sides only load and store atomics, which the Cortex-M0+ supports.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Ring buffer of `N` samples, one slot always stays empty
+pub struct SampleQueue<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    // Index of the next sample to read, only written by the consumer
+    read: AtomicUsize,
+    // Index of the next sample to write, only written by the producer
+    write: AtomicUsize,
+}
+
+// SAFETY: the producer and consumer only touch the part of the buffer the
+// other side has handed over through the indices.
+unsafe impl<const N: usize> Sync for SampleQueue<N> {}
+
+impl<const N: usize> SampleQueue<N> {
+    /// Creates an empty queue, `N` has to be at least 2
+    pub const fn new() -> Self {
+        assert!(N >= 2);
+
+        Self {
+            buffer: UnsafeCell::new([0; N]),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the queue into its two ends
+    pub fn split(&mut self) -> (Producer<'_, N>, Consumer<'_, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+        (write + N - read) % N
+    }
+}
+
+impl<const N: usize> Default for SampleQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writing end of a [`SampleQueue`]
+pub struct Producer<'a, const N: usize> {
+    queue: &'a SampleQueue<N>,
+}
+
+impl<const N: usize> Producer<'_, N> {
+    /// Returns the number of samples waiting to be played
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if no samples are waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fills all free space by calling `render` with up to two free parts of the buffer
+    pub fn fill(&mut self, mut render: impl FnMut(&mut [u8])) {
+        let read = self.queue.read.load(Ordering::Acquire);
+        let mut write = self.queue.write.load(Ordering::Relaxed);
+
+        // Stop one short of the read index, a full queue would look empty otherwise
+        let end = (read + N - 1) % N;
+        while write != end {
+            let stop = if end > write { end } else { N };
+
+            // SAFETY: samples from `write` up to `end` are not read until the index is published
+            let free = unsafe {
+                let start = self.queue.buffer.get().cast::<u8>().add(write);
+                core::slice::from_raw_parts_mut(start, stop - write)
+            };
+            render(free);
+
+            write = stop % N;
+            self.queue.write.store(write, Ordering::Release);
+        }
+    }
+}
+
+/// Reading end of a [`SampleQueue`]
+pub struct Consumer<'a, const N: usize> {
+    queue: &'a SampleQueue<N>,
+}
+
+impl<const N: usize> Consumer<'_, N> {
+    /// Takes the oldest sample, `None` if the producer fell behind
+    pub fn pop(&mut self) -> Option<u8> {
+        let read = self.queue.read.load(Ordering::Relaxed);
+        let write = self.queue.write.load(Ordering::Acquire);
+        if read == write {
+            return None;
+        }
+
+        // SAFETY: the producer does not write this sample before the index moves on
+        let sample = unsafe { self.queue.buffer.get().cast::<u8>().add(read).read() };
+        self.queue.read.store((read + 1) % N, Ordering::Release);
+
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const N: usize = 8;
+
+    /// Fills the queue with counting samples starting at `next`, returns the lengths of the parts
+    fn fill(producer: &mut Producer<'_, N>, next: &mut u8) -> Vec<usize> {
+        let mut parts = Vec::new();
+        producer.fill(|free| {
+            parts.push(free.len());
+            for sample in free {
+                *sample = *next;
+                *next = next.wrapping_add(1);
+            }
+        });
+        parts
+    }
+
+    /// Pops `count` samples, which have to be there
+    fn pop(consumer: &mut Consumer<'_, N>, count: usize) -> Vec<u8> {
+        (0..count).map(|_| consumer.pop().unwrap()).collect()
+    }
+
+    #[test]
+    fn fills_one_short_of_the_read_index() {
+        let mut queue = SampleQueue::<N>::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert!(producer.is_empty());
+        assert_eq!(consumer.pop(), None);
+
+        let mut next = 0;
+        assert_eq!(fill(&mut producer, &mut next), [N - 1]);
+        assert_eq!(producer.len(), N - 1);
+
+        // A full queue has nothing to render
+        assert_eq!(fill(&mut producer, &mut next), []);
+        assert_eq!(pop(&mut consumer, N - 1), [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(consumer.pop(), None);
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn fills_around_the_wrap_with_the_read_index_in_the_middle() {
+        let mut queue = SampleQueue::<N>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut next = 0;
+        fill(&mut producer, &mut next);
+        assert_eq!(pop(&mut consumer, 3), [0, 1, 2]);
+
+        // From the write index to the end, then from the start to just before the read index
+        assert_eq!(fill(&mut producer, &mut next), [1, 2]);
+        assert_eq!(producer.len(), N - 1);
+        assert_eq!(pop(&mut consumer, N - 1), [3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn fills_around_the_wrap_with_the_read_index_at_the_end() {
+        let mut queue = SampleQueue::<N>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut next = 0;
+        fill(&mut producer, &mut next);
+        pop(&mut consumer, N - 1);
+
+        assert_eq!(fill(&mut producer, &mut next), [1, N - 2]);
+        assert_eq!(pop(&mut consumer, N - 1), [7, 8, 9, 10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn keeps_order_across_many_wraps() {
+        let mut queue = SampleQueue::<N>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut next = 0;
+        let mut expected = 0u8;
+
+        // Taking a different number each time moves the indices to every position
+        for count in (1..N).cycle().take(50) {
+            fill(&mut producer, &mut next);
+            for sample in pop(&mut consumer, count) {
+                assert_eq!(sample, expected);
+                expected = expected.wrapping_add(1);
+            }
+            assert_eq!(producer.len(), N - 1 - count);
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! Scenes and a stack-based scene manager.
+//!
+//! A game consists of scenes like a title screen, the gameplay or a pause
+//! menu. Since there is no allocator, the game collects its scenes in an enum
+//! and implements [`Scene`] for it. The [`SceneManager`] keeps a stack of
+//! these, forwards updates to the topmost scene and applies the [`Transition`]
+//! it returns, optionally fading to black and back.
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+
+use crate::display::Display;
+use crate::input::Input;
+
+/// Default duration of a fade out or fade in in milliseconds
+pub const FADE_DURATION_MS: u32 = 250;
+
+/// A single screen of the game
+pub trait Scene: Sized {
+    /// Called when the scene is put onto the stack
+    fn enter(&mut self) {}
+
+    /// Advances the scene while it is on top of the stack
+    fn update(&mut self, input: &Input) -> Transition<Self>;
+
+    /// Draws the whole scene
+    fn draw<D: Display>(&mut self, target: &mut D) -> Result<(), D::Error>;
+
+    /// Called when the scene is removed from the stack
+    fn exit(&mut self) {}
+
+    /// Returns `true` if the scene below is drawn first, e.g. for a pause menu
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+/// Change of the scene stack
+#[derive(Debug)]
+pub enum Action<S> {
+    /// Keep the current scene
+    None,
+    /// Put a scene on top of the current one
+    Push(S),
+    /// Return to the scene below, the last scene is never popped
+    Pop,
+    /// Swap the current scene for another one
+    Replace(S),
+}
+
+/// Result of [`Scene::update`], a stack change with an optional fade
+#[derive(Debug)]
+pub struct Transition<S> {
+    pub action: Action<S>,
+    pub fade: bool,
+}
+
+impl<S> Transition<S> {
+    /// Keep the current scene
+    pub const fn none() -> Self {
+        Self::new(Action::None)
+    }
+
+    /// Put a scene on top of the current one
+    pub const fn push(scene: S) -> Self {
+        Self::new(Action::Push(scene))
+    }
+
+    /// Return to the scene below
+    pub const fn pop() -> Self {
+        Self::new(Action::Pop)
+    }
+
+    /// Swap the current scene for another one
+    pub const fn replace(scene: S) -> Self {
+        Self::new(Action::Replace(scene))
+    }
+
+    /// Fades to black before and back after the change
+    pub const fn with_fade(mut self) -> Self {
+        self.fade = true;
+        self
+    }
+
+    const fn new(action: Action<S>) -> Self {
+        Self {
+            action,
+            fade: false,
+        }
+    }
+}
+
+enum Fade<S> {
+    None,
+    Out { elapsed_ms: u32, action: Action<S> },
+    In { elapsed_ms: u32 },
+}
+
+/// Stack of up to `N` scenes
+pub struct SceneManager<S: Scene, const N: usize> {
+    stack: [Option<S>; N],
+    len: usize,
+    fade: Fade<S>,
+    fade_duration_ms: u32,
+}
+
+impl<S: Scene, const N: usize> SceneManager<S, N> {
+    /// Creates a manager and enters the first scene
+    pub fn new(mut scene: S) -> Self {
+        assert!(N > 0, "the scene stack needs room for at least one scene");
+
+        scene.enter();
+
+        let mut stack = core::array::from_fn(|_| None);
+        stack[0] = Some(scene);
+
+        Self {
+            stack,
+            len: 1,
+            fade: Fade::None,
+            fade_duration_ms: FADE_DURATION_MS,
+        }
+    }
+
+    /// Changes the duration of fades in milliseconds, each direction takes that long
+    pub fn set_fade_duration(&mut self, duration_ms: u32) {
+        self.fade_duration_ms = duration_ms;
+    }
+
+    /// Returns the topmost scene
+    pub fn current(&self) -> &S {
+        self.stack[self.len - 1].as_ref().unwrap()
+    }
+
+    /// Returns the number of scenes on the stack
+    pub fn depth(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` while a fade is running
+    pub fn is_fading(&self) -> bool {
+        !matches!(self.fade, Fade::None)
+    }
+
+    /// Advances the topmost scene or the running fade by `dt_ms` milliseconds
+    pub fn update(&mut self, input: &Input, dt_ms: u32) {
+        match core::mem::replace(&mut self.fade, Fade::None) {
+            Fade::None => {
+                let transition = self.top().update(input);
+                if !transition.fade || self.fade_duration_ms == 0 {
+                    self.apply(transition.action);
+                } else if !matches!(transition.action, Action::None) {
+                    self.fade = Fade::Out {
+                        elapsed_ms: 0,
+                        action: transition.action,
+                    };
+                }
+            }
+            Fade::Out { elapsed_ms, action } => {
+                let elapsed_ms = elapsed_ms.saturating_add(dt_ms);
+                if elapsed_ms >= self.fade_duration_ms {
+                    self.apply(action);
+                    self.fade = Fade::In { elapsed_ms: 0 };
+                } else {
+                    self.fade = Fade::Out { elapsed_ms, action };
+                }
+            }
+            Fade::In { elapsed_ms } => {
+                let elapsed_ms = elapsed_ms.saturating_add(dt_ms);
+                if elapsed_ms < self.fade_duration_ms {
+                    self.fade = Fade::In { elapsed_ms };
+                }
+            }
+        }
+    }
+
+    /// Draws the topmost scene, preceded by the scenes it overlays
+    pub fn draw<D: Display>(&mut self, target: &mut D) -> Result<(), D::Error> {
+        let mut bottom = self.len - 1;
+        while bottom > 0 && self.stack[bottom].as_ref().is_some_and(S::is_overlay) {
+            bottom -= 1;
+        }
+
+        let level = self.brightness();
+        for scene in self.stack[bottom..self.len].iter_mut().flatten() {
+            if level == u8::MAX {
+                scene.draw(target)?;
+            } else {
+                scene.draw(&mut Dimmed { target, level })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Current brightness of the fade, 255 means not dimmed at all
+    fn brightness(&self) -> u8 {
+        let duration_ms = self.fade_duration_ms.max(1);
+        let progress = |elapsed_ms: u32| (elapsed_ms.min(duration_ms) * 255 / duration_ms) as u8;
+
+        match self.fade {
+            Fade::None => u8::MAX,
+            Fade::Out { elapsed_ms, .. } => u8::MAX - progress(elapsed_ms),
+            Fade::In { elapsed_ms } => progress(elapsed_ms),
+        }
+    }
+
+    fn top(&mut self) -> &mut S {
+        self.stack[self.len - 1].as_mut().unwrap()
+    }
+
+    fn apply(&mut self, action: Action<S>) {
+        match action {
+            Action::None => {}
+            Action::Push(mut scene) => {
+                // A full stack ignores the push instead of losing a scene below
+                if self.len < N {
+                    scene.enter();
+                    self.stack[self.len] = Some(scene);
+                    self.len += 1;
+                }
+            }
+            Action::Pop => {
+                if self.len > 1 {
+                    self.top().exit();
+                    self.len -= 1;
+                    self.stack[self.len] = None;
+                }
+            }
+            Action::Replace(mut scene) => {
+                self.top().exit();
+                scene.enter();
+                self.stack[self.len - 1] = Some(scene);
+            }
+        }
+    }
+}
+
+/// Draw target scaling all colors towards black
+struct Dimmed<'a, D> {
+    target: &'a mut D,
+    level: u8,
+}
+
+fn dim(color: Rgb565, level: u8) -> Rgb565 {
+    let scale = |channel: u8| (u16::from(channel) * u16::from(level) / 255) as u8;
+    Rgb565::new(scale(color.r()), scale(color.g()), scale(color.b()))
+}
+
+impl<D: Display> Dimensions for Dimmed<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D: Display> DrawTarget for Dimmed<'_, D> {
+    type Color = Rgb565;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let level = self.level;
+        let dimmed = pixels
+            .into_iter()
+            .map(|Pixel(point, color)| Pixel(point, dim(color, level)));
+        self.target.draw_iter(dimmed)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let level = self.level;
+        let dimmed = colors.into_iter().map(|color| dim(color, level));
+        self.target.fill_contiguous(area, dimmed)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.fill_solid(area, dim(color, self.level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Enter(u8),
+        Update(u8),
+        Draw(u8),
+        Exit(u8),
+    }
+
+    /// What the scenes did, and the transition the next update returns
+    struct Script<'a> {
+        events: Vec<Event>,
+        next: Option<Transition<Recorder<'a>>>,
+    }
+
+    /// Scene logging its calls, drawn as a white pixel in the column of its number
+    struct Recorder<'a> {
+        id: u8,
+        overlay: bool,
+        script: &'a RefCell<Script<'a>>,
+    }
+
+    impl Scene for Recorder<'_> {
+        fn enter(&mut self) {
+            self.log(Event::Enter(self.id));
+        }
+
+        fn update(&mut self, _input: &Input) -> Transition<Self> {
+            self.log(Event::Update(self.id));
+            self.script
+                .borrow_mut()
+                .next
+                .take()
+                .unwrap_or(Transition::none())
+        }
+
+        fn draw<D: Display>(&mut self, target: &mut D) -> Result<(), D::Error> {
+            self.log(Event::Draw(self.id));
+            Pixel(Point::new(self.id.into(), 0), Rgb565::WHITE).draw(target)
+        }
+
+        fn exit(&mut self) {
+            self.log(Event::Exit(self.id));
+        }
+
+        fn is_overlay(&self) -> bool {
+            self.overlay
+        }
+    }
+
+    impl Recorder<'_> {
+        fn log(&self, event: Event) {
+            self.script.borrow_mut().events.push(event);
+        }
+    }
+
+    fn script<'a>() -> RefCell<Script<'a>> {
+        RefCell::new(Script {
+            events: Vec::new(),
+            next: None,
+        })
+    }
+
+    fn scene<'a>(script: &'a RefCell<Script<'a>>, id: u8) -> Recorder<'a> {
+        Recorder {
+            id,
+            overlay: false,
+            script,
+        }
+    }
+
+    fn overlay<'a>(script: &'a RefCell<Script<'a>>, id: u8) -> Recorder<'a> {
+        Recorder {
+            overlay: true,
+            ..scene(script, id)
+        }
+    }
+
+    /// Updates `scenes` once, the top scene returning `transition`
+    fn run<'a, const N: usize>(
+        scenes: &mut SceneManager<Recorder<'a>, N>,
+        script: &RefCell<Script<'a>>,
+        transition: Transition<Recorder<'a>>,
+    ) {
+        script.borrow_mut().next = Some(transition);
+        scenes.update(&Input::new(), 10);
+    }
+
+    /// Returns and clears the events so far
+    fn events(script: &RefCell<Script<'_>>) -> Vec<Event> {
+        core::mem::take(&mut script.borrow_mut().events)
+    }
+
+    #[test]
+    fn pushes_and_pops_scenes() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 1));
+        assert_eq!(events(&script), [Event::Enter(1)]);
+
+        run(&mut scenes, &script, Transition::push(scene(&script, 2)));
+        assert_eq!(events(&script), [Event::Update(1), Event::Enter(2)]);
+        assert_eq!((scenes.depth(), scenes.current().id), (2, 2));
+
+        // Only the top scene is updated
+        scenes.update(&Input::new(), 10);
+        assert_eq!(events(&script), [Event::Update(2)]);
+
+        run(&mut scenes, &script, Transition::pop());
+        assert_eq!(events(&script), [Event::Update(2), Event::Exit(2)]);
+        assert_eq!((scenes.depth(), scenes.current().id), (1, 1));
+    }
+
+    #[test]
+    fn keeps_the_last_scene() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 1));
+        events(&script);
+
+        run(&mut scenes, &script, Transition::pop());
+        assert_eq!(events(&script), [Event::Update(1)]);
+        assert_eq!((scenes.depth(), scenes.current().id), (1, 1));
+    }
+
+    #[test]
+    fn ignores_push_on_a_full_stack() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 2>::new(scene(&script, 1));
+        run(&mut scenes, &script, Transition::push(scene(&script, 2)));
+        events(&script);
+
+        run(&mut scenes, &script, Transition::push(scene(&script, 3)));
+        assert_eq!(events(&script), [Event::Update(2)]);
+        assert_eq!((scenes.depth(), scenes.current().id), (2, 2));
+    }
+
+    #[test]
+    fn exits_before_entering_on_replace() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 1));
+        run(&mut scenes, &script, Transition::push(scene(&script, 2)));
+        events(&script);
+
+        run(&mut scenes, &script, Transition::replace(scene(&script, 3)));
+        assert_eq!(
+            events(&script),
+            [Event::Update(2), Event::Exit(2), Event::Enter(3)]
+        );
+        assert_eq!((scenes.depth(), scenes.current().id), (2, 3));
+    }
+
+    #[test]
+    fn fades_out_changes_and_fades_in() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 1));
+        scenes.set_fade_duration(100);
+        events(&script);
+
+        // The change waits until the screen is black
+        run(
+            &mut scenes,
+            &script,
+            Transition::replace(scene(&script, 2)).with_fade(),
+        );
+        assert!(scenes.is_fading());
+        assert_eq!(scenes.brightness(), 255);
+        for brightness in [153, 51] {
+            scenes.update(&Input::new(), 40);
+            assert_eq!(scenes.brightness(), brightness);
+        }
+        assert_eq!(events(&script), [Event::Update(1)]);
+
+        scenes.update(&Input::new(), 40);
+        assert_eq!(events(&script), [Event::Exit(1), Event::Enter(2)]);
+        assert_eq!(scenes.brightness(), 0);
+
+        // Input reaches the new scene only once it is fully visible
+        for brightness in [102, 204] {
+            scenes.update(&Input::new(), 40);
+            assert_eq!(scenes.brightness(), brightness);
+        }
+        scenes.update(&Input::new(), 40);
+        assert!(!scenes.is_fading());
+        assert_eq!(scenes.brightness(), 255);
+        assert_eq!(events(&script), []);
+
+        scenes.update(&Input::new(), 40);
+        assert_eq!(events(&script), [Event::Update(2)]);
+    }
+
+    #[test]
+    fn fades_only_for_a_change() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 1));
+        run(&mut scenes, &script, Transition::none().with_fade());
+        assert!(!scenes.is_fading());
+
+        // Without a duration the change happens at once
+        scenes.set_fade_duration(0);
+        run(
+            &mut scenes,
+            &script,
+            Transition::push(scene(&script, 2)).with_fade(),
+        );
+        assert!(!scenes.is_fading());
+        assert_eq!(scenes.current().id, 2);
+    }
+
+    #[test]
+    fn draws_overlays_over_the_scene_below() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(overlay(&script, 1));
+        run(&mut scenes, &script, Transition::push(scene(&script, 2)));
+        events(&script);
+
+        let mut display = MockDisplay::new();
+        scenes.draw(&mut display).unwrap();
+        assert_eq!(events(&script), [Event::Draw(2)]);
+
+        run(&mut scenes, &script, Transition::push(overlay(&script, 3)));
+        run(&mut scenes, &script, Transition::push(overlay(&script, 4)));
+        events(&script);
+        let mut display = MockDisplay::new();
+        scenes.draw(&mut display).unwrap();
+        assert_eq!(
+            events(&script),
+            [Event::Draw(2), Event::Draw(3), Event::Draw(4)]
+        );
+        display.assert_pattern(&["  WWW"]);
+
+        // The bottom scene is drawn even if it is an overlay itself
+        for _ in 0..3 {
+            run(&mut scenes, &script, Transition::pop());
+        }
+        events(&script);
+        scenes.draw(&mut MockDisplay::new()).unwrap();
+        assert_eq!(events(&script), [Event::Draw(1)]);
+    }
+
+    #[test]
+    fn dims_colors_while_fading() {
+        let script = script();
+        let mut scenes = SceneManager::<_, 4>::new(scene(&script, 0));
+        scenes.set_fade_duration(100);
+        run(
+            &mut scenes,
+            &script,
+            Transition::push(scene(&script, 1)).with_fade(),
+        );
+        scenes.update(&Input::new(), 50);
+
+        let mut display = MockDisplay::new();
+        scenes.draw(&mut display).unwrap();
+        assert_eq!(
+            display.get_pixel(Point::zero()),
+            Some(Rgb565::new(15, 31, 15))
+        );
+
+        assert_eq!(dim(Rgb565::WHITE, 0), Rgb565::BLACK);
+        assert_eq!(dim(Rgb565::WHITE, 255), Rgb565::WHITE);
+        assert_eq!(dim(Rgb565::new(31, 10, 4), 128), Rgb565::new(15, 5, 2));
+    }
+}
@@ -0,0 +1,536 @@
+//! Screenshots of the framebuffer for bug reports and documentation.
+//!
+//! A [`Dump`] turns a region of a [`FrameBuffer`] into a compact byte stream:
+//!
+//! * a [`Header`] with the magic `PBSS`, the format version and the region as
+//!   x, y, width and height, each a little endian `u16`
+//! * runs of equal pixels in rows from top to bottom, each a count from 1 to
+//!   255 followed by the RGB565 color as little endian `u16`
+//! * the CRC-32 of everything before it, little endian
+//!
+//! The firmware sends the stream in numbered chunks of [`CHUNK_LEN`] bytes,
+//! see [`send`] and [`log`]. The `screenshot` host tool collects them from
+//! the log and [`decode`]s them into a PNG file.
+
+use embedded_graphics::{
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use crate::framebuffer::FrameBuffer;
+use crate::storage::Crc;
+
+/// First bytes of every dump
+pub const MAGIC: [u8; 4] = *b"PBSS";
+
+/// Version of the format described in the module documentation
+pub const VERSION: u8 = 1;
+
+/// Length of the encoded [`Header`] in bytes
+pub const HEADER_LEN: usize = 13;
+
+/// Length of the checksum at the end in bytes
+pub const CHECKSUM_LEN: usize = 4;
+
+/// Length of a run of equal pixels in bytes
+const RUN_LEN: usize = 3;
+
+/// Longest run of equal pixels
+const MAX_RUN: u8 = u8::MAX;
+
+/// Bytes per chunk passed on by [`send`]
+pub const CHUNK_LEN: usize = 64;
+
+/// Errors while decoding a dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// Shorter than a header and a checksum
+    TooShort,
+    /// Does not start with [`MAGIC`]
+    Magic,
+    /// Written in an unknown version of the format
+    Version(u8),
+    /// The checksum does not match, a chunk got lost or damaged
+    Checksum,
+    /// A run of length 0 or the runs cover more pixels than the region
+    Runs,
+    /// The runs cover fewer pixels than the region
+    Incomplete,
+    /// The buffer for the pixels is smaller than the region
+    BufferTooSmall,
+}
+
+/// Region of the framebuffer a dump was taken of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Header {
+    /// Creates a header for `area`, which has to lie within the framebuffer
+    pub fn new(area: &Rectangle) -> Self {
+        Self {
+            x: area.top_left.x as u16,
+            y: area.top_left.y as u16,
+            width: area.size.width as u16,
+            height: area.size.height as u16,
+        }
+    }
+
+    /// Returns the region
+    pub fn area(&self) -> Rectangle {
+        Rectangle::new(
+            Point::new(i32::from(self.x), i32::from(self.y)),
+            Size::new(u32::from(self.width), u32::from(self.height)),
+        )
+    }
+
+    /// Returns the number of pixels of the region
+    pub fn pixel_count(&self) -> usize {
+        usize::from(self.width) * usize::from(self.height)
+    }
+
+    /// Encodes the header
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        for (i, value) in [self.x, self.y, self.width, self.height]
+            .into_iter()
+            .enumerate()
+        {
+            bytes[5 + 2 * i..7 + 2 * i].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes the header at the start of a dump, without checking the rest
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(DecodeError::TooShort);
+        }
+        if bytes[..4] != MAGIC {
+            return Err(DecodeError::Magic);
+        }
+        if bytes[4] != VERSION {
+            return Err(DecodeError::Version(bytes[4]));
+        }
+
+        let value = |i: usize| u16::from_le_bytes([bytes[5 + 2 * i], bytes[6 + 2 * i]]);
+        Ok(Self {
+            x: value(0),
+            y: value(1),
+            width: value(2),
+            height: value(3),
+        })
+    }
+}
+
+/// Part of the stream a [`Dump`] is at
+#[derive(Clone, Copy)]
+enum Section {
+    Header,
+    Runs,
+    Checksum,
+    Done,
+}
+
+/// Encoder of a dump, yields its bytes one by one
+pub struct Dump<'a> {
+    framebuffer: &'a FrameBuffer,
+    area: Rectangle,
+    section: Section,
+    // Encoded header, run or checksum and how much of it was yielded
+    buffer: [u8; HEADER_LEN],
+    len: usize,
+    position: usize,
+    // Next pixel of the area to encode, counted in rows
+    pixel: u32,
+    crc: Crc,
+}
+
+impl<'a> Dump<'a> {
+    /// Creates a dump of `area`, clipped to the framebuffer
+    pub fn new(framebuffer: &'a FrameBuffer, area: Rectangle) -> Self {
+        let area = area.intersection(&framebuffer.bounding_box());
+        Self {
+            framebuffer,
+            area,
+            section: Section::Header,
+            buffer: Header::new(&area).to_bytes(),
+            len: HEADER_LEN,
+            position: 0,
+            pixel: 0,
+            crc: Crc::new(),
+        }
+    }
+
+    /// Creates a dump of the whole framebuffer
+    pub fn full(framebuffer: &'a FrameBuffer) -> Self {
+        Self::new(framebuffer, framebuffer.bounding_box())
+    }
+
+    /// Returns the region being dumped
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+
+    /// Fills `chunk` with the next bytes and returns how many, 0 at the end
+    pub fn fill(&mut self, chunk: &mut [u8]) -> usize {
+        let mut len = 0;
+        for (slot, byte) in chunk.iter_mut().zip(self.by_ref()) {
+            *slot = byte;
+            len += 1;
+        }
+        len
+    }
+
+    fn color(&self, pixel: u32) -> u16 {
+        let width = self.area.size.width;
+        let point = self.area.top_left + Point::new((pixel % width) as i32, (pixel / width) as i32);
+        self.framebuffer
+            .pixel(point)
+            .map_or(0, |color| color.into_storage())
+    }
+
+    /// Encodes the next section into the buffer, returns `false` at the end
+    fn advance(&mut self) -> bool {
+        let pixel_count = self.area.size.width * self.area.size.height;
+
+        if let Section::Header | Section::Runs = self.section {
+            if self.pixel < pixel_count {
+                let color = self.color(self.pixel);
+                let mut count = 1;
+                while count < MAX_RUN
+                    && self.pixel + u32::from(count) < pixel_count
+                    && self.color(self.pixel + u32::from(count)) == color
+                {
+                    count += 1;
+                }
+                self.pixel += u32::from(count);
+
+                self.buffer[0] = count;
+                self.buffer[1..RUN_LEN].copy_from_slice(&color.to_le_bytes());
+                self.section = Section::Runs;
+                self.len = RUN_LEN;
+            } else {
+                let checksum = self.crc.finish().to_le_bytes();
+                self.buffer[..CHECKSUM_LEN].copy_from_slice(&checksum);
+                self.section = Section::Checksum;
+                self.len = CHECKSUM_LEN;
+            }
+            self.position = 0;
+            return true;
+        }
+
+        self.section = Section::Done;
+        false
+    }
+}
+
+impl Iterator for Dump<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if let Section::Done = self.section {
+            return None;
+        }
+        if self.position == self.len && !self.advance() {
+            return None;
+        }
+
+        let byte = self.buffer[self.position];
+        self.position += 1;
+        if let Section::Header | Section::Runs = self.section {
+            self.crc = self.crc.update(&[byte]);
+        }
+        Some(byte)
+    }
+}
+
+/// Decodes a complete dump into `pixels`, rows from top to bottom.
+///
+/// `pixels` needs room for at least [`Header::pixel_count`] pixels, the
+/// region is returned in the header.
+pub fn decode(bytes: &[u8], pixels: &mut [Rgb565]) -> Result<Header, DecodeError> {
+    let header = Header::parse(bytes)?;
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(DecodeError::TooShort);
+    }
+
+    let (data, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if Crc::new().update(data).finish() != checksum {
+        return Err(DecodeError::Checksum);
+    }
+
+    let pixels = pixels
+        .get_mut(..header.pixel_count())
+        .ok_or(DecodeError::BufferTooSmall)?;
+
+    let runs = data[HEADER_LEN..].chunks(RUN_LEN);
+    let mut filled = 0;
+    for run in runs {
+        let &[count, low, high] = run else {
+            return Err(DecodeError::Runs);
+        };
+        let end = filled + usize::from(count);
+        if count == 0 || end > pixels.len() {
+            return Err(DecodeError::Runs);
+        }
+        let color = Rgb565::from(RawU16::new(u16::from_le_bytes([low, high])));
+        pixels[filled..end].fill(color);
+        filled = end;
+    }
+
+    if filled < pixels.len() {
+        return Err(DecodeError::Incomplete);
+    }
+
+    Ok(header)
+}
+
+/// Passes a dump of `area` to `send` in chunks of [`CHUNK_LEN`] bytes, numbered from 0
+pub fn send(framebuffer: &FrameBuffer, area: Rectangle, mut send: impl FnMut(u16, &[u8])) {
+    let mut dump = Dump::new(framebuffer, area);
+    let mut chunk = [0; CHUNK_LEN];
+    let mut index: u16 = 0;
+    loop {
+        let len = dump.fill(&mut chunk);
+        if len == 0 {
+            break;
+        }
+        send(index, &chunk[..len]);
+        index = index.wrapping_add(1);
+    }
+}
+
+/// Sends a dump of `area` in numbered chunks through defmt.
+///
+/// Each chunk is printed as `screenshot <index> <bytes>`, starting at index 0.
+/// RTT drops what does not fit into its buffer, so `pause` is called after
+/// every chunk to give the probe time to read it. A missing index tells the
+/// host tool that the dump has to be taken again.
+#[cfg(feature = "defmt")]
+pub fn log(framebuffer: &FrameBuffer, area: Rectangle, mut pause: impl FnMut()) {
+    send(framebuffer, area, |index, chunk| {
+        defmt::println!("screenshot {=u16} {=[u8]}", index, chunk);
+        pause();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{boxed::Box, vec, vec::Vec};
+
+    use embedded_graphics::primitives::{Circle, PrimitiveStyle};
+
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    /// Framebuffer with a few shapes, so runs of many lengths occur
+    fn scene() -> Box<FrameBuffer> {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        let Ok(()) = rect(10, 20, 100, 50)
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+            .draw(framebuffer.as_mut());
+        let Ok(()) = Circle::new(Point::new(60, 100), 90)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::CYAN, 3))
+            .draw(framebuffer.as_mut());
+        for x in 0..240 {
+            let color = RawU16::new(x as u16 * 273).into();
+            let Ok(()) = Pixel(Point::new(x, 279), color).draw(framebuffer.as_mut());
+        }
+        framebuffer
+    }
+
+    /// Collects the chunks passed on by [`send`], checking their numbering
+    fn sent(framebuffer: &FrameBuffer, area: Rectangle) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut next_index = 0;
+        send(framebuffer, area, |index, chunk| {
+            assert_eq!(index, next_index);
+            assert!(!chunk.is_empty() && chunk.len() <= CHUNK_LEN);
+            next_index += 1;
+            bytes.extend_from_slice(chunk);
+        });
+        bytes
+    }
+
+    /// Appends the checksum to a header and runs
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let checksum = Crc::new().update(&bytes).finish();
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn assert_pixels(framebuffer: &FrameBuffer, area: Rectangle, pixels: &[Rgb565]) {
+        assert_eq!(pixels.len(), area.points().count());
+        for (point, &pixel) in area.points().zip(pixels) {
+            assert_eq!(framebuffer.pixel(point), Some(pixel), "at {point:?}");
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header::new(&rect(1, 2, 300, 40));
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[..5], b"PBSS\x01");
+        assert_eq!(bytes[9..11], [0x2c, 0x01]);
+        assert_eq!(Header::parse(&bytes), Ok(header));
+        assert_eq!(header.area(), rect(1, 2, 300, 40));
+        assert_eq!(header.pixel_count(), 12_000);
+    }
+
+    #[test]
+    fn rejects_foreign_headers() {
+        let bytes = Header::new(&rect(0, 0, 1, 1)).to_bytes();
+        assert_eq!(Header::parse(&bytes[..12]), Err(DecodeError::TooShort));
+
+        let mut other = bytes;
+        other[0] = b'X';
+        assert_eq!(Header::parse(&other), Err(DecodeError::Magic));
+
+        let mut other = bytes;
+        other[4] = 7;
+        assert_eq!(Header::parse(&other), Err(DecodeError::Version(7)));
+    }
+
+    #[test]
+    fn round_trip_of_whole_framebuffer() {
+        let framebuffer = scene();
+        let area = framebuffer.bounding_box();
+        let bytes = sent(&framebuffer, area);
+        assert_eq!(bytes, Dump::full(&framebuffer).collect::<Vec<u8>>());
+
+        let mut pixels = vec![Rgb565::BLACK; area.points().count()];
+        let header = decode(&bytes, &mut pixels).unwrap();
+        assert_eq!(header.area(), area);
+        assert_pixels(&framebuffer, area, &pixels);
+    }
+
+    #[test]
+    fn round_trip_of_region() {
+        let framebuffer = scene();
+        let area = rect(5, 15, 70, 90);
+        let bytes = sent(&framebuffer, area);
+
+        let mut pixels = vec![Rgb565::BLACK; 70 * 90 + 10];
+        let header = decode(&bytes, &mut pixels).unwrap();
+        assert_eq!(header.area(), area);
+        assert_pixels(&framebuffer, area, &pixels[..70 * 90]);
+    }
+
+    #[test]
+    fn clips_region_to_framebuffer() {
+        let framebuffer = scene();
+        let dump = Dump::new(&framebuffer, rect(200, 270, 100, 100));
+        assert_eq!(dump.area(), rect(200, 270, 40, 10));
+
+        let bytes: Vec<u8> = dump.collect();
+        let mut pixels = vec![Rgb565::BLACK; 400];
+        let header = decode(&bytes, &mut pixels).unwrap();
+        assert_eq!(header.area(), rect(200, 270, 40, 10));
+        assert_pixels(&framebuffer, header.area(), &pixels);
+    }
+
+    #[test]
+    fn splits_long_runs() {
+        let framebuffer = Box::new(FrameBuffer::new());
+        let bytes: Vec<u8> = Dump::full(&framebuffer).collect();
+
+        // 67 200 black pixels in 263 full runs and one of 135
+        assert_eq!(bytes.len(), HEADER_LEN + 264 * RUN_LEN + CHECKSUM_LEN);
+        assert_eq!(bytes[HEADER_LEN..HEADER_LEN + RUN_LEN], [255, 0, 0]);
+        assert_eq!(bytes[bytes.len() - CHECKSUM_LEN - RUN_LEN], 135);
+
+        let mut pixels = vec![Rgb565::WHITE; 67_200];
+        decode(&bytes, &mut pixels).unwrap();
+        assert!(pixels.iter().all(|&pixel| pixel == Rgb565::BLACK));
+    }
+
+    #[test]
+    fn fill_yields_the_same_bytes() {
+        let framebuffer = scene();
+        let expected: Vec<u8> = Dump::full(&framebuffer).collect();
+
+        let mut dump = Dump::full(&framebuffer);
+        let mut bytes = Vec::new();
+        let mut chunk = [0; 7];
+        loop {
+            let len = dump.fill(&mut chunk);
+            if len == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..len]);
+        }
+        assert_eq!(bytes, expected);
+        assert_eq!(dump.next(), None);
+    }
+
+    #[test]
+    fn detects_damaged_dumps() {
+        let framebuffer = scene();
+        let bytes = sent(&framebuffer, rect(0, 0, 120, 120));
+        let mut pixels = vec![Rgb565::BLACK; 120 * 120];
+
+        let mut damaged = bytes.clone();
+        damaged[HEADER_LEN + 10] ^= 0x40;
+        assert_eq!(decode(&damaged, &mut pixels), Err(DecodeError::Checksum));
+
+        // A chunk got lost
+        let mut lost = bytes.clone();
+        lost.drain(CHUNK_LEN..2 * CHUNK_LEN);
+        assert_eq!(decode(&lost, &mut pixels), Err(DecodeError::Checksum));
+
+        assert_eq!(
+            decode(&bytes[..HEADER_LEN + 2], &mut pixels),
+            Err(DecodeError::TooShort)
+        );
+        assert_eq!(
+            decode(&bytes, &mut pixels[..100]),
+            Err(DecodeError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn checks_runs_against_region() {
+        let header = Header::new(&rect(0, 0, 2, 2)).to_bytes().to_vec();
+        let mut pixels = [Rgb565::BLACK; 4];
+
+        let dump = |runs: &[u8]| with_checksum([header.as_slice(), runs].concat());
+        assert_eq!(
+            decode(&dump(&[4, 0x1f, 0x00]), &mut pixels),
+            Ok(Header::new(&rect(0, 0, 2, 2)))
+        );
+        assert_eq!(pixels, [Rgb565::BLUE; 4]);
+
+        assert_eq!(
+            decode(&dump(&[0, 0, 0, 4, 0, 0]), &mut pixels),
+            Err(DecodeError::Runs)
+        );
+        assert_eq!(
+            decode(&dump(&[3, 0, 0, 2, 0, 0]), &mut pixels),
+            Err(DecodeError::Runs)
+        );
+        assert_eq!(
+            decode(&dump(&[4, 0, 0, 1]), &mut pixels),
+            Err(DecodeError::Runs)
+        );
+        assert_eq!(
+            decode(&dump(&[3, 0, 0]), &mut pixels),
+            Err(DecodeError::Incomplete)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Async sleeps based on timer alarm 1.
+//!
+//! A pending [`sleep_until`] arms the alarm for its deadline unless an earlier
+//! one is already armed. The `TIMER_IRQ_1` interrupt, which the application
+//! forwards to [`on_alarm_interrupt`], wakes the
+//! [`Executor`](crate::executor::Executor) from its sleep, whereupon every
+//! pending sleep checks its deadline and arms the alarm again if needed.
+
+use core::cell::RefCell;
+use core::task::Poll;
+
+use cortex_m::interrupt::Mutex;
+use picoboy_color::hal::{
+    fugit::MicrosDurationU64,
+    timer::{Alarm, Alarm1},
+    Timer,
+};
+
+/// Alarm 1 and the deadline it is armed for
+struct State {
+    timer: Timer,
+    alarm: Alarm1,
+    armed: Option<u64>,
+}
+
+static STATE: Mutex<RefCell<Option<State>>> = Mutex::new(RefCell::new(None));
+
+/// Longest time the alarm can be armed for, about 71 minutes
+const MAX_ALARM_US: u64 = u32::MAX as u64;
+
+/// Takes the alarm and enables its interrupt, call this before sleeping
+pub fn init(timer: Timer, mut alarm: Alarm1) {
+    alarm.enable_interrupt();
+    cortex_m::interrupt::free(|cs| {
+        STATE.borrow(cs).replace(Some(State {
+            timer,
+            alarm,
+            armed: None,
+        }))
+    });
+}
+
+/// Handles the alarm, call this from `TIMER_IRQ_1`
+pub fn on_alarm_interrupt() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(state) = STATE.borrow(cs).borrow_mut().as_mut() {
+            state.alarm.clear_interrupt();
+            state.armed = None;
+        }
+    });
+}
+
+/// Returns the time since boot in microseconds
+pub fn now_us() -> u64 {
+    cortex_m::interrupt::free(|cs| match STATE.borrow(cs).borrow().as_ref() {
+        Some(state) => state.timer.get_counter().ticks(),
+        None => 0,
+    })
+}
+
+/// Sleeps until the timer reaches `deadline_us`
+pub async fn sleep_until(deadline_us: u64) {
+    core::future::poll_fn(|_| {
+        cortex_m::interrupt::free(|cs| {
+            let mut state = STATE.borrow(cs).borrow_mut();
+            let Some(state) = state.as_mut() else {
+                // Without the alarm the deadline can never be checked
+                return Poll::Ready(());
+            };
+
+            let now = state.timer.get_counter();
+            if now.ticks() >= deadline_us {
+                return Poll::Ready(());
+            }
+
+            if state.armed.is_none_or(|armed| deadline_us < armed) {
+                // Far deadlines are approached in steps
+                let at = deadline_us.min(now.ticks() + MAX_ALARM_US);
+                let _ = state
+                    .alarm
+                    .schedule_at(now + MicrosDurationU64::micros(at - now.ticks()));
+                state.armed = Some(at);
+            }
+
+            Poll::Pending
+        })
+    })
+    .await
+}
+
+/// Sleeps for `duration_us` microseconds
+pub async fn sleep_us(duration_us: u64) {
+    sleep_until(now_us() + duration_us).await
+}
+
+/// Sleeps for `duration_ms` milliseconds
+pub async fn sleep_ms(duration_ms: u32) {
+    sleep_us(u64::from(duration_ms) * 1000).await
+}
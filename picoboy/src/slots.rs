@@ -0,0 +1,345 @@
+//! Flash layout and metadata of apps started by the launcher.
+//!
+//! The launcher in `src/bin/launcher.rs` owns the start of the flash, the
+//! second stage boot loader included. Behind it are [`SLOT_COUNT`] slots for
+//! apps, followed by the `SAVE` region, which all apps share:
+//!
+//! ```text
+//! 0x1000_0000  launcher, 256 KiB
+//! 0x1004_0000  slot 0, 576 KiB
+//! 0x100D_0000  slot 1, 576 KiB
+//! 0x1016_0000  slot 2, 576 KiB
+//! 0x101F_0000  save data, 64 KiB
+//! ```
+//!
+//! Each slot starts with an [`AppHeader`], the vector table of the app
+//! follows at [`VECTOR_TABLE_OFFSET`]. Building with `PICOBOY_SLOT` set to
+//! the number of a slot links the firmware for it, see the README. The
+//! layout itself lives in the `flash-layout` crate, which the build script
+//! shares.
+
+use core::fmt;
+
+pub use flash_layout::{
+    slot_address, vector_table_address, FLASH_BASE, FLASH_LEN, LAUNCHER_LEN, SAVE_LEN, SLOT_COUNT,
+    SLOT_LEN, VECTOR_TABLE_OFFSET,
+};
+
+/// First bytes of every [`AppHeader`]
+pub const MAGIC: [u8; 4] = *b"PBAP";
+
+/// Version of the header layout
+pub const VERSION: u8 = 1;
+
+/// Longest app name in bytes
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Length of the encoded [`AppHeader`] in bytes
+pub const APP_HEADER_LEN: usize = 8 + MAX_NAME_LEN;
+
+/// Bytes at the start of a slot needed by [`parse`], the header and the first two vectors
+pub const SLOT_PREFIX_LEN: usize = VECTOR_TABLE_OFFSET as usize + 8;
+
+/// Range of the RAM the initial stack pointer has to lie in, including the end
+const RAM: core::ops::RangeInclusive<u32> = 0x2000_0000..=0x2004_2000;
+
+/// Reasons a slot holds no app that can be started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlotError {
+    /// There is no such slot
+    NoSlot,
+    /// Fewer bytes than [`SLOT_PREFIX_LEN`]
+    TooShort,
+    /// The slot is erased
+    Empty,
+    /// Does not start with [`MAGIC`]
+    Magic,
+    /// Written with an unknown version of the header
+    Version(u8),
+    /// The name is too long or not valid UTF-8
+    Name,
+    /// The initial stack pointer lies outside of the RAM
+    StackPointer(u32),
+    /// The reset vector lies outside of the slot or is no Thumb address
+    ResetVector(u32),
+}
+
+impl fmt::Display for SlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotError::NoSlot => f.write_str("no such slot"),
+            SlotError::TooShort => f.write_str("too short"),
+            SlotError::Empty => f.write_str("empty"),
+            SlotError::Magic => f.write_str("no app header"),
+            SlotError::Version(version) => write!(f, "unknown header version {version}"),
+            SlotError::Name => f.write_str("invalid name"),
+            SlotError::StackPointer(address) => write!(f, "stack pointer {address:#010x}"),
+            SlotError::ResetVector(address) => write!(f, "reset vector {address:#010x}"),
+        }
+    }
+}
+
+/// Name and format version of an app, placed at the start of its slot.
+///
+/// Apps put the encoded header into the `.app_header` section, which the
+/// linker script of slot builds places in front of the vector table:
+///
+/// ```ignore
+/// #[link_section = ".app_header"]
+/// #[used]
+/// static APP_HEADER: [u8; APP_HEADER_LEN] = AppHeader::new("Breakout").to_bytes();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppHeader {
+    name: [u8; MAX_NAME_LEN],
+    name_len: u8,
+}
+
+impl AppHeader {
+    /// Creates a header, names longer than [`MAX_NAME_LEN`] bytes fail to compile
+    pub const fn new(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        assert!(bytes.len() <= MAX_NAME_LEN, "app name too long");
+
+        let mut header = Self {
+            name: [0; MAX_NAME_LEN],
+            name_len: bytes.len() as u8,
+        };
+        let mut i = 0;
+        while i < bytes.len() {
+            header.name[i] = bytes[i];
+            i += 1;
+        }
+        header
+    }
+
+    /// Returns the name shown by the launcher
+    pub fn name(&self) -> &str {
+        // Checked by `new` and `parse`
+        core::str::from_utf8(&self.name[..usize::from(self.name_len)]).unwrap_or_default()
+    }
+
+    /// Encodes the header: magic, version, name length, two reserved bytes and the name
+    pub const fn to_bytes(self) -> [u8; APP_HEADER_LEN] {
+        let mut bytes = [0; APP_HEADER_LEN];
+        let mut i = 0;
+        while i < MAGIC.len() {
+            bytes[i] = MAGIC[i];
+            i += 1;
+        }
+        bytes[4] = VERSION;
+        bytes[5] = self.name_len;
+
+        let mut i = 0;
+        while i < MAX_NAME_LEN {
+            bytes[8 + i] = self.name[i];
+            i += 1;
+        }
+        bytes
+    }
+
+    /// Decodes a header
+    pub fn parse(bytes: &[u8]) -> Result<Self, SlotError> {
+        let bytes = bytes.get(..APP_HEADER_LEN).ok_or(SlotError::TooShort)?;
+        if bytes.iter().all(|&byte| byte == 0xFF) {
+            return Err(SlotError::Empty);
+        }
+        if bytes[..4] != MAGIC {
+            return Err(SlotError::Magic);
+        }
+        if bytes[4] != VERSION {
+            return Err(SlotError::Version(bytes[4]));
+        }
+
+        let name_len = bytes[5];
+        let name = &bytes[8..];
+        if usize::from(name_len) > MAX_NAME_LEN
+            || core::str::from_utf8(&name[..usize::from(name_len)]).is_err()
+        {
+            return Err(SlotError::Name);
+        }
+
+        let mut header = Self {
+            name: [0; MAX_NAME_LEN],
+            name_len,
+        };
+        header.name.copy_from_slice(name);
+        Ok(header)
+    }
+}
+
+/// Checks the first [`SLOT_PREFIX_LEN`] bytes of a slot and returns the header of its app.
+///
+/// Besides the header, the initial stack pointer has to lie in the RAM and the
+/// reset vector in the slot, otherwise the slot was written for another
+/// address or not completely.
+pub fn parse(slot: usize, bytes: &[u8]) -> Result<AppHeader, SlotError> {
+    if slot >= SLOT_COUNT {
+        return Err(SlotError::NoSlot);
+    }
+    let bytes = bytes.get(..SLOT_PREFIX_LEN).ok_or(SlotError::TooShort)?;
+    let header = AppHeader::parse(bytes)?;
+
+    let vector = |i: usize| {
+        let start = VECTOR_TABLE_OFFSET as usize + 4 * i;
+        u32::from_le_bytes([
+            bytes[start],
+            bytes[start + 1],
+            bytes[start + 2],
+            bytes[start + 3],
+        ])
+    };
+
+    let stack_pointer = vector(0);
+    if !RAM.contains(&stack_pointer) || !stack_pointer.is_multiple_of(4) {
+        return Err(SlotError::StackPointer(stack_pointer));
+    }
+
+    let reset = vector(1);
+    let code = vector_table_address(slot)..slot_address(slot) + SLOT_LEN;
+    if !code.contains(&(reset & !1)) || reset & 1 == 0 {
+        return Err(SlotError::ResetVector(reset));
+    }
+
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACK: u32 = 0x2004_2000;
+
+    /// Start of a slot with `header`, the initial stack pointer and the reset vector
+    fn slot_bytes(header: [u8; APP_HEADER_LEN], stack: u32, reset: u32) -> [u8; SLOT_PREFIX_LEN] {
+        let mut bytes = [0xFF; SLOT_PREFIX_LEN];
+        bytes[..APP_HEADER_LEN].copy_from_slice(&header);
+        let vectors = VECTOR_TABLE_OFFSET as usize;
+        bytes[vectors..vectors + 4].copy_from_slice(&stack.to_le_bytes());
+        bytes[vectors + 4..vectors + 8].copy_from_slice(&reset.to_le_bytes());
+        bytes
+    }
+
+    /// Thumb address of the code right behind the vector table of `slot`
+    fn reset(slot: usize) -> u32 {
+        vector_table_address(slot) + 0xC0 + 1
+    }
+
+    #[test]
+    fn layout_fills_the_flash() {
+        assert_eq!(SLOT_LEN, 576 * 1024);
+        assert_eq!(slot_address(0), 0x1004_0000);
+        assert_eq!(slot_address(1), 0x100D_0000);
+        assert_eq!(slot_address(2), 0x1016_0000);
+        assert_eq!(slot_address(SLOT_COUNT) + SAVE_LEN, FLASH_BASE + FLASH_LEN);
+        for slot in 0..SLOT_COUNT {
+            assert_eq!(vector_table_address(slot) % 256, 0);
+        }
+        assert!(APP_HEADER_LEN <= VECTOR_TABLE_OFFSET as usize);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        const HEADER: AppHeader = AppHeader::new("Breakout");
+        const BYTES: [u8; APP_HEADER_LEN] = HEADER.to_bytes();
+
+        assert_eq!(&BYTES[..8], b"PBAP\x01\x08\x00\x00");
+        assert_eq!(&BYTES[8..16], b"Breakout");
+        assert!(BYTES[16..].iter().all(|&byte| byte == 0));
+
+        let header = AppHeader::parse(&BYTES).unwrap();
+        assert_eq!(header, HEADER);
+        assert_eq!(header.name(), "Breakout");
+
+        let longest = "äöü The longest app name here";
+        assert_eq!(longest.len(), MAX_NAME_LEN);
+        let header = AppHeader::parse(&AppHeader::new(longest).to_bytes()).unwrap();
+        assert_eq!(header.name(), longest);
+        assert_eq!(AppHeader::new("").name(), "");
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let bytes = AppHeader::new("Snake").to_bytes();
+        assert_eq!(
+            AppHeader::parse(&bytes[..APP_HEADER_LEN - 1]),
+            Err(SlotError::TooShort)
+        );
+        assert_eq!(
+            AppHeader::parse(&[0xFF; APP_HEADER_LEN]),
+            Err(SlotError::Empty)
+        );
+
+        let mut other = bytes;
+        other[3] = b'X';
+        assert_eq!(AppHeader::parse(&other), Err(SlotError::Magic));
+
+        let mut other = bytes;
+        other[4] = 2;
+        assert_eq!(AppHeader::parse(&other), Err(SlotError::Version(2)));
+
+        let mut other = bytes;
+        other[5] = MAX_NAME_LEN as u8 + 1;
+        assert_eq!(AppHeader::parse(&other), Err(SlotError::Name));
+
+        // A multibyte character cut in half
+        let mut other = AppHeader::new("ä").to_bytes();
+        other[5] = 1;
+        assert_eq!(AppHeader::parse(&other), Err(SlotError::Name));
+    }
+
+    #[test]
+    fn parses_slots() {
+        let header = AppHeader::new("Breakout");
+        for slot in 0..SLOT_COUNT {
+            let bytes = slot_bytes(header.to_bytes(), STACK, reset(slot));
+            assert_eq!(parse(slot, &bytes), Ok(header));
+        }
+
+        // Erased slots and ones the header has not been written to
+        assert_eq!(parse(0, &[0xFF; SLOT_PREFIX_LEN]), Err(SlotError::Empty));
+        let bytes = slot_bytes(AppHeader::new("Breakout").to_bytes(), STACK, reset(0));
+        assert_eq!(parse(SLOT_COUNT, &bytes), Err(SlotError::NoSlot));
+        assert_eq!(
+            parse(0, &bytes[..SLOT_PREFIX_LEN - 1]),
+            Err(SlotError::TooShort)
+        );
+    }
+
+    #[test]
+    fn checks_stack_pointer() {
+        let header = AppHeader::new("Snake").to_bytes();
+        assert!(parse(0, &slot_bytes(header, 0x2000_0000, reset(0))).is_ok());
+
+        for stack in [0x1FFF_FFFC, STACK + 4, 0x2000_1002, 0xFFFF_FFFF] {
+            assert_eq!(
+                parse(0, &slot_bytes(header, stack, reset(0))),
+                Err(SlotError::StackPointer(stack))
+            );
+        }
+    }
+
+    #[test]
+    fn checks_reset_vector() {
+        let header = AppHeader::new("Snake").to_bytes();
+        let last = slot_address(1) + SLOT_LEN - 2 + 1;
+        assert!(parse(1, &slot_bytes(header, STACK, last)).is_ok());
+
+        for reset in [
+            // Not a Thumb address
+            reset(1) - 1,
+            // Linked for another slot
+            reset(0),
+            reset(2),
+            // In the header or behind the slot
+            slot_address(1) + 1,
+            slot_address(2) + 1,
+        ] {
+            assert_eq!(
+                parse(1, &slot_bytes(header, STACK, reset)),
+                Err(SlotError::ResetVector(reset))
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! PWM output to the speaker.
+//!
+//! Channel B of PWM slice 7 drives the speaker on GPIO15 with a carrier far
+//! above the audible range, its duty cycle is the current sample. A timer
+//! alarm interrupt fires [`SAMPLE_RATE`] times per second and takes the next
+//! sample from a [`SampleQueue`](crate::sample_queue::SampleQueue), which the
+//! main loop keeps filled through an [`AudioProducer`].
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use picoboy_color::hal::{
+    fugit::MicrosDurationU64,
+    gpio::{bank0::Gpio15, FunctionPwm, Pin, PullDown},
+    pwm::{FreeRunning, Pwm7, Slice},
+    timer::{Alarm, Alarm0, Instant},
+    Timer,
+};
+
+use crate::audio::{SAMPLE_RATE, SILENCE};
+use crate::sample_queue::{Consumer, Producer};
+
+/// Number of samples buffered between the main loop and the interrupt, about 100 ms
+pub const AUDIO_QUEUE_LEN: usize = 2048;
+
+/// Writing end of the sample queue, owned by the main loop
+pub type AudioProducer = Producer<'static, AUDIO_QUEUE_LEN>;
+
+/// Reading end of the sample queue, owned by the speaker
+pub type AudioConsumer = Consumer<'static, AUDIO_QUEUE_LEN>;
+
+/// Time between two samples
+const SAMPLE_PERIOD: MicrosDurationU64 = MicrosDurationU64::micros(1_000_000 / SAMPLE_RATE as u64);
+
+/// Speaker fed by the `TIMER_IRQ_0` interrupt
+pub struct Speaker {
+    pwm: Slice<Pwm7, FreeRunning>,
+    _pin: Pin<Gpio15, FunctionPwm, PullDown>,
+    alarm: Alarm0,
+    timer: Timer,
+    samples: AudioConsumer,
+    next: Instant,
+    last: u8,
+}
+
+impl Speaker {
+    pub(crate) fn new(
+        mut pwm: Slice<Pwm7, FreeRunning>,
+        pin: Pin<Gpio15, FunctionPwm, PullDown>,
+        alarm: Alarm0,
+        timer: Timer,
+        samples: AudioConsumer,
+    ) -> Self {
+        // 8 bit duty cycle at the full system clock, a carrier of about 490 kHz
+        pwm.set_top(u16::from(u8::MAX));
+        pwm.set_div_int(1);
+        let _ = pwm.channel_b.set_duty_cycle(u16::from(SILENCE));
+        pwm.enable();
+
+        Self {
+            pwm,
+            _pin: pin,
+            alarm,
+            timer,
+            samples,
+            next: timer.get_counter(),
+            last: SILENCE,
+        }
+    }
+
+    /// Starts the sample interrupt, `TIMER_IRQ_0` still needs to be unmasked
+    pub fn start(&mut self) {
+        self.next = self.timer.get_counter() + SAMPLE_PERIOD;
+        let _ = self.alarm.schedule_at(self.next);
+        self.alarm.enable_interrupt();
+    }
+
+    /// Stops the sample interrupt and silences the speaker
+    pub fn stop(&mut self) {
+        self.alarm.disable_interrupt();
+        let _ = self.alarm.cancel();
+        self.output(SILENCE);
+    }
+
+    /// Outputs the next sample, call this from `TIMER_IRQ_0`
+    pub fn on_interrupt(&mut self) {
+        self.alarm.clear_interrupt();
+
+        // Schedule relative to the previous sample so the rate does not drift
+        self.next += SAMPLE_PERIOD;
+        let now = self.timer.get_counter();
+        if self.next < now {
+            // Far behind, e.g. after a breakpoint, so skip instead of catching up
+            self.next = now + SAMPLE_PERIOD;
+        }
+        let _ = self.alarm.schedule_at(self.next);
+
+        // Hold the last level if the main loop fell behind, jumping to silence would click
+        let sample = self.samples.pop().unwrap_or(self.last);
+        self.output(sample);
+    }
+
+    fn output(&mut self, sample: u8) {
+        self.last = sample;
+        // PWM duty cycles on the RP2040 cannot fail
+        let _ = self.pwm.channel_b.set_duty_cycle(u16::from(sample));
+    }
+}
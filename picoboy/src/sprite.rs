@@ -0,0 +1,380 @@
+//! Bitmap images and sprites drawing them.
+//!
+//! [`Image`]s are usually generated from PNG files by the asset pipeline, see
+//! the `asset-pipeline` crate. Pixels are stored either as raw RGB565 or as
+//! indices into a palette with 1, 2, 4 or 8 bits per pixel. Transparency is a
+//! key color, pixels of that color are skipped when drawing.
+//!
+//! A [`Sprite`] draws a part of an image at a position, optionally flipped,
+//! and clips it to the bounds of the draw target.
+
+use embedded_graphics::{
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+/// Storage of the pixels of an [`Image`]
+#[derive(Debug, Clone, Copy)]
+pub enum Pixels<'a> {
+    /// One raw RGB565 value per pixel, row by row
+    Rgb565(&'a [u16]),
+    /// Palette indices packed into bytes, most significant bits first.
+    ///
+    /// Each row starts at a new byte.
+    Indexed {
+        bits: u8,
+        palette: &'a [u16],
+        data: &'a [u8],
+    },
+}
+
+/// Bitmap with an optional transparency key
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    width: u32,
+    height: u32,
+    pixels: Pixels<'a>,
+    transparent: Option<u16>,
+}
+
+impl<'a> Image<'a> {
+    /// Creates an image from raw RGB565 values
+    pub const fn rgb565(
+        width: u32,
+        height: u32,
+        pixels: &'a [u16],
+        transparent: Option<u16>,
+    ) -> Self {
+        assert!(pixels.len() == (width * height) as usize);
+
+        Self {
+            width,
+            height,
+            pixels: Pixels::Rgb565(pixels),
+            transparent,
+        }
+    }
+
+    /// Creates an image from palette indices with `bits` bits per pixel
+    pub const fn indexed(
+        width: u32,
+        height: u32,
+        bits: u8,
+        palette: &'a [u16],
+        data: &'a [u8],
+        transparent: Option<u16>,
+    ) -> Self {
+        assert!(matches!(bits, 1 | 2 | 4 | 8));
+        assert!(data.len() == (stride(width, bits) * height) as usize);
+
+        Self {
+            width,
+            height,
+            pixels: Pixels::Indexed {
+                bits,
+                palette,
+                data,
+            },
+            transparent,
+        }
+    }
+
+    /// Returns the storage of the pixels
+    pub fn pixels(&self) -> Pixels<'a> {
+        self.pixels
+    }
+
+    /// Returns the raw color treated as transparent
+    pub fn transparent(&self) -> Option<u16> {
+        self.transparent
+    }
+
+    /// Returns the raw RGB565 value of a pixel, `None` outside of the image
+    pub fn raw(&self, point: Point) -> Option<u16> {
+        if !self.bounding_box().contains(point) {
+            return None;
+        }
+
+        let (x, y) = (point.x as u32, point.y as u32);
+        match self.pixels {
+            Pixels::Rgb565(pixels) => Some(pixels[(y * self.width + x) as usize]),
+            Pixels::Indexed {
+                bits,
+                palette,
+                data,
+            } => {
+                let bit = x * u32::from(bits);
+                let byte = data[(y * stride(self.width, bits) + bit / 8) as usize];
+                let shift = 8 - u32::from(bits) - bit % 8;
+                let index = (byte >> shift) & (u8::MAX >> (8 - bits));
+                palette.get(usize::from(index)).copied()
+            }
+        }
+    }
+
+    /// Returns the color of a pixel, `None` outside of the image or if transparent
+    pub fn pixel(&self, point: Point) -> Option<Rgb565> {
+        self.raw(point)
+            .filter(|&raw| Some(raw) != self.transparent)
+            .map(|raw| RawU16::new(raw).into())
+    }
+}
+
+impl OriginDimensions for Image<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+/// Number of bytes per row of an indexed image
+const fn stride(width: u32, bits: u8) -> u32 {
+    (width * bits as u32).div_ceil(8)
+}
+
+/// Mirroring of a sprite
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Flip {
+    /// Mirror left and right
+    pub horizontal: bool,
+    /// Mirror top and bottom
+    pub vertical: bool,
+}
+
+impl Flip {
+    pub const NONE: Flip = Flip {
+        horizontal: false,
+        vertical: false,
+    };
+    pub const HORIZONTAL: Flip = Flip {
+        horizontal: true,
+        vertical: false,
+    };
+    pub const VERTICAL: Flip = Flip {
+        horizontal: false,
+        vertical: true,
+    };
+    pub const BOTH: Flip = Flip {
+        horizontal: true,
+        vertical: true,
+    };
+}
+
+/// An image, or a part of it, placed on the screen
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite<'a> {
+    image: &'a Image<'a>,
+    source: Rectangle,
+    position: Point,
+    flip: Flip,
+}
+
+impl<'a> Sprite<'a> {
+    /// Creates a sprite showing the whole image with its top left corner at `position`
+    pub fn new(image: &'a Image<'a>, position: Point) -> Self {
+        Self {
+            image,
+            source: image.bounding_box(),
+            position,
+            flip: Flip::NONE,
+        }
+    }
+
+    /// Shows only a part of the image, e.g. a single frame of a sprite sheet
+    pub fn with_source(mut self, source: Rectangle) -> Self {
+        self.source = source.intersection(&self.image.bounding_box());
+        self
+    }
+
+    /// Mirrors the sprite
+    pub fn with_flip(mut self, flip: Flip) -> Self {
+        self.flip = flip;
+        self
+    }
+
+    /// Moves the top left corner of the sprite
+    pub fn set_position(&mut self, position: Point) {
+        self.position = position;
+    }
+
+    /// Returns the image pixel shown at a point of the sprite relative to its top left corner
+    fn source_point(&self, offset: Point) -> Point {
+        let size = self.source.size;
+        let x = if self.flip.horizontal {
+            size.width as i32 - 1 - offset.x
+        } else {
+            offset.x
+        };
+        let y = if self.flip.vertical {
+            size.height as i32 - 1 - offset.y
+        } else {
+            offset.y
+        };
+
+        self.source.top_left + Point::new(x, y)
+    }
+}
+
+impl Dimensions for Sprite<'_> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.position, self.source.size)
+    }
+}
+
+impl Drawable for Sprite<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let visible = self.bounding_box().intersection(&target.bounding_box());
+        if visible.is_zero_sized() {
+            return Ok(());
+        }
+
+        let source = |point: Point| self.source_point(point - self.position);
+
+        if self.image.transparent().is_none() {
+            // Opaque images are sent as one block
+            let colors = visible
+                .points()
+                .map(|point| self.image.pixel(source(point)).unwrap_or(Rgb565::BLACK));
+            target.fill_contiguous(&visible, colors)
+        } else {
+            let pixels = visible.points().filter_map(|point| {
+                self.image
+                    .pixel(source(point))
+                    .map(|color| Pixel(point, color))
+            });
+            target.draw_iter(pixels)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+
+    const R: u16 = 0xF800;
+    const G: u16 = 0x07E0;
+    const B: u16 = 0x001F;
+    const W: u16 = 0xFFFF;
+    const KEY: u16 = 0xF81F;
+
+    /// Red, green, blue over white, transparent, red
+    const IMAGE: Image<'static> = Image::rgb565(3, 2, &[R, G, B, W, KEY, R], Some(KEY));
+
+    fn draw(sprite: Sprite<'_>) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        sprite.draw(&mut display).unwrap();
+        display
+    }
+
+    #[test]
+    fn reads_rgb565_pixels() {
+        assert_eq!(IMAGE.size(), Size::new(3, 2));
+        assert_eq!(IMAGE.raw(Point::new(1, 1)), Some(KEY));
+        assert_eq!(IMAGE.pixel(Point::new(1, 1)), None);
+        assert_eq!(IMAGE.pixel(Point::new(2, 1)), Some(Rgb565::RED));
+        assert_eq!(IMAGE.raw(Point::new(3, 0)), None);
+        assert_eq!(IMAGE.raw(Point::new(0, -1)), None);
+    }
+
+    #[test]
+    fn reads_indexed_pixels() {
+        // Indices 0 to 3 in one byte
+        let image = Image::indexed(4, 1, 2, &[KEY, R, G, B], &[0b00_01_10_11], Some(KEY));
+        let pixels: [Option<Rgb565>; 4] =
+            core::array::from_fn(|x| image.pixel(Point::new(x as i32, 0)));
+        assert_eq!(
+            pixels,
+            [
+                None,
+                Some(Rgb565::RED),
+                Some(Rgb565::GREEN),
+                Some(Rgb565::BLUE)
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_indexed_rows_at_a_new_byte() {
+        let image = Image::indexed(3, 2, 1, &[W, B], &[0b1010_0000, 0b0100_0000], None);
+        let colors: [u16; 6] =
+            core::array::from_fn(|i| image.raw(Point::new(i as i32 % 3, i as i32 / 3)).unwrap());
+        assert_eq!(colors, [B, W, B, W, B, W]);
+    }
+
+    #[test]
+    fn reads_eight_bit_indices() {
+        let palette: [u16; 256] = core::array::from_fn(|i| i as u16);
+        let image = Image::indexed(2, 1, 8, &palette, &[200, 17], None);
+        assert_eq!(image.raw(Point::new(0, 0)), Some(200));
+        assert_eq!(image.raw(Point::new(1, 0)), Some(17));
+    }
+
+    #[test]
+    fn skips_transparent_pixels() {
+        draw(Sprite::new(&IMAGE, Point::zero())).assert_pattern(&["RGB", "W R"]);
+    }
+
+    #[test]
+    fn draws_opaque_images_as_block() {
+        let image = Image::rgb565(2, 2, &[R, G, B, KEY], None);
+        // Without transparency the key is an ordinary color
+        draw(Sprite::new(&image, Point::new(1, 1))).assert_pattern(&["   ", " RG", " BM"]);
+    }
+
+    #[test]
+    fn flips() {
+        let sprite = Sprite::new(&IMAGE, Point::zero());
+        draw(sprite.with_flip(Flip::HORIZONTAL)).assert_pattern(&["BGR", "R W"]);
+        draw(sprite.with_flip(Flip::VERTICAL)).assert_pattern(&["W R", "RGB"]);
+        draw(sprite.with_flip(Flip::BOTH)).assert_pattern(&["R W", "BGR"]);
+    }
+
+    #[test]
+    fn clips_to_the_target() {
+        // Only the bottom right pixel is on the display
+        let display = draw(Sprite::new(&IMAGE, Point::new(-2, -1)));
+        display.assert_pattern(&["R"]);
+
+        let display = draw(Sprite::new(&IMAGE, Point::new(62, 63)));
+        assert_eq!(display.get_pixel(Point::new(62, 63)), Some(Rgb565::RED));
+        assert_eq!(display.get_pixel(Point::new(63, 63)), Some(Rgb565::GREEN));
+        assert_eq!(
+            display.affected_area(),
+            Rectangle::new(Point::new(62, 63), Size::new(2, 1))
+        );
+
+        // Completely outside
+        let display = draw(Sprite::new(&IMAGE, Point::new(64, 0)));
+        assert!(display.affected_area().is_zero_sized());
+    }
+
+    #[test]
+    fn draws_part_of_the_image() {
+        let sprite = Sprite::new(&IMAGE, Point::zero())
+            .with_source(Rectangle::new(Point::new(1, 0), Size::new(2, 2)));
+        assert_eq!(sprite.bounding_box().size, Size::new(2, 2));
+        draw(sprite).assert_pattern(&["GB", " R"]);
+        draw(sprite.with_flip(Flip::HORIZONTAL)).assert_pattern(&["BG", "R "]);
+
+        // Sources beyond the image are cut
+        let sprite = Sprite::new(&IMAGE, Point::zero())
+            .with_source(Rectangle::new(Point::new(2, 1), Size::new(5, 5)));
+        draw(sprite).assert_pattern(&["R"]);
+    }
+
+    #[test]
+    fn moves() {
+        let mut sprite = Sprite::new(&IMAGE, Point::zero());
+        sprite.set_position(Point::new(1, 0));
+        draw(sprite).assert_pattern(&[" RGB", " W R"]);
+    }
+}
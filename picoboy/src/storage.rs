@@ -0,0 +1,953 @@
+//! Key/value store for save data in flash.
+//!
+//! The store is a log spread over the sectors of a [`Flash`] region. Every
+//! [`Store::set`] appends a record to the newest sector, the last record of a
+//! key wins. When a sector is full, writing continues in a free one, so all
+//! sectors get erased equally often. One sector is always kept free: the
+//! oldest sector is collected by copying its still current records to the
+//! newest one before it is erased.
+//!
+//! Sectors and records carry CRC-32 checksums. A write interrupted by a power
+//! loss leaves a record with a wrong checksum, which is ignored together with
+//! the rest of its sector, so the previous value of the key remains. Sectors
+//! are invalidated before they are erased, an interrupted erase therefore
+//! never leaves a sector which looks valid but lost records.
+
+/// Errors reported by a [`Flash`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlashError {
+    /// The range is not within the flash region
+    OutOfBounds,
+    /// The offset of an erase is not at the start of a sector
+    NotAligned,
+    /// The operation did not complete, e.g. because of a power loss
+    Interrupted,
+}
+
+/// NOR flash region, erased bytes read as `0xFF` and programming only clears bits
+pub trait Flash {
+    /// Size of an erasable sector in bytes
+    const SECTOR_SIZE: usize;
+
+    /// Returns the size of the region in bytes
+    fn capacity(&self) -> usize;
+
+    /// Reads bytes starting at `offset`
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), FlashError>;
+
+    /// Sets all bytes of the sector starting at `offset` to `0xFF`
+    fn erase(&mut self, offset: usize) -> Result<(), FlashError>;
+
+    /// Programs bytes starting at `offset`, which should have been erased before
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError>;
+}
+
+/// A store can borrow the flash, e.g. to look at it after a failed [`Store::mount`]
+impl<F: Flash> Flash for &mut F {
+    const SECTOR_SIZE: usize = F::SECTOR_SIZE;
+
+    fn capacity(&self) -> usize {
+        F::capacity(self)
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), FlashError> {
+        F::read(self, offset, buffer)
+    }
+
+    fn erase(&mut self, offset: usize) -> Result<(), FlashError> {
+        F::erase(self, offset)
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        F::program(self, offset, data)
+    }
+}
+
+/// Errors of the [`Store`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The flash reported an error
+    Flash(FlashError),
+    /// The region has less than two sectors or more than [`MAX_SECTORS`]
+    Region,
+    /// The key is empty or longer than [`MAX_KEY_LEN`]
+    Key,
+    /// The value does not fit into a sector
+    ValueTooLarge,
+    /// The buffer is too small for the value, which has the given length
+    BufferTooSmall(usize),
+    /// There is no room left, even after removing outdated records
+    Full,
+}
+
+impl From<FlashError> for Error {
+    fn from(error: FlashError) -> Self {
+        Error::Flash(error)
+    }
+}
+
+/// Maximum number of sectors of the region
+pub const MAX_SECTORS: usize = 64;
+
+/// Maximum length of a key in bytes
+pub const MAX_KEY_LEN: usize = 32;
+
+/// Marks a valid sector, "PBSV" read as little endian
+const SECTOR_MAGIC: u32 = 0x5653_4250;
+
+/// Magic, sequence number and checksum
+const SECTOR_HEADER_LEN: usize = 12;
+
+/// Kind, key length, value length and checksum
+const RECORD_HEADER_LEN: usize = 8;
+
+/// Kind of an erased record header
+const KIND_ERASED: u8 = 0xFF;
+const KIND_VALUE: u8 = 0x01;
+const KIND_REMOVED: u8 = 0x02;
+
+/// Size of the buffer used to copy and check data
+const CHUNK_LEN: usize = 32;
+
+/// CRC-32 as used by Ethernet and zlib
+#[derive(Clone, Copy)]
+pub(crate) struct Crc(u32);
+
+impl Crc {
+    pub(crate) fn new() -> Self {
+        Self(!0)
+    }
+
+    pub(crate) fn update(mut self, data: &[u8]) -> Self {
+        for &byte in data {
+            self.0 ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sector {
+    /// Erased, invalidated or never completed
+    Free,
+    /// Holds records, a higher sequence number means newer
+    Used(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    kind: u8,
+    key_len: usize,
+    value_len: usize,
+    crc: u32,
+}
+
+impl Record {
+    fn parse(bytes: &[u8; RECORD_HEADER_LEN]) -> Self {
+        Self {
+            kind: bytes[0],
+            key_len: usize::from(bytes[1]),
+            value_len: usize::from(u16::from_le_bytes([bytes[2], bytes[3]])),
+            crc: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    fn header(&self) -> [u8; RECORD_HEADER_LEN] {
+        let value_len = (self.value_len as u16).to_le_bytes();
+        let crc = self.crc.to_le_bytes();
+        [
+            self.kind,
+            self.key_len as u8,
+            value_len[0],
+            value_len[1],
+            crc[0],
+            crc[1],
+            crc[2],
+            crc[3],
+        ]
+    }
+
+    /// Checksum over everything but the checksum itself
+    fn checksum(kind: u8, key: &[u8], value_len: usize) -> Crc {
+        let value_len = (value_len as u16).to_le_bytes();
+        Crc::new()
+            .update(&[kind, key.len() as u8, value_len[0], value_len[1]])
+            .update(key)
+    }
+
+    /// Length in flash, padded to whole words
+    fn len(&self) -> usize {
+        (RECORD_HEADER_LEN + self.key_len + self.value_len).next_multiple_of(4)
+    }
+}
+
+/// Position of a record in flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    offset: usize,
+    record: Record,
+}
+
+/// Log-structured key/value store
+pub struct Store<F: Flash> {
+    flash: F,
+    sectors: [Sector; MAX_SECTORS],
+    count: usize,
+    head: usize,
+    // Offset of the next record within the head sector
+    end: usize,
+    next_sequence: u32,
+}
+
+impl<F: Flash> Store<F> {
+    /// Opens the store, formatting the region if it holds no valid sector.
+    ///
+    /// Finishes or rolls back a collection interrupted by a power loss.
+    pub fn mount(flash: F) -> Result<Self, Error> {
+        let count = flash.capacity() / F::SECTOR_SIZE;
+        if !(2..=MAX_SECTORS).contains(&count) {
+            return Err(Error::Region);
+        }
+
+        let mut store = Self {
+            flash,
+            sectors: [Sector::Free; MAX_SECTORS],
+            count,
+            head: 0,
+            end: 0,
+            next_sequence: 1,
+        };
+
+        for sector in 0..count {
+            store.sectors[sector] = store.read_sector_header(sector)?;
+        }
+
+        // Without any free sector the newest one is the target of an interrupted
+        // collection, all of its records still exist in the oldest sector
+        if store.free_sectors() == 0 {
+            if let Some(newest) = store.newest() {
+                store.retire(newest)?;
+            }
+        }
+
+        match store.newest() {
+            Some(head) => {
+                store.head = head;
+                if let Sector::Used(sequence) = store.sectors[head] {
+                    store.next_sequence = sequence.wrapping_add(1);
+                }
+                store.end = store.find_end(head)?;
+            }
+            None => store.open(0)?,
+        }
+
+        Ok(store)
+    }
+
+    /// Returns the flash, e.g. to inspect it in tests
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Reads the value of a key into `buffer` and returns its length, `None` if there is no such key
+    pub fn get(&mut self, key: &str, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        let key = check_key(key)?;
+
+        let Some(location) = self.find(key)? else {
+            return Ok(None);
+        };
+
+        let value_len = location.record.value_len;
+        if buffer.len() < value_len {
+            return Err(Error::BufferTooSmall(value_len));
+        }
+
+        let value_offset = location.offset + RECORD_HEADER_LEN + key.len();
+        self.flash.read(value_offset, &mut buffer[..value_len])?;
+
+        Ok(Some(value_len))
+    }
+
+    /// Returns `true` if there is a value for the key
+    pub fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        Ok(self.find(check_key(key)?)?.is_some())
+    }
+
+    /// Stores a value, replacing the previous one
+    pub fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let key = check_key(key)?;
+        let record = Record {
+            kind: KIND_VALUE,
+            key_len: key.len(),
+            value_len: value.len(),
+            crc: Record::checksum(KIND_VALUE, key, value.len())
+                .update(value)
+                .finish(),
+        };
+
+        if value.len() > u16::MAX as usize || record.len() > F::SECTOR_SIZE - SECTOR_HEADER_LEN {
+            return Err(Error::ValueTooLarge);
+        }
+
+        self.reserve(record.len())?;
+        self.append(record, key, |flash, offset| flash.program(offset, value))
+    }
+
+    /// Removes a key, nothing is written if it does not exist
+    pub fn remove(&mut self, key: &str) -> Result<(), Error> {
+        let key = check_key(key)?;
+        if self.find(key)?.is_none() {
+            return Ok(());
+        }
+
+        let record = Record {
+            kind: KIND_REMOVED,
+            key_len: key.len(),
+            value_len: 0,
+            crc: Record::checksum(KIND_REMOVED, key, 0).finish(),
+        };
+
+        self.reserve(record.len())?;
+        self.append(record, key, |_, _| Ok(()))
+    }
+
+    /// Makes sure the head sector has room for `len` bytes
+    fn reserve(&mut self, len: usize) -> Result<(), Error> {
+        // Every round collects one sector, once all were collected there is no more room
+        for _ in 0..=self.count {
+            if self.end + len <= F::SECTOR_SIZE {
+                return Ok(());
+            }
+            self.advance()?;
+        }
+
+        Err(Error::Full)
+    }
+
+    /// Writes a record at the end of the head sector, the value is written by `write_value`
+    fn append(
+        &mut self,
+        record: Record,
+        key: &[u8],
+        write_value: impl FnOnce(&mut F, usize) -> Result<(), FlashError>,
+    ) -> Result<(), Error> {
+        let offset = self.head * F::SECTOR_SIZE + self.end;
+
+        // Skip the record in any case, a partially written one must not be overwritten
+        self.end += record.len();
+
+        self.flash.program(offset, &record.header())?;
+        self.flash.program(offset + RECORD_HEADER_LEN, key)?;
+        write_value(&mut self.flash, offset + RECORD_HEADER_LEN + key.len())?;
+
+        Ok(())
+    }
+
+    /// Continues in a free sector and collects the oldest one if no other sector is free
+    fn advance(&mut self) -> Result<(), Error> {
+        // Take the free sectors in turns to spread the wear
+        let free = (1..self.count)
+            .map(|step| (self.head + step) % self.count)
+            .find(|&sector| self.sectors[sector] == Sector::Free)
+            .ok_or(Error::Full)?;
+        self.open(free)?;
+
+        if self.free_sectors() == 0 {
+            if let Some(oldest) = self.oldest() {
+                self.collect(oldest)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the current records of a sector to the head sector and retires it
+    fn collect(&mut self, sector: usize) -> Result<(), Error> {
+        let mut offset = SECTOR_HEADER_LEN;
+        while let Some(record) = self.read_record(sector, offset)? {
+            let location = Location {
+                offset: sector * F::SECTOR_SIZE + offset,
+                record,
+            };
+            offset += record.len();
+
+            // Removals can be dropped, there are no older records left they would hide
+            if record.kind != KIND_VALUE {
+                continue;
+            }
+
+            let mut key = [0; MAX_KEY_LEN];
+            let key = &mut key[..record.key_len];
+            self.flash.read(location.offset + RECORD_HEADER_LEN, key)?;
+
+            if self.find(key)? == Some(location) {
+                if self.end + record.len() > F::SECTOR_SIZE {
+                    return Err(Error::Full);
+                }
+
+                let source = location.offset + RECORD_HEADER_LEN + key.len();
+                self.append(record, key, |flash, target| {
+                    copy(flash, source, target, record.value_len)
+                })?;
+            }
+        }
+
+        self.retire(sector)
+    }
+
+    /// Erases a free sector and makes it the head
+    fn open(&mut self, sector: usize) -> Result<(), Error> {
+        let offset = sector * F::SECTOR_SIZE;
+        self.flash.erase(offset)?;
+
+        let sequence = self.next_sequence;
+        let mut header = [0; SECTOR_HEADER_LEN];
+        header[0..4].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let crc = Crc::new().update(&header[0..8]).finish();
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+        self.flash.program(offset, &header)?;
+
+        self.sectors[sector] = Sector::Used(sequence);
+        self.next_sequence = sequence.wrapping_add(1);
+        self.head = sector;
+        self.end = SECTOR_HEADER_LEN;
+
+        Ok(())
+    }
+
+    /// Invalidates a sector before erasing it, so an interrupted erase leaves no valid header
+    fn retire(&mut self, sector: usize) -> Result<(), Error> {
+        let offset = sector * F::SECTOR_SIZE;
+        self.sectors[sector] = Sector::Free;
+        self.flash.program(offset, &[0; 4])?;
+        self.flash.erase(offset)?;
+        Ok(())
+    }
+
+    fn read_sector_header(&mut self, sector: usize) -> Result<Sector, Error> {
+        let mut header = [0; SECTOR_HEADER_LEN];
+        self.flash.read(sector * F::SECTOR_SIZE, &mut header)?;
+
+        let word = |index: usize| {
+            u32::from_le_bytes([
+                header[index],
+                header[index + 1],
+                header[index + 2],
+                header[index + 3],
+            ])
+        };
+
+        let valid = word(0) == SECTOR_MAGIC && word(8) == Crc::new().update(&header[0..8]).finish();
+        Ok(if valid {
+            Sector::Used(word(4))
+        } else {
+            Sector::Free
+        })
+    }
+
+    /// Returns the record at an offset within a sector if it is complete and intact
+    fn read_record(&mut self, sector: usize, offset: usize) -> Result<Option<Record>, Error> {
+        if offset + RECORD_HEADER_LEN > F::SECTOR_SIZE {
+            return Ok(None);
+        }
+        let offset = sector * F::SECTOR_SIZE + offset;
+        let sector_end = (sector + 1) * F::SECTOR_SIZE;
+
+        let mut header = [0; RECORD_HEADER_LEN];
+        self.flash.read(offset, &mut header)?;
+        let record = Record::parse(&header);
+
+        let known = matches!(record.kind, KIND_VALUE | KIND_REMOVED);
+        if !known
+            || record.key_len == 0
+            || record.key_len > MAX_KEY_LEN
+            || offset + record.len() > sector_end
+        {
+            return Ok(None);
+        }
+
+        let mut crc = Crc::new().update(&header[0..4]);
+        let mut chunk = [0; CHUNK_LEN];
+        let mut position = offset + RECORD_HEADER_LEN;
+        let mut left = record.key_len + record.value_len;
+        while left > 0 {
+            let len = left.min(CHUNK_LEN);
+            self.flash.read(position, &mut chunk[..len])?;
+            crc = crc.update(&chunk[..len]);
+            position += len;
+            left -= len;
+        }
+
+        Ok((crc.finish() == record.crc).then_some(record))
+    }
+
+    /// Returns where the next record of a sector goes, the end of the sector if the rest is not erased
+    fn find_end(&mut self, sector: usize) -> Result<usize, Error> {
+        let mut offset = SECTOR_HEADER_LEN;
+        while let Some(record) = self.read_record(sector, offset)? {
+            offset += record.len();
+        }
+
+        // An interrupted write leaves programmed bytes behind, which cannot be written again
+        let mut chunk = [0; CHUNK_LEN];
+        let mut position = offset;
+        while position < F::SECTOR_SIZE {
+            let len = (F::SECTOR_SIZE - position).min(CHUNK_LEN);
+            self.flash
+                .read(sector * F::SECTOR_SIZE + position, &mut chunk[..len])?;
+            if chunk[..len].iter().any(|&byte| byte != KIND_ERASED) {
+                return Ok(F::SECTOR_SIZE);
+            }
+            position += len;
+        }
+
+        Ok(offset)
+    }
+
+    /// Returns the newest record of a key, `None` if there is none or it was removed
+    fn find(&mut self, key: &[u8]) -> Result<Option<Location>, Error> {
+        let mut latest: Option<(u32, Location)> = None;
+
+        for sector in 0..self.count {
+            let Sector::Used(sequence) = self.sectors[sector] else {
+                continue;
+            };
+            if latest.is_some_and(|(newest, _)| newest > sequence) {
+                continue;
+            }
+
+            let mut offset = SECTOR_HEADER_LEN;
+            while let Some(record) = self.read_record(sector, offset)? {
+                let location = Location {
+                    offset: sector * F::SECTOR_SIZE + offset,
+                    record,
+                };
+                offset += record.len();
+
+                if record.key_len == key.len() && self.key_matches(location, key)? {
+                    latest = Some((sequence, location));
+                }
+            }
+        }
+
+        Ok(latest
+            .map(|(_, location)| location)
+            .filter(|location| location.record.kind == KIND_VALUE))
+    }
+
+    fn key_matches(&mut self, location: Location, key: &[u8]) -> Result<bool, Error> {
+        let mut stored = [0; MAX_KEY_LEN];
+        let stored = &mut stored[..key.len()];
+        self.flash
+            .read(location.offset + RECORD_HEADER_LEN, stored)?;
+        Ok(stored == key)
+    }
+
+    fn free_sectors(&self) -> usize {
+        self.sectors[..self.count]
+            .iter()
+            .filter(|&&sector| sector == Sector::Free)
+            .count()
+    }
+
+    fn newest(&self) -> Option<usize> {
+        self.used()
+            .max_by_key(|&(_, sequence)| sequence)
+            .map(|(sector, _)| sector)
+    }
+
+    fn oldest(&self) -> Option<usize> {
+        self.used()
+            .min_by_key(|&(_, sequence)| sequence)
+            .map(|(sector, _)| sector)
+    }
+
+    fn used(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        self.sectors[..self.count]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, sector)| match sector {
+                Sector::Used(sequence) => Some((index, *sequence)),
+                Sector::Free => None,
+            })
+    }
+}
+
+fn check_key(key: &str) -> Result<&[u8], Error> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(Error::Key);
+    }
+    Ok(key.as_bytes())
+}
+
+/// Copies bytes within the flash
+fn copy<F: Flash>(
+    flash: &mut F,
+    source: usize,
+    target: usize,
+    len: usize,
+) -> Result<(), FlashError> {
+    let mut chunk = [0; CHUNK_LEN];
+    let mut done = 0;
+    while done < len {
+        let part = (len - done).min(CHUNK_LEN);
+        flash.read(source + done, &mut chunk[..part])?;
+        flash.program(target + done, &chunk[..part])?;
+        done += part;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, string::String, vec, vec::Vec};
+
+    use super::*;
+    use crate::flash_emulator::{FlashEmulator, SECTOR_SIZE};
+
+    type Emulator = FlashEmulator<4>;
+
+    fn mount(flash: Emulator) -> Store<Emulator> {
+        Store::mount(flash).expect("mounts")
+    }
+
+    fn get(store: &mut Store<Emulator>, key: &str) -> Option<Vec<u8>> {
+        let mut buffer = vec![0; SECTOR_SIZE];
+        let len = store.get(key, &mut buffer).expect("reads")?;
+        buffer.truncate(len);
+        Some(buffer)
+    }
+
+    /// Value of a key in a generation, never containing erased bytes
+    fn value(key: usize, generation: usize, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| ((key * 31 + generation * 7 + i) % 250) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn sets_gets_and_removes() {
+        let mut store = mount(Emulator::new());
+        assert_eq!(get(&mut store, "score"), None);
+        assert!(!store.contains("score").unwrap());
+
+        store.set("score", &[1, 2, 3]).unwrap();
+        store.set("name", b"ada").unwrap();
+        assert_eq!(get(&mut store, "score"), Some(vec![1, 2, 3]));
+        assert!(store.contains("name").unwrap());
+
+        // The last value wins
+        store.set("score", &[4]).unwrap();
+        assert_eq!(get(&mut store, "score"), Some(vec![4]));
+
+        store.remove("score").unwrap();
+        assert_eq!(get(&mut store, "score"), None);
+        assert!(!store.contains("score").unwrap());
+        assert_eq!(get(&mut store, "name"), Some(b"ada".to_vec()));
+
+        // Removing twice writes nothing
+        store.remove("score").unwrap();
+
+        // Empty values are values
+        store.set("empty", &[]).unwrap();
+        assert_eq!(get(&mut store, "empty"), Some(vec![]));
+    }
+
+    #[test]
+    fn keeps_values_after_remount() {
+        let mut store = mount(Emulator::new());
+        store.set("level", &[7]).unwrap();
+        store.set("gone", &[1]).unwrap();
+        store.remove("gone").unwrap();
+
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "level"), Some(vec![7]));
+        assert_eq!(get(&mut store, "gone"), None);
+
+        // Appends after the records found on mount
+        store.set("level", &[8]).unwrap();
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "level"), Some(vec![8]));
+    }
+
+    #[test]
+    fn rejects_bad_keys_and_buffers() {
+        let mut store = mount(Emulator::new());
+        let long = "k".repeat(MAX_KEY_LEN + 1);
+        assert_eq!(store.set("", &[1]), Err(Error::Key));
+        assert_eq!(store.set(&long, &[1]), Err(Error::Key));
+        assert_eq!(store.get(&long, &mut [0; 4]), Err(Error::Key));
+        store.set(&long[..MAX_KEY_LEN], &[1]).unwrap();
+
+        store.set("pair", &[1, 2]).unwrap();
+        assert_eq!(
+            store.get("pair", &mut [0; 1]),
+            Err(Error::BufferTooSmall(2))
+        );
+    }
+
+    #[test]
+    fn rejects_values_larger_than_a_sector() {
+        let mut store = mount(Emulator::new());
+        let fitting = SECTOR_SIZE - SECTOR_HEADER_LEN - RECORD_HEADER_LEN - 1;
+        store.set("k", &vec![1; fitting]).unwrap();
+        assert_eq!(
+            store.set("k", &vec![1; fitting + 1]),
+            Err(Error::ValueTooLarge)
+        );
+        assert_eq!(get(&mut store, "k").map(|value| value.len()), Some(fitting));
+    }
+
+    #[test]
+    fn reports_full_region() {
+        let mut store = mount(Emulator::new());
+        let len = 1000;
+
+        // Three sectors for values, one is kept free
+        let mut stored = 0;
+        let error = loop {
+            match store.set(&format!("key{stored}"), &value(stored, 0, len)) {
+                Ok(()) => stored += 1,
+                Err(error) => break error,
+            }
+            assert!(stored < 100, "never full");
+        };
+        assert_eq!(error, Error::Full);
+        assert!(stored >= 8, "only {stored} values fit");
+
+        // Everything stored so far survives
+        let mut store = mount(store.into_inner());
+        for key in 0..stored {
+            assert_eq!(
+                get(&mut store, &format!("key{key}")),
+                Some(value(key, 0, len))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_regions() {
+        assert!(matches!(
+            Store::mount(FlashEmulator::<1>::new()),
+            Err(Error::Region)
+        ));
+        assert!(Store::mount(FlashEmulator::<2>::new()).is_ok());
+    }
+
+    #[test]
+    fn formats_garbage() {
+        let mut flash = Emulator::new();
+        flash.program(0, &[0x12; 64]).unwrap();
+        flash.program(SECTOR_SIZE + 100, &[0; 8]).unwrap();
+
+        let mut store = mount(flash);
+        assert_eq!(get(&mut store, "anything"), None);
+        store.set("new", &[1]).unwrap();
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "new"), Some(vec![1]));
+    }
+
+    #[test]
+    fn rotates_through_all_sectors() {
+        let mut store = mount(Emulator::new());
+        let len = 500;
+
+        for generation in 0..100 {
+            for key in 0..3 {
+                store
+                    .set(&format!("key{key}"), &value(key, generation, len))
+                    .unwrap();
+            }
+        }
+
+        let flash = store.into_inner();
+        let counts: Vec<u32> = (0..4).map(|sector| flash.erase_count(sector)).collect();
+        let (min, max) = (*counts.iter().min().unwrap(), *counts.iter().max().unwrap());
+        assert!(min > 10, "erases {counts:?}");
+        // Sectors are taken in turns, so they wear evenly
+        assert!(max - min <= 1, "erases {counts:?}");
+
+        let mut store = mount(flash);
+        for key in 0..3 {
+            assert_eq!(
+                get(&mut store, &format!("key{key}")),
+                Some(value(key, 99, len))
+            );
+        }
+    }
+
+    #[test]
+    fn collection_keeps_current_values_only() {
+        let mut store = mount(Emulator::new());
+        store.set("stays", &[1]).unwrap();
+        store.set("removed", &[2]).unwrap();
+        store.remove("removed").unwrap();
+
+        // Push the first sector through a collection
+        for generation in 0..40 {
+            store.set("churn", &value(0, generation, 400)).unwrap();
+        }
+
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "stays"), Some(vec![1]));
+        assert_eq!(get(&mut store, "removed"), None);
+        assert_eq!(get(&mut store, "churn"), Some(value(0, 39, 400)));
+    }
+
+    /// Expected content of the store, `None` for keys without a value
+    type Expected = Vec<(String, Option<Vec<u8>>)>;
+
+    fn check(store: &mut Store<Emulator>, expected: &Expected, context: &str) {
+        for (key, value) in expected {
+            assert_eq!(&get(store, key), value, "{key} {context}");
+        }
+    }
+
+    /// Cuts the power at every byte programmed or erased by `operation` on
+    /// `flash` and checks that mounting afterwards restores either the state
+    /// before or, if the operation completed, the one after it.
+    fn cut_power_everywhere<O>(flash: &Emulator, before: &Expected, after: &Expected, operation: O)
+    where
+        O: Fn(&mut Store<Emulator>) -> Result<(), Error>,
+    {
+        for budget in 0.. {
+            let mut flash = flash.clone();
+            flash.fail_after(budget);
+
+            let mut store = mount(flash);
+            let result = operation(&mut store);
+            let mut flash = store.into_inner();
+            let completed = flash.is_powered();
+            assert_eq!(result.is_ok(), completed, "after {budget} bytes");
+            flash.power_cycle();
+
+            let mut store = mount(flash);
+            let expected = if completed { after } else { before };
+            check(&mut store, expected, &format!("after {budget} bytes"));
+
+            // The store keeps working
+            store.set("later", &[9]).unwrap();
+            let mut store = mount(store.into_inner());
+            check(
+                &mut store,
+                expected,
+                &format!("after {budget} bytes and a write"),
+            );
+            assert_eq!(get(&mut store, "later"), Some(vec![9]));
+
+            if completed {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn survives_power_loss_during_append() {
+        let mut store = mount(Emulator::new());
+        store.set("score", &value(1, 0, 40)).unwrap();
+        store.set("name", &value(2, 0, 10)).unwrap();
+        let flash = store.into_inner();
+
+        let before: Expected = vec![
+            ("score".into(), Some(value(1, 0, 40))),
+            ("name".into(), Some(value(2, 0, 10))),
+        ];
+        let mut after = before.clone();
+        after[0].1 = Some(value(1, 1, 40));
+        cut_power_everywhere(&flash, &before, &after, |store| {
+            store.set("score", &value(1, 1, 40))
+        });
+
+        let mut after = before.clone();
+        after[1].1 = None;
+        cut_power_everywhere(&flash, &before, &after, |store| store.remove("name"));
+    }
+
+    /// A store whose next write of a 1000 byte value collects the oldest sector
+    fn nearly_full() -> (Emulator, Expected) {
+        let mut store = mount(Emulator::new());
+        let mut expected = Expected::new();
+
+        store.set("first", &value(0, 0, 20)).unwrap();
+        expected.push(("first".into(), Some(value(0, 0, 20))));
+
+        // Outdated values fill three sectors, four per sector
+        for generation in 0..12 {
+            store.set("big", &value(1, generation, 1000)).unwrap();
+        }
+        expected.push(("big".into(), Some(value(1, 11, 1000))));
+
+        let flash = store.into_inner();
+        assert_eq!(used_sectors(&flash), 3);
+        (flash, expected)
+    }
+
+    fn used_sectors(flash: &Emulator) -> usize {
+        (0..4)
+            .filter(|&sector| flash.sector(sector)[0..4] == SECTOR_MAGIC.to_le_bytes())
+            .count()
+    }
+
+    #[test]
+    fn survives_power_loss_during_collection() {
+        let (flash, before) = nearly_full();
+
+        // Opens the free sector, collects the oldest one and retires it
+        let mut after = before.clone();
+        after[1].1 = Some(value(1, 12, 1000));
+        cut_power_everywhere(&flash, &before, &after, |store| {
+            store.set("big", &value(1, 12, 1000))
+        });
+    }
+
+    #[test]
+    fn survives_power_loss_during_recovery() {
+        let (flash, before) = nearly_full();
+
+        // A collection interrupted before the oldest sector was retired leaves no free sector
+        let interrupted = (0..)
+            .map(|budget| {
+                let mut flash = flash.clone();
+                flash.fail_after(budget);
+                let mut store = mount(flash);
+                assert!(store.set("big", &value(1, 12, 1000)).is_err());
+                let mut flash = store.into_inner();
+                flash.power_cycle();
+                flash
+            })
+            .find(|flash| used_sectors(flash) == 4)
+            .unwrap();
+
+        // Mounting retires the new sector, cut the power there as well
+        for budget in 0.. {
+            let mut flash = interrupted.clone();
+            flash.fail_after(budget);
+            let completed = Store::mount(&mut flash).is_ok();
+            flash.power_cycle();
+
+            let mut store = mount(flash);
+            check(
+                &mut store,
+                &before,
+                &format!("after {budget} bytes of recovery"),
+            );
+
+            if completed {
+                break;
+            }
+        }
+    }
+}
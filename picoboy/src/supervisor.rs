@@ -0,0 +1,446 @@
+//! Check-ins of supervised tasks and the reason of the last reset.
+//!
+//! Every supervised [`Task`] checks in regularly, from the main loop, core 1
+//! or an interrupt. A [`Monitor`] advanced by the main loop notices a task
+//! that stayed silent for longer than its timeout, the watchdog then resets
+//! the chip, see [`watchdog`](crate::watchdog). The [`ResetReason`] is kept in
+//! two watchdog scratch registers across the reset and reported after it.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::crash::Message;
+use crate::text::{fonts, FontStyle};
+
+/// Marks a reset record in the first of its two scratch registers
+pub const RESET_MAGIC: u32 = 0x5042_5253;
+
+/// Height of the strip a [`ResetNotice`] is drawn on
+const NOTICE_HEIGHT: u32 = 20;
+
+/// Part of the firmware which has to check in regularly
+#[derive(Debug)]
+pub struct Task {
+    name: &'static str,
+    timeout_ms: u32,
+    // Only the task itself writes it, which needs no compare and swap
+    check_ins: AtomicU32,
+}
+
+impl Task {
+    /// Creates a task which has to check in at least every `timeout_ms`
+    pub const fn new(name: &'static str, timeout_ms: u32) -> Self {
+        Self {
+            name,
+            timeout_ms,
+            check_ins: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns the name shown when the task hangs
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the longest time without a check-in in milliseconds
+    pub fn timeout_ms(&self) -> u32 {
+        self.timeout_ms
+    }
+
+    /// Shows that the task is still alive, call this only from the task itself
+    pub fn check_in(&self) {
+        let check_ins = self.check_ins.load(Ordering::Relaxed);
+        self.check_ins
+            .store(check_ins.wrapping_add(1), Ordering::Release);
+    }
+
+    fn check_ins(&self) -> u32 {
+        self.check_ins.load(Ordering::Acquire)
+    }
+}
+
+/// Task which stayed silent for longer than its timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Missed {
+    /// Position of the task in the [`Monitor`]
+    pub index: u8,
+    /// Name of the task
+    pub name: &'static str,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Watched<'a> {
+    task: &'a Task,
+    seen: u32,
+    silent_ms: u32,
+    enabled: bool,
+}
+
+/// Time since the last check-in of each of `N` tasks
+#[derive(Debug)]
+pub struct Monitor<'a, const N: usize> {
+    tasks: [Watched<'a>; N],
+    missed: Option<Missed>,
+}
+
+impl<'a, const N: usize> Monitor<'a, N> {
+    /// Watches `tasks`, all of them enabled
+    pub fn new(tasks: [&'a Task; N]) -> Self {
+        Self {
+            tasks: tasks.map(|task| Watched {
+                task,
+                seen: task.check_ins(),
+                silent_ms: 0,
+                enabled: true,
+            }),
+            missed: None,
+        }
+    }
+
+    /// Returns the name of the task at `index`, e.g. the one of a [`ResetReason::Watchdog`]
+    pub fn name(&self, index: u8) -> Option<&'static str> {
+        self.tasks
+            .get(usize::from(index))
+            .map(|watched| watched.task.name())
+    }
+
+    /// Starts or stops watching `task`, e.g. the display while nothing is drawn
+    pub fn set_enabled(&mut self, task: &Task, enabled: bool) {
+        for watched in &mut self.tasks {
+            if core::ptr::eq(watched.task, task) && watched.enabled != enabled {
+                watched.enabled = enabled;
+                watched.seen = task.check_ins();
+                watched.silent_ms = 0;
+            }
+        }
+    }
+
+    /// Forgets the time without check-ins, e.g. after waking from dormant mode
+    pub fn restart(&mut self) {
+        for watched in &mut self.tasks {
+            watched.seen = watched.task.check_ins();
+            watched.silent_ms = 0;
+        }
+    }
+
+    /// Advances the time by `dt_ms`, returns the first task which missed its check-in.
+    ///
+    /// A missed task stays reported, the watchdog is meant to reset the chip.
+    pub fn update(&mut self, dt_ms: u32) -> Result<(), Missed> {
+        if let Some(missed) = self.missed {
+            return Err(missed);
+        }
+
+        for (index, watched) in self.tasks.iter_mut().enumerate() {
+            let check_ins = watched.task.check_ins();
+            if check_ins != watched.seen {
+                watched.seen = check_ins;
+                watched.silent_ms = 0;
+            } else if watched.enabled {
+                watched.silent_ms = watched.silent_ms.saturating_add(dt_ms);
+            }
+
+            if watched.enabled && watched.silent_ms > watched.task.timeout_ms() {
+                let missed = Missed {
+                    index: index as u8,
+                    name: watched.task.name(),
+                };
+                self.missed = Some(missed);
+                return Err(missed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Software which reset the chip on purpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SoftwareReset {
+    /// No record was left, e.g. by a debugger
+    Unknown,
+    /// The crash screen restarted the firmware
+    Crash,
+    /// The launcher started an app
+    Launch,
+    /// The USB boot loader of the ROM started the new firmware
+    Bootsel,
+}
+
+/// Why the chip was reset the last time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    /// Switched on, or reset with the RUN pin
+    PowerOn,
+    /// The watchdog expired, with the index of the task which missed its
+    /// check-in, `None` if the main loop hung
+    Watchdog(Option<u8>),
+    /// Reset by the firmware
+    Software(SoftwareReset),
+}
+
+impl ResetReason {
+    /// Returns the contents of the two scratch registers keeping the reason across a reset
+    pub const fn to_record(self) -> [u32; 2] {
+        let (kind, detail) = match self {
+            ResetReason::PowerOn => (0, 0),
+            ResetReason::Watchdog(None) => (1, 0),
+            ResetReason::Watchdog(Some(index)) => (2, index),
+            ResetReason::Software(SoftwareReset::Unknown) => (3, 0),
+            ResetReason::Software(SoftwareReset::Crash) => (4, 0),
+            ResetReason::Software(SoftwareReset::Launch) => (5, 0),
+            ResetReason::Software(SoftwareReset::Bootsel) => (6, 0),
+        };
+        [RESET_MAGIC, (kind << 8) | detail as u32]
+    }
+
+    /// Reads a record written with [`to_record`](Self::to_record), `None` if there is none
+    pub fn from_record(record: [u32; 2]) -> Option<Self> {
+        let [magic, value] = record;
+        if magic != RESET_MAGIC {
+            return None;
+        }
+
+        let detail = value as u8;
+        Some(match value >> 8 {
+            0 => ResetReason::PowerOn,
+            1 => ResetReason::Watchdog(None),
+            2 => ResetReason::Watchdog(Some(detail)),
+            3 => ResetReason::Software(SoftwareReset::Unknown),
+            4 => ResetReason::Software(SoftwareReset::Crash),
+            5 => ResetReason::Software(SoftwareReset::Launch),
+            6 => ResetReason::Software(SoftwareReset::Bootsel),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ResetReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResetReason::PowerOn => f.write_str("power-on"),
+            ResetReason::Watchdog(None) => f.write_str("watchdog"),
+            ResetReason::Watchdog(Some(index)) => write!(f, "watchdog, task {index} hung"),
+            ResetReason::Software(SoftwareReset::Unknown) => f.write_str("software"),
+            ResetReason::Software(SoftwareReset::Crash) => f.write_str("software, after a crash"),
+            ResetReason::Software(SoftwareReset::Launch) => {
+                f.write_str("software, by the launcher")
+            }
+            ResetReason::Software(SoftwareReset::Bootsel) => {
+                f.write_str("software, by the USB boot loader")
+            }
+        }
+    }
+}
+
+/// One line along the bottom of the screen telling why the firmware restarted
+#[derive(Debug, Clone, Copy)]
+pub struct ResetNotice<'a> {
+    reason: ResetReason,
+    task: Option<&'a str>,
+}
+
+impl<'a> ResetNotice<'a> {
+    /// Creates the notice, `task` names the task of a [`ResetReason::Watchdog`]
+    pub fn new(reason: ResetReason, task: Option<&'a str>) -> Self {
+        Self { reason, task }
+    }
+}
+
+impl Drawable for ResetNotice<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let mut text = Message::new();
+        let _ = match (self.reason, self.task) {
+            (ResetReason::Watchdog(Some(_)), Some(task)) => {
+                write!(text, "Reset by the watchdog, {task} hung")
+            }
+            (reason, _) => write!(text, "Reset: {reason}"),
+        };
+
+        let area = target.bounding_box();
+        let strip = Rectangle::new(
+            Point::new(0, area.size.height as i32 - NOTICE_HEIGHT as i32),
+            Size::new(area.size.width, NOTICE_HEIGHT),
+        );
+        strip
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_DARK_RED))
+            .draw(target)?;
+        Text::with_text_style(
+            text.as_str(),
+            strip.center(),
+            FontStyle::new(&fonts::SMALL, Rgb565::WHITE),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .build(),
+        )
+        .draw(target)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REASONS: [ResetReason; 7] = [
+        ResetReason::PowerOn,
+        ResetReason::Watchdog(None),
+        ResetReason::Watchdog(Some(3)),
+        ResetReason::Software(SoftwareReset::Unknown),
+        ResetReason::Software(SoftwareReset::Crash),
+        ResetReason::Software(SoftwareReset::Launch),
+        ResetReason::Software(SoftwareReset::Bootsel),
+    ];
+
+    #[test]
+    fn misses_a_silent_task() {
+        let display = Task::new("display", 100);
+        let audio = Task::new("audio", 300);
+        let mut monitor = Monitor::new([&display, &audio]);
+
+        assert_eq!(monitor.update(100), Ok(()));
+        assert_eq!(
+            monitor.update(1),
+            Err(Missed {
+                index: 0,
+                name: "display"
+            })
+        );
+        assert_eq!(monitor.name(0), Some("display"));
+        assert_eq!(monitor.name(1), Some("audio"));
+        assert_eq!(monitor.name(2), None);
+    }
+
+    #[test]
+    fn restarts_the_silence_on_check_in() {
+        let display = Task::new("display", 100);
+        let audio = Task::new("audio", 300);
+        let mut monitor = Monitor::new([&display, &audio]);
+
+        for _ in 0..10 {
+            display.check_in();
+            audio.check_in();
+            assert_eq!(monitor.update(90), Ok(()));
+        }
+
+        // The update seeing a check-in does not count its time as silent
+        for _ in 0..3 {
+            assert_eq!(monitor.update(100), Ok(()));
+            display.check_in();
+        }
+        assert_eq!(
+            monitor.update(1),
+            Err(Missed {
+                index: 1,
+                name: "audio"
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_reporting_a_missed_task() {
+        let display = Task::new("display", 100);
+        let mut monitor = Monitor::new([&display]);
+        let missed = monitor.update(200).unwrap_err();
+
+        display.check_in();
+        monitor.restart();
+        assert_eq!(monitor.update(0), Err(missed));
+    }
+
+    #[test]
+    fn ignores_disabled_tasks() {
+        let display = Task::new("display", 100);
+        let audio = Task::new("audio", 300);
+        let mut monitor = Monitor::new([&display, &audio]);
+
+        monitor.set_enabled(&display, false);
+        assert_eq!(monitor.update(250), Ok(()));
+
+        // Enabling again starts with a full timeout
+        monitor.set_enabled(&display, true);
+        assert_eq!(monitor.update(50), Ok(()));
+        assert_eq!(
+            monitor.update(50),
+            Err(Missed {
+                index: 1,
+                name: "audio"
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_the_silence_when_enabled_again() {
+        let display = Task::new("display", 100);
+        let mut monitor = Monitor::new([&display]);
+
+        assert_eq!(monitor.update(80), Ok(()));
+        // Enabling an enabled task changes nothing
+        monitor.set_enabled(&display, true);
+        assert!(monitor.update(30).is_err());
+    }
+
+    #[test]
+    fn forgets_the_silence_on_restart() {
+        let display = Task::new("display", 100);
+        let mut monitor = Monitor::new([&display]);
+
+        assert_eq!(monitor.update(80), Ok(()));
+        monitor.restart();
+        assert_eq!(monitor.update(100), Ok(()));
+        assert!(monitor.update(1).is_err());
+    }
+
+    #[test]
+    fn keeps_reset_reasons_in_records() {
+        for reason in REASONS {
+            assert_eq!(
+                ResetReason::from_record(reason.to_record()),
+                Some(reason),
+                "{reason}"
+            );
+        }
+        for index in [0, u8::MAX] {
+            let reason = ResetReason::Watchdog(Some(index));
+            assert_eq!(ResetReason::from_record(reason.to_record()), Some(reason));
+        }
+
+        assert_eq!(
+            ResetReason::Watchdog(Some(3)).to_record(),
+            [RESET_MAGIC, 0x203]
+        );
+        assert_eq!(ResetReason::PowerOn.to_record(), [RESET_MAGIC, 0]);
+    }
+
+    #[test]
+    fn rejects_foreign_records() {
+        // Scratch registers after power-on or written by other firmware
+        assert_eq!(ResetReason::from_record([0, 0]), None);
+        for reason in REASONS {
+            let [magic, value] = reason.to_record();
+            assert_eq!(ResetReason::from_record([!magic, value]), None);
+            assert_eq!(ResetReason::from_record([magic ^ 1, value]), None);
+        }
+
+        for kind in [7, 0xFF, 0x1_0000] {
+            assert_eq!(ResetReason::from_record([RESET_MAGIC, kind << 8]), None);
+        }
+    }
+}
@@ -0,0 +1,733 @@
+//! Bitmap fonts and text layout.
+//!
+//! [`Font`]s are generated at build time from BDF files, the ones in
+//! `fonts/` are available in [`fonts`]. Glyphs are looked up by Unicode code
+//! point, so umlauts and the rest of Latin-1 work with plain `&str`. A
+//! [`FontStyle`] draws single lines through `embedded_graphics::text::Text`,
+//! which takes care of alignment and baselines.
+//!
+//! [`TextBox`] wraps text at spaces to fit a rectangle and supports inline
+//! color changes with [`COLOR_MARK`]. [`wrap`] does the line breaking without
+//! drawing anything.
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::Rectangle,
+    text::{
+        renderer::{CharacterStyle, TextMetrics, TextRenderer},
+        Alignment, Baseline,
+    },
+};
+
+/// Fonts generated from the BDF files in `fonts/`.
+///
+/// They are subsets of the public domain misc-fixed fonts with ASCII, Latin-1,
+/// typographic quotes, the ellipsis and the euro sign. [`SCORE`](fonts::SCORE)
+/// only has digits and a few signs.
+pub mod fonts {
+    include!(concat!(env!("OUT_DIR"), "/fonts.rs"));
+}
+
+/// Starts an inline color change in a [`TextBox`], `^^` draws the mark itself
+pub const COLOR_MARK: char = '^';
+
+/// Bitmap of one character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    character: char,
+    advance: u8,
+    width: u8,
+    height: u8,
+    x_offset: i8,
+    y_offset: i8,
+    offset: u32,
+}
+
+impl Glyph {
+    /// Creates a glyph whose bitmap starts at `offset` in the bitmap of its font.
+    ///
+    /// The offsets place the bottom left pixel relative to the origin on the
+    /// baseline, a positive `y_offset` moves it up.
+    pub const fn new(
+        character: char,
+        advance: u8,
+        width: u8,
+        height: u8,
+        x_offset: i8,
+        y_offset: i8,
+        offset: u32,
+    ) -> Self {
+        Self {
+            character,
+            advance,
+            width,
+            height,
+            x_offset,
+            y_offset,
+            offset,
+        }
+    }
+
+    /// Returns the character drawn by the glyph
+    pub fn character(&self) -> char {
+        self.character
+    }
+
+    /// Returns the distance to the next character in pixels
+    pub fn advance(&self) -> u32 {
+        u32::from(self.advance)
+    }
+}
+
+/// Bitmap font, usually generated by the asset pipeline
+#[derive(Debug, Clone, Copy)]
+pub struct Font<'a> {
+    ascent: u8,
+    descent: u8,
+    replacement: Option<char>,
+    glyphs: &'a [Glyph],
+    bitmap: &'a [u8],
+}
+
+impl<'a> Font<'a> {
+    /// Creates a font from glyphs sorted by character.
+    ///
+    /// The bitmap holds the rows of all glyphs, most significant bit first,
+    /// each row starting at a new byte. The `replacement` glyph is drawn for
+    /// characters the font lacks.
+    pub const fn new(
+        ascent: u8,
+        descent: u8,
+        replacement: Option<char>,
+        glyphs: &'a [Glyph],
+        bitmap: &'a [u8],
+    ) -> Self {
+        Self {
+            ascent,
+            descent,
+            replacement,
+            glyphs,
+            bitmap,
+        }
+    }
+
+    /// Returns the height above the baseline in pixels
+    pub fn ascent(&self) -> u32 {
+        u32::from(self.ascent)
+    }
+
+    /// Returns the depth below the baseline in pixels
+    pub fn descent(&self) -> u32 {
+        u32::from(self.descent)
+    }
+
+    /// Returns the distance between two lines in pixels
+    pub fn line_height(&self) -> u32 {
+        self.ascent() + self.descent()
+    }
+
+    /// Returns the glyph of a character, or the replacement glyph if there is none
+    pub fn glyph(&self, c: char) -> Option<&'a Glyph> {
+        self.find(c).or_else(|| {
+            self.replacement
+                .and_then(|replacement| self.find(replacement))
+        })
+    }
+
+    /// Returns the width of a single line without markup in pixels
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(Glyph::advance)
+            .sum()
+    }
+
+    fn find(&self, c: char) -> Option<&'a Glyph> {
+        let glyphs = self.glyphs;
+        glyphs
+            .binary_search_by_key(&c, |glyph| glyph.character)
+            .ok()
+            .map(|index| &glyphs[index])
+    }
+
+    /// Returns the set pixels of a glyph whose origin is at `origin` on the baseline
+    fn pixels(&self, glyph: &Glyph, origin: Point) -> impl Iterator<Item = Point> + 'a {
+        let bitmap = self.bitmap;
+        let (width, height) = (usize::from(glyph.width), usize::from(glyph.height));
+        let stride = width.div_ceil(8);
+        let start = glyph.offset as usize;
+        let top_left = origin
+            + Point::new(
+                i32::from(glyph.x_offset),
+                1 - i32::from(glyph.y_offset) - height as i32,
+            );
+
+        (0..height).flat_map(move |y| {
+            (0..width).filter_map(move |x| {
+                let byte = bitmap.get(start + y * stride + x / 8)?;
+                (byte & (0x80 >> (x % 8)) != 0).then(|| top_left + Point::new(x as i32, y as i32))
+            })
+        })
+    }
+}
+
+/// Font and colors for drawing text
+#[derive(Debug, Clone, Copy)]
+pub struct FontStyle<'a, C> {
+    pub font: &'a Font<'a>,
+    /// Color of the glyphs, none are drawn if `None`
+    pub text_color: Option<C>,
+    /// Color filled behind the glyphs, transparent if `None`
+    pub background_color: Option<C>,
+}
+
+impl<'a, C: PixelColor> FontStyle<'a, C> {
+    /// Creates a style with a transparent background
+    pub fn new(font: &'a Font<'a>, text_color: C) -> Self {
+        Self {
+            font,
+            text_color: Some(text_color),
+            background_color: None,
+        }
+    }
+
+    /// Returns the row of the baseline for text positioned at `y`
+    fn baseline_y(&self, y: i32, baseline: Baseline) -> i32 {
+        let ascent = self.font.ascent() as i32;
+        let line_height = self.font.line_height() as i32;
+        match baseline {
+            Baseline::Top => y + ascent - 1,
+            Baseline::Bottom => y - self.font.descent() as i32,
+            Baseline::Middle => y + ascent - 1 - (line_height - 1) / 2,
+            Baseline::Alphabetic => y,
+        }
+    }
+
+    /// Returns the area covered by a line of the given width
+    fn line_area(&self, x: i32, baseline_y: i32, width: u32) -> Rectangle {
+        let top = baseline_y + 1 - self.font.ascent() as i32;
+        Rectangle::new(
+            Point::new(x, top),
+            Size::new(width, self.font.line_height()),
+        )
+    }
+}
+
+impl<C: PixelColor> CharacterStyle for FontStyle<'_, C> {
+    type Color = C;
+
+    fn set_text_color(&mut self, text_color: Option<C>) {
+        self.text_color = text_color;
+    }
+
+    fn set_background_color(&mut self, background_color: Option<C>) {
+        self.background_color = background_color;
+    }
+}
+
+impl<C: PixelColor> TextRenderer for FontStyle<'_, C> {
+    type Color = C;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let baseline_y = self.baseline_y(position.y, baseline);
+        let mut x = position.x;
+
+        for glyph in text.chars().filter_map(|c| self.font.glyph(c)) {
+            if let Some(color) = self.background_color {
+                target.fill_solid(&self.line_area(x, baseline_y, glyph.advance()), color)?;
+            }
+            if let Some(color) = self.text_color {
+                let pixels = self.font.pixels(glyph, Point::new(x, baseline_y));
+                target.draw_iter(pixels.map(|point| Pixel(point, color)))?;
+            }
+            x += glyph.advance() as i32;
+        }
+
+        Ok(Point::new(x, position.y))
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        if let Some(color) = self.background_color {
+            let baseline_y = self.baseline_y(position.y, baseline);
+            target.fill_solid(&self.line_area(position.x, baseline_y, width), color)?;
+        }
+
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+        let width = self.font.text_width(text);
+        let baseline_y = self.baseline_y(position.y, baseline);
+
+        TextMetrics {
+            bounding_box: self.line_area(position.x, baseline_y, width),
+            next_position: position + Point::new(width as i32, 0),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.line_height()
+    }
+}
+
+/// Line of wrapped text, markup included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    /// Width in pixels, without markup and trailing spaces
+    pub width: u32,
+}
+
+/// Splits text into lines at most `max_width` pixels wide.
+///
+/// Lines break at newlines and at spaces, which are dropped at the break.
+/// Words wider than a line are broken between characters. Color marks as in
+/// [`TextBox`] take no space.
+pub fn wrap<'a>(font: &'a Font<'a>, text: &'a str, max_width: u32) -> Lines<'a> {
+    Lines {
+        font,
+        rest: Some(text),
+        max_width,
+    }
+}
+
+/// Iterator over the lines of [`wrap`]
+#[derive(Debug, Clone)]
+pub struct Lines<'a> {
+    font: &'a Font<'a>,
+    rest: Option<&'a str>,
+    max_width: u32,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Line<'a>> {
+        let text = self.rest?;
+
+        let mut width = 0;
+        let mut visible = false;
+        // End of the line, its width and the start of the next line at the last space
+        let mut space: Option<(usize, u32, usize)> = None;
+        let mut after_space = false;
+
+        let mut chars = text.char_indices();
+        while let Some((index, c)) = chars.next() {
+            let c = match c {
+                '\n' => {
+                    self.rest = Some(&text[index + 1..]);
+                    let width = space.filter(|_| after_space).map_or(width, |(_, w, _)| w);
+                    return Some(Line {
+                        text: &text[..index],
+                        width,
+                    });
+                }
+                COLOR_MARK => match chars.next() {
+                    Some((_, COLOR_MARK)) => COLOR_MARK,
+                    _ => continue,
+                },
+                c => c,
+            };
+
+            let advance = self.font.glyph(c).map_or(0, Glyph::advance);
+
+            if c == ' ' {
+                space = match space {
+                    Some((end, line_width, _)) if after_space => Some((end, line_width, index + 1)),
+                    _ => Some((index, width, index + 1)),
+                };
+                after_space = true;
+            } else {
+                if visible && width + advance > self.max_width {
+                    let (end, line_width, next) = space.unwrap_or((index, width, index));
+                    self.rest = Some(text[next..].trim_start_matches(' '));
+                    return Some(Line {
+                        text: &text[..end],
+                        width: line_width,
+                    });
+                }
+                after_space = false;
+            }
+
+            width += advance;
+            visible = true;
+        }
+
+        self.rest = None;
+        let width = space.filter(|_| after_space).map_or(width, |(_, w, _)| w);
+        Some(Line { text, width })
+    }
+}
+
+/// Word-wrapped text within a rectangle.
+///
+/// `^` followed by a digit switches to that color of the palette, or back to
+/// the text color of the style if the palette is shorter. Lines which do not
+/// fit into the rectangle are left out.
+#[derive(Debug, Clone, Copy)]
+pub struct TextBox<'a, C> {
+    text: &'a str,
+    bounds: Rectangle,
+    style: FontStyle<'a, C>,
+    alignment: Alignment,
+    palette: &'a [C],
+}
+
+impl<'a, C: PixelColor> TextBox<'a, C> {
+    /// Creates a left aligned text box without palette
+    pub fn new(text: &'a str, bounds: Rectangle, style: FontStyle<'a, C>) -> Self {
+        Self {
+            text,
+            bounds,
+            style,
+            alignment: Alignment::Left,
+            palette: &[],
+        }
+    }
+
+    /// Aligns every line within the rectangle
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Sets the colors selected by `^0` to `^9`
+    pub fn with_palette(mut self, palette: &'a [C]) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Returns the lines as they are drawn
+    pub fn lines(&self) -> Lines<'a> {
+        wrap(self.style.font, self.text, self.bounds.size.width)
+    }
+
+    /// Returns the height of all lines, which may be more than fits into the rectangle
+    pub fn text_height(&self) -> u32 {
+        self.lines().count() as u32 * self.style.font.line_height()
+    }
+}
+
+impl<C: PixelColor> Dimensions for TextBox<'_, C> {
+    fn bounding_box(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl<C: PixelColor> Drawable for TextBox<'_, C> {
+    type Color = C;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let line_height = self.style.font.line_height() as i32;
+        let bottom = self.bounds.top_left.y + self.bounds.size.height as i32;
+        let width = self.bounds.size.width;
+
+        // The color carries over from one line to the next
+        let mut style = self.style;
+        let mut y = self.bounds.top_left.y;
+
+        for line in self.lines() {
+            if y + line_height > bottom {
+                break;
+            }
+
+            let indent = match self.alignment {
+                Alignment::Left => 0,
+                Alignment::Center => width.saturating_sub(line.width) / 2,
+                Alignment::Right => width.saturating_sub(line.width),
+            };
+            let mut position = Point::new(self.bounds.top_left.x + indent as i32, y);
+
+            let mut rest = line.text;
+            while let Some(index) = rest.find(COLOR_MARK) {
+                position = style.draw_string(&rest[..index], position, Baseline::Top, target)?;
+
+                let mut after = rest[index + 1..].chars();
+                match after.next() {
+                    Some(COLOR_MARK) => {
+                        position = style.draw_string("^", position, Baseline::Top, target)?;
+                    }
+                    Some(c) => {
+                        style.text_color = c
+                            .to_digit(10)
+                            .and_then(|index| self.palette.get(index as usize))
+                            .copied()
+                            .or(self.style.text_color);
+                    }
+                    None => {}
+                }
+                rest = after.as_str();
+            }
+            style.draw_string(rest, position, Baseline::Top, target)?;
+
+            y += line_height;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565, text::Text};
+
+    use super::*;
+
+    /// 4 pixels per character, ascent 3 and descent 1
+    const FONT: Font<'static> = Font::new(
+        3,
+        1,
+        Some('?'),
+        &[
+            Glyph::new(' ', 4, 0, 0, 0, 0, 0),
+            Glyph::new('?', 4, 1, 1, 1, 0, 0),
+            Glyph::new('A', 4, 3, 3, 0, 0, 1),
+            Glyph::new('B', 4, 1, 1, 0, 0, 0),
+            Glyph::new('^', 4, 1, 1, 1, 2, 0),
+            // Descends below the baseline
+            Glyph::new('g', 4, 1, 2, 0, -1, 0),
+        ],
+        &[0x80, 0xE0, 0xE0, 0xE0],
+    );
+
+    fn lines(text: &'static str, max_width: u32) -> ([(&'static str, u32); 6], usize) {
+        let mut lines = [("", 0); 6];
+        let mut count = 0;
+        for line in wrap(&FONT, text, max_width) {
+            lines[count] = (line.text, line.width);
+            count += 1;
+        }
+        (lines, count)
+    }
+
+    fn assert_lines(text: &'static str, max_width: u32, expected: &[(&str, u32)]) {
+        let (lines, count) = lines(text, max_width);
+        assert_eq!(&lines[..count], expected, "{text:?} in {max_width} pixels");
+    }
+
+    #[test]
+    fn measures_text() {
+        assert_eq!(FONT.line_height(), 4);
+        assert_eq!(FONT.text_width("AB A"), 16);
+        assert_eq!(FONT.text_width(""), 0);
+        // Unknown characters take the space of the replacement
+        assert_eq!(FONT.text_width("Aö"), 8);
+        assert_eq!(FONT.glyph('ö').map(Glyph::character), Some('?'));
+    }
+
+    #[test]
+    fn measures_umlauts_of_the_generated_fonts() {
+        assert_eq!(fonts::NORMAL.text_width("Größe"), 30);
+        assert_eq!(fonts::NORMAL.glyph('ö').map(Glyph::character), Some('ö'));
+        assert_eq!(fonts::LARGE.glyph('€').map(Glyph::character), Some('€'));
+        assert!(fonts::SCORE.glyph('7').is_some());
+    }
+
+    #[test]
+    fn keeps_short_text_on_one_line() {
+        assert_lines("AB AB", 100, &[("AB AB", 20)]);
+        assert_lines("", 100, &[("", 0)]);
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_lines("AB AB", 12, &[("AB", 8), ("AB", 8)]);
+        assert_lines("AB AB", 19, &[("AB", 8), ("AB", 8)]);
+        assert_lines("AB AB", 20, &[("AB AB", 20)]);
+        assert_lines("A B A B", 12, &[("A B", 12), ("A B", 12)]);
+    }
+
+    #[test]
+    fn drops_spaces_at_the_break() {
+        assert_lines("AB   AB", 12, &[("AB", 8), ("AB", 8)]);
+        // Trailing spaces do not count
+        assert_lines("AB  ", 100, &[("AB  ", 8)]);
+        assert_lines("AB  \nA", 100, &[("AB  ", 8), ("A", 4)]);
+    }
+
+    #[test]
+    fn breaks_long_words() {
+        assert_lines("AAAAA", 12, &[("AAA", 12), ("AA", 8)]);
+        assert_lines("B AAAAA", 12, &[("B", 4), ("AAA", 12), ("AA", 8)]);
+        // At least one character per line, even if it is too wide
+        assert_lines("AA", 2, &[("A", 4), ("A", 4)]);
+    }
+
+    #[test]
+    fn breaks_at_newlines() {
+        assert_lines("A\nB", 100, &[("A", 4), ("B", 4)]);
+        assert_lines("A\n\nB\n", 100, &[("A", 4), ("", 0), ("B", 4), ("", 0)]);
+    }
+
+    #[test]
+    fn ignores_color_marks() {
+        assert_lines("^1AB ^2AB", 8, &[("^1AB", 8), ("^2AB", 8)]);
+        // An escaped mark is drawn
+        assert_lines("A^^B", 100, &[("A^^B", 12)]);
+    }
+
+    fn draw_text(text: &str, baseline: Baseline) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        Text::with_baseline(
+            text,
+            Point::new(0, 4),
+            FontStyle::new(&FONT, Rgb565::RED),
+            baseline,
+        )
+        .draw(&mut display)
+        .unwrap();
+        display
+    }
+
+    #[test]
+    fn draws_glyphs_on_the_baseline() {
+        draw_text("AgA", Baseline::Alphabetic).assert_pattern(&[
+            "            ",
+            "            ",
+            "RRR     RRR ",
+            "RRR     RRR ",
+            "RRR R   RRR ",
+            "    R       ",
+        ]);
+    }
+
+    #[test]
+    fn positions_baselines() {
+        let top = draw_text("A", Baseline::Top);
+        assert_eq!(
+            top.affected_area(),
+            Rectangle::new(Point::new(0, 4), Size::new(3, 3))
+        );
+        let bottom = draw_text("A", Baseline::Bottom);
+        assert_eq!(
+            bottom.affected_area(),
+            Rectangle::new(Point::new(0, 1), Size::new(3, 3))
+        );
+    }
+
+    #[test]
+    fn fills_the_background() {
+        let mut display = MockDisplay::new();
+        // Glyphs are drawn over the background
+        display.set_allow_overdraw(true);
+        let style = FontStyle {
+            background_color: Some(Rgb565::BLUE),
+            ..FontStyle::new(&FONT, Rgb565::RED)
+        };
+        Text::with_baseline("B A", Point::zero(), style, Baseline::Top)
+            .draw(&mut display)
+            .unwrap_or_else(|_| unreachable!());
+        display.assert_pattern(&[
+            "BBBBBBBBRRRB",
+            "BBBBBBBBRRRB",
+            "RBBBBBBBRRRB",
+            "BBBBBBBBBBBB",
+        ]);
+    }
+
+    #[test]
+    fn measures_bounding_box() {
+        let text = Text::with_baseline(
+            "AB",
+            Point::new(5, 10),
+            FontStyle::new(&FONT, Rgb565::RED),
+            Baseline::Top,
+        );
+        assert_eq!(
+            text.bounding_box(),
+            Rectangle::new(Point::new(5, 10), Size::new(8, 4))
+        );
+    }
+
+    fn text_box(text: &'static str, width: u32, height: u32) -> TextBox<'static, Rgb565> {
+        TextBox::new(
+            text,
+            Rectangle::new(Point::zero(), Size::new(width, height)),
+            FontStyle::new(&FONT, Rgb565::RED),
+        )
+    }
+
+    #[test]
+    fn aligns_lines_in_the_box() {
+        let mut display = MockDisplay::new();
+        text_box("A AA", 8, 8)
+            .with_alignment(Alignment::Center)
+            .draw(&mut display)
+            .unwrap();
+        display.assert_pattern(&[
+            "  RRR   ", "  RRR   ", "  RRR   ", "        ", "RRR RRR ", "RRR RRR ", "RRR RRR ",
+        ]);
+
+        let mut display = MockDisplay::new();
+        text_box("A", 8, 8)
+            .with_alignment(Alignment::Right)
+            .draw(&mut display)
+            .unwrap();
+        display.assert_pattern(&["    RRR", "    RRR", "    RRR"]);
+    }
+
+    #[test]
+    fn leaves_out_lines_below_the_box() {
+        let text_box = text_box("A A A", 4, 9);
+        assert_eq!(text_box.lines().count(), 3);
+        assert_eq!(text_box.text_height(), 12);
+
+        let mut display = MockDisplay::new();
+        text_box.draw(&mut display).unwrap();
+        assert_eq!(
+            display.affected_area(),
+            Rectangle::new(Point::zero(), Size::new(3, 7))
+        );
+    }
+
+    #[test]
+    fn switches_colors_inline() {
+        let mut display = MockDisplay::new();
+        text_box("B^1B^0B ^9B^^", 100, 4)
+            .with_palette(&[Rgb565::GREEN, Rgb565::BLUE])
+            .draw(&mut display)
+            .unwrap();
+        // Blue, then green, then back to red for a color beyond the palette
+        display.assert_pattern(&[
+            "                     R",
+            "                      ",
+            "R   B   G       R     ",
+        ]);
+
+        let mut display = MockDisplay::new();
+        text_box("^^", 100, 4).draw(&mut display).unwrap();
+        display.assert_pattern(&[" R"]);
+    }
+
+    #[test]
+    fn carries_colors_to_the_next_line() {
+        let mut display = MockDisplay::new();
+        text_box("^1B B", 4, 8)
+            .with_palette(&[Rgb565::GREEN, Rgb565::BLUE])
+            .draw(&mut display)
+            .unwrap();
+        display.assert_pattern(&[" ", " ", "B", " ", " ", " ", "B"]);
+    }
+}
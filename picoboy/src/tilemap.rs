@@ -0,0 +1,305 @@
+//! Backgrounds built from tiles.
+//!
+//! A [`Tileset`] cuts an image into equally sized tiles, numbered row by row.
+//! A [`TileMap`] is a grid of tile numbers. Only the tiles overlapping the
+//! draw target are drawn, so a map may be larger than the screen and scrolled
+//! by moving it.
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+
+use crate::sprite::{Image, Sprite};
+
+/// Image cut into tiles
+#[derive(Debug, Clone, Copy)]
+pub struct Tileset<'a> {
+    image: &'a Image<'a>,
+    tile_size: Size,
+    columns: u32,
+    len: u32,
+}
+
+impl<'a> Tileset<'a> {
+    /// Cuts `image` into tiles of `tile_size`, incomplete tiles at the edges are ignored
+    pub fn new(image: &'a Image<'a>, tile_size: Size) -> Self {
+        let columns = image.size().width / tile_size.width.max(1);
+        let rows = image.size().height / tile_size.height.max(1);
+
+        Self {
+            image,
+            tile_size,
+            columns,
+            len: columns * rows,
+        }
+    }
+
+    /// Returns the size of a single tile
+    pub fn tile_size(&self) -> Size {
+        self.tile_size
+    }
+
+    /// Returns the number of tiles
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Returns `true` if the image is smaller than a single tile
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a sprite showing a tile, `None` if there is no such tile
+    pub fn sprite(&self, tile: u8, position: Point) -> Option<Sprite<'a>> {
+        let tile = u32::from(tile);
+        if tile >= self.len {
+            return None;
+        }
+
+        let top_left = Point::zero()
+            + Size::new(tile % self.columns, tile / self.columns).component_mul(self.tile_size);
+
+        Some(
+            Sprite::new(self.image, position).with_source(Rectangle::new(top_left, self.tile_size)),
+        )
+    }
+}
+
+/// Grid of tiles
+#[derive(Debug, Clone, Copy)]
+pub struct TileMap<'a> {
+    tileset: Tileset<'a>,
+    tiles: &'a [u8],
+    columns: u32,
+    position: Point,
+}
+
+impl<'a> TileMap<'a> {
+    /// Creates a map from tile numbers, row by row with `columns` tiles each.
+    ///
+    /// Numbers without a tile in the tileset are left empty.
+    pub fn new(tileset: Tileset<'a>, columns: u32, tiles: &'a [u8]) -> Self {
+        Self {
+            tileset,
+            tiles,
+            columns: columns.max(1),
+            position: Point::zero(),
+        }
+    }
+
+    /// Returns the number of columns and rows
+    pub fn grid_size(&self) -> Size {
+        Size::new(self.columns, self.tiles.len() as u32 / self.columns)
+    }
+
+    /// Returns the size of a single tile
+    pub fn tile_size(&self) -> Size {
+        self.tileset.tile_size()
+    }
+
+    /// Returns the top left corner of the map on the screen
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    /// Moves the map, a negative position scrolls it to the left or up
+    pub fn set_position(&mut self, position: Point) {
+        self.position = position;
+    }
+
+    /// Returns the tile number at a column and row
+    pub fn tile(&self, column: u32, row: u32) -> Option<u8> {
+        let size = self.grid_size();
+        if column >= size.width || row >= size.height {
+            return None;
+        }
+
+        self.tiles
+            .get((row * self.columns + column) as usize)
+            .copied()
+    }
+
+    /// Returns the column and row of the tile covering a point on the screen
+    pub fn cell_at(&self, point: Point) -> Option<(u32, u32)> {
+        if !self.bounding_box().contains(point) {
+            return None;
+        }
+
+        let offset = point - self.position;
+        let tile_size = self.tileset.tile_size();
+        Some((
+            offset.x as u32 / tile_size.width,
+            offset.y as u32 / tile_size.height,
+        ))
+    }
+}
+
+impl Dimensions for TileMap<'_> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(
+            self.position,
+            self.grid_size().component_mul(self.tileset.tile_size()),
+        )
+    }
+}
+
+impl Drawable for TileMap<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let tile_size = self.tileset.tile_size();
+        if tile_size.width == 0 || tile_size.height == 0 {
+            return Ok(());
+        }
+
+        let visible = self.bounding_box().intersection(&target.bounding_box());
+        let Some(bottom_right) = visible.bottom_right() else {
+            return Ok(());
+        };
+
+        // Range of cells overlapping the target
+        let first = visible.top_left - self.position;
+        let last = bottom_right - self.position;
+        let columns = first.x as u32 / tile_size.width..=last.x as u32 / tile_size.width;
+        let rows = first.y as u32 / tile_size.height..=last.y as u32 / tile_size.height;
+
+        for row in rows {
+            for column in columns.clone() {
+                let position = self.position + Size::new(column, row).component_mul(tile_size);
+
+                let sprite = self
+                    .tile(column, row)
+                    .and_then(|tile| self.tileset.sprite(tile, position));
+                if let Some(sprite) = sprite {
+                    sprite.draw(target)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+
+    const R: u16 = 0xF800;
+    const G: u16 = 0x07E0;
+    const KEY: u16 = 0xF81F;
+
+    /// Tile 0 red, tile 1 green with a transparent bottom right pixel
+    const IMAGE: Image<'static> = Image::rgb565(4, 2, &[R, R, G, G, R, R, G, KEY], Some(KEY));
+
+    /// Tile 9 does not exist and stays empty
+    const TILES: [u8; 6] = [0, 1, 0, 1, 9, 0];
+
+    fn map(position: Point) -> TileMap<'static> {
+        let mut map = TileMap::new(Tileset::new(&IMAGE, Size::new(2, 2)), 3, &TILES);
+        map.set_position(position);
+        map
+    }
+
+    fn draw(map: TileMap<'_>) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        map.draw(&mut display).unwrap();
+        display
+    }
+
+    #[test]
+    fn cuts_the_image_into_tiles() {
+        let tileset = Tileset::new(&IMAGE, Size::new(2, 2));
+        assert_eq!(tileset.len(), 2);
+        assert!(tileset.sprite(1, Point::zero()).is_some());
+        assert!(tileset.sprite(2, Point::zero()).is_none());
+
+        // Incomplete tiles are ignored
+        assert_eq!(Tileset::new(&IMAGE, Size::new(3, 2)).len(), 1);
+        assert!(Tileset::new(&IMAGE, Size::new(2, 3)).is_empty());
+    }
+
+    #[test]
+    fn draws_tiles_row_by_row() {
+        #[rustfmt::skip]
+        draw(map(Point::zero())).assert_pattern(&[
+            "RRGGRR",
+            "RRG RR",
+            "GG  RR",
+            "G   RR",
+        ]);
+        #[rustfmt::skip]
+        draw(map(Point::new(1, 2))).assert_pattern(&[
+            "       ",
+            "       ",
+            " RRGGRR",
+            " RRG RR",
+            " GG  RR",
+            " G   RR",
+        ]);
+    }
+
+    #[test]
+    fn clips_at_a_negative_position() {
+        // Only the visible parts of the tiles are drawn, the display rejects any other pixel
+        draw(map(Point::new(-3, -1))).assert_pattern(&[" RR", " RR", " RR"]);
+        draw(map(Point::new(-6, 0))).assert_pattern(&[]);
+        draw(map(Point::new(0, -4))).assert_pattern(&[]);
+    }
+
+    #[test]
+    fn clips_to_the_target() {
+        let mut display = MockDisplay::new();
+        let area = Rectangle::new(Point::new(3, 1), Size::new(2, 2));
+        map(Point::zero())
+            .draw(&mut display.clipped(&area))
+            .unwrap();
+        display.assert_pattern(&["     ", "    R", "    R"]);
+
+        draw(map(Point::new(64, 0))).assert_pattern(&[]);
+    }
+
+    #[test]
+    fn draws_nothing_with_empty_tiles() {
+        let map = TileMap::new(Tileset::new(&IMAGE, Size::zero()), 3, &TILES);
+        draw(map).assert_pattern(&[]);
+    }
+
+    #[test]
+    fn finds_tiles_by_cell() {
+        let map = map(Point::new(-3, -1));
+        assert_eq!(map.grid_size(), Size::new(3, 2));
+        assert_eq!(map.tile(1, 0), Some(1));
+        assert_eq!(map.tile(2, 1), Some(0));
+        // Numbers without a tile are still returned
+        assert_eq!(map.tile(1, 1), Some(9));
+        assert_eq!(map.tile(3, 0), None);
+        assert_eq!(map.tile(0, 2), None);
+
+        // An incomplete last row is not part of the map
+        let partial = TileMap::new(Tileset::new(&IMAGE, Size::new(2, 2)), 4, &TILES);
+        assert_eq!(partial.grid_size(), Size::new(4, 1));
+        assert_eq!(partial.tile(0, 1), None);
+    }
+
+    #[test]
+    fn finds_cells_on_the_screen() {
+        let map = map(Point::new(-3, -1));
+        assert_eq!(
+            map.bounding_box(),
+            Rectangle::new(Point::new(-3, -1), Size::new(6, 4))
+        );
+        assert_eq!(map.cell_at(Point::new(-3, -1)), Some((0, 0)));
+        assert_eq!(map.cell_at(Point::new(0, 0)), Some((1, 0)));
+        assert_eq!(map.cell_at(Point::new(2, 2)), Some((2, 1)));
+
+        // Outside of the map, also left of and above it
+        assert_eq!(map.cell_at(Point::new(-4, 0)), None);
+        assert_eq!(map.cell_at(Point::new(0, -2)), None);
+        assert_eq!(map.cell_at(Point::new(3, 0)), None);
+        assert_eq!(map.cell_at(Point::new(0, 3)), None);
+    }
+}
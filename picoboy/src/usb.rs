@@ -0,0 +1,86 @@
+//! USB device with a serial console and a gamepad, each behind a cargo feature.
+//!
+//! With the `usb-serial` feature the device offers a CDC-ACM
+//! [`SerialPort`](crate::usb_serial::SerialPort), for the
+//! [`console`](crate::console) and for logs when no debug probe is attached.
+//! With `usb-gamepad` it reports the buttons as a HID
+//! [`Gamepad`](crate::usb_gamepad::Gamepad). Both together make a composite
+//! device.
+//!
+//! The device has to be polled within a few milliseconds of every USB event,
+//! so [`Usb::poll`] belongs into the `USBCTRL_IRQ` interrupt.
+
+use picoboy_color::hal::usb::UsbBus;
+use usb_device::bus::UsbBusAllocator;
+use usb_device::device::{
+    StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbDeviceState, UsbVidPid,
+};
+
+#[cfg(feature = "usb-gamepad")]
+use crate::usb_gamepad::Gamepad;
+#[cfg(feature = "usb-serial")]
+use crate::usb_serial::SerialPort;
+
+/// Test IDs of pid.codes, replace them with your own before you share the firmware
+const VID_PID: UsbVidPid = UsbVidPid(0x1209, 0x0001);
+
+/// Current drawn from the host in milliamperes
+const MAX_POWER_MA: usize = 500;
+
+/// USB device with the classes enabled by cargo features
+pub struct Usb {
+    device: UsbDevice<'static, UsbBus>,
+    /// Serial console
+    #[cfg(feature = "usb-serial")]
+    pub serial: SerialPort<'static, UsbBus>,
+    /// Gamepad reporting the buttons
+    #[cfg(feature = "usb-gamepad")]
+    pub gamepad: Gamepad<'static, UsbBus>,
+}
+
+impl Usb {
+    pub(crate) fn new(bus: &'static UsbBusAllocator<UsbBus>) -> Self {
+        // The classes allocate their interfaces and endpoints before the device is built
+        #[cfg(feature = "usb-serial")]
+        let serial = SerialPort::new(bus);
+        #[cfg(feature = "usb-gamepad")]
+        let gamepad = Gamepad::new(bus);
+
+        let strings = StringDescriptors::default()
+            .manufacturer("PicoBoy")
+            .product("PicoBoy Color")
+            .serial_number("0");
+
+        // Neither the descriptors nor the limits can be out of range
+        let Ok(builder) = UsbDeviceBuilder::new(bus, VID_PID).strings(&[strings]) else {
+            unreachable!()
+        };
+        let Ok(builder) = builder.max_power(MAX_POWER_MA) else {
+            unreachable!()
+        };
+        let device = builder.composite_with_iads().build();
+
+        Self {
+            device,
+            #[cfg(feature = "usb-serial")]
+            serial,
+            #[cfg(feature = "usb-gamepad")]
+            gamepad,
+        }
+    }
+
+    /// Handles USB events, call it from the `USBCTRL_IRQ` interrupt
+    pub fn poll(&mut self) {
+        self.device.poll(&mut [
+            #[cfg(feature = "usb-serial")]
+            &mut self.serial,
+            #[cfg(feature = "usb-gamepad")]
+            &mut self.gamepad,
+        ]);
+    }
+
+    /// Returns `true` once the host has configured the device
+    pub fn is_configured(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured
+    }
+}
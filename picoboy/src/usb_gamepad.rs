@@ -0,0 +1,213 @@
+//! USB HID gamepad reporting the joystick and buttons to a PC.
+//!
+//! The joystick shows up as the X and Y axes of a digital pad, A, B and the
+//! joystick center as buttons 1 to 3. Operating systems pick the gamepad up
+//! without a driver, so the PicoBoy can play games on the PC.
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+use crate::input::{Button, Buttons};
+
+const CLASS_HID: u8 = 0x03;
+const SUBCLASS_NONE: u8 = 0x00;
+const PROTOCOL_NONE: u8 = 0x00;
+
+const DESCRIPTOR_HID: u8 = 0x21;
+const DESCRIPTOR_REPORT: u8 = 0x22;
+
+const REQUEST_GET_REPORT: u8 = 0x01;
+const REQUEST_GET_IDLE: u8 = 0x02;
+const REQUEST_GET_PROTOCOL: u8 = 0x03;
+const REQUEST_SET_IDLE: u8 = 0x0A;
+const REQUEST_SET_PROTOCOL: u8 = 0x0B;
+
+/// Interval at which the host polls for reports in milliseconds
+const POLL_INTERVAL_MS: u8 = 10;
+
+/// Layout of [`GamepadReport`]
+#[rustfmt::skip]
+const REPORT_DESCRIPTOR: [u8; 45] = [
+    0x05, 0x01, // Usage page (generic desktop)
+    0x09, 0x05, // Usage (gamepad)
+    0xA1, 0x01, // Collection (application)
+    0x05, 0x09, //   Usage page (button)
+    0x19, 0x01, //   Usage minimum (1)
+    0x29, 0x03, //   Usage maximum (3)
+    0x15, 0x00, //   Logical minimum (0)
+    0x25, 0x01, //   Logical maximum (1)
+    0x75, 0x01, //   Report size (1)
+    0x95, 0x03, //   Report count (3)
+    0x81, 0x02, //   Input (data, variable, absolute)
+    0x75, 0x05, //   Report size (5)
+    0x95, 0x01, //   Report count (1)
+    0x81, 0x03, //   Input (constant), padding
+    0x05, 0x01, //   Usage page (generic desktop)
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x15, 0xFF, //   Logical minimum (-1)
+    0x25, 0x01, //   Logical maximum (1)
+    0x75, 0x08, //   Report size (8)
+    0x95, 0x02, //   Report count (2)
+    0x81, 0x02, //   Input (data, variable, absolute)
+    0xC0,       // End collection
+];
+
+/// Version and report descriptor of the HID class, including length and type
+const HID_DESCRIPTOR: [u8; 9] = [
+    9,
+    DESCRIPTOR_HID,
+    0x11, // HID 1.11
+    0x01,
+    0x00, // Not localized
+    0x01, // One report descriptor
+    DESCRIPTOR_REPORT,
+    REPORT_DESCRIPTOR.len() as u8,
+    0x00,
+];
+
+/// State of the buttons as sent to the host
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GamepadReport {
+    /// A, B and center in bits 0 to 2
+    pub buttons: u8,
+    /// -1 for left, 1 for right
+    pub x: i8,
+    /// -1 for up, 1 for down
+    pub y: i8,
+}
+
+impl GamepadReport {
+    /// Encodes the report as described by the report descriptor
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [self.buttons, self.x as u8, self.y as u8]
+    }
+}
+
+impl From<Buttons> for GamepadReport {
+    fn from(buttons: Buttons) -> Self {
+        let axis = |negative, positive| {
+            i8::from(buttons.contains(positive)) - i8::from(buttons.contains(negative))
+        };
+
+        Self {
+            buttons: u8::from(buttons.contains(Button::A))
+                | u8::from(buttons.contains(Button::B)) << 1
+                | u8::from(buttons.contains(Button::Center)) << 2,
+            x: axis(Button::Left, Button::Right),
+            y: axis(Button::Up, Button::Down),
+        }
+    }
+}
+
+/// HID class sending a [`GamepadReport`] whenever the buttons change
+pub struct Gamepad<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    endpoint: EndpointIn<'a, B>,
+    report: GamepadReport,
+    // The report changed since it was last accepted by the endpoint
+    pending: bool,
+    idle: u8,
+    protocol: u8,
+}
+
+impl<'a, B: UsbBus> Gamepad<'a, B> {
+    /// Allocates the interface and endpoint
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            interface: alloc.interface(),
+            endpoint: alloc.interrupt(8, POLL_INTERVAL_MS),
+            report: GamepadReport::default(),
+            pending: false,
+            idle: 0,
+            protocol: 1,
+        }
+    }
+
+    /// Reports the buttons, the host sees them once the endpoint is free
+    pub fn update(&mut self, buttons: Buttons) {
+        let report = GamepadReport::from(buttons);
+        if report != self.report {
+            self.report = report;
+            self.pending = true;
+        }
+        self.flush();
+    }
+
+    /// Sends the latest report if it is still pending
+    pub fn flush(&mut self) {
+        if self.pending && self.endpoint.write(&self.report.to_bytes()).is_ok() {
+            self.pending = false;
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for Gamepad<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(self.interface, CLASS_HID, SUBCLASS_NONE, PROTOCOL_NONE)?;
+        writer.write(DESCRIPTOR_HID, &HID_DESCRIPTOR[2..])?;
+        writer.endpoint(&self.endpoint)
+    }
+
+    fn reset(&mut self) {
+        self.pending = true;
+        self.idle = 0;
+        self.protocol = 1;
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.endpoint.address() {
+            self.flush();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let request = xfer.request();
+        if request.recipient != control::Recipient::Interface
+            || request.index != u8::from(self.interface).into()
+        {
+            return;
+        }
+
+        let _ = match (request.request_type, request.request) {
+            (control::RequestType::Standard, control::Request::GET_DESCRIPTOR) => {
+                match request.descriptor_type_index() {
+                    (DESCRIPTOR_HID, 0) => xfer.accept_with_static(&HID_DESCRIPTOR),
+                    (DESCRIPTOR_REPORT, 0) => xfer.accept_with_static(&REPORT_DESCRIPTOR),
+                    _ => xfer.reject(),
+                }
+            }
+            (control::RequestType::Class, REQUEST_GET_REPORT) => {
+                xfer.accept_with(&self.report.to_bytes())
+            }
+            (control::RequestType::Class, REQUEST_GET_IDLE) => xfer.accept_with(&[self.idle]),
+            (control::RequestType::Class, REQUEST_GET_PROTOCOL) => {
+                xfer.accept_with(&[self.protocol])
+            }
+            _ => return,
+        };
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let request = xfer.request();
+        if request.request_type != control::RequestType::Class
+            || request.recipient != control::Recipient::Interface
+            || request.index != u8::from(self.interface).into()
+        {
+            return;
+        }
+
+        let _ = match request.request {
+            REQUEST_SET_IDLE => {
+                self.idle = (request.value >> 8) as u8;
+                xfer.accept()
+            }
+            REQUEST_SET_PROTOCOL => {
+                self.protocol = request.value as u8;
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        };
+    }
+}
@@ -0,0 +1,236 @@
+//! USB CDC-ACM serial port, shown as `/dev/ttyACM*` or a COM port.
+//!
+//! The port works like a UART with a transmit buffer: [`SerialPort::write`]
+//! queues bytes, which go out one packet at a time whenever the host asks
+//! for them. Output is dropped while no terminal has the port open, so
+//! nothing stale shows up when one connects later.
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+/// Size of the bulk packets in bytes
+const PACKET_LEN: u16 = 64;
+
+/// Bytes queued for the host
+const TX_BUFFER_LEN: usize = 512;
+
+const CLASS_CDC: u8 = 0x02;
+const CLASS_CDC_DATA: u8 = 0x0A;
+const SUBCLASS_ACM: u8 = 0x02;
+const PROTOCOL_NONE: u8 = 0x00;
+
+const CS_INTERFACE: u8 = 0x24;
+const DESCRIPTOR_HEADER: u8 = 0x00;
+const DESCRIPTOR_CALL_MANAGEMENT: u8 = 0x01;
+const DESCRIPTOR_ACM: u8 = 0x02;
+const DESCRIPTOR_UNION: u8 = 0x06;
+
+const REQUEST_SET_LINE_CODING: u8 = 0x20;
+const REQUEST_GET_LINE_CODING: u8 = 0x21;
+const REQUEST_SET_CONTROL_LINE_STATE: u8 = 0x22;
+const REQUEST_SEND_BREAK: u8 = 0x23;
+
+/// Data terminal ready bit of `SET_CONTROL_LINE_STATE`
+const DTR: u16 = 0x01;
+
+/// CDC-ACM class with a transmit buffer
+pub struct SerialPort<'a, B: UsbBus> {
+    comm_interface: InterfaceNumber,
+    data_interface: InterfaceNumber,
+    // Unused, but ACM requires a notification endpoint
+    comm_endpoint: EndpointIn<'a, B>,
+    read_endpoint: EndpointOut<'a, B>,
+    write_endpoint: EndpointIn<'a, B>,
+    // Baud rate, stop bits, parity and data bits as set by the host, meaningless over USB
+    line_coding: [u8; 7],
+    dtr: bool,
+    tx: [u8; TX_BUFFER_LEN],
+    tx_start: usize,
+    tx_len: usize,
+    // A packet is on its way to the host
+    writing: bool,
+    // The last packet was full, the transfer has to be ended with an empty one
+    needs_zlp: bool,
+}
+
+impl<'a, B: UsbBus> SerialPort<'a, B> {
+    /// Allocates the interfaces and endpoints
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            comm_interface: alloc.interface(),
+            data_interface: alloc.interface(),
+            comm_endpoint: alloc.interrupt(8, 255),
+            read_endpoint: alloc.bulk(PACKET_LEN),
+            write_endpoint: alloc.bulk(PACKET_LEN),
+            // 115200 baud, 1 stop bit, no parity, 8 data bits
+            line_coding: [0x00, 0xC2, 0x01, 0x00, 0, 0, 8],
+            dtr: false,
+            tx: [0; TX_BUFFER_LEN],
+            tx_start: 0,
+            tx_len: 0,
+            writing: false,
+            needs_zlp: false,
+        }
+    }
+
+    /// Returns `true` while a terminal has the port open
+    pub fn is_open(&self) -> bool {
+        self.dtr
+    }
+
+    /// Reads received bytes into `data`, returns how many, 0 if there are none.
+    ///
+    /// `data` should hold a full packet of 64 bytes, shorter buffers may lose
+    /// the rest of one.
+    pub fn read(&mut self, data: &mut [u8]) -> usize {
+        let mut packet = [0; PACKET_LEN as usize];
+        match self.read_endpoint.read(&mut packet) {
+            Ok(len) => {
+                let len = len.min(data.len());
+                data[..len].copy_from_slice(&packet[..len]);
+                len
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// Queues bytes for the host, returns how many fit into the buffer.
+    ///
+    /// Pretends to write everything while no terminal has the port open.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        if !self.dtr {
+            return data.len();
+        }
+
+        let len = data.len().min(TX_BUFFER_LEN - self.tx_len);
+        for &byte in &data[..len] {
+            self.tx[(self.tx_start + self.tx_len) % TX_BUFFER_LEN] = byte;
+            self.tx_len += 1;
+        }
+        self.flush();
+        len
+    }
+
+    /// Returns `true` if everything written was handed to the host
+    pub fn is_flushed(&self) -> bool {
+        self.tx_len == 0 && !self.writing
+    }
+
+    /// Starts the next packet unless one is on its way
+    pub fn flush(&mut self) {
+        if self.writing || (self.tx_len == 0 && !self.needs_zlp) {
+            return;
+        }
+
+        let mut packet = [0; PACKET_LEN as usize];
+        let len = self.tx_len.min(packet.len());
+        for (i, byte) in packet[..len].iter_mut().enumerate() {
+            *byte = self.tx[(self.tx_start + i) % TX_BUFFER_LEN];
+        }
+
+        if self.write_endpoint.write(&packet[..len]).is_ok() {
+            self.tx_start = (self.tx_start + len) % TX_BUFFER_LEN;
+            self.tx_len -= len;
+            self.writing = true;
+            self.needs_zlp = len == packet.len();
+        }
+    }
+
+    fn discard(&mut self) {
+        self.tx_start = 0;
+        self.tx_len = 0;
+        self.needs_zlp = false;
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for SerialPort<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.iad(
+            self.comm_interface,
+            2,
+            CLASS_CDC,
+            SUBCLASS_ACM,
+            PROTOCOL_NONE,
+            None,
+        )?;
+
+        writer.interface(self.comm_interface, CLASS_CDC, SUBCLASS_ACM, PROTOCOL_NONE)?;
+        // CDC 1.10
+        writer.write(CS_INTERFACE, &[DESCRIPTOR_HEADER, 0x10, 0x01])?;
+        // The device does not handle call management itself
+        writer.write(
+            CS_INTERFACE,
+            &[DESCRIPTOR_CALL_MANAGEMENT, 0x00, self.data_interface.into()],
+        )?;
+        // Supports the line coding and control line state requests
+        writer.write(CS_INTERFACE, &[DESCRIPTOR_ACM, 0x02])?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                DESCRIPTOR_UNION,
+                self.comm_interface.into(),
+                self.data_interface.into(),
+            ],
+        )?;
+        writer.endpoint(&self.comm_endpoint)?;
+
+        writer.interface(self.data_interface, CLASS_CDC_DATA, 0x00, PROTOCOL_NONE)?;
+        writer.endpoint(&self.write_endpoint)?;
+        writer.endpoint(&self.read_endpoint)
+    }
+
+    fn reset(&mut self) {
+        self.dtr = false;
+        self.writing = false;
+        self.discard();
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_endpoint.address() {
+            self.writing = false;
+            self.flush();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let request = xfer.request();
+        if request.request_type != control::RequestType::Class
+            || request.recipient != control::Recipient::Interface
+            || request.index != u8::from(self.comm_interface).into()
+        {
+            return;
+        }
+
+        let _ = match request.request {
+            REQUEST_GET_LINE_CODING => xfer.accept_with(&self.line_coding),
+            _ => xfer.reject(),
+        };
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let request = xfer.request();
+        if request.request_type != control::RequestType::Class
+            || request.recipient != control::Recipient::Interface
+            || request.index != u8::from(self.comm_interface).into()
+        {
+            return;
+        }
+
+        let _ = match request.request {
+            REQUEST_SET_LINE_CODING if xfer.data().len() >= self.line_coding.len() => {
+                let len = self.line_coding.len();
+                self.line_coding.copy_from_slice(&xfer.data()[..len]);
+                xfer.accept()
+            }
+            REQUEST_SET_CONTROL_LINE_STATE => {
+                self.dtr = request.value & DTR != 0;
+                if !self.dtr {
+                    self.discard();
+                }
+                xfer.accept()
+            }
+            REQUEST_SEND_BREAK => xfer.accept(),
+            _ => xfer.reject(),
+        };
+    }
+}
@@ -0,0 +1,45 @@
+//! ADC measurement of the supply voltage.
+//!
+//! GPIO29 is ADC channel 3 and sees VSYS through a divider of 3, so the
+//! 3.3 V range of the 12 bit ADC covers up to 9.9 V. The readings feed a
+//! [`Battery`](crate::battery::Battery), which smooths and interprets them.
+
+use embedded_hal_0_2::adc::OneShot;
+use picoboy_color::hal::{
+    adc::AdcPin,
+    gpio::{bank0::Gpio29, FunctionSioInput, Pin, PullNone},
+    Adc,
+};
+
+/// Reference voltage of the ADC in millivolts
+const REFERENCE_MV: u32 = 3_300;
+
+/// Ratio of the voltage divider between VSYS and GPIO29
+const DIVIDER: u32 = 3;
+
+/// Full scale of the 12 bit ADC
+const ADC_RANGE: u32 = 1 << 12;
+
+/// Supply voltage input
+pub struct VoltageMonitor {
+    adc: Adc,
+    pin: AdcPin<Pin<Gpio29, FunctionSioInput, PullNone>>,
+}
+
+impl VoltageMonitor {
+    pub(crate) fn new(adc: Adc, pin: AdcPin<Pin<Gpio29, FunctionSioInput, PullNone>>) -> Self {
+        Self { adc, pin }
+    }
+
+    /// Returns the raw reading of the ADC
+    pub fn read_raw(&mut self) -> u16 {
+        // One-shot reads of the RP2040 ADC cannot fail
+        self.adc.read(&mut self.pin).unwrap_or(0)
+    }
+
+    /// Measures the supply voltage in millivolts, this takes about 2 µs
+    pub fn read_mv(&mut self) -> u16 {
+        let raw = u32::from(self.read_raw());
+        (raw * REFERENCE_MV * DIVIDER / ADC_RANGE) as u16
+    }
+}
@@ -0,0 +1,136 @@
+//! Watchdog supervising the main loop and its tasks, and the reset reason.
+//!
+//! Once started, the [`Supervisor`] has to be fed more often than its
+//! timeout, otherwise the watchdog resets the chip. The main loop feeds it
+//! through [`Supervisor::check`] with a [`Monitor`] of the supervised tasks:
+//! as soon as one of them misses its check-in, the supervisor records the task
+//! in watchdog scratch registers 2 and 3 and stops feeding. Resets by the
+//! firmware, see [`boot`](crate::boot), leave a record as well, so
+//! [`take_reset_reason`] can tell after the reset what happened. A main loop
+//! which hangs itself, e.g. waiting for core 1, leaves no record, its reset
+//! is reported as [`ResetReason::Watchdog`] without a task.
+
+use picoboy_color::hal::{fugit::ExtU32, pac, watchdog::Watchdog};
+
+use crate::supervisor::{Missed, Monitor, ResetReason, SoftwareReset};
+
+/// Longest timeout of the watchdog in milliseconds, its counter has 23 usable bits
+pub const MAX_TIMEOUT_MS: u32 = 8_388;
+
+/// Watchdog which resets the chip unless it is fed in time
+pub struct Supervisor {
+    watchdog: Watchdog,
+    running: bool,
+    expired: bool,
+}
+
+impl Supervisor {
+    pub(crate) fn new(watchdog: Watchdog) -> Self {
+        Self {
+            watchdog,
+            running: false,
+            expired: false,
+        }
+    }
+
+    /// Starts the watchdog, the chip resets unless it is fed every `timeout_ms`.
+    ///
+    /// The watchdog pauses while a debugger halts the cores.
+    ///
+    /// # Panics
+    ///
+    /// If `timeout_ms` exceeds [`MAX_TIMEOUT_MS`].
+    pub fn start(&mut self, timeout_ms: u32) {
+        assert!(timeout_ms <= MAX_TIMEOUT_MS, "watchdog timeout too long");
+
+        self.watchdog.start(timeout_ms.millis());
+        // Starting clears the pause bits
+        self.watchdog.pause_on_debug(true);
+        self.running = true;
+    }
+
+    /// Stops the watchdog, e.g. before dormant mode
+    pub fn stop(&mut self) {
+        self.watchdog.disable();
+        self.running = false;
+    }
+
+    /// Returns `true` while the watchdog runs
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Restarts the timeout, unless a task missed its check-in
+    pub fn feed(&mut self) {
+        if !self.expired {
+            self.watchdog.feed();
+        }
+    }
+
+    /// Advances `monitor` by `dt_ms` and feeds the watchdog if every task checked in in time.
+    ///
+    /// Otherwise the missed task is recorded as the reset reason and the
+    /// watchdog is no longer fed, it resets the chip after its timeout.
+    pub fn check<const N: usize>(
+        &mut self,
+        monitor: &mut Monitor<'_, N>,
+        dt_ms: u32,
+    ) -> Result<(), Missed> {
+        match monitor.update(dt_ms) {
+            Ok(()) => {
+                self.feed();
+                Ok(())
+            }
+            Err(missed) if self.running => {
+                if !self.expired {
+                    defmt::error!("Task {} missed its check-in, resetting", missed.name);
+                    self.expired = true;
+                    record(ResetReason::Watchdog(Some(missed.index)));
+                }
+                Err(missed)
+            }
+            // Nothing would reset the chip, keep watching
+            Err(missed) => Err(missed),
+        }
+    }
+}
+
+/// Stops the watchdog without a [`Supervisor`], e.g. in a fault handler
+pub(crate) fn disable() {
+    // SAFETY: clearing the enable bit only stops the countdown
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    watchdog.ctrl().modify(|_, w| w.enable().clear_bit());
+}
+
+/// Keeps `reason` in the scratch registers for [`take_reset_reason`] after the next reset
+pub(crate) fn record(reason: ResetReason) {
+    // SAFETY: scratch registers 2 and 3 belong to the reset record
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+
+    let [magic, value] = reason.to_record();
+    watchdog.scratch3().write(|w| unsafe { w.bits(value) });
+    watchdog.scratch2().write(|w| unsafe { w.bits(magic) });
+}
+
+/// Returns why the chip was reset, the record left before the reset is cleared
+pub fn take_reset_reason() -> ResetReason {
+    // SAFETY: reads the reason and clears the reset record, nothing else uses them
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+
+    let record = [
+        watchdog.scratch2().read().bits(),
+        watchdog.scratch3().read().bits(),
+    ];
+    watchdog.scratch2().write(|w| unsafe { w.bits(0) });
+
+    // Both bits are clear after power-on, the scratch registers as well
+    let reason = watchdog.reason().read();
+    if reason.timer().bit_is_clear() && reason.force().bit_is_clear() {
+        return ResetReason::PowerOn;
+    }
+    match ResetReason::from_record(record) {
+        Some(recorded) => recorded,
+        None if reason.timer().bit_is_set() => ResetReason::Watchdog(None),
+        None => ResetReason::Software(SoftwareReset::Unknown),
+    }
+}
@@ -0,0 +1,217 @@
+//! Reassembly of the dump chunks printed by `picoboy::screenshot::log`.
+//!
+//! Chunks are found anywhere in a line, so timestamps and other decorations
+//! of the log do not matter, and lines which only look similar, like the
+//! echoed `screenshot` command of the serial console, are skipped. The bytes
+//! are either a list of decimal numbers as printed by defmt or hex digits:
+//!
+//! ```text
+//! 0.512345 screenshot 0 [80, 66, 83, 83, 1, 0, 0]
+//! screenshot 1 5042535301
+//! ```
+
+use std::fmt;
+
+/// Marks a line with a chunk
+const TAG: &str = "screenshot ";
+
+/// A log did not contain a complete dump
+#[derive(Debug)]
+pub struct ChunkError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// Returns the bytes of the last dump in `log`, started by chunk 0.
+///
+/// A dump which misses chunks is only an error if no later dump follows.
+pub fn last_dump(log: &str) -> Result<Vec<u8>, ChunkError> {
+    let mut dump: Option<Vec<u8>> = None;
+    let mut error = None;
+    let mut next_index = 0;
+
+    for (index, line) in log.lines().enumerate() {
+        let Some(start) = line.find(TAG) else {
+            continue;
+        };
+        let line_error = |message: String| ChunkError {
+            line: index + 1,
+            message,
+        };
+
+        let Some((chunk_index, bytes)) = parse_chunk(&line[start + TAG.len()..]) else {
+            continue;
+        };
+        if chunk_index == 0 {
+            dump = Some(Vec::new());
+            error = None;
+            next_index = 0;
+        }
+        if error.is_some() {
+            continue;
+        }
+
+        match dump.as_mut() {
+            Some(dump) if chunk_index == next_index => {
+                dump.extend_from_slice(&bytes);
+                next_index = next_index.wrapping_add(1);
+            }
+            Some(_) => {
+                error = Some(line_error(format!(
+                    "chunk {chunk_index} instead of {next_index}, some were dropped"
+                )));
+            }
+            None => {
+                error = Some(line_error(format!(
+                    "chunk {chunk_index} without the start of its dump"
+                )));
+            }
+        }
+    }
+
+    match (error, dump) {
+        (Some(error), _) => Err(error),
+        (None, Some(dump)) => Ok(dump),
+        (None, None) => Err(ChunkError {
+            line: log.lines().count(),
+            message: String::from("no screenshot found"),
+        }),
+    }
+}
+
+/// Parses `<index> <bytes>` following the tag
+fn parse_chunk(text: &str) -> Option<(u16, Vec<u8>)> {
+    let (index, bytes) = text.trim().split_once(' ')?;
+    let index = index.parse::<u16>().ok()?;
+
+    let bytes = bytes.trim();
+    let bytes = match bytes.strip_prefix('[') {
+        Some(list) => parse_list(list.strip_suffix(']')?)?,
+        None => parse_hex(bytes)?,
+    };
+
+    (!bytes.is_empty()).then_some((index, bytes))
+}
+
+/// Parses `80, 66, 83`
+fn parse_list(text: &str) -> Option<Vec<u8>> {
+    text.split(',')
+        .map(|byte| byte.trim().parse::<u8>().ok())
+        .collect()
+}
+
+/// Parses `504253`
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+    use picoboy::framebuffer::FrameBuffer;
+    use picoboy::screenshot;
+
+    use super::*;
+
+    /// Formats chunks the way defmt prints them
+    fn line(index: u16, bytes: &[u8]) -> String {
+        format!("0.{index:06} screenshot {index} {bytes:?}\n")
+    }
+
+    fn error(log: &str) -> String {
+        last_dump(log).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn joins_chunks_of_both_notations() {
+        let log = "INFO booted\n\
+                   > screenshot\n\
+                   0.512345 screenshot 0 [80, 66, 83, 83, 1, 0, 0]\n\
+                   screenshot 1 0a0B\n\
+                   0.600000 frame took 12 ms\n";
+        assert_eq!(
+            last_dump(log).unwrap(),
+            [80, 66, 83, 83, 1, 0, 0, 0x0a, 0x0b]
+        );
+    }
+
+    #[test]
+    fn skips_lines_which_are_no_chunks() {
+        let log = format!(
+            "{}screenshot 1 [256]\nscreenshot x 00\nscreenshot 1 abc\nscreenshot 1 []\n{}",
+            line(0, &[1, 2]),
+            line(1, &[3]),
+        );
+        assert_eq!(last_dump(&log).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn takes_the_last_dump() {
+        let log = [line(0, &[1]), line(1, &[2]), line(0, &[3]), line(1, &[4])].concat();
+        assert_eq!(last_dump(&log).unwrap(), [3, 4]);
+    }
+
+    #[test]
+    fn reports_dropped_chunks() {
+        let log = [line(0, &[1]), line(1, &[2]), line(3, &[4]), line(4, &[5])].concat();
+        assert_eq!(
+            error(&log),
+            "line 3: chunk 3 instead of 2, some were dropped"
+        );
+
+        // A missing start
+        let log = [line(1, &[2]), line(2, &[3])].concat();
+        assert_eq!(error(&log), "line 1: chunk 1 without the start of its dump");
+
+        assert_eq!(error("booted\nidle\n"), "line 2: no screenshot found");
+    }
+
+    #[test]
+    fn a_restarted_dump_replaces_a_broken_one() {
+        let log = [line(0, &[1]), line(2, &[3]), line(0, &[7]), line(1, &[8])].concat();
+        assert_eq!(last_dump(&log).unwrap(), [7, 8]);
+
+        // Restarted before the first one was complete
+        let log = [line(0, &[1]), line(1, &[2]), line(0, &[5]), line(1, &[6])].concat();
+        assert_eq!(last_dump(&log).unwrap(), [5, 6]);
+    }
+
+    #[test]
+    fn round_trip_through_the_log() {
+        let mut framebuffer = Box::new(FrameBuffer::new());
+        let Ok(()) = Rectangle::new(Point::new(30, 40), Size::new(100, 60))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::MAGENTA))
+            .draw(framebuffer.as_mut());
+        let area = Rectangle::new(Point::new(20, 30), Size::new(150, 100));
+
+        let mut log = String::from("0.100000 booted\n");
+        screenshot::send(&framebuffer, area, |index, chunk| {
+            log.push_str(&line(index, chunk));
+        });
+
+        let dump = last_dump(&log).unwrap();
+        let mut pixels = vec![Rgb565::BLACK; 150 * 100];
+        let header = screenshot::decode(&dump, &mut pixels).unwrap();
+        assert_eq!(header.area(), area);
+        for (point, pixel) in area.points().zip(pixels) {
+            assert_eq!(framebuffer.pixel(point), Some(pixel));
+        }
+    }
+}
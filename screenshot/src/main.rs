@@ -0,0 +1,63 @@
+//! Turns a screenshot sent by the firmware into a PNG file.
+//!
+//! Reads a log with the chunks of a dump, e.g. the one `cargo embed` writes
+//! with `log_enabled`, and decodes the last dump in it.
+//!
+//! Usage: `cargo run -- <log> [output.png]`
+
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use embedded_graphics::pixelcolor::{Rgb565, Rgb888};
+use embedded_graphics::prelude::*;
+use picoboy::screenshot::{self, Header};
+
+mod chunks;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let log_path = args.next().ok_or("usage: screenshot <log> [output.png]")?;
+    let png_path = args
+        .next()
+        .unwrap_or_else(|| String::from("screenshot.png"));
+
+    let dump = chunks::last_dump(&fs::read_to_string(&log_path)?)?;
+
+    let header = Header::parse(&dump).map_err(|error| format!("{error:?}"))?;
+    let mut pixels = vec![Rgb565::BLACK; header.pixel_count()];
+    screenshot::decode(&dump, &mut pixels).map_err(|error| format!("{error:?}"))?;
+
+    save_png(Path::new(&png_path), &header, &pixels)?;
+
+    let area = header.area();
+    println!(
+        "Saved {}x{} pixels at ({}, {}) to {png_path}",
+        area.size.width, area.size.height, area.top_left.x, area.top_left.y
+    );
+
+    Ok(())
+}
+
+/// Writes the pixels as RGB PNG image
+fn save_png(path: &Path, header: &Header, pixels: &[Rgb565]) -> Result<(), Box<dyn Error>> {
+    let writer = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(writer, header.width.into(), header.height.into());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|&pixel| {
+            let rgb = Rgb888::from(pixel);
+            [rgb.r(), rgb.g(), rgb.b()]
+        })
+        .collect();
+
+    encoder.write_header()?.write_image_data(&data)?;
+
+    Ok(())
+}
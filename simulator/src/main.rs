@@ -0,0 +1,95 @@
+//! Runs the game on the host and renders every frame to a PNG file.
+//!
+//! Time is simulated, every frame advances the clock by exactly one update.
+//! The audio of the whole run is written to `audio.wav`.
+//!
+//! Usage: `cargo run -- <script> [output directory]`
+
+use std::cell::Cell;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use embedded_graphics::geometry::OriginDimensions;
+
+use game::{Game, UPDATE_INTERVAL_MS};
+use picoboy::audio::{SAMPLE_RATE, SILENCE};
+use picoboy::framebuffer::FrameBuffer;
+use picoboy::game_loop::{Clock, GameLoop, Step};
+use picoboy::input::{Controls, Input};
+
+mod panel;
+mod script;
+mod wav;
+
+use panel::Panel;
+use script::Script;
+
+/// Clock which only moves when told to
+struct SimulatedClock {
+    now_us: Cell<u64>,
+}
+
+impl SimulatedClock {
+    fn advance(&self, us: u32) {
+        self.now_us.set(self.now_us.get() + u64::from(us));
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now_us(&self) -> u64 {
+        self.now_us.get()
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let script_path = args
+        .next()
+        .ok_or("usage: simulator <script> [output directory]")?;
+    let out_dir = PathBuf::from(args.next().unwrap_or_else(|| String::from("frames")));
+
+    let mut script = Script::parse(&fs::read_to_string(&script_path)?)?;
+    fs::create_dir_all(&out_dir)?;
+
+    let mut display = Panel::new();
+    let mut framebuffer = Box::new(FrameBuffer::new());
+    let mut input = Input::new();
+    let mut game = Game::new(framebuffer.size());
+    let mut game_loop = GameLoop::new(UPDATE_INTERVAL_MS * 1000);
+    let clock = SimulatedClock {
+        now_us: Cell::new(0),
+    };
+
+    let mut audio = Vec::new();
+    let mut frame = 0;
+    while !script.finished() {
+        let mut result = Ok(());
+        game_loop.frame(&clock, |step| match step {
+            Step::Update(dt_ms) => {
+                input.update(script.sample(), dt_ms);
+                game.update(&input, dt_ms);
+
+                let start = audio.len();
+                audio.resize(start + (dt_ms * SAMPLE_RATE / 1000) as usize, SILENCE);
+                game.render_audio(&mut audio[start..]);
+            }
+            Step::Render => {
+                let Ok(()) = game.draw(framebuffer.as_mut());
+                let Ok(()) = framebuffer.flush(&mut display);
+                result = display.save_png(&out_dir.join(format!("frame_{frame:05}.png")));
+                frame += 1;
+            }
+        });
+        result?;
+
+        clock.advance(game_loop.step_us());
+    }
+
+    wav::save(&out_dir.join("audio.wav"), SAMPLE_RATE, &audio)?;
+
+    println!("Rendered {frame} frames to {}", out_dir.display());
+
+    Ok(())
+}
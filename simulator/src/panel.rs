@@ -0,0 +1,76 @@
+//! In-memory stand-in for the ST7789 display.
+
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::*,
+};
+use picoboy::display::DISPLAY_SIZE;
+
+/// Pixels of the simulated display
+pub struct Panel {
+    pixels: Vec<Rgb565>,
+}
+
+impl Panel {
+    /// Creates a black panel with the size of the display
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![Rgb565::BLACK; (DISPLAY_SIZE.width * DISPLAY_SIZE.height) as usize],
+        }
+    }
+
+    /// Writes the current content as RGB PNG image
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+
+        let mut encoder = png::Encoder::new(writer, DISPLAY_SIZE.width, DISPLAY_SIZE.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|&pixel| {
+                let rgb = Rgb888::from(pixel);
+                [rgb.r(), rgb.g(), rgb.b()]
+            })
+            .collect();
+
+        encoder.write_header()?.write_image_data(&data)?;
+
+        Ok(())
+    }
+}
+
+impl DrawTarget for Panel {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+
+        for Pixel(point, color) in pixels {
+            // Drawing outside of the display is silently clipped, just like on the hardware
+            if bounds.contains(point) {
+                let index = point.y as usize * DISPLAY_SIZE.width as usize + point.x as usize;
+                self.pixels[index] = color;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Panel {
+    fn size(&self) -> Size {
+        DISPLAY_SIZE
+    }
+}
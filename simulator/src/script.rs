@@ -0,0 +1,206 @@
+//! Scripted button input.
+//!
+//! Every line of a script holds a number of frames followed by the buttons
+//! held during these frames. Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Move right for one second, then idle for half a second
+//! 20 right
+//! 10
+//! 5 up left a
+//! ```
+
+use std::fmt;
+
+use picoboy::input::{Button, Buttons, Controls};
+
+/// A parsed script, replayed one frame per [`Controls::sample`]
+pub struct Script {
+    steps: Vec<(u32, Buttons)>,
+    step: usize,
+    frame: u32,
+}
+
+/// A line of the script could not be parsed
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Script {
+    /// Parses a script from its text representation
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut steps = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let frames = words.next().unwrap_or_default();
+            let frames = frames
+                .parse::<u32>()
+                .map_err(|_| error(format!("invalid frame count `{frames}`")))?;
+
+            let mut buttons = Buttons::NONE;
+            for word in words {
+                let button = match word {
+                    "up" => Button::Up,
+                    "down" => Button::Down,
+                    "left" => Button::Left,
+                    "right" => Button::Right,
+                    "center" => Button::Center,
+                    "a" => Button::A,
+                    "b" => Button::B,
+                    _ => return Err(error(format!("unknown button `{word}`"))),
+                };
+                buttons = buttons.with(button);
+            }
+
+            steps.push((frames, buttons));
+        }
+
+        Ok(Self {
+            steps,
+            step: 0,
+            frame: 0,
+        })
+    }
+
+    /// Returns `true` once all frames of the script have been read
+    pub fn finished(&self) -> bool {
+        self.steps[self.step..]
+            .iter()
+            .map(|(frames, _)| frames)
+            .sum::<u32>()
+            <= self.frame
+    }
+}
+
+impl Controls for Script {
+    fn sample(&mut self) -> Buttons {
+        while let Some(&(frames, buttons)) = self.steps.get(self.step) {
+            if self.frame < frames {
+                self.frame += 1;
+                return buttons;
+            }
+
+            self.step += 1;
+            self.frame = 0;
+        }
+
+        Buttons::NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples the whole script, one set of buttons per frame
+    fn frames(script: &mut Script) -> Vec<Buttons> {
+        let mut frames = Vec::new();
+        while !script.finished() {
+            frames.push(script.sample());
+        }
+        frames
+    }
+
+    fn parse_error(text: &str) -> String {
+        match Script::parse(text) {
+            Ok(_) => panic!("`{text}` parsed"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn holds_buttons_for_the_given_frames() {
+        let mut script = Script::parse("2 right a\n1\n3 b").unwrap();
+        let right_a = Buttons::NONE.with(Button::Right).with(Button::A);
+        let b = Buttons::from(Button::B);
+        assert_eq!(
+            frames(&mut script),
+            [right_a, right_a, Buttons::NONE, b, b, b]
+        );
+
+        // Nothing is held after the end
+        assert_eq!(script.sample(), Buttons::NONE);
+        assert!(script.finished());
+    }
+
+    #[test]
+    fn knows_every_button() {
+        let script = "1 up down left right center a b";
+        let buttons = frames(&mut Script::parse(script).unwrap())[0];
+        for button in [
+            Button::Up,
+            Button::Down,
+            Button::Left,
+            Button::Right,
+            Button::Center,
+            Button::A,
+            Button::B,
+        ] {
+            assert!(buttons.contains(button), "{button:?}");
+        }
+    }
+
+    #[test]
+    fn releases_buttons_between_steps() {
+        // A press is a step holding the button, the release the next step without it
+        let mut script = Script::parse("1 a\n1\n1 a").unwrap();
+        let a = Buttons::from(Button::A);
+        assert_eq!(frames(&mut script), [a, Buttons::NONE, a]);
+    }
+
+    #[test]
+    fn skips_comments_and_empty_lines() {
+        let text = "# Start\n\n   \n  # Indented comment\n\t2 a  \n0 b\n";
+        let mut script = Script::parse(text).unwrap();
+        let a = Buttons::from(Button::A);
+        assert_eq!(frames(&mut script), [a, a]);
+
+        assert!(Script::parse("").unwrap().finished());
+        assert!(Script::parse("# Only a comment\n0 a").unwrap().finished());
+    }
+
+    #[test]
+    fn reports_errors_with_line() {
+        assert_eq!(
+            parse_error("# Waits\n10\nright"),
+            "line 3: invalid frame count `right`"
+        );
+        assert_eq!(parse_error("-1 a"), "line 1: invalid frame count `-1`");
+        assert_eq!(
+            parse_error("1 a\n\n2 jump"),
+            "line 3: unknown button `jump`"
+        );
+        assert_eq!(parse_error("1 A"), "line 1: unknown button `A`");
+    }
+
+    #[test]
+    fn finishes_after_the_last_frame() {
+        let mut script = Script::parse("2 a\n0\n1").unwrap();
+        for _ in 0..3 {
+            assert!(!script.finished());
+            script.sample();
+        }
+        assert!(script.finished());
+    }
+}
@@ -0,0 +1,31 @@
+//! Minimal WAV writer for the rendered audio.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes unsigned 8 bit mono samples as WAV file
+pub fn save(path: &Path, sample_rate: u32, samples: &[u8]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let len = samples.len() as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    // Format: PCM, one channel, one byte per sample
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&8u16.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(samples)?;
+
+    writer.flush()
+}
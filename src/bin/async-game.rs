@@ -0,0 +1,238 @@
+//! Runs the game like `src/main.rs`, but as cooperating async tasks.
+//!
+//! Instead of polling, the tasks sleep on timer alarms, button edges and the
+//! display task on core 1, and the core waits for interrupts in between.
+//! Build it with `cargo run --release --features async --bin async-game`.
+#![no_std]
+#![no_main]
+
+use core::cell::{Cell, RefCell};
+use core::panic::PanicInfo;
+use core::pin::pin;
+use core::ptr::addr_of_mut;
+
+use bsp::entry;
+use bsp::hal::pac::{self, interrupt};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::{exception, ExceptionFrame};
+use defmt::{debug, info, unwrap, warn};
+use defmt_rtt as _;
+
+use picoboy_color as bsp;
+
+use embedded_graphics::geometry::OriginDimensions;
+
+use game::{Game, UPDATE_INTERVAL_MS};
+
+use picoboy::board::Board;
+use picoboy::button_edges;
+use picoboy::crash_screen;
+use picoboy::dimmer::Dimmer;
+use picoboy::executor::{wait_for, Executor};
+use picoboy::framebuffer::FrameBuffer;
+use picoboy::game_loop::GameLoop;
+use picoboy::handoff::Handoff;
+use picoboy::input::{Controls, Input};
+use picoboy::led_pattern::Pattern;
+use picoboy::multicore::{self, Stack};
+use picoboy::sleep::{self, sleep_ms, sleep_us};
+use picoboy::slots::{AppHeader, APP_HEADER_LEN};
+use picoboy::speaker::Speaker;
+
+/// Shown on the green LED while the game runs
+const HEARTBEAT: Pattern = Pattern::Heartbeat { period_ms: 1200 };
+
+/// Seconds the crash screen stays before the firmware restarts, `None` keeps it until switched off
+const CRASH_RESET_S: Option<u32> = None;
+
+/// Stack of the display task on core 1, in 32 bit words
+const CORE1_STACK_WORDS: usize = 2048;
+
+/// Time between two refills of the audio queue, well below the 100 ms it holds
+const AUDIO_REFILL_MS: u32 = 20;
+
+/// Time between two steps of the LED patterns
+const LED_TICK_MS: u32 = 20;
+
+// Name shown by the launcher when built for one of its slots
+#[link_section = ".app_header"]
+#[used]
+static APP_HEADER: [u8; APP_HEADER_LEN] = AppHeader::new("PicoBoy Color async demo").to_bytes();
+
+// Far too large for the stack, zero initialised so it ends up in .bss
+static mut FRAMEBUFFER: FrameBuffer = FrameBuffer::new();
+
+// Passes the framebuffer between the game on core 0 and the display task on core 1
+static mut HANDOFF: Handoff<FrameBuffer> = Handoff::new();
+
+static mut CORE1_STACK: Stack<CORE1_STACK_WORDS> = Stack::new();
+
+// Owned by the sample interrupt once started
+static SPEAKER: Mutex<RefCell<Option<Speaker>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    info!("Program start");
+
+    let mut board = unwrap!(Board::take());
+
+    // SAFETY: `main` is entered only once and is the only user of these statics
+    let (framebuffer, handoff, core1_stack) = unsafe {
+        (
+            &mut *addr_of_mut!(FRAMEBUFFER),
+            &mut *addr_of_mut!(HANDOFF),
+            &mut *addr_of_mut!(CORE1_STACK),
+        )
+    };
+    let screen = framebuffer.size();
+    let (mut frames, mut flushes) = handoff.split(framebuffer, None);
+
+    // Status led, beating while the game runs
+    board.leds.green.play(HEARTBEAT);
+
+    // Hand the speaker over to its interrupt
+    let mut speaker = board.speaker;
+    speaker.start();
+    cortex_m::interrupt::free(|cs| SPEAKER.borrow(cs).replace(Some(speaker)));
+
+    // Configure sleeps and button edges
+    sleep::init(board.timer, unwrap!(board.timer.alarm_1()));
+    let mut buttons = board.buttons;
+    buttons.listen();
+
+    // SAFETY: the handlers only use state which is in place now
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_0);
+        pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_1);
+        pac::NVIC::unmask(pac::Interrupt::IO_IRQ_BANK0);
+    }
+
+    // Flush the frames on core 1 while core 0 runs the tasks
+    let mut display = board.display;
+    let flush = move || loop {
+        let framebuffer = multicore::wait(|| flushes.take());
+        // The next frame is sent in full after a reset of the display
+        if let Err(error) = display.flush(framebuffer) {
+            warn!("Frame lost: {}", error);
+        }
+        flushes.release(framebuffer);
+        multicore::notify();
+    };
+    unwrap!(board.core1.spawn(core1_stack, flush));
+
+    let game = RefCell::new(Game::new(screen));
+    let held = Cell::new(buttons.sample());
+    let mut audio = board.audio;
+    let mut leds = board.leds;
+    let mut backlight = board.backlight;
+
+    // Updates and draws the game at a fixed rate
+    let play = pin!(async {
+        let mut input = Input::new();
+        let mut dimmer = Dimmer::default();
+        let mut game_loop = GameLoop::new(UPDATE_INTERVAL_MS * 1000);
+
+        loop {
+            sleep_us(game_loop.time_to_next_update_us(sleep::now_us())).await;
+
+            let start_us = sleep::now_us();
+            let frame = game_loop.advance(start_us);
+            let step_ms = game_loop.step_ms();
+            for _ in 0..frame.updates {
+                input.update(held.get(), step_ms);
+                // The press waking the backlight is not passed on to the game
+                let waking = dimmer.is_off() && !input.buttons().is_empty();
+                backlight.set_brightness(dimmer.update(&input, step_ms));
+                if !waking {
+                    game.borrow_mut().update(&input, step_ms);
+                }
+            }
+
+            if frame.rendered {
+                // Resolves once core 1 has flushed the previous frame
+                let framebuffer = wait_for(|| frames.acquire()).await;
+                // Drawing into the framebuffer cannot fail
+                let Ok(()) = game.borrow_mut().draw(framebuffer);
+                frames.present(framebuffer);
+                multicore::notify();
+            }
+
+            game_loop.record(&frame, start_us, sleep::now_us());
+            if let Some(stats) = game_loop.take_stats() {
+                debug!("{}", stats);
+            }
+        }
+    });
+
+    // Samples the buttons whenever one of them changes
+    let read_buttons = pin!(async {
+        loop {
+            button_edges::changed().await;
+            held.set(buttons.sample());
+        }
+    });
+
+    // Keeps the speaker supplied
+    let play_audio = pin!(async {
+        loop {
+            audio.fill(|samples| game.borrow_mut().render_audio(samples));
+            sleep_ms(AUDIO_REFILL_MS).await;
+        }
+    });
+
+    // Plays the LED patterns
+    let blink = pin!(async {
+        loop {
+            leds.update(LED_TICK_MS);
+            sleep_ms(LED_TICK_MS).await;
+        }
+    });
+
+    Executor::new(cortex_m::asm::wfe, cortex_m::asm::sev).run([
+        play,
+        read_buttons,
+        play_audio,
+        blink,
+    ]);
+
+    // The tasks never finish
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crash_screen::panic(info, CRASH_RESET_S)
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    crash_screen::hard_fault(frame, CRASH_RESET_S)
+}
+
+#[interrupt]
+fn TIMER_IRQ_0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(speaker) = SPEAKER.borrow(cs).borrow_mut().as_mut() {
+            speaker.on_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER_IRQ_1() {
+    sleep::on_alarm_interrupt();
+}
+
+#[interrupt]
+fn IO_IRQ_BANK0() {
+    button_edges::on_gpio_interrupt();
+}
+
+#[interrupt]
+fn SIO_IRQ_PROC1() {
+    multicore::on_fifo_interrupt();
+}
+
+// End of file
@@ -0,0 +1,229 @@
+//! Menu starting the apps in the flash slots, see `picoboy::slots`.
+//!
+//! Up and down pick an app, releasing A or the joystick center starts it.
+//! Holding A and B for two seconds reboots into the USB boot loader instead,
+//! so new firmware can be flashed without the BOOTSEL button. Flash the launcher itself with
+//! `cargo run --release --bin launcher`, and apps built with `PICOBOY_SLOT`.
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use core::ptr::addr_of_mut;
+
+use bsp::entry;
+use cortex_m_rt::{exception, ExceptionFrame};
+use defmt::{info, unwrap, warn};
+use defmt_rtt as _;
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use picoboy_color as bsp;
+
+use picoboy::board::Board;
+use picoboy::boot;
+use picoboy::crash_screen;
+use picoboy::display::{DisplayConfig, Orientation};
+use picoboy::framebuffer::FrameBuffer;
+use picoboy::input::{Button, ButtonConfig, Controls, Input, Repeat};
+use picoboy::slots::{AppHeader, SlotError, SLOT_COUNT};
+use picoboy::text::{fonts, FontStyle};
+
+/// Same orientation as the game in `src/main.rs`
+const DISPLAY: DisplayConfig = DisplayConfig::new(Orientation::PortraitSwapped);
+
+/// Holding A and B this long reboots into the USB boot loader, in milliseconds
+const BOOTSEL_HOLD_MS: u32 = 2000;
+
+/// Time between two samples of the buttons in milliseconds
+const TICK_MS: u32 = 10;
+
+/// Seconds the crash screen stays before the launcher restarts
+const CRASH_RESET_S: Option<u32> = Some(10);
+
+/// Top of the first entry of the menu
+const MENU_TOP: i32 = 80;
+
+/// Height of an entry of the menu including the gap to the next
+const ENTRY_HEIGHT: i32 = 40;
+
+// Far too large for the stack, zero initialised so it ends up in .bss
+static mut FRAMEBUFFER: FrameBuffer = FrameBuffer::new();
+
+#[entry]
+fn main() -> ! {
+    // The app expects a freshly reset chip, so nothing may be set up before
+    if let Some(slot) = boot::take_launch_request() {
+        // SAFETY: no peripheral is configured and no interrupt enabled yet
+        let Err(error) = unsafe { boot::launch(slot) };
+        warn!("Cannot start slot {}: {}", slot, error);
+    }
+
+    info!("Launcher start");
+
+    let mut board = unwrap!(Board::take());
+
+    // SAFETY: `main` is entered only once and is the only user of the framebuffer
+    let framebuffer = unsafe { &mut *addr_of_mut!(FRAMEBUFFER) };
+    if let Err(error) = board.display.set_config(DISPLAY) {
+        warn!("Display not turned: {}", error);
+    }
+    framebuffer.set_size(DISPLAY.size());
+
+    let apps: [Result<AppHeader, SlotError>; SLOT_COUNT] = core::array::from_fn(boot::app);
+    for (slot, app) in apps.iter().enumerate() {
+        match app {
+            Ok(header) => info!("Slot {}: {}", slot, header.name()),
+            Err(error) => info!("Slot {}: {}", slot, error),
+        }
+    }
+    let mut selected = apps.iter().position(Result::is_ok).unwrap_or(0);
+
+    let mut input = Input::new();
+    let scroll = ButtonConfig {
+        repeat: Some(Repeat {
+            delay_ms: 400,
+            interval_ms: 150,
+        }),
+        ..ButtonConfig::default()
+    };
+    input.configure(Button::Up, scroll);
+    input.configure(Button::Down, scroll);
+
+    // B was held while A or the center was down, their release starts nothing
+    let mut chord = false;
+
+    let mut redraw = true;
+    loop {
+        input.update(DISPLAY.remap(board.buttons.sample()), TICK_MS);
+
+        if input.pressed_or_repeated(Button::Up) {
+            selected = (selected + SLOT_COUNT - 1) % SLOT_COUNT;
+            redraw = true;
+        }
+        if input.pressed_or_repeated(Button::Down) {
+            selected = (selected + 1) % SLOT_COUNT;
+            redraw = true;
+        }
+
+        // Starting on the release leaves time to add B for the boot loader
+        if input.is_down(Button::B) {
+            chord = true;
+        }
+        if (input.released(Button::A) || input.released(Button::Center)) && !chord {
+            match &apps[selected] {
+                Ok(header) => {
+                    info!("Starting {}", header.name());
+                    boot::request_launch(selected);
+                }
+                Err(error) => warn!("Slot {} cannot be started: {}", selected, error),
+            }
+        }
+        if [Button::A, Button::Center, Button::B]
+            .into_iter()
+            .all(|button| !input.is_down(button))
+        {
+            chord = false;
+        }
+
+        let held_ms = input
+            .held(Button::A)
+            .zip(input.held(Button::B))
+            .map_or(0, |(a, b)| a.min(b));
+        if held_ms >= BOOTSEL_HOLD_MS {
+            info!("Rebooting into the USB boot loader");
+            boot::reboot_to_bootsel();
+        }
+
+        if redraw {
+            let Ok(()) = draw_menu(framebuffer, &apps, selected);
+            // Drawn again on the next tick, after a reset of the display
+            match board.display.flush(framebuffer) {
+                Ok(()) => redraw = false,
+                Err(error) => warn!("Menu not shown: {}", error),
+            }
+        }
+
+        board.delay.delay_ms(TICK_MS);
+    }
+}
+
+/// Draws the title, an entry per slot and the hints
+fn draw_menu<D>(
+    display: &mut D,
+    apps: &[Result<AppHeader, SlotError>],
+    selected: usize,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let center_x = DISPLAY.width() / 2;
+    let centered = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Middle)
+        .build();
+
+    Text::with_text_style(
+        "PicoBoy",
+        Point::new(center_x, 40),
+        FontStyle::new(&fonts::LARGE, Rgb565::WHITE),
+        centered,
+    )
+    .draw(display)?;
+
+    for (slot, app) in apps.iter().enumerate() {
+        let entry = Rectangle::new(
+            Point::new(16, MENU_TOP + slot as i32 * ENTRY_HEIGHT),
+            Size::new(DISPLAY.width() as u32 - 32, ENTRY_HEIGHT as u32 - 8),
+        );
+        let background = if slot == selected {
+            Rgb565::CSS_STEEL_BLUE
+        } else {
+            Rgb565::BLACK
+        };
+        entry
+            .into_styled(PrimitiveStyle::with_fill(background))
+            .draw(display)?;
+
+        let (name, color) = match app {
+            Ok(header) => (header.name(), Rgb565::WHITE),
+            Err(SlotError::Empty) => ("empty", Rgb565::CSS_GRAY),
+            Err(_) => ("invalid", Rgb565::CSS_GRAY),
+        };
+        Text::with_text_style(
+            name,
+            entry.center(),
+            FontStyle::new(&fonts::NORMAL, color),
+            centered,
+        )
+        .draw(display)?;
+    }
+
+    let hints = ["A: start", "Hold A+B: USB boot loader"];
+    for (line, hint) in hints.into_iter().enumerate() {
+        Text::with_text_style(
+            hint,
+            Point::new(center_x, DISPLAY.height() - 40 + line as i32 * 16),
+            FontStyle::new(&fonts::SMALL, Rgb565::CSS_LIGHT_GRAY),
+            centered,
+        )
+        .draw(display)?;
+    }
+
+    Ok(())
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crash_screen::panic(info, CRASH_RESET_S)
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    crash_screen::hard_fault(frame, CRASH_RESET_S)
+}
+
+// End of file
@@ -1,172 +1,522 @@
-//! Draws a controllable circle on the display.
+//! Runs the game: a title screen, a controllable ball and a pause overlay, with music.
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
+#[cfg(feature = "usb-serial")]
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr::addr_of_mut;
+
 use bsp::entry;
-use defmt::*;
+use bsp::hal::pac::{self, interrupt};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::{exception, ExceptionFrame};
+use defmt::{debug, info, unwrap, warn};
 use defmt_rtt as _;
-use panic_probe as _;
-
-use rp2040_hal::Spi;
-
-use embedded_graphics::{
-    pixelcolor::Rgb565,
-    prelude::*,
-    primitives::{Circle, PrimitiveStyleBuilder},
-};
-use embedded_hal::digital::{InputPin, OutputPin};
-
-use display_interface_spi::SPIInterface;
-use st7789::{Orientation, ST7789};
+use embedded_graphics::{prelude::*, primitives::Rectangle};
 
 // Provide an alias for our BSP so we can switch targets quickly.
 // Uncomment the BSP you included in Cargo.toml, the rest of the code does not need to change.
 use picoboy_color as bsp;
 
-use bsp::hal::{
-    clocks::{init_clocks_and_plls, Clock},
-    pac,
-    sio::Sio,
-    watchdog::Watchdog,
+use game::{Game, UPDATE_INTERVAL_MS};
+
+use picoboy::battery::{Battery, BatteryIndicator, BatteryLevel};
+use picoboy::board::Board;
+use picoboy::boot;
+#[cfg(feature = "usb-serial")]
+use picoboy::console::{self, Command, LineBuffer, ParseError};
+use picoboy::crash_screen;
+use picoboy::dimmer::Dimmer;
+use picoboy::display::{DisplayConfig, Orientation};
+use picoboy::framebuffer::FrameBuffer;
+use picoboy::game_loop::{GameLoop, Step};
+use picoboy::handoff::Handoff;
+use picoboy::idle::{IdleTimer, PowerMode};
+use picoboy::input::{Button, Controls, Input};
+use picoboy::led_pattern::Pattern;
+use picoboy::multicore::{self, Stack};
+use picoboy::screenshot;
+use picoboy::slots::{AppHeader, APP_HEADER_LEN};
+use picoboy::speaker::Speaker;
+use picoboy::supervisor::{Monitor, ResetNotice, ResetReason, Task};
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+use picoboy::usb::Usb;
+
+/// Orientation of the picture, landscape games pick `Landscape` or `LandscapeSwapped`
+const DISPLAY: DisplayConfig = DisplayConfig::new(Orientation::PortraitSwapped);
+
+/// Shown on the green LED while the game runs
+const HEARTBEAT: Pattern = Pattern::Heartbeat { period_ms: 1200 };
+
+/// Shown on the red LED if the save data cannot be mounted or written
+const SAVE_ERROR: Pattern = Pattern::ErrorCode(1);
+
+/// Shown on the yellow LED while the battery is low
+const BATTERY_LOW: Pattern = Pattern::Blink {
+    on_ms: 100,
+    off_ms: 1900,
+};
+
+/// Shown on the yellow LED while the battery is nearly empty
+const BATTERY_CRITICAL: Pattern = Pattern::Blink {
+    on_ms: 200,
+    off_ms: 200,
 };
-use rp2040_hal::fugit::RateExtU32;
+
+/// Top left corner of the battery icon in the top right corner of the screen
+const BATTERY_POSITION: Point =
+    Point::new(DISPLAY.width() - BatteryIndicator::SIZE.width as i32 - 4, 4);
+
+/// Holding the joystick down this long sends a screenshot, in milliseconds
+const SCREENSHOT_HOLD_MS: u32 = 1000;
+
+/// Holding A and B this long reboots into the USB boot loader, in milliseconds
+const BOOTSEL_HOLD_MS: u32 = 2000;
+
+/// Seconds the crash screen stays before the firmware restarts, `None` keeps it until switched off
+const CRASH_RESET_S: Option<u32> = None;
+
+/// The watchdog resets the chip if the main loop stalls this long, in milliseconds
+const WATCHDOG_TIMEOUT_MS: u32 = 1000;
+
+/// How long the reason of a reset other than power-on is shown, 0 never shows it
+const RESET_NOTICE_MS: u32 = 5000;
+
+/// Stack of the display task on core 1, in 32 bit words
+const CORE1_STACK_WORDS: usize = 2048;
+
+// Name shown by the launcher when built for one of its slots
+#[link_section = ".app_header"]
+#[used]
+static APP_HEADER: [u8; APP_HEADER_LEN] = AppHeader::new("PicoBoy Color demo").to_bytes();
+
+// Far too large for the stack, zero initialised so it ends up in .bss
+static mut FRAMEBUFFER: FrameBuffer = FrameBuffer::new();
+
+// Passes the framebuffer between the game on core 0 and the display task on core 1
+static mut HANDOFF: Handoff<FrameBuffer> = Handoff::new();
+
+static mut CORE1_STACK: Stack<CORE1_STACK_WORDS> = Stack::new();
+
+// Check in with the watchdog supervisor, see `Monitor::update`
+static DISPLAY_TASK: Task = Task::new("display", 1000);
+static AUDIO_TASK: Task = Task::new("audio", 500);
+
+// Owned by the sample interrupt once started
+static SPEAKER: Mutex<RefCell<Option<Speaker>>> = Mutex::new(RefCell::new(None));
+
+// Polled by the USB interrupt, shared with the game loop
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+static USB: Mutex<RefCell<Option<Usb>>> = Mutex::new(RefCell::new(None));
+
+/// Where a requested screenshot is sent
+#[derive(Clone, Copy)]
+enum Destination {
+    Rtt,
+    #[cfg(feature = "usb-serial")]
+    Console,
+}
 
 #[entry]
 fn main() -> ! {
     info!("Program start");
 
-    let mut pac = pac::Peripherals::take().unwrap();
-    let core = pac::CorePeripherals::take().unwrap();
-    let mut watchdog = Watchdog::new(pac.WATCHDOG);
-    let sio = Sio::new(pac.SIO);
-
-    // External high-speed crystal on the pico board is 12Mhz
-    let external_xtal_freq_hz = 12_000_000u32;
-    let clocks = init_clocks_and_plls(
-        external_xtal_freq_hz,
-        pac.XOSC,
-        pac.CLOCKS,
-        pac.PLL_SYS,
-        pac.PLL_USB,
-        &mut pac.RESETS,
-        &mut watchdog,
-    )
-    .ok()
-    .unwrap();
-
-    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
-
-    let pins = bsp::Pins::new(
-        pac.IO_BANK0,
-        pac.PADS_BANK0,
-        sio.gpio_bank0,
-        &mut pac.RESETS,
-    );
-
-    // Define display width and height
-    const DISPLAY_WIDTH: i32 = 240;
-    const DISPLAY_HEIGHT: i32 = 280;
-
-    // Switch on backlight
-    let mut backlight = pins.backlight.into_push_pull_output();
-    backlight.set_high().unwrap();
-
-    // Configure SPI pins
-    let spi_sclk = pins.sck.into_function::<rp2040_hal::gpio::FunctionSpi>(); // SCK
-    let spi_mosi = pins.mosi.into_function::<rp2040_hal::gpio::FunctionSpi>(); // MOSI
-    let spi_miso = pins.gpio16.into_function::<rp2040_hal::gpio::FunctionSpi>(); // MISO (not needed)
-
-    // Create spi instance
-    let spi = Spi::<_, _, _, 8>::new(pac.SPI0, (spi_mosi, spi_miso, spi_sclk));
-
-    // Init spi
-    let spi = spi.init(
-        &mut pac.RESETS,
-        clocks.peripheral_clock.freq(),
-        125000000u32.Hz(),
-        embedded_hal::spi::MODE_3, // ST7789 requires SPI mode 3
-    );
-
-    // Configure display pins
-    let dc = pins.dc.into_push_pull_output(); // Data/command pin
-    let rst = pins.reset.into_push_pull_output(); // Reset pin
-    let cs = pins.cs.into_push_pull_output(); // Chip select
-
-    // Create display interface
-    let di = SPIInterface::new(spi, dc, cs);
-
-    // Create and init display
-    let mut display = ST7789::new(di, rst, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
-
-    display.init(&mut delay).unwrap();
-    display
-        .set_orientation(Orientation::PortraitSwapped)
-        .unwrap();
-
-    // Clear display
-    display.clear(Rgb565::RED).unwrap();
-
-    // Status led
-    let mut led_pin = pins.led_red.into_push_pull_output();
-    led_pin.set_high().unwrap();
-
-    // Configuring joystick buttons
-    let mut joystick_up = pins.joystick_up.into_pull_up_input();
-    let mut joystick_down = pins.joystick_down.into_pull_up_input();
-    let mut joystick_left = pins.joystick_left.into_pull_up_input();
-    let mut joystick_right = pins.joystick_right.into_pull_up_input();
-
-    let mut x: i32 = DISPLAY_WIDTH / 2;
-    let mut y: i32 = DISPLAY_HEIGHT / 2;
-
-    let mut old_x = 0;
-    let mut old_y = 0;
-
-    // Clear display
-    display.clear(Rgb565::BLACK).unwrap();
+    let mut board = unwrap!(Board::take());
+
+    // Indices as in a `ResetReason::Watchdog` of this firmware
+    let mut monitor = Monitor::new([&DISPLAY_TASK, &AUDIO_TASK]);
+    let hung_task = match board.reset_reason {
+        ResetReason::Watchdog(Some(index)) => monitor.name(index),
+        _ => None,
+    };
+    match hung_task {
+        Some(task) => warn!("Reset by the watchdog, {} hung", task),
+        None => info!("Reset reason: {}", board.reset_reason),
+    }
+    let mut notice_ms = match board.reset_reason {
+        ResetReason::PowerOn => 0,
+        _ => RESET_NOTICE_MS,
+    };
+
+    // SAFETY: `main` is entered only once and is the only user of these statics
+    let (framebuffer, handoff, core1_stack) = unsafe {
+        (
+            &mut *addr_of_mut!(FRAMEBUFFER),
+            &mut *addr_of_mut!(HANDOFF),
+            &mut *addr_of_mut!(CORE1_STACK),
+        )
+    };
+    // The game goes on without a picture
+    if let Err(error) = board.display.set_config(DISPLAY) {
+        warn!("Display not turned: {}", error);
+    }
+    framebuffer.set_size(DISPLAY.size());
+    let (mut frames, mut flushes) = handoff.split(framebuffer, None);
+
+    // Status led, beating while the game runs
+    board.leds.green.play(HEARTBEAT);
+
+    // Count the starts in the save data
+    match board.storage.as_mut() {
+        Some(storage) => {
+            let mut value = [0; 4];
+            let starts = match storage.get("starts", &mut value) {
+                Ok(Some(4)) => u32::from_le_bytes(value) + 1,
+                _ => 1,
+            };
+            if let Err(error) = storage.set("starts", &starts.to_le_bytes()) {
+                warn!("Saving failed: {}", error);
+                board.leds.red.play(SAVE_ERROR);
+            }
+            info!("Start number {}", starts);
+        }
+        None => board.leds.red.play(SAVE_ERROR),
+    }
+
+    // Hand the speaker over to its interrupt
+    let mut speaker = board.speaker;
+    speaker.start();
+    cortex_m::interrupt::free(|cs| SPEAKER.borrow(cs).replace(Some(speaker)));
+    // SAFETY: the handler only uses the speaker, which is in place now
+    unsafe { pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_0) };
+
+    // Hand the USB device over to its interrupt
+    #[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+    {
+        let usb = board.usb;
+        cortex_m::interrupt::free(|cs| USB.borrow(cs).replace(Some(usb)));
+        // SAFETY: the handler only uses the USB device, which is in place now
+        unsafe { pac::NVIC::unmask(pac::Interrupt::USBCTRL_IRQ) };
+    }
+
+    // Flush the frames on core 1 while core 0 updates the game and mixes audio
+    let mut display = board.display;
+    let flush = move || loop {
+        let framebuffer = multicore::wait(|| flushes.take());
+        // The next frame is sent in full after a reset of the display
+        if let Err(error) = display.flush(framebuffer) {
+            warn!("Frame lost: {}", error);
+        }
+        flushes.release(framebuffer);
+        multicore::notify();
+        DISPLAY_TASK.check_in();
+    };
+    unwrap!(board.core1.spawn(core1_stack, flush));
+
+    // From here on a stalled main loop or a hung task resets the chip
+    board.watchdog.start(WATCHDOG_TIMEOUT_MS);
+
+    let mut input = Input::new();
+    let mut dimmer = Dimmer::default();
+    let mut idle = IdleTimer::default();
+    let mut battery = Battery::default();
+    let mut mode = PowerMode::Run;
+    let mut screenshot: Option<(Rectangle, Destination)> = None;
+    #[cfg(feature = "usb-serial")]
+    let mut line = LineBuffer::new();
+    #[cfg(feature = "usb-serial")]
+    let mut console_log = false;
+    let mut game = Game::new(DISPLAY.size());
+    let mut game_loop = GameLoop::new(UPDATE_INTERVAL_MS * 1000);
 
     loop {
-        // Check entries and adjust position
-        if joystick_down.is_low().unwrap() {
-            y = y.saturating_add(2);
+        game_loop.frame(&board.timer, |step| match step {
+            Step::Update(dt_ms) => {
+                input.update(DISPLAY.remap(board.buttons.sample()), dt_ms);
+                board.leds.update(dt_ms);
+                // The press waking the backlight is not passed on to the game
+                let waking = dimmer.is_off() && !input.buttons().is_empty();
+                board.backlight.set_brightness(dimmer.update(&input, dt_ms));
+                if !waking {
+                    game.update(&input, dt_ms);
+                }
+                #[cfg(feature = "usb-gamepad")]
+                with_usb(|usb| usb.gamepad.update(input.buttons()));
+                mode = idle.update(&input, dt_ms);
+                notice_ms = notice_ms.saturating_sub(dt_ms);
+
+                // Nothing is drawn while the clock is slow
+                monitor.set_enabled(&DISPLAY_TASK, mode == PowerMode::Run);
+                // A hung task is logged once, the watchdog resets the chip soon after
+                let _ = board.watchdog.check(&mut monitor, dt_ms);
+
+                // Once per hold, the press itself still reaches the game
+                let held_ms = input.held(Button::Center).unwrap_or(0);
+                if held_ms >= SCREENSHOT_HOLD_MS && held_ms < SCREENSHOT_HOLD_MS + dt_ms {
+                    screenshot = Some((DISPLAY.bounding_box(), Destination::Rtt));
+                }
+
+                let bootsel_ms = input
+                    .held(Button::A)
+                    .zip(input.held(Button::B))
+                    .map_or(0, |(a, b)| a.min(b));
+                if bootsel_ms >= BOOTSEL_HOLD_MS {
+                    info!("Rebooting into the USB boot loader");
+                    boot::reboot_to_bootsel();
+                }
+
+                if let Some(level) = battery.update(board.voltage.read_mv()) {
+                    info!("Battery {} at {} mV", level, battery.voltage_mv());
+                    #[cfg(feature = "usb-serial")]
+                    if console_log {
+                        let _ = write!(
+                            Console::dropping(),
+                            "Battery {:?} at {} mV\r\n",
+                            level,
+                            battery.voltage_mv()
+                        );
+                    }
+                    board.leds.yellow.play(match level {
+                        BatteryLevel::Low => BATTERY_LOW,
+                        BatteryLevel::Critical => BATTERY_CRITICAL,
+                        BatteryLevel::Normal | BatteryLevel::External => Pattern::OFF,
+                    });
+                }
+            }
+            // Nobody is watching while the clock is slow
+            Step::Render if mode != PowerMode::Run => {}
+            Step::Render => {
+                let framebuffer = multicore::wait(|| frames.acquire());
+                // Drawing into the framebuffer cannot fail
+                let Ok(()) = game.draw(framebuffer);
+                let Ok(()) = BatteryIndicator::new(BATTERY_POSITION, &battery).draw(framebuffer);
+                if notice_ms > 0 {
+                    let Ok(()) = ResetNotice::new(board.reset_reason, hung_task).draw(framebuffer);
+                }
+                frames.present(framebuffer);
+                multicore::notify();
+            }
+        });
+
+        // Keep the speaker supplied while waiting for the next update
+        board.audio.fill(|samples| game.render_audio(samples));
+
+        // Run the commands typed into the console
+        #[cfg(feature = "usb-serial")]
+        {
+            let mut received = [0; 64];
+            let len = with_usb(|usb| usb.serial.read(&mut received)).unwrap_or(0);
+            for &byte in &received[..len] {
+                echo(byte);
+                let Some(result) = line.push(byte) else {
+                    continue;
+                };
+
+                let mut console = Console::dropping();
+                let _ = match result {
+                    Ok(Command::Help) => console::HELP
+                        .lines()
+                        .try_for_each(|help| write!(console, "{}\r\n", help)),
+                    Ok(Command::Status) => write!(
+                        console,
+                        "battery {:?} at {} mV, {}%\r\nbacklight {}%\r\npower {:?}\r\n",
+                        battery.level(),
+                        battery.voltage_mv(),
+                        battery.percent(),
+                        dimmer.brightness(),
+                        mode
+                    ),
+                    Ok(Command::Brightness(Some(percent))) => {
+                        dimmer.set_brightness(percent);
+                        Ok(())
+                    }
+                    Ok(Command::Brightness(None)) => {
+                        write!(console, "{}%\r\n", dimmer.config().brightness)
+                    }
+                    Ok(Command::Screenshot(area)) => {
+                        let area = area.unwrap_or(DISPLAY.bounding_box());
+                        screenshot = Some((area, Destination::Console));
+                        Ok(())
+                    }
+                    Ok(Command::Log(Some(enabled))) => {
+                        console_log = enabled;
+                        Ok(())
+                    }
+                    Ok(Command::Log(None)) => {
+                        write!(console, "{}\r\n", if console_log { "on" } else { "off" })
+                    }
+                    Err(ParseError::Empty) => Ok(()),
+                    Err(error) => write!(console, "error: {}\r\n", error),
+                };
+                let _ = console.write_str("> ");
+            }
+        }
+
+        if let Some((area, destination)) = screenshot.take() {
+            info!("Sending a screenshot");
+
+            // The framebuffer holds the last frame once core 1 has flushed it
+            let framebuffer = multicore::wait(|| frames.acquire());
+            match destination {
+                Destination::Rtt => screenshot::log(framebuffer, area, || {
+                    board.watchdog.feed();
+                    board.delay.delay_ms(1);
+                }),
+                // Hex digits, which the screenshot tool reads as well as the defmt output
+                #[cfg(feature = "usb-serial")]
+                Destination::Console => screenshot::send(framebuffer, area, |index, chunk| {
+                    board.watchdog.feed();
+                    let mut console = Console::waiting();
+                    let _ = write!(console, "screenshot {} ", index);
+                    for byte in chunk {
+                        let _ = write!(console, "{:02x}", byte);
+                    }
+                    let _ = console.write_str("\r\n");
+                }),
+            }
+            frames.present(framebuffer);
+            multicore::notify();
         }
 
-        if joystick_up.is_low().unwrap() {
-            y = y.saturating_sub(2);
+        // The USB connection would not survive dormant mode
+        #[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+        if mode == PowerMode::Dormant && with_usb(|usb| usb.is_configured()) == Some(true) {
+            mode = PowerMode::Slow;
         }
 
-        if joystick_right.is_low().unwrap() {
-            x = x.saturating_add(2);
+        if mode == PowerMode::Dormant {
+            info!("Dormant until a button is pressed");
+
+            // Finish the flush on core 1 and silence the outputs, they would freeze as they are
+            let framebuffer = multicore::wait(|| frames.acquire());
+            with_speaker(Speaker::stop);
+            board.leds.blank();
+            board.backlight.off();
+
+            // The watchdog would count on once the crystal starts again
+            board.watchdog.stop();
+            board.power.dormant(&mut board.buttons);
+            board.watchdog.start(WATCHDOG_TIMEOUT_MS);
+            monitor.restart();
+
+            with_speaker(Speaker::start);
+            frames.present(framebuffer);
+            multicore::notify();
+            dimmer.wake();
+            idle.wake();
+            mode = PowerMode::Run;
+            info!("Awake");
         }
 
-        if joystick_left.is_low().unwrap() {
-            x = x.saturating_sub(2);
+        // Sleep until the next update is due, interrupts like the speaker's wake up in between
+        board.power.set_slow(mode == PowerMode::Slow);
+        let now_us = board.timer.get_counter().ticks();
+        board
+            .power
+            .wait_until(now_us + game_loop.time_to_next_update_us(now_us));
+
+        if let Some(stats) = game_loop.take_stats() {
+            debug!("{}", stats);
+            #[cfg(feature = "usb-serial")]
+            if console_log {
+                let _ = write!(Console::dropping(), "{:?}\r\n", stats);
+            }
+        }
+        if let Some(stats) = board.power.take_stats() {
+            debug!("{} ({}% asleep)", stats, stats.asleep_percent());
+            #[cfg(feature = "usb-serial")]
+            if console_log {
+                let _ = write!(
+                    Console::dropping(),
+                    "{:?} ({}% asleep)\r\n",
+                    stats,
+                    stats.asleep_percent()
+                );
+            }
         }
+    }
+}
 
-        if x != old_x || y != old_y {
-            // Only paint over the old circle with black instead of erasing the entire screen
-            let old_circle = Circle::new(Point::new(old_x, old_y), 25).into_styled(
-                PrimitiveStyleBuilder::new()
-                    .fill_color(Rgb565::BLACK)
-                    .build(),
-            );
-            old_circle.draw(&mut display).unwrap();
-
-            // Draw a new circle
-            let new_circle = Circle::new(Point::new(x, y), 25).into_styled(
-                PrimitiveStyleBuilder::new()
-                    .fill_color(Rgb565::MAGENTA)
-                    .build(),
-            );
-            new_circle.draw(&mut display).unwrap();
-
-            // Update positions
-            old_x = x;
-            old_y = y;
+fn with_speaker(f: impl FnOnce(&mut Speaker)) {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(speaker) = SPEAKER.borrow(cs).borrow_mut().as_mut() {
+            f(speaker);
         }
+    });
+}
 
-        delay.delay_ms(50);
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+fn with_usb<R>(f: impl FnOnce(&mut Usb) -> R) -> Option<R> {
+    cortex_m::interrupt::free(|cs| USB.borrow(cs).borrow_mut().as_mut().map(f))
+}
+
+/// Text output on the USB serial console
+#[cfg(feature = "usb-serial")]
+struct Console {
+    // Wait for the host to take the output instead of dropping what does not fit
+    wait: bool,
+}
+
+#[cfg(feature = "usb-serial")]
+impl Console {
+    /// For logs, which must not stall the game
+    fn dropping() -> Self {
+        Self { wait: false }
     }
+
+    /// For screenshots, which are useless with gaps
+    fn waiting() -> Self {
+        Self { wait: true }
+    }
+}
+
+#[cfg(feature = "usb-serial")]
+impl fmt::Write for Console {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let mut bytes = text.as_bytes();
+        while !bytes.is_empty() {
+            match with_usb(|usb| usb.serial.write(bytes)) {
+                Some(0) if self.wait => cortex_m::asm::wfi(),
+                Some(len) if len > 0 => bytes = &bytes[len..],
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Shows a typed character in the terminal
+#[cfg(feature = "usb-serial")]
+fn echo(byte: u8) {
+    let typed = [byte];
+    let echoed: &[u8] = match byte {
+        b'\r' => b"\r\n",
+        // Terminals ending lines with both already moved to the next one
+        b'\n' => b"",
+        // Move back, blank the character and move back again
+        0x08 | 0x7F => b"\x08 \x08",
+        _ => &typed,
+    };
+    with_usb(|usb| usb.serial.write(echoed));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crash_screen::panic(info, CRASH_RESET_S)
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    crash_screen::hard_fault(frame, CRASH_RESET_S)
+}
+
+#[interrupt]
+fn TIMER_IRQ_0() {
+    with_speaker(Speaker::on_interrupt);
+    AUDIO_TASK.check_in();
+}
+
+#[cfg(any(feature = "usb-serial", feature = "usb-gamepad"))]
+#[interrupt]
+fn USBCTRL_IRQ() {
+    with_usb(Usb::poll);
+}
+
+#[interrupt]
+fn SIO_IRQ_PROC1() {
+    multicore::on_fifo_interrupt();
 }
 
 // End of file